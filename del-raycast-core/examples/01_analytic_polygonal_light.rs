@@ -1,4 +1,4 @@
-use num_traits::float::FloatConst;
+use del_raycast_core::polygonal_light::{LtcTable, Microfacet};
 
 struct Ray {
     o: [f64; 3],
@@ -11,9 +11,27 @@ impl Ray {
     }
 }
 
-fn radiance(ray: &Ray, vtx2xyz: &[f64], _rng: &mut rand::rngs::ThreadRng) -> [f64; 3] {
-    use del_geo_core::mat3_col_major::Mat3ColMajor;
+/// shading of the floor lit by the polygonal light
+enum Shading<'a> {
+    /// white Lambertian floor
+    Diffuse,
+    /// glossy floor shaded analytically with the LTC
+    GlossyLtc(&'a LtcTable),
+    /// glossy floor shaded by sampling the BRDF (reference of the LTC)
+    GlossyMonteCarlo,
+}
+
+const MICROFACET: Microfacet = Microfacet::Ggx;
+const ALPHA: f32 = 0.2;
+
+fn radiance(
+    ray: &Ray,
+    vtx2xyz: &[f64],
+    shading: &Shading,
+    rng: &mut rand::rngs::ThreadRng,
+) -> [f64; 3] {
     use del_geo_core::vec3::Vec3;
+    use rand::Rng;
     let floor = ([0., 0., 0.], [0., 1., 0.]);
     let mut i_material = 0; // none
     let mut hit_depth = Option::<f64>::None;
@@ -43,21 +61,47 @@ fn radiance(ray: &Ray, vtx2xyz: &[f64], _rng: &mut rand::rngs::ThreadRng) -> [f6
     match i_material {
         1 => {
             let n = [0., 1., 0.];
-            let m = [1.0, 0., 0., 0., 1.0, 0., 0., 0., 1.0];
             let hit_pos = ray.o.add(&ray.d.scale(hit_depth.unwrap()));
-            let mut a = 0.;
-            let num_vtx = vtx2xyz.len() / 3;
-            for ino0 in 0..num_vtx {
-                let ino1 = (ino0 + 1) % num_vtx;
-                let v0 = arrayref::array_ref![&vtx2xyz, ino0 * 3, 3];
-                let v1 = arrayref::array_ref![&vtx2xyz, ino1 * 3, 3];
-                let v0 = m.mult_vec(&v0.sub(&hit_pos)).normalize();
-                let v1 = m.mult_vec(&v1.sub(&hit_pos)).normalize();
-                let t0 = v0.cross(&v1).normalize().dot(&n);
-                let t1 = v0.dot(&v1).acos();
-                a += t0 * t1;
-            }
-            a *= -f64::FRAC_1_PI();
+            let dir_out = ray.d.scale(-1.).normalize();
+            // the floor lit by the two-sided polygon with the unit radiance
+            let a = match shading {
+                Shading::Diffuse => {
+                    del_raycast_core::polygonal_light::form_factor(vtx2xyz, &hit_pos, &n, true)
+                }
+                Shading::GlossyLtc(table) => {
+                    let vtx2xyz: Vec<f32> = vtx2xyz.iter().map(|&v| v as f32).collect();
+                    del_raycast_core::polygonal_light::ltc_glossy(
+                        table,
+                        &vtx2xyz,
+                        &hit_pos.map(|v| v as f32),
+                        &n.map(|v| v as f32),
+                        &dir_out.map(|v| v as f32),
+                        ALPHA,
+                        true,
+                    ) as f64
+                }
+                Shading::GlossyMonteCarlo => {
+                    // the frame whose x-axis is the tangent toward the viewer
+                    let ex = dir_out.sub(&n.scale(n.dot(&dir_out))).normalize();
+                    let ey = n.cross(&ex);
+                    let wo = [dir_out.dot(&ex) as f32, 0f32, dir_out.dot(&n) as f32];
+                    let wi = MICROFACET.sample_wi(ALPHA, &wo, &[rng.random(), rng.random()]);
+                    let pdf = MICROFACET.pdf_wi(ALPHA, &wo, &wi);
+                    let dir_in = del_geo_core::vec3::add_three(
+                        &ex.scale(wi[0] as f64),
+                        &ey.scale(wi[1] as f64),
+                        &n.scale(wi[2] as f64),
+                    );
+                    // the polygon is on the plane `z = 0` in front of the ray
+                    let is_front = dir_in[2] * hit_pos[2] < 0.;
+                    let wn = del_msh_cpu::polyloop3::winding_number(vtx2xyz, &hit_pos, &dir_in);
+                    if is_front && wi[2] > 0f32 && pdf > 0f32 && (wn.abs() - 1.0).abs() < 1.0e-3 {
+                        (MICROFACET.eval_brdf_cos(ALPHA, &wo, &wi) / pdf) as f64
+                    } else {
+                        0.
+                    }
+                }
+            };
             [a, a, a]
         }
         2 => [1., 1., 1.],
@@ -70,21 +114,38 @@ fn main() {
     let mut rng = rand::rng();
     let cam =
         del_raycast_core::cam3::Camera3::new(1024, 768, [50., 52., 295.6], [0., -0.042612, -1.]);
-    let mut img = Vec::<image::Rgb<f32>>::new();
-    img.resize(cam.w * cam.h, image::Rgb([0_f32; 3]));
-    for y in 0..cam.h {
-        // Loop over image rows
-        // dbg!(stderr, "\rRendering (%d spp) %5.2f%%", samps * 4, 100. * y / (h - 1));
-        for x in 0..cam.w {
-            let ray = cam.ray(0.5 + x as f64, 0.5 + y as f64);
-            let ray = Ray::new(ray.0, ray.1);
-            let c = radiance(&ray, &vtx2xyz, &mut rng);
-            img[(cam.h - 1 - y) * cam.w + x] = image::Rgb([c[0] as f32, c[1] as f32, c[2] as f32]);
+    let table = LtcTable::new(MICROFACET, 32, 32, 256);
+    for (shading, num_sample, name) in [
+        (Shading::Diffuse, 1, "01_analytic_polygonal_light"),
+        (
+            Shading::GlossyLtc(&table),
+            1,
+            "01_analytic_polygonal_light__glossy_ltc",
+        ),
+        (
+            Shading::GlossyMonteCarlo,
+            64,
+            "01_analytic_polygonal_light__glossy_mc",
+        ),
+    ] {
+        let mut img = Vec::<image::Rgb<f32>>::new();
+        img.resize(cam.w * cam.h, image::Rgb([0_f32; 3]));
+        for y in 0..cam.h {
+            for x in 0..cam.w {
+                let ray = cam.ray(0.5 + x as f64, 0.5 + y as f64);
+                let ray = Ray::new(ray.0, ray.1);
+                let mut c = [0f64; 3];
+                for _i_sample in 0..num_sample {
+                    let l = radiance(&ray, &vtx2xyz, &shading, &mut rng);
+                    c = del_geo_core::vec3::add(&c, &l);
+                }
+                let c = del_geo_core::vec3::scale(&c, 1. / num_sample as f64);
+                img[(cam.h - 1 - y) * cam.w + x] =
+                    image::Rgb([c[0] as f32, c[1] as f32, c[2] as f32]);
+            }
         }
-    }
-    {
         use image::codecs::hdr::HdrEncoder;
-        let file = std::fs::File::create("target/01_analytic_polygonal_light.hdr").unwrap();
+        let file = std::fs::File::create(format!("target/{}.hdr", name)).unwrap();
         let enc = HdrEncoder::new(file);
         let _ = enc.encode(&img, cam.w, cam.h);
     }
@@ -4,8 +4,13 @@ use del_raycast_core::shape::ShapeEntity;
 struct MyScene {
     shape_entities: Vec<ShapeEntity>,
     materials: Vec<del_raycast_core::material::Material>,
+    textures: Vec<del_raycast_core::textures::Texture>,
+    normal_perturbations: Vec<del_raycast_core::shading_normal::NormalPerturbation>,
     area_lights: Vec<AreaLight>,
     i_shape_entity_light: usize,
+    /// integrate the direct light analytically on the diffuse and the rough conductor surfaces
+    /// where the light is unoccluded if `Some`. The table fits the GGX BRDF of the conductor
+    ltc_table: Option<del_raycast_core::polygonal_light::LtcTable>,
 }
 
 fn parse_pbrt_file(
@@ -13,8 +18,11 @@ fn parse_pbrt_file(
 ) -> anyhow::Result<(MyScene, del_raycast_core::parse_pbrt::Camera)> {
     let scene = pbrt4::Scene::from_file(file_path)?;
     let camera = del_raycast_core::parse_pbrt::camera(&scene);
-    let materials = del_raycast_core::parse_pbrt::parse_material(&scene);
-    let area_lights = del_raycast_core::parse_pbrt::parse_area_light(&scene);
+    let materials = del_raycast_core::parse_pbrt::parse_material(&scene, file_path);
+    let textures = del_raycast_core::parse_pbrt::parse_texture(&scene);
+    let normal_perturbations =
+        del_raycast_core::parse_pbrt::parse_normal_perturbation(&scene, file_path);
+    let area_lights = del_raycast_core::parse_pbrt::parse_area_light(&scene, file_path);
     let shape_entities = del_raycast_core::parse_pbrt::parse_shapes(&scene);
     //
     use itertools::Itertools;
@@ -29,12 +37,63 @@ fn parse_pbrt_file(
         shape_entities,
         area_lights,
         materials,
+        textures,
+        normal_perturbations,
         i_shape_entity_light: i_trimesh_light,
+        ltc_table: None,
     };
     Ok((scene, camera))
 }
 
 impl MyScene {
+    /// true if no triangle of the other shapes overlaps the tetrahedra spanned by `pos` and the light's triangles.
+    /// This test only for this example visits all the triangles in the scene at each shading point.
+    /// The shapes other than the triangle mesh are regarded as occluders
+    fn is_light_unoccluded(&self, pos: &[f32; 3]) -> bool {
+        use del_raycast_core::shape::ShapeType;
+        let corner_points = |se: &ShapeEntity, tri2vtx: &[usize], vtx2xyz: &[f32], i_tri: usize| {
+            [0, 1, 2].map(|i| {
+                let p = arrayref::array_ref![vtx2xyz, tri2vtx[i_tri * 3 + i] * 3, 3];
+                del_geo_core::mat4_col_major::transform_homogeneous(&se.transform_objlcl2world, p)
+                    .unwrap()
+            })
+        };
+        let se_light = &self.shape_entities[self.i_shape_entity_light];
+        let ShapeType::TriangleMesh {
+            tri2vtx: tri2vtx_light,
+            vtx2xyz: vtx2xyz_light,
+            ..
+        } = &se_light.shape
+        else {
+            return false;
+        };
+        self.shape_entities
+            .iter()
+            .enumerate()
+            .all(|(i_shape_entity, se)| {
+                if i_shape_entity == self.i_shape_entity_light {
+                    return true;
+                }
+                let ShapeType::TriangleMesh {
+                    tri2vtx, vtx2xyz, ..
+                } = &se.shape
+                else {
+                    return false;
+                };
+                (0..tri2vtx.len() / 3).all(|i_tri| {
+                    let tri = corner_points(se, tri2vtx, vtx2xyz, i_tri);
+                    (0..tri2vtx_light.len() / 3).all(|i_tri_light| {
+                        let [q0, q1, q2] =
+                            corner_points(se_light, tri2vtx_light, vtx2xyz_light, i_tri_light);
+                        !del_raycast_core::area_light::is_triangle_overlapping_tetrahedron(
+                            &tri,
+                            &[*pos, q0, q1, q2],
+                        )
+                    })
+                })
+            })
+    }
+
     /// # Return
     /// - `Some(radiance: [f32;3], pdf: f32, uvec_hit2light:[f32;3])`
     ///    - `pdf: f32` the pdf is computed on the unit hemisphere (pdf of light / geometric term)
@@ -45,8 +104,14 @@ impl MyScene {
         rng: &mut Rng,
     ) -> Option<([f32; 3], f32, [f32; 3])> {
         use del_geo_core::vec3;
-        let (pos_light, nrm_light, pdf_shape) = self.shape_entities[self.i_shape_entity_light]
-            .sample_uniform(&[rng.random(), rng.random()]);
+        let se_light = &self.shape_entities[self.i_shape_entity_light];
+        let al = &self.area_lights[se_light.area_light_index.unwrap()];
+        let (pos_light, nrm_light, uv_light, pdf_shape) =
+            del_raycast_core::area_light::sample_emitter(
+                se_light,
+                al,
+                &[rng.random(), rng.random()],
+            )?;
         let uvec_hit2light = vec3::normalize(&vec3::sub(&pos_light, &pos_observe));
         let cos_theta_light = -vec3::dot(&nrm_light, &uvec_hit2light);
         if cos_theta_light < 0. {
@@ -60,10 +125,7 @@ impl MyScene {
         ) {
             return None;
         }
-        let i_area_light = self.shape_entities[self.i_shape_entity_light]
-            .area_light_index
-            .unwrap();
-        let l_i = self.area_lights[i_area_light].spectrum_rgb.unwrap();
+        let l_i = al.emission(&uv_light);
         let r2 = del_geo_core::edge3::squared_length(&pos_light, &pos_observe);
         let geo_term = cos_theta_light / r2;
         Some((l_i, pdf_shape / geo_term, uvec_hit2light))
@@ -74,6 +136,12 @@ impl MyScene {
         pos_observe: &[f32; 3],
         rng: &mut Rng,
     ) -> Option<([f32; 3], f32, [f32; 3])> {
+        let se_light = &self.shape_entities[self.i_shape_entity_light];
+        let al = &self.area_lights[se_light.area_light_index.unwrap()];
+        if al.image.is_some() {
+            // the textured emission is importance sampled on the area
+            return self.sample_light_uniform(pos_observe, rng);
+        }
         // sampling light on the unit sphere around `pos_observe`
         let Some((uvec_obs2light, pos_light, pdf_usphere)) = self.shape_entities
             [self.i_shape_entity_light]
@@ -90,10 +158,8 @@ impl MyScene {
         ) {
             return None;
         }
-        let i_area_light = self.shape_entities[self.i_shape_entity_light]
-            .area_light_index
-            .unwrap();
-        let l_i = self.area_lights[i_area_light].spectrum_rgb.unwrap();
+        // the emission is uniform without the image
+        let l_i = al.emission(&[0f32; 2]);
         Some((l_i, pdf_usphere, uvec_obs2light))
     }
 }
@@ -105,7 +171,16 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
         &self,
         ray_org: &[f32; 3],
         ray_dir: &[f32; 3],
-    ) -> Option<([f32; 3], [f32; 3], [f32; 3], f32, usize)> {
+    ) -> Option<(
+        [f32; 3],
+        [f32; 3],
+        [f32; 3],
+        f32,
+        usize,
+        [f32; 2],
+        [f32; 3],
+        Option<[f32; 3]>,
+    )> {
         let Some((t, i_shape_entity, i_elem)) =
             del_raycast_core::shape::intersection_ray_against_shape_entities(
                 ray_org,
@@ -122,28 +197,53 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
             i_elem,
         );
         use del_geo_core::vec3;
+        let hit_uv =
+            del_raycast_core::shape::uv_at(&self.shape_entities[i_shape_entity], &hit_pos, i_elem);
         let mut hit_emission = [0f32; 3];
         if let Some(i_area_light) = self.shape_entities[i_shape_entity].area_light_index {
             let al = &self.area_lights[i_area_light];
-            if let Some(spectrum) = al.spectrum_rgb {
-                // hit light
-                if al.two_sided || del_geo_core::vec3::dot(&hit_nrm, ray_dir) < 0.0 {
-                    hit_emission = spectrum;
-                }
+            // hit light
+            if al.two_sided || del_geo_core::vec3::dot(&hit_nrm, ray_dir) < 0.0 {
+                hit_emission = al.emission(&hit_uv);
             }
         }
-        let hit_nrm = if vec3::dot(&hit_nrm, &ray_dir) > 0. {
-            [-hit_nrm[0], -hit_nrm[1], -hit_nrm[2]]
+        let i_material = self.shape_entities[i_shape_entity].material_index.unwrap();
+        let shading_nrm = del_raycast_core::shading_normal::shading_normal(
+            &self.normal_perturbations[i_material],
+            &self.textures,
+            &hit_uv,
+            &hit_nrm,
+            del_raycast_core::shape::dpdu_dpdv_at(
+                &self.shape_entities[i_shape_entity],
+                &hit_pos,
+                i_elem,
+            ),
+        );
+        let is_transmissive =
+            del_raycast_core::material::is_transmissive(&self.materials[i_material]);
+        // keep the outward normal for the transmissive material to tell the inside from the outside
+        let (hit_nrm, shading_nrm) = if !is_transmissive && vec3::dot(&hit_nrm, &ray_dir) > 0. {
+            (vec3::scale(&hit_nrm, -1.), vec3::scale(&shading_nrm, -1.))
         } else {
-            hit_nrm
+            (hit_nrm, shading_nrm)
         };
-        Some((hit_pos, hit_nrm, hit_emission, 100.0, i_shape_entity))
+        Some((
+            hit_pos,
+            hit_nrm,
+            hit_emission,
+            100.0,
+            i_shape_entity,
+            hit_uv,
+            shading_nrm,
+            del_raycast_core::shape::tangent_at(&self.shape_entities[i_shape_entity], i_elem),
+        ))
     }
     fn pdf_light(
         &self,
         hit_pos: &[f32; 3],
         hit_pos_light: &[f32; 3],
         hit_nrm_light: &[f32; 3],
+        hit_uv_light: &[f32; 2],
         _i_shape_entity: usize,
     ) -> f32 {
         use del_geo_core::vec3;
@@ -152,25 +252,21 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
         let distance = del_geo_core::edge3::length(&hit_pos, &hit_pos_light);
         let geo_term = -del_geo_core::vec3::dot(&uvec_obj2light, &hit_nrm_light) / distance.powi(2);
         let se = &self.shape_entities[self.i_shape_entity_light];
-        let area = match &se.shape {
-            del_raycast_core::shape::ShapeType::TriangleMesh { tri2cumsumarea, .. } => {
-                tri2cumsumarea.as_ref().unwrap().last().unwrap()
-            }
-            _ => {
-                todo!()
-            }
-        };
-        1.0 / area / geo_term
+        let al = &self.area_lights[se.area_light_index.unwrap()];
+        del_raycast_core::area_light::pdf_emitter(se, al, hit_uv_light) / geo_term
     }
 
     fn sample_brdf<RNG>(
         &self,
         obj_nrm: &[f32; 3],
+        tangent: Option<&[f32; 3]>,
         uvec_ray_in_outward: &[f32; 3],
         i_shape_entity: usize,
+        hit_pos: &[f32; 3],
+        hit_uv: &[f32; 2],
         rng: &mut RNG,
         min_roughness: f32,
-    ) -> Option<([f32; 3], [f32; 3], f32)>
+    ) -> Option<del_raycast_core::bxdf::BSDFSample>
     where
         RNG: rand::Rng,
     {
@@ -178,8 +274,18 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
         let i_material = se.material_index.unwrap();
         assert!(i_material < self.materials.len());
         del_raycast_core::material::sample_brdf(
-            &self.materials[i_material],
+            del_raycast_core::material::choose_material(
+                &self.materials,
+                i_material,
+                &self.textures,
+                hit_uv,
+                hit_pos,
+                uvec_ray_in_outward,
+            ),
+            &self.textures,
+            hit_uv,
             obj_nrm,
+            tangent,
             uvec_ray_in_outward,
             rng,
             0.0,
@@ -189,7 +295,10 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
     fn eval_brdf(
         &self,
         i_shape_entity: usize,
+        hit_pos: &[f32; 3],
+        hit_uv: &[f32; 2],
         obj_nrm: &[f32; 3],
+        tangent: Option<&[f32; 3]>,
         ray_in_outward_normalized: &[f32; 3],
         ray_out_normalized: &[f32; 3],
         min_roughness: f32,
@@ -201,14 +310,65 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
         );
         let i_material = self.shape_entities[i_shape_entity].material_index.unwrap();
         del_raycast_core::material::eval_brdf(
-            &self.materials[i_material],
+            del_raycast_core::material::choose_material(
+                &self.materials,
+                i_material,
+                &self.textures,
+                hit_uv,
+                hit_pos,
+                ray_in_outward_normalized,
+            ),
+            &self.textures,
+            hit_uv,
             obj_nrm,
+            tangent,
             ray_in_outward_normalized,
             ray_out_normalized,
             0.0,
         )
     }
 
+    fn pdf_brdf(
+        &self,
+        i_shape_entity: usize,
+        hit_pos: &[f32; 3],
+        hit_uv: &[f32; 2],
+        obj_nrm: &[f32; 3],
+        tangent: Option<&[f32; 3]>,
+        ray_in_outward_normalized: &[f32; 3],
+        ray_out_normalized: &[f32; 3],
+        _minimum_roughness: f32,
+    ) -> f32 {
+        let i_material = self.shape_entities[i_shape_entity].material_index.unwrap();
+        del_raycast_core::material::pdf_brdf(
+            del_raycast_core::material::choose_material(
+                &self.materials,
+                i_material,
+                &self.textures,
+                hit_uv,
+                hit_pos,
+                ray_in_outward_normalized,
+            ),
+            &self.textures,
+            hit_uv,
+            obj_nrm,
+            tangent,
+            ray_in_outward_normalized,
+            ray_out_normalized,
+            0.0,
+        )
+    }
+
+    fn bxdf_flags(
+        &self,
+        i_shape_entity: usize,
+        hit_uv: &[f32; 2],
+        _minimum_roughness: f32,
+    ) -> del_raycast_core::bxdf::BxDFFlags {
+        let i_material = self.shape_entities[i_shape_entity].material_index.unwrap();
+        del_raycast_core::material::flags(&self.materials, i_material, &self.textures, hit_uv, 0.0)
+    }
+
     /// # Return
     /// - `Some(radiance: [f32;3], pdf: f32, uvec_hit2light:[f32;3])`
     ///    - `pdf: f32` the pdf is computed on the unit hemisphere (pdf of light / geometric term)
@@ -222,6 +382,98 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
         // self.sample_light_uniform(pos_observe, rng)
         self.sample_light_visible(pos_observe, rng)
     }
+
+    fn direct_light_analytic(
+        &self,
+        hit_pos: &[f32; 3],
+        hit_nrm: &[f32; 3],
+        uvec_ray_in_outward: &[f32; 3],
+        i_shape_entity: usize,
+        hit_uv: &[f32; 2],
+    ) -> Option<[f32; 3]> {
+        use del_raycast_core::material::MaterialBxDF;
+        use del_raycast_core::polygonal_light::Receiver;
+        let table = self.ltc_table.as_ref()?;
+        let mat = del_raycast_core::material::choose_material(
+            &self.materials,
+            self.shape_entities[i_shape_entity].material_index.unwrap(),
+            &self.textures,
+            hit_uv,
+            hit_pos,
+            uvec_ray_in_outward,
+        );
+        let receiver =
+            match del_raycast_core::material::bxdf(mat, &self.textures, hit_uv, None, 0.0)? {
+                MaterialBxDF::Diffuse(b) => Receiver::Diffuse(b.reflectance),
+                // the LTC fits the single scattering of the isotropic GGX
+                MaterialBxDF::Conductor(b)
+                    if b.distrib.alpha_x == b.distrib.alpha_y
+                        && !b.distrib.effectively_smooth()
+                        && !b.multiple_scattering =>
+                {
+                    let f0 = del_raycast_core::material::fresnel_conductor_reflectance_rgb(
+                        &b.eta, &b.k, 1.0,
+                    );
+                    Receiver::Glossy {
+                        table,
+                        alpha: b.distrib.alpha_x,
+                        specular: std::array::from_fn(|i| f0[i] * b.reflectance[i]),
+                    }
+                }
+                _ => return None,
+            };
+        let pos_observe = del_raycast_core::monte_carlo_integrator::offset_ray_origin(
+            hit_pos,
+            hit_nrm,
+            uvec_ray_in_outward,
+        );
+        // the analytic integral has no shadow. The light is sampled where it may be occluded
+        if !self.is_light_unoccluded(&pos_observe) {
+            return None;
+        }
+        let se_light = &self.shape_entities[self.i_shape_entity_light];
+        del_raycast_core::area_light::direct_radiance_analytic(
+            se_light,
+            &self.area_lights[se_light.area_light_index.unwrap()],
+            &receiver,
+            &pos_observe,
+            hit_nrm,
+            uvec_ray_in_outward,
+        )
+    }
+
+    fn sample_subsurface<Rng: rand::Rng>(
+        &self,
+        hit_pos: &[f32; 3],
+        hit_nrm: &[f32; 3],
+        uvec_ray_in_outward: &[f32; 3],
+        i_shape_entity: usize,
+        hit_uv: &[f32; 2],
+        rng: &mut Rng,
+    ) -> Option<([f32; 3], [f32; 3], [f32; 3], [f32; 3])> {
+        let se = &self.shape_entities[i_shape_entity];
+        let i_material = se.material_index.unwrap();
+        let del_raycast_core::material::Material::Subsurface(ss) =
+            del_raycast_core::material::choose_material(
+                &self.materials,
+                i_material,
+                &self.textures,
+                hit_uv,
+                hit_pos,
+                uvec_ray_in_outward,
+            )
+        else {
+            return None;
+        };
+        Some(del_raycast_core::subsurface::sample_random_walk(
+            se,
+            ss,
+            hit_pos,
+            hit_nrm,
+            uvec_ray_in_outward,
+            rng,
+        ))
+    }
 }
 
 enum IntegrationType {
@@ -303,7 +555,7 @@ fn render_and_save_image_and_compute_error(
 
 fn main() -> anyhow::Result<()> {
     let pbrt_file_path = "asset/cornell-box/scene-v4.pbrt";
-    let (scene, camera) = parse_pbrt_file(pbrt_file_path)?;
+    let (mut scene, camera) = parse_pbrt_file(pbrt_file_path)?;
     del_raycast_core::shape::write_wavefront_obj_file_from_camera_view(
         "target/02_cornell_box.obj",
         &scene.shape_entities,
@@ -419,6 +671,26 @@ fn main() -> anyhow::Result<()> {
             &img_gt,
         )?;
     }
+    println!("---------------------NEE analytic---------------------");
+    scene.ltc_table = Some(del_raycast_core::polygonal_light::LtcTable::new(
+        del_raycast_core::polygonal_light::Microfacet::Ggx,
+        32,
+        32,
+        256,
+    ));
+    for i in 1..4 {
+        let num_sample = 8 * i;
+        render_and_save_image_and_compute_error(
+            IntegrationType::NextEventEstimation,
+            num_sample,
+            65,
+            "nee_analytic",
+            &scene,
+            &camera,
+            &img_gt,
+        )?;
+    }
+    scene.ltc_table = None;
     println!("---------------------light sampling---------------------");
     for i in 1..4 {
         let num_sample = 8 * i;
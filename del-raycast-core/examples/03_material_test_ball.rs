@@ -1,8 +1,4 @@
 use del_msh_cpu::search_bvh3::TriMeshWithBvh;
-use del_raycast_core::{
-    parse_pbrt,
-    textures::{CheckerBoardTexture, Texture},
-};
 
 struct Shape {
     vtx2xyz: Vec<f32>,
@@ -59,7 +55,7 @@ fn intersect_bvh(
 
 // get uv coordinates of position of a triangle tri_i
 fn get_tri_uv(tri_i: usize, pos: &[f32; 3], shape: &Shape) -> [f32; 2] {
-    let vertices = &shape.tri2vtx[tri_i..tri_i + 3];
+    let vertices = &shape.tri2vtx[tri_i * 3..tri_i * 3 + 3];
 
     let f: Vec<[f32; 3]> = vertices
         .iter()
@@ -93,12 +89,13 @@ fn get_tri_uv(tri_i: usize, pos: &[f32; 3], shape: &Shape) -> [f32; 2] {
     acc
 }
 
-fn parse() -> anyhow::Result<(
+fn parse(
+    path_file: &str,
+) -> anyhow::Result<(
     Vec<Shape>,
     del_raycast_core::parse_pbrt::Camera,
     pbrt4::Scene,
 )> {
-    let path_file = "asset/material-testball/scene-v4.pbrt";
     let scene = pbrt4::Scene::from_file(path_file)?;
     let mut shapes: Vec<Shape> = vec![];
     let camera = del_raycast_core::parse_pbrt::camera(&scene);
@@ -130,7 +127,8 @@ fn parse() -> anyhow::Result<(
 }
 
 fn main() -> anyhow::Result<()> {
-    let (shapes, camera, scene) = parse()?;
+    let path_file = "asset/material-testball/scene-v4.pbrt";
+    let (shapes, camera, scene) = parse(path_file)?;
     {
         let mut tri2vtx: Vec<usize> = vec![];
         let mut vtx2xyz: Vec<f32> = vec![];
@@ -195,7 +193,7 @@ fn main() -> anyhow::Result<()> {
 
     {
         // computing reflectance image
-        let materials = del_raycast_core::parse_pbrt::parse_material(&scene);
+        let materials = del_raycast_core::parse_pbrt::parse_material(&scene, path_file);
         let textures = del_raycast_core::parse_pbrt::parse_texture(&scene);
         let shoot_ray = |i_pix: usize, pix: &mut [f32]| {
             let pix = arrayref::array_mut_ref![pix, 0, 3];
@@ -226,25 +224,26 @@ fn main() -> anyhow::Result<()> {
                 materials.len()
             );
 
+            // the position in the local coordinate of the shape where the uv is interpolated
+            let pos = {
+                let ti = del_geo_core::mat4_col_major::try_inverse(&shape.transform).unwrap();
+                let ray_org =
+                    del_geo_core::mat4_col_major::transform_homogeneous(&ti, &ray_org).unwrap();
+                let ray_dir = del_geo_core::mat4_col_major::transform_direction(&ti, &ray_dir);
+                del_geo_core::vec3::axpy(t, &ray_dir, &ray_org)
+            };
+            let uv = get_tri_uv(tri_i, &pos, shape);
+            use del_raycast_core::textures::eval_spectrum;
             let reflectance = match &materials[i_material] {
                 del_raycast_core::material::Material::Diff(mat) => {
-                    if mat.reflectance_texture != usize::MAX {
-                        let tex = &textures[mat.reflectance_texture];
-                        match tex {
-                            Texture::Checkerboard(tex) => {
-                                let pos = del_geo_core::vec3::axpy(t, &ray_dir, &ray_org);
-                                let uv = get_tri_uv(tri_i, &pos, shape);
-                                del_raycast_core::textures::sample_checkerboard(
-                                    &uv, tex.uscale, tex.vscale, &tex.tex1, &tex.tex2,
-                                )
-                            }
-                        }
-                    } else {
-                        mat.reflectance
-                    }
+                    eval_spectrum(&textures, mat.reflectance_texture, &mat.reflectance, &uv)
+                }
+                del_raycast_core::material::Material::Cond(mat) => {
+                    eval_spectrum(&textures, mat.reflectance_texture, &mat.reflectance, &uv)
+                }
+                del_raycast_core::material::Material::CoaDiff(mat) => {
+                    eval_spectrum(&textures, mat.reflectance_texture, &mat.reflectance, &uv)
                 }
-                del_raycast_core::material::Material::Cond(mat) => mat.reflectance,
-                del_raycast_core::material::Material::CoaDiff(mat) => mat.reflectance,
                 _ => {
                     panic!("No reflectance of Material");
                 }
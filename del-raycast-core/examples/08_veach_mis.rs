@@ -9,6 +9,8 @@ struct MyScene {
     shape_entities: Vec<del_raycast_core::shape::ShapeEntity>,
     area_lights: Vec<del_raycast_core::area_light::AreaLight>,
     materials: Vec<del_raycast_core::material::Material>,
+    textures: Vec<del_raycast_core::textures::Texture>,
+    normal_perturbations: Vec<del_raycast_core::shading_normal::NormalPerturbation>,
     area_light_geometries: Vec<AreaLightGeometry>,
     is_light_sample_uniform: bool,
 }
@@ -18,8 +20,11 @@ fn parse_pbrt_file(
 ) -> anyhow::Result<(MyScene, del_raycast_core::parse_pbrt::Camera)> {
     let scene = pbrt4::Scene::from_file(file_path)?;
     let camera = del_raycast_core::parse_pbrt::camera(&scene);
-    let materials = del_raycast_core::parse_pbrt::parse_material(&scene);
-    let area_lights = del_raycast_core::parse_pbrt::parse_area_light(&scene);
+    let materials = del_raycast_core::parse_pbrt::parse_material(&scene, file_path);
+    let textures = del_raycast_core::parse_pbrt::parse_texture(&scene);
+    let normal_perturbations =
+        del_raycast_core::parse_pbrt::parse_normal_perturbation(&scene, file_path);
+    let area_lights = del_raycast_core::parse_pbrt::parse_area_light(&scene, file_path);
     let shape_entities = del_raycast_core::parse_pbrt::parse_shapes(&scene);
     let area_light_geometries = {
         let mut area_light_geometries = vec![AreaLightGeometry::default(); area_lights.len()];
@@ -39,6 +44,8 @@ fn parse_pbrt_file(
         shape_entities,
         area_lights,
         materials,
+        textures,
+        normal_perturbations,
         area_light_geometries,
         is_light_sample_uniform: false,
     };
@@ -52,7 +59,7 @@ impl MyScene {
             let area = self.area_light_geometries[ial].area;
             let cog = self.area_light_geometries[ial].cog;
             let dist_sq = del_geo_core::edge3::squared_length(&cog, hit_pos);
-            let emission = self.area_lights[ial].spectrum_rgb.unwrap();
+            let emission = self.area_lights[ial].average_emission();
             let emission = emission.iter().fold(f32::NAN, |a, b| a.max(*b));
             let mag = area * emission / dist_sq;
             al2mag[ial + 1] = al2mag[ial] + mag;
@@ -71,8 +78,14 @@ impl MyScene {
         rng: &mut Rng,
     ) -> Option<([f32; 3], f32, [f32; 3])> {
         use del_geo_core::vec3;
-        let (pos_light, nrm_light, pdf_shape) =
-            self.shape_entities[i_shape_entity_light].sample_uniform(&[rng.random(), rng.random()]);
+        let se_light = &self.shape_entities[i_shape_entity_light];
+        let al = &self.area_lights[se_light.area_light_index.unwrap()];
+        let (pos_light, nrm_light, uv_light, pdf_shape) =
+            del_raycast_core::area_light::sample_emitter(
+                se_light,
+                al,
+                &[rng.random(), rng.random()],
+            )?;
         let uvec_hit2light = vec3::normalize(&vec3::sub(&pos_light, &pos_observe));
         let cos_theta_light = -vec3::dot(&nrm_light, &uvec_hit2light);
         if cos_theta_light < 0. {
@@ -88,10 +101,7 @@ impl MyScene {
         }
         let r2 = del_geo_core::edge3::squared_length(&pos_light, &pos_observe);
         let geo_term = cos_theta_light / r2;
-        let i_area_light = self.shape_entities[i_shape_entity_light]
-            .area_light_index
-            .unwrap();
-        let l_i = self.area_lights[i_area_light].spectrum_rgb.unwrap();
+        let l_i = al.emission(&uv_light);
         Some((l_i, pdf_shape / geo_term, uvec_hit2light))
     }
 
@@ -100,14 +110,14 @@ impl MyScene {
         i_shape_entity_light: usize,
         pos_light: &[f32; 3],
         nrm_light: &[f32; 3],
+        uv_light: &[f32; 2],
         pos_observe: &[f32; 3],
     ) -> f32 {
         use del_geo_core::vec3;
         use del_geo_core::vec3::Vec3;
-        let ial = self.shape_entities[i_shape_entity_light]
-            .area_light_index
-            .unwrap();
-        let pdf1_obj = 1.0 / self.area_light_geometries[ial].area;
+        let se_light = &self.shape_entities[i_shape_entity_light];
+        let al = &self.area_lights[se_light.area_light_index.unwrap()];
+        let pdf1_obj = del_raycast_core::area_light::pdf_emitter(se_light, al, uv_light);
         let r2 = del_geo_core::edge3::squared_length(&pos_light, &pos_observe);
         let uvec_hit2light = pos_light.sub(&pos_observe).normalize();
         let cos_theta_light = -vec3::dot(&nrm_light, &uvec_hit2light);
@@ -127,6 +137,9 @@ impl MyScene {
         pos_observe: &[f32; 3],
         rng: &mut Rng,
     ) -> Option<([f32; 3], f32, [f32; 3])> {
+        let al = &self.area_lights[self.shape_entities[i_shape_entity_light]
+            .area_light_index
+            .unwrap()];
         // sampling light on the unit sphere around `pos_observe`
         let Some((uvec_obs2light, pos_light, pdf_usphere)) = self.shape_entities
             [i_shape_entity_light]
@@ -143,10 +156,8 @@ impl MyScene {
         ) {
             return None;
         }
-        let i_area_light = self.shape_entities[i_shape_entity_light]
-            .area_light_index
-            .unwrap();
-        let l_i = self.area_lights[i_area_light].spectrum_rgb.unwrap();
+        // the emission is uniform without the image
+        let l_i = al.emission(&[0f32; 2]);
         Some((l_i, pdf_usphere, uvec_obs2light))
     }
     fn pdf_light_visible(&self, i_shape_entity_light: usize, pos_observe: &[f32; 3]) -> f32 {
@@ -158,7 +169,10 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
     fn eval_brdf(
         &self,
         i_shape_entity: usize,
+        hit_pos: &[f32; 3],
+        hit_uv: &[f32; 2],
         obj_nrm: &[f32; 3],
+        tangent: Option<&[f32; 3]>,
         ray_in_outward_normalized: &[f32; 3],
         ray_out_normalized: &[f32; 3],
         minimum_roughness: f32,
@@ -167,19 +181,77 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
         assert!((ray_in_outward_normalized.norm() - 1.0).abs() < 1.0e-5);
         let i_material = self.shape_entities[i_shape_entity].material_index.unwrap();
         del_raycast_core::material::eval_brdf(
-            &self.materials[i_material],
+            del_raycast_core::material::choose_material(
+                &self.materials,
+                i_material,
+                &self.textures,
+                hit_uv,
+                hit_pos,
+                ray_in_outward_normalized,
+            ),
+            &self.textures,
+            hit_uv,
+            obj_nrm,
+            tangent,
+            ray_in_outward_normalized,
+            ray_out_normalized,
+            minimum_roughness,
+        )
+    }
+
+    fn pdf_brdf(
+        &self,
+        i_shape_entity: usize,
+        hit_pos: &[f32; 3],
+        hit_uv: &[f32; 2],
+        obj_nrm: &[f32; 3],
+        tangent: Option<&[f32; 3]>,
+        ray_in_outward_normalized: &[f32; 3],
+        ray_out_normalized: &[f32; 3],
+        minimum_roughness: f32,
+    ) -> f32 {
+        let i_material = self.shape_entities[i_shape_entity].material_index.unwrap();
+        del_raycast_core::material::pdf_brdf(
+            del_raycast_core::material::choose_material(
+                &self.materials,
+                i_material,
+                &self.textures,
+                hit_uv,
+                hit_pos,
+                ray_in_outward_normalized,
+            ),
+            &self.textures,
+            hit_uv,
             obj_nrm,
+            tangent,
             ray_in_outward_normalized,
             ray_out_normalized,
             minimum_roughness,
         )
     }
 
+    fn bxdf_flags(
+        &self,
+        i_shape_entity: usize,
+        hit_uv: &[f32; 2],
+        minimum_roughness: f32,
+    ) -> del_raycast_core::bxdf::BxDFFlags {
+        let i_material = self.shape_entities[i_shape_entity].material_index.unwrap();
+        del_raycast_core::material::flags(
+            &self.materials,
+            i_material,
+            &self.textures,
+            hit_uv,
+            minimum_roughness,
+        )
+    }
+
     fn pdf_light(
         &self,
         pos_observe: &[f32; 3],
         pos_light: &[f32; 3],
         nrm_light: &[f32; 3],
+        uv_light: &[f32; 2],
         i_shape_element_light: usize,
     ) -> f32 {
         let ial = self.shape_entities[i_shape_element_light]
@@ -187,8 +259,15 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
             .unwrap();
         let al2mag = self.build_area_light_importance_heuristic(pos_observe);
         let pdf0 = (al2mag[ial + 1] - al2mag[ial]) / al2mag.last().unwrap();
-        let pdf1_usphere = if self.is_light_sample_uniform {
-            self.pdf_light_uniform(i_shape_element_light, pos_light, nrm_light, pos_observe)
+        let pdf1_usphere = if self.is_light_sample_uniform || self.area_lights[ial].image.is_some()
+        {
+            self.pdf_light_uniform(
+                i_shape_element_light,
+                pos_light,
+                nrm_light,
+                uv_light,
+                pos_observe,
+            )
         } else {
             self.pdf_light_visible(i_shape_element_light, pos_observe)
         };
@@ -197,11 +276,14 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
     fn sample_brdf<RNG>(
         &self,
         nrm_obj: &[f32; 3],
+        tangent: Option<&[f32; 3]>,
         ray_in_uvec_outward: &[f32; 3],
         i_shape_entity: usize,
+        hit_pos: &[f32; 3],
+        hit_uv: &[f32; 2],
         rng: &mut RNG,
         min_roughness: f32,
-    ) -> Option<([f32; 3], [f32; 3], f32)>
+    ) -> Option<del_raycast_core::bxdf::BSDFSample>
     where
         RNG: rand::Rng,
     {
@@ -211,8 +293,18 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
         let i_material = se.material_index.unwrap();
         assert!(i_material < self.materials.len());
         del_raycast_core::material::sample_brdf(
-            &self.materials[i_material],
+            del_raycast_core::material::choose_material(
+                &self.materials,
+                i_material,
+                &self.textures,
+                hit_uv,
+                hit_pos,
+                ray_in_uvec_outward,
+            ),
+            &self.textures,
+            hit_uv,
             nrm_obj,
+            tangent,
             ray_in_uvec_outward,
             rng,
             min_roughness,
@@ -222,7 +314,16 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
         &self,
         ray_org: &[f32; 3],
         ray_dir: &[f32; 3],
-    ) -> Option<([f32; 3], [f32; 3], [f32; 3], f32, usize)> {
+    ) -> Option<(
+        [f32; 3],
+        [f32; 3],
+        [f32; 3],
+        f32,
+        usize,
+        [f32; 2],
+        [f32; 3],
+        Option<[f32; 3]>,
+    )> {
         let Some((t, i_shape_entity, i_elem)) =
             del_raycast_core::shape::intersection_ray_against_shape_entities(
                 ray_org,
@@ -235,23 +336,55 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
         let hit_pos_world = del_geo_core::vec3::axpy(t, &ray_dir, &ray_org);
         let se = &self.shape_entities[i_shape_entity];
         let hit_nrm_world = del_raycast_core::shape::normal_at(se, &hit_pos_world, i_elem);
+        let hit_uv = del_raycast_core::shape::uv_at(se, &hit_pos_world, i_elem);
         let hit_emission = if let Some(ial) = se.area_light_index {
-            self.area_lights[ial]
-                .spectrum_rgb
-                .unwrap_or_else(|| [0f32; 3])
+            self.area_lights[ial].emission(&hit_uv)
         } else {
             [0f32; 3]
         };
+        let shading_nrm = match se.material_index {
+            Some(i_material) => del_raycast_core::shading_normal::shading_normal(
+                &self.normal_perturbations[i_material],
+                &self.textures,
+                &hit_uv,
+                &hit_nrm_world,
+                del_raycast_core::shape::dpdu_dpdv_at(se, &hit_pos_world, i_elem),
+            ),
+            None => hit_nrm_world,
+        };
         let hit_roughness = {
-            let i_material = self.shape_entities[i_shape_entity].material_index.unwrap();
-            let material = &self.materials[i_material];
-            match material {
-                del_raycast_core::material::Material::None => 100f32,
-                del_raycast_core::material::Material::Diff(_diff) => 100f32,
-                del_raycast_core::material::Material::Cond(cond) => {
-                    cond.uroughness.max(cond.vroughness)
-                }
-                _ => panic!("Not implement material for roughness"),
+            use del_raycast_core::material::Material;
+            let i_material = se.material_index.unwrap();
+            let flags = del_raycast_core::material::flags(
+                &self.materials,
+                i_material,
+                &self.textures,
+                &hit_uv,
+                0f32,
+            );
+            // the mix material is resolved in the same way as the shading at this hit
+            let ray_in_uvec_outward =
+                del_geo_core::vec3::normalize(&del_geo_core::vec3::scale(ray_dir, -1f32));
+            match del_raycast_core::material::choose_material(
+                &self.materials,
+                i_material,
+                &self.textures,
+                &hit_uv,
+                &hit_pos_world,
+                &ray_in_uvec_outward,
+            ) {
+                _ if !flags.is_non_specular() => 0f32,
+                Material::Principled(p) => p.roughness,
+                Material::Cond(cond) => cond.uroughness.max(cond.vroughness),
+                Material::Diel(diel) => diel.uroughness.max(diel.vroughness),
+                Material::CoaDiff(coadiff) => coadiff.uroughness.max(coadiff.vroughness),
+                Material::CoaCond(coacond) => coacond
+                    .interface_uroughness
+                    .max(coacond.interface_vroughness),
+                Material::Hair(hair) => hair.beta_m,
+                _ if flags.is_diffuse() => 100f32,
+                // the glossy lobe without the roughness parameter (e.g., the measured BRDF)
+                _ => 1f32,
             }
         };
         Some((
@@ -260,6 +393,9 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
             hit_emission,
             hit_roughness,
             i_shape_entity,
+            hit_uv,
+            shading_nrm,
+            del_raycast_core::shape::tangent_at(se, i_elem),
         ))
     }
 
@@ -279,7 +415,8 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
         if i_shape_entity_observe == ise {
             return None;
         }
-        let res = if self.is_light_sample_uniform {
+        // the textured emission is importance sampled on the area
+        let res = if self.is_light_sample_uniform || self.area_lights[ial].image.is_some() {
             self.sample_light_uniform(ise, pos_observe, rng)
         } else {
             self.sample_light_visible(ise, pos_observe, rng)
@@ -289,6 +426,39 @@ impl del_raycast_core::monte_carlo_integrator::Scene for MyScene {
         };
         Some((radiance, pdf0 * pdf1, uvec_obsrv2light))
     }
+
+    fn sample_subsurface<Rng: rand::Rng>(
+        &self,
+        hit_pos: &[f32; 3],
+        hit_nrm: &[f32; 3],
+        uvec_ray_in_outward: &[f32; 3],
+        i_shape_entity: usize,
+        hit_uv: &[f32; 2],
+        rng: &mut Rng,
+    ) -> Option<([f32; 3], [f32; 3], [f32; 3], [f32; 3])> {
+        let se = &self.shape_entities[i_shape_entity];
+        let i_material = se.material_index.unwrap();
+        let del_raycast_core::material::Material::Subsurface(ss) =
+            del_raycast_core::material::choose_material(
+                &self.materials,
+                i_material,
+                &self.textures,
+                hit_uv,
+                hit_pos,
+                uvec_ray_in_outward,
+            )
+        else {
+            return None;
+        };
+        Some(del_raycast_core::subsurface::sample_random_walk(
+            se,
+            ss,
+            hit_pos,
+            hit_nrm,
+            uvec_ray_in_outward,
+            rng,
+        ))
+    }
 }
 
 enum IntegrationType {
@@ -437,6 +607,8 @@ fn main() -> anyhow::Result<()> {
     let scene = MyScene {
         shape_entities: scene.shape_entities,
         materials: scene.materials,
+        textures: scene.textures,
+        normal_perturbations: scene.normal_perturbations,
         area_lights: scene.area_lights,
         area_light_geometries: scene.area_light_geometries,
         is_light_sample_uniform: false,
@@ -514,6 +686,8 @@ fn main() -> anyhow::Result<()> {
     let scene = MyScene {
         shape_entities: scene.shape_entities,
         materials: scene.materials,
+        textures: scene.textures,
+        normal_perturbations: scene.normal_perturbations,
         area_lights: scene.area_lights,
         area_light_geometries: scene.area_light_geometries,
         is_light_sample_uniform: true,
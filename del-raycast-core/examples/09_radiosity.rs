@@ -0,0 +1,87 @@
+fn main() -> anyhow::Result<()> {
+    let pbrt_file_path = "asset/cornell-box/scene-v4.pbrt";
+    let scene = pbrt4::Scene::from_file(pbrt_file_path)?;
+    let camera = del_raycast_core::parse_pbrt::camera(&scene);
+    let materials = del_raycast_core::parse_pbrt::parse_material(&scene, pbrt_file_path);
+    let textures = del_raycast_core::parse_pbrt::parse_texture(&scene);
+    let area_lights = del_raycast_core::parse_pbrt::parse_area_light(&scene, pbrt_file_path);
+    let mut shape_entities = del_raycast_core::parse_pbrt::parse_shapes(&scene);
+    for se in shape_entities.iter_mut() {
+        del_raycast_core::radiosity::refine_shape_entity(se, 3);
+    }
+    let patches = del_raycast_core::radiosity::Patches::from_shape_entities(&shape_entities);
+    let (patch2emission, patch2reflectance) = del_raycast_core::radiosity::emission_and_reflectance(
+        &patches,
+        &shape_entities,
+        &materials,
+        &textures,
+        &area_lights,
+    );
+    let patch2ff = del_raycast_core::radiosity::form_factors(&patches, &shape_entities, 256);
+    let patch2radiance = del_raycast_core::radiosity::solve_gauss_seidel(
+        &patch2ff,
+        &patch2emission,
+        &patch2reflectance,
+        100,
+        1.0e-4,
+    );
+    let shape2vtx2radiance: Vec<Vec<f32>> = (0..shape_entities.len())
+        .map(|i_shape_entity| {
+            del_raycast_core::radiosity::vtx2radiance(
+                &patches,
+                &shape_entities,
+                i_shape_entity,
+                &patch2radiance,
+            )
+        })
+        .collect();
+    for (is_smooth, path) in [
+        (false, "target/09_radiosity_patch.hdr"),
+        (true, "target/09_radiosity_vertex.hdr"),
+    ] {
+        let shoot_ray = |i_pix: usize, pix: &mut [f32]| {
+            let pix = arrayref::array_mut_ref![pix, 0, 3];
+            let (ray_org, ray_dir) = camera.ray(i_pix, [0f32; 2]);
+            let Some((t, i_shape_entity, i_tri)) =
+                del_raycast_core::shape::intersection_ray_against_shape_entities(
+                    &ray_org,
+                    &ray_dir,
+                    &shape_entities,
+                )
+            else {
+                return;
+            };
+            let Some(i_patch) = patches.patch_index(i_shape_entity, i_tri) else {
+                return;
+            };
+            if !is_smooth {
+                *pix = patch2radiance[i_patch];
+                return;
+            }
+            let del_raycast_core::shape::ShapeType::TriangleMesh { tri2vtx, .. } =
+                &shape_entities[i_shape_entity].shape
+            else {
+                return;
+            };
+            let [p0, p1, p2] = &patches.patch2xyz[i_patch];
+            let hit_pos = del_geo_core::vec3::axpy(t, &ray_dir, &ray_org);
+            let bc = del_geo_core::tri3::to_barycentric_coords(p0, p1, p2, &hit_pos);
+            let vtx2radiance = &shape2vtx2radiance[i_shape_entity];
+            let mut rad = [0f32; 3];
+            for (i_node, &w) in bc.iter().enumerate() {
+                let i_vtx = tri2vtx[i_tri * 3 + i_node];
+                let rad_vtx = arrayref::array_ref![vtx2radiance, i_vtx * 3, 3];
+                rad = del_geo_core::vec3::axpy(w, rad_vtx, &rad);
+            }
+            *pix = rad;
+        };
+        let mut img_out = vec![0f32; camera.img_shape.0 * camera.img_shape.1 * 3];
+        use rayon::prelude::*;
+        img_out
+            .par_chunks_mut(3)
+            .enumerate()
+            .for_each(|(i_pix, pix)| shoot_ray(i_pix, pix));
+        del_canvas::write_hdr_file(path, camera.img_shape, &img_out)?;
+    }
+    Ok(())
+}
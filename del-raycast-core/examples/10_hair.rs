@@ -0,0 +1,121 @@
+fn main() -> anyhow::Result<()> {
+    use del_geo_core::vec3::Vec3;
+    use rand::Rng;
+    use rand::SeedableRng;
+    // tuft of wavy fibers growing from the disk
+    let radius = 0.004f32;
+    let (polyline2idx, vtx2xyz) = {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+        let num_fiber = 100;
+        let num_vtx = 13;
+        let mut polyline2idx = vec![0usize];
+        let mut vtx2xyz = vec![];
+        for _i_fiber in 0..num_fiber {
+            let r = 0.2 * rng.random::<f32>().sqrt();
+            let theta = 2. * std::f32::consts::PI * rng.random::<f32>();
+            let phase = 2. * std::f32::consts::PI * rng.random::<f32>();
+            let root = [r * theta.cos(), 0., r * theta.sin()];
+            for i_vtx in 0..num_vtx {
+                let s = i_vtx as f32 / (num_vtx - 1) as f32;
+                let wave = 0.05 * s * (6. * s + phase).sin();
+                vtx2xyz.push(root[0] * (1. + s) + wave);
+                vtx2xyz.push(0.8 * s);
+                vtx2xyz.push(root[2] * (1. + s) + wave);
+            }
+            polyline2idx.push(polyline2idx.last().unwrap() + num_vtx);
+        }
+        (polyline2idx, vtx2xyz)
+    };
+    let identity = [
+        1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.,
+    ];
+    let shape_entities = vec![del_raycast_core::shape::ShapeEntity {
+        transform_objlcl2world: identity,
+        transform_world2objlcl: identity,
+        shape: del_raycast_core::shape::ShapeType::Curves {
+            edge2vtx: del_raycast_core::shape::edge2vtx_from_polylines(&polyline2idx),
+            vtx2xyz,
+            radius,
+            edge2cumsumlength: None,
+        },
+        material_index: Some(0),
+        area_light_index: None,
+    }];
+    let beta_n = 0.3;
+    let hair = del_raycast_core::material::HairMaterial {
+        sigma_a: del_raycast_core::hair_bsdf::sigma_a_from_reflectance(&[0.6, 0.4, 0.2], beta_n),
+        eta: 1.55,
+        beta_m: 0.3,
+        beta_n,
+        alpha: 2.,
+    };
+    // --------------------
+    let img_shape = (128, 128);
+    let camera_fov = 30.0;
+    let transform_camlcl2world = [
+        1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0.4, -2.0, 1.,
+    ];
+    let sky = [1f32; 3];
+    let num_sample = 8;
+    let max_depth = 16;
+    let shoot_ray = |i_pix: usize, pix: &mut [f32]| {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(i_pix as u64);
+        let mut l_i = [0f32; 3];
+        for _i_sample in 0..num_sample {
+            let (mut ray_org, mut ray_dir) = del_raycast_core::cam_pbrt::cast_ray_plus_z(
+                (i_pix % img_shape.0, i_pix / img_shape.0),
+                (rng.random::<f32>(), rng.random::<f32>()),
+                img_shape,
+                camera_fov,
+                transform_camlcl2world,
+            );
+            let mut throughput = [1f32; 3];
+            for _i_depth in 0..max_depth {
+                let Some((t, i_shape_entity, i_elem)) =
+                    del_raycast_core::shape::intersection_ray_against_shape_entities(
+                        &ray_org,
+                        &ray_dir,
+                        &shape_entities,
+                    )
+                else {
+                    l_i = l_i.add(&sky.element_wise_mult(&throughput));
+                    break;
+                };
+                let se = &shape_entities[i_shape_entity];
+                let hit_pos = del_geo_core::vec3::axpy(t, &ray_dir, &ray_org);
+                let hit_nrm = del_raycast_core::shape::normal_at(se, &hit_pos, i_elem);
+                let tangent = del_raycast_core::shape::tangent_at(se, i_elem).unwrap();
+                let Some(bs) = del_raycast_core::hair_bsdf::sample_hair(
+                    &hair,
+                    &hit_nrm,
+                    &tangent,
+                    &ray_dir.scale(-1f32).normalize(),
+                    &mut rng,
+                ) else {
+                    break;
+                };
+                let cos_hit = bs.wi.dot(&hit_nrm);
+                throughput = throughput.element_wise_mult(&bs.f.scale(cos_hit.abs() / bs.pdf));
+                // the transmitted ray leaves from the other side of the fiber
+                ray_org = if cos_hit < 0f32 {
+                    let wi_perp = bs.wi.sub(&tangent.scale(bs.wi.dot(&tangent)));
+                    let t = -2f32 * radius * cos_hit / wi_perp.dot(&wi_perp);
+                    del_geo_core::vec3::axpy(t + 1.0e-4, &bs.wi, &hit_pos)
+                } else {
+                    del_geo_core::vec3::axpy(1.0e-4, &hit_nrm, &hit_pos)
+                };
+                ray_dir = bs.wi;
+            }
+        }
+        pix.copy_from_slice(&l_i.scale(1f32 / num_sample as f32));
+    };
+    use rayon::iter::IndexedParallelIterator;
+    use rayon::iter::ParallelIterator;
+    use rayon::prelude::ParallelSliceMut;
+    let mut img = vec![0f32; img_shape.0 * img_shape.1 * 3];
+    img.par_chunks_mut(3)
+        .enumerate()
+        .for_each(|(i_pix, pix)| shoot_ray(i_pix, pix));
+    del_canvas::write_hdr_file("target/10_hair.hdr", img_shape, &img)?;
+    Ok(())
+}
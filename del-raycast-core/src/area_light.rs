@@ -1,6 +1,454 @@
 pub struct AreaLight {
     pub spectrum_rgb: Option<[f32; 3]>,
     pub two_sided: bool,
+    /// multiplied to the emission
+    pub scale: f32,
+    /// emission looked up with the texture coordinate. `spectrum_rgb` is ignored if this is given
+    pub image: Option<EmissionImage>,
+    /// triangles of the emitter mesh looked up with the texture coordinate.
+    /// The `image` is importance sampled only if this is given. See [`UvTriangleGrid::new`]
+    pub uv_grid: Option<UvTriangleGrid>,
+}
+
+impl AreaLight {
+    /// emitted radiance at the texture coordinate `uv` of the emitter
+    pub fn emission(&self, uv: &[f32; 2]) -> [f32; 3] {
+        let l = match (&self.image, self.spectrum_rgb) {
+            (Some(image), _) => image.lookup(uv),
+            (None, Some(rgb)) => rgb,
+            (None, None) => return [0f32; 3],
+        };
+        l.map(|v| v * self.scale)
+    }
+
+    /// emitted radiance averaged over the texture
+    pub fn average_emission(&self) -> [f32; 3] {
+        let l = match (&self.image, self.spectrum_rgb) {
+            (Some(image), _) => image.average(),
+            (None, Some(rgb)) => rgb,
+            (None, None) => return [0f32; 3],
+        };
+        l.map(|v| v * self.scale)
+    }
+}
+
+/// image of the emission in the linear sRGB.
+/// The texels are looked up without the interpolation so that
+/// the emission is exactly proportional to the sampling density of `distrib`
+pub struct EmissionImage {
+    pub img_shape: (usize, usize),
+    /// `img_shape.0 * img_shape.1 * 3` values. The first row is at the top (`v = 1`)
+    pub data: Vec<f32>,
+    /// distribution of the luminance on `(x, y) = (u, 1 - v)`
+    pub distrib: crate::sampling::PiecewiseConstant2D,
+}
+
+fn luminance(rgb: &[f32]) -> f32 {
+    0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2]
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl EmissionImage {
+    pub fn new(img_shape: (usize, usize), data: Vec<f32>) -> Self {
+        assert_eq!(data.len(), img_shape.0 * img_shape.1 * 3);
+        let func: Vec<f32> = data.chunks(3).map(luminance).collect();
+        let distrib = crate::sampling::PiecewiseConstant2D::new(&func, img_shape.0, img_shape.1);
+        EmissionImage {
+            img_shape,
+            data,
+            distrib,
+        }
+    }
+
+    /// the PFM file is read as it is. The 8-bit images are converted from the sRGB to the linear
+    pub fn read_from<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|ext| ext == "pfm") {
+            let pfm = crate::io_pfm::PFM::read_from(path)?;
+            let mut data = Vec::<f32>::with_capacity(pfm.w * pfm.h * 3);
+            // the rows of the PFM are stored from the bottom to the top
+            for iy in (0..pfm.h).rev() {
+                for ix in 0..pfm.w {
+                    let i_pix = iy * pfm.w + ix;
+                    let c = &pfm.data[i_pix * pfm.channels..(i_pix + 1) * pfm.channels];
+                    if pfm.channels == 1 {
+                        data.extend_from_slice(&[c[0]; 3]);
+                    } else {
+                        data.extend_from_slice(&c[0..3]);
+                    }
+                }
+            }
+            return Ok(Self::new((pfm.w, pfm.h), data));
+        }
+        let img = image::open(path)?;
+        let is_srgb = matches!(
+            img,
+            image::DynamicImage::ImageLuma8(_)
+                | image::DynamicImage::ImageLumaA8(_)
+                | image::DynamicImage::ImageRgb8(_)
+                | image::DynamicImage::ImageRgba8(_)
+        );
+        let img = img.to_rgb32f();
+        let img_shape = (img.width() as usize, img.height() as usize);
+        let data = img
+            .into_raw()
+            .into_iter()
+            .map(|v| if is_srgb { srgb_to_linear(v) } else { v })
+            .collect();
+        Ok(Self::new(img_shape, data))
+    }
+
+    /// texel at the texture coordinate. The texture repeats outside `[0, 1]`
+    pub fn lookup(&self, uv: &[f32; 2]) -> [f32; 3] {
+        let (w, h) = self.img_shape;
+        let x = uv[0].rem_euclid(1f32);
+        let y = (1f32 - uv[1]).rem_euclid(1f32);
+        let ix = ((x * w as f32) as usize).min(w - 1);
+        let iy = ((y * h as f32) as usize).min(h - 1);
+        let i_pix = iy * w + ix;
+        [
+            self.data[i_pix * 3],
+            self.data[i_pix * 3 + 1],
+            self.data[i_pix * 3 + 2],
+        ]
+    }
+
+    pub fn average(&self) -> [f32; 3] {
+        let num_pix = (self.img_shape.0 * self.img_shape.1) as f32;
+        let mut sum = [0f32; 3];
+        for c in self.data.chunks(3) {
+            sum = std::array::from_fn(|i| sum[i] + c[i] / num_pix);
+        }
+        sum
+    }
+}
+
+/// signed area of the triangle in the texture space
+fn area_tri2(p0: &[f32; 2], p1: &[f32; 2], p2: &[f32; 2]) -> f32 {
+    0.5 * ((p1[0] - p0[0]) * (p2[1] - p0[1]) - (p2[0] - p0[0]) * (p1[1] - p0[1]))
+}
+
+fn uv_corner_points(tri2vtx: &[usize], vtx2uv: &[f32], i_tri: usize) -> [[f32; 2]; 3] {
+    std::array::from_fn(|i_node| {
+        let i_vtx = tri2vtx[i_tri * 3 + i_node];
+        [vtx2uv[i_vtx * 2], vtx2uv[i_vtx * 2 + 1]]
+    })
+}
+
+/// triangles binned on the uniform grid over `[0, 1]^2` in the texture space
+pub struct UvTriangleGrid {
+    num_div: usize,
+    /// the triangles in the `i_cell`-th cell are `idx2tri[cell2idx[i_cell]..cell2idx[i_cell + 1]]`
+    cell2idx: Vec<usize>,
+    idx2tri: Vec<usize>,
+}
+
+impl UvTriangleGrid {
+    /// # Return
+    /// `None` unless the texture coordinates are in `[0, 1]` and do not overlap.
+    /// The overlap is detected approximately by the total area in the texture space
+    pub fn new(tri2vtx: &[usize], vtx2uv: &[f32]) -> Option<Self> {
+        if vtx2uv.is_empty() || vtx2uv.iter().any(|&v| !(0f32..=1f32).contains(&v)) {
+            return None;
+        }
+        let num_tri = tri2vtx.len() / 3;
+        let area: f32 = (0..num_tri)
+            .map(|i_tri| {
+                let [q0, q1, q2] = uv_corner_points(tri2vtx, vtx2uv, i_tri);
+                area_tri2(&q0, &q1, &q2).abs()
+            })
+            .sum();
+        if area > 1f32 + 1.0e-4 {
+            return None;
+        }
+        // a few triangles per cell on average
+        let num_div = ((num_tri as f32).sqrt().ceil() as usize).clamp(1, 256);
+        let cell = |v: f32| ((v * num_div as f32) as usize).min(num_div - 1);
+        let tri2range = |i_tri: usize| {
+            let q = uv_corner_points(tri2vtx, vtx2uv, i_tri);
+            let (u0, u1) = (
+                q[0][0].min(q[1][0]).min(q[2][0]),
+                q[0][0].max(q[1][0]).max(q[2][0]),
+            );
+            let (v0, v1) = (
+                q[0][1].min(q[1][1]).min(q[2][1]),
+                q[0][1].max(q[1][1]).max(q[2][1]),
+            );
+            (cell(u0)..=cell(u1), cell(v0)..=cell(v1))
+        };
+        let mut cell2idx = vec![0usize; num_div * num_div + 1];
+        for i_tri in 0..num_tri {
+            let (range_u, range_v) = tri2range(i_tri);
+            for iv in range_v {
+                for iu in range_u.clone() {
+                    cell2idx[iv * num_div + iu + 1] += 1;
+                }
+            }
+        }
+        for i_cell in 0..num_div * num_div {
+            cell2idx[i_cell + 1] += cell2idx[i_cell];
+        }
+        let mut idx2tri = vec![0usize; cell2idx[num_div * num_div]];
+        let mut cell2count = cell2idx.clone();
+        for i_tri in 0..num_tri {
+            let (range_u, range_v) = tri2range(i_tri);
+            for iv in range_v {
+                for iu in range_u.clone() {
+                    let i_cell = iv * num_div + iu;
+                    idx2tri[cell2count[i_cell]] = i_tri;
+                    cell2count[i_cell] += 1;
+                }
+            }
+        }
+        Some(UvTriangleGrid {
+            num_div,
+            cell2idx,
+            idx2tri,
+        })
+    }
+
+    /// triangle covering the texture coordinate `uv`
+    /// # Return
+    /// `Some((i_tri, barycentric coordinates, area of the triangle in the texture space))`
+    pub fn triangle_at_uv(
+        &self,
+        tri2vtx: &[usize],
+        vtx2uv: &[f32],
+        uv: &[f32; 2],
+    ) -> Option<(usize, [f32; 3], f32)> {
+        let cell = |v: f32| ((v * self.num_div as f32) as usize).min(self.num_div - 1);
+        if !(0f32..=1f32).contains(&uv[0]) || !(0f32..=1f32).contains(&uv[1]) {
+            return None;
+        }
+        let i_cell = cell(uv[1]) * self.num_div + cell(uv[0]);
+        for &i_tri in &self.idx2tri[self.cell2idx[i_cell]..self.cell2idx[i_cell + 1]] {
+            let [q0, q1, q2] = uv_corner_points(tri2vtx, vtx2uv, i_tri);
+            let area = area_tri2(&q0, &q1, &q2);
+            if area.abs() < f32::EPSILON {
+                continue;
+            }
+            let bc = [
+                area_tri2(uv, &q1, &q2) / area,
+                area_tri2(&q0, uv, &q2) / area,
+                area_tri2(&q0, &q1, uv) / area,
+            ];
+            if bc.iter().all(|&v| v >= -1.0e-6) {
+                return Some((i_tri, bc, area.abs()));
+            }
+        }
+        None
+    }
+}
+
+/// corners of the `i_tri`-th triangle of the emitter in the world coordinate.
+/// The area on the world coordinate is computed from them because the transformation may scale the mesh
+fn world_corner_points(
+    se: &crate::shape::ShapeEntity,
+    tri2vtx: &[usize],
+    vtx2xyz: &[f32],
+    i_tri: usize,
+) -> [[f32; 3]; 3] {
+    let (p0, p1, p2) = del_msh_cpu::trimesh3::to_corner_points(tri2vtx, vtx2xyz, i_tri);
+    [p0, p1, p2].map(|p| {
+        del_geo_core::mat4_col_major::transform_homogeneous(&se.transform_objlcl2world, &p).unwrap()
+    })
+}
+
+/// normal transformed by the inverse-transpose of the transformation
+/// * `transform_world2objlcl` - inverse of the transformation
+fn transform_normal(transform_world2objlcl: &[f32; 16], n: &[f32; 3]) -> [f32; 3] {
+    let m = transform_world2objlcl;
+    std::array::from_fn(|i| m[i * 4] * n[0] + m[i * 4 + 1] * n[1] + m[i * 4 + 2] * n[2])
+}
+
+/// sample a point on the emitter. The triangle mesh with the image emission is importance sampled
+/// by the luminance of the image if [`AreaLight::uv_grid`] is given.
+/// Otherwise, the point is sampled uniformly on the area
+/// # Return
+/// `Some((pos, nrm, uv, pdf))` in the world coordinate where `pdf` is the density on the area.
+/// `None` if the sampled texture coordinate is not covered by the mesh
+#[allow(clippy::type_complexity)]
+pub fn sample_emitter(
+    se: &crate::shape::ShapeEntity,
+    light: &AreaLight,
+    rnd: &[f32; 2],
+) -> Option<([f32; 3], [f32; 3], [f32; 2], f32)> {
+    use del_geo_core::vec3::Vec3;
+    if let (
+        Some(image),
+        Some(uv_grid),
+        crate::shape::ShapeType::TriangleMesh {
+            tri2vtx,
+            vtx2xyz,
+            vtx2uv,
+            ..
+        },
+    ) = (&light.image, &light.uv_grid, &se.shape)
+    {
+        let (xy, pdf_uv) = image.distrib.sample(rnd);
+        let uv = [xy[0], 1f32 - xy[1]];
+        let (i_tri, bc, area_uv) = uv_grid.triangle_at_uv(tri2vtx, vtx2uv, &uv)?;
+        let (p0, p1, p2) = del_msh_cpu::trimesh3::to_corner_points(tri2vtx, vtx2xyz, i_tri);
+        let nrm = transform_normal(&se.transform_world2objlcl, &p1.sub(&p0).cross(&p2.sub(&p0)));
+        let [q0, q1, q2] = world_corner_points(se, tri2vtx, vtx2xyz, i_tri);
+        let pos = del_geo_core::tri3::position_from_barycentric_coords(&q0, &q1, &q2, &bc);
+        let area_xyz = 0.5 * q1.sub(&q0).cross(&q2.sub(&q0)).norm();
+        return Some((pos, nrm.normalize(), uv, pdf_uv * area_uv / area_xyz));
+    }
+    let (pos, nrm, pdf) = se.sample_uniform(rnd);
+    let i_elem = match &se.shape {
+        crate::shape::ShapeType::TriangleMesh {
+            tri2cumsumarea: Some(tri2cumsumarea),
+            ..
+        } => {
+            // the same triangle as the one sampled in `ShapeEntity::sample_uniform`
+            del_msh_cpu::trimesh::sample_uniformly(tri2cumsumarea, rnd[0], rnd[1]).0
+        }
+        crate::shape::ShapeType::Curves {
+            edge2vtx, vtx2xyz, ..
+        } => {
+            // the segment whose axis is the closest to the sampled point
+            let pos = del_geo_core::mat4_col_major::transform_homogeneous(
+                &se.transform_world2objlcl,
+                &pos,
+            )
+            .unwrap();
+            (0..edge2vtx.len() / 2)
+                .map(|i_edge| {
+                    let p0 = arrayref::array_ref![vtx2xyz, edge2vtx[i_edge * 2] * 3, 3];
+                    let p1 = arrayref::array_ref![vtx2xyz, edge2vtx[i_edge * 2 + 1] * 3, 3];
+                    let d = p1.sub(p0);
+                    let s = (pos.sub(p0).dot(&d) / d.dot(&d)).clamp(0f32, 1f32);
+                    let q = p0.add(&d.scale(s));
+                    (i_edge, del_geo_core::edge3::squared_length(&pos, &q))
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap()
+                .0
+        }
+        _ => 0,
+    };
+    let uv = crate::shape::uv_at(se, &pos, i_elem);
+    Some((pos, nrm, uv, pdf))
+}
+
+/// density on the area of the point sampled by [`sample_emitter`]
+/// * `uv` - texture coordinate of the point on the emitter
+pub fn pdf_emitter(se: &crate::shape::ShapeEntity, light: &AreaLight, uv: &[f32; 2]) -> f32 {
+    if let (
+        Some(image),
+        Some(uv_grid),
+        crate::shape::ShapeType::TriangleMesh {
+            tri2vtx,
+            vtx2xyz,
+            vtx2uv,
+            ..
+        },
+    ) = (&light.image, &light.uv_grid, &se.shape)
+    {
+        use del_geo_core::vec3::Vec3;
+        let Some((i_tri, _bc, area_uv)) = uv_grid.triangle_at_uv(tri2vtx, vtx2uv, uv) else {
+            return 0f32;
+        };
+        let [q0, q1, q2] = world_corner_points(se, tri2vtx, vtx2xyz, i_tri);
+        let area_xyz = 0.5 * q1.sub(&q0).cross(&q2.sub(&q0)).norm();
+        let pdf_uv = image.distrib.pdf(&[uv[0], 1f32 - uv[1]]);
+        return pdf_uv * area_uv / area_xyz;
+    }
+    let area = match &se.shape {
+        crate::shape::ShapeType::TriangleMesh {
+            tri2cumsumarea: Some(tri2cumsumarea),
+            ..
+        } => *tri2cumsumarea.last().unwrap(),
+        _ => se.cog_and_area().1,
+    };
+    1f32 / area
+}
+
+/// radiance reflected toward `dir_out` at `pos` lit by the emitter, integrated analytically over the emitter
+/// with [`crate::polygonal_light::direct_radiance_trimesh`] instead of sampled by [`sample_emitter`].
+/// The occluders between `pos` and the emitter are ignored. See [`is_triangle_overlapping_tetrahedron`]
+/// # Return
+/// `None` if the emitter is not the triangle mesh or its emission is given by the image
+pub fn direct_radiance_analytic(
+    se: &crate::shape::ShapeEntity,
+    light: &AreaLight,
+    receiver: &crate::polygonal_light::Receiver,
+    pos: &[f32; 3],
+    nrm: &[f32; 3],
+    dir_out: &[f32; 3],
+) -> Option<[f32; 3]> {
+    let crate::shape::ShapeType::TriangleMesh {
+        tri2vtx, vtx2xyz, ..
+    } = &se.shape
+    else {
+        return None;
+    };
+    if light.image.is_some() {
+        return None;
+    }
+    let vtx2xyz: Vec<f32> = vtx2xyz
+        .chunks(3)
+        .flat_map(|p| {
+            del_geo_core::mat4_col_major::transform_homogeneous(
+                &se.transform_objlcl2world,
+                arrayref::array_ref![p, 0, 3],
+            )
+            .unwrap()
+        })
+        .collect();
+    Some(crate::polygonal_light::direct_radiance_trimesh(
+        receiver,
+        tri2vtx,
+        &vtx2xyz,
+        &light.emission(&[0f32; 2]),
+        light.two_sided,
+        pos,
+        nrm,
+        dir_out,
+    ))
+}
+
+/// separating axis test of the triangle and the tetrahedron.
+/// The touching ones and the ones whose separating axis is degenerate are regarded as overlapping.
+/// The triangle overlapping the tetrahedron spanned by the shading point and an emitter's triangle
+/// may occlude the emitter, where [`direct_radiance_analytic`] is not applicable
+pub fn is_triangle_overlapping_tetrahedron(tri: &[[f32; 3]; 3], tet: &[[f32; 3]; 4]) -> bool {
+    use del_geo_core::vec3::Vec3;
+    let tri_edges = [0, 1, 2].map(|i| tri[(i + 1) % 3].sub(&tri[i]));
+    let tet_edges =
+        [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)].map(|(i, j)| tet[j].sub(&tet[i]));
+    let mut axes = vec![tri_edges[0].cross(&tri_edges[1])];
+    for (i, j) in [(0, 1), (0, 2), (1, 2), (3, 4)] {
+        // the normals of the four faces of the tetrahedron
+        axes.push(tet_edges[i].cross(&tet_edges[j]));
+    }
+    for e0 in tri_edges.iter() {
+        for e1 in tet_edges.iter() {
+            axes.push(e0.cross(e1));
+        }
+    }
+    let range = |points: &[[f32; 3]], axis: &[f32; 3]| {
+        points.iter().fold((f32::MAX, f32::MIN), |(min, max), p| {
+            let d = p.dot(axis);
+            (min.min(d), max.max(d))
+        })
+    };
+    !axes.iter().any(|axis| {
+        if axis.norm() < 1.0e-10 {
+            return false;
+        }
+        let (min0, max0) = range(&tri[..], axis);
+        let (min1, max1) = range(&tet[..], axis);
+        max0 < min1 || max1 < min0
+    })
 }
 
 pub fn sampling_light(
@@ -24,3 +472,267 @@ pub fn sampling_light(
     let light_pos = del_geo_core::vec3::axpy(1.0e-3, &light_nrm, &light_pos);
     (light_pos, light_nrm)
 }
+
+#[test]
+fn test_textured_emitter() {
+    use rand::Rng;
+    use rand::SeedableRng;
+    // unit square whose texture coordinates are the same as the positions
+    let tri2vtx = vec![0, 1, 2, 0, 2, 3];
+    let vtx2xyz = vec![0., 0., 0., 1., 0., 0., 1., 1., 0., 0., 1., 0.];
+    let tri2cumsumarea = del_msh_cpu::trimesh::tri2cumsumarea(&tri2vtx, &vtx2xyz, 3);
+    let vtx2uv: Vec<f32> = vtx2xyz.chunks(3).flat_map(|p| [p[0], p[1]]).collect();
+    let uv_grid = UvTriangleGrid::new(&tri2vtx, &vtx2uv);
+    assert!(uv_grid.is_some());
+    // the overlapping texture coordinates are not importance sampled
+    assert!(UvTriangleGrid::new(&[0, 1, 2, 0, 1, 2, 0, 2, 3], &vtx2uv).is_none());
+    let se = crate::shape::ShapeEntity {
+        area_light_index: Some(0),
+        ..crate::shape::ShapeEntity::new_untransformed(crate::shape::ShapeType::TriangleMesh {
+            tri2vtx,
+            vtx2xyz: vtx2xyz.clone(),
+            vtx2nrm: vec![],
+            vtx2uv,
+            tri2cumsumarea: Some(tri2cumsumarea),
+        })
+    };
+    let img_shape = (4, 2);
+    let data: Vec<f32> = (0..img_shape.0 * img_shape.1)
+        .flat_map(|i| [i as f32, 0.5, (i % 3) as f32])
+        .collect();
+    let image = EmissionImage::new(img_shape, data.clone());
+    let average = image.average();
+    // the first row of the image is at the top
+    assert_eq!(image.lookup(&[0.1, 0.9]), [0., 0.5, 0.]);
+    assert_eq!(image.lookup(&[0.9, 0.1]), [7., 0.5, 1.]);
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    for (image, uv_grid) in [(Some(image), uv_grid), (None, None)] {
+        let light = AreaLight {
+            spectrum_rgb: Some([1., 2., 3.]),
+            two_sided: false,
+            scale: 2.,
+            image,
+            uv_grid,
+        };
+        let num_sample = 10000;
+        let mut sum = [0f32; 3];
+        for _ in 0..num_sample {
+            let Some((pos, nrm, uv, pdf)) =
+                sample_emitter(&se, &light, &[rng.random::<f32>(), rng.random::<f32>()])
+            else {
+                continue;
+            };
+            assert!((nrm[2] - 1.).abs() < 1.0e-5);
+            assert!((uv[0] - pos[0]).abs() < 1.0e-5 && (uv[1] - pos[1]).abs() < 1.0e-5);
+            assert!((pdf - pdf_emitter(&se, &light, &uv)).abs() < 1.0e-3 * pdf);
+            let l = light.emission(&uv);
+            sum = std::array::from_fn(|i| sum[i] + l[i] / pdf / num_sample as f32);
+        }
+        // the integral of the emission over the unit square
+        let expected = light.average_emission();
+        for i in 0..3 {
+            assert!(
+                (sum[i] - expected[i]).abs() < 3.0e-2 * expected[i].max(1.),
+                "{:?}",
+                sum
+            );
+        }
+    }
+    assert_eq!(average, [3.5, 0.5, 0.875]);
+    {
+        // the non-uniform scale changes the area but not the normal
+        let (sx, sy) = (2f32, 3f32);
+        let mut se = se;
+        se.transform_objlcl2world = [
+            sx, 0., 0., 0., 0., sy, 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.,
+        ];
+        se.transform_world2objlcl =
+            del_geo_core::mat4_col_major::try_inverse(&se.transform_objlcl2world).unwrap();
+        let crate::shape::ShapeType::TriangleMesh {
+            tri2vtx, vtx2uv, ..
+        } = &se.shape
+        else {
+            unreachable!()
+        };
+        let light = AreaLight {
+            spectrum_rgb: Some([1., 2., 3.]),
+            two_sided: false,
+            scale: 2.,
+            image: Some(EmissionImage::new(img_shape, data)),
+            uv_grid: UvTriangleGrid::new(tri2vtx, vtx2uv),
+        };
+        let num_sample = 10000;
+        let mut sum = [0f32; 3];
+        for _ in 0..num_sample {
+            let Some((pos, nrm, uv, pdf)) =
+                sample_emitter(&se, &light, &[rng.random::<f32>(), rng.random::<f32>()])
+            else {
+                continue;
+            };
+            assert!((nrm[2] - 1.).abs() < 1.0e-5);
+            assert!((uv[0] * sx - pos[0]).abs() < 1.0e-5 && (uv[1] * sy - pos[1]).abs() < 1.0e-5);
+            assert!((pdf - pdf_emitter(&se, &light, &uv)).abs() < 1.0e-3 * pdf);
+            let l = light.emission(&uv);
+            sum = std::array::from_fn(|i| sum[i] + l[i] / pdf / num_sample as f32);
+        }
+        let expected = light.average_emission().map(|v| v * sx * sy);
+        for i in 0..3 {
+            assert!(
+                (sum[i] - expected[i]).abs() < 3.0e-2 * expected[i].max(1.),
+                "{:?}",
+                sum
+            );
+        }
+    }
+}
+
+#[test]
+fn test_uv_triangle_grid() {
+    use rand::Rng;
+    use rand::SeedableRng;
+    // the unit square split into `n * n` quads
+    let n = 7;
+    let vtx2uv: Vec<f32> = (0..(n + 1) * (n + 1))
+        .flat_map(|i| {
+            [
+                (i % (n + 1)) as f32 / n as f32,
+                (i / (n + 1)) as f32 / n as f32,
+            ]
+        })
+        .collect();
+    let tri2vtx: Vec<usize> = (0..n * n)
+        .flat_map(|i| {
+            let i0 = (i / n) * (n + 1) + i % n;
+            let (i1, i2, i3) = (i0 + 1, i0 + n + 2, i0 + n + 1);
+            [i0, i1, i2, i0, i2, i3]
+        })
+        .collect();
+    let uv_grid = UvTriangleGrid::new(&tri2vtx, &vtx2uv).unwrap();
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    for _ in 0..1000 {
+        let uv = [rng.random::<f32>(), rng.random::<f32>()];
+        let (i_tri, bc, area_uv) = uv_grid.triangle_at_uv(&tri2vtx, &vtx2uv, &uv).unwrap();
+        assert!((area_uv - 0.5 / (n * n) as f32).abs() < 1.0e-5);
+        // the barycentric coordinates reproduce the texture coordinate
+        let q = uv_corner_points(&tri2vtx, &vtx2uv, i_tri);
+        for i in 0..2 {
+            let v = bc[0] * q[0][i] + bc[1] * q[1][i] + bc[2] * q[2][i];
+            assert!((v - uv[i]).abs() < 1.0e-5);
+        }
+    }
+    assert!(uv_grid
+        .triangle_at_uv(&tri2vtx, &vtx2uv, &[1.5, 0.5])
+        .is_none());
+}
+
+#[test]
+fn test_direct_radiance_analytic() {
+    use del_geo_core::vec3::Vec3;
+    use rand::Rng;
+    use rand::SeedableRng;
+    let trimesh = |vtx2xyz: Vec<f32>, area_light_index: Option<usize>| {
+        let tri2vtx = vec![0, 2, 1, 0, 3, 2];
+        let tri2cumsumarea = del_msh_cpu::trimesh::tri2cumsumarea(&tri2vtx, &vtx2xyz, 3);
+        crate::shape::ShapeEntity {
+            area_light_index,
+            ..crate::shape::ShapeEntity::new_untransformed(crate::shape::ShapeType::TriangleMesh {
+                tri2vtx,
+                vtx2xyz,
+                vtx2nrm: vec![],
+                vtx2uv: vec![],
+                tri2cumsumarea: Some(tri2cumsumarea),
+            })
+        }
+    };
+    // square light facing down at the height of one
+    let square = |c: [f32; 2], h: f32, z: f32| -> Vec<f32> {
+        [[-h, -h], [h, -h], [h, h], [-h, h]]
+            .iter()
+            .flat_map(|p| [c[0] + p[0], c[1] + p[1], z])
+            .collect()
+    };
+    let se = trimesh(square([0.3, 0.], 0.5, 1.), Some(0));
+    let light = AreaLight {
+        spectrum_rgb: Some([1., 2., 3.]),
+        two_sided: false,
+        scale: 1.,
+        image: None,
+        uv_grid: None,
+    };
+    let table =
+        crate::polygonal_light::LtcTable::new(crate::polygonal_light::Microfacet::Ggx, 6, 6, 128);
+    let (pos, nrm) = ([0f32; 3], [0f32, 0., 1.]);
+    let dir_out = [-0.5f32, 0., 1.].normalize();
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    for (receiver, tolerance) in [
+        (
+            crate::polygonal_light::Receiver::Diffuse([0.5, 0.5, 0.5]),
+            0.02f32,
+        ),
+        (
+            crate::polygonal_light::Receiver::Glossy {
+                table: &table,
+                alpha: 0.36,
+                specular: [1., 1., 1.],
+            },
+            0.08,
+        ),
+    ] {
+        let analytic =
+            direct_radiance_analytic(&se, &light, &receiver, &pos, &nrm, &dir_out).unwrap();
+        // light sampling on the area
+        let num_sample = 100000;
+        let mut sum = 0f32;
+        for _ in 0..num_sample {
+            let (pos_light, nrm_light, uv_light, pdf) =
+                sample_emitter(&se, &light, &[rng.random(), rng.random()]).unwrap();
+            let d = pos_light.sub(&pos);
+            let wi = d.normalize();
+            let cos_light = -wi.dot(&nrm_light);
+            if cos_light <= 0f32 {
+                continue;
+            }
+            let brdf_cos = match &receiver {
+                crate::polygonal_light::Receiver::Diffuse(r) => r[0] * wi[2] / std::f32::consts::PI,
+                crate::polygonal_light::Receiver::Glossy { alpha, .. } => {
+                    crate::polygonal_light::Microfacet::Ggx.eval_brdf_cos(*alpha, &dir_out, &wi)
+                }
+            };
+            let li = light.emission(&uv_light)[0];
+            sum += brdf_cos * li * cos_light / d.dot(&d) / pdf / num_sample as f32;
+        }
+        assert!(
+            (analytic[0] - sum).abs() < tolerance * sum,
+            "{:?} {}",
+            analytic,
+            sum
+        );
+        assert!((analytic[2] - 3. * analytic[0]).abs() < 1.0e-5 * analytic[2]);
+    }
+    // the triangles occluding the light overlap the tetrahedra spanned by `pos` and the light's triangles
+    let is_occluded = |occluder: Vec<f32>| {
+        let crate::shape::ShapeType::TriangleMesh {
+            tri2vtx, vtx2xyz, ..
+        } = &se.shape
+        else {
+            unreachable!()
+        };
+        (0..2).any(|i_tri_occluder| {
+            let tri = [0, 1, 2].map(|i| {
+                let iv = [0, 2, 1, 0, 3, 2][i_tri_occluder * 3 + i];
+                *arrayref::array_ref![occluder, iv * 3, 3]
+            });
+            (0..2).any(|i_tri| {
+                let [q0, q1, q2] = world_corner_points(&se, tri2vtx, vtx2xyz, i_tri);
+                is_triangle_overlapping_tetrahedron(&tri, &[pos, q0, q1, q2])
+            })
+        })
+    };
+    // the occluder hiding a corner of the light is detected
+    assert!(is_occluded(square([0.4, 0.25], 0.1, 0.5)));
+    // so is the occluder hiding only the interior of the light
+    assert!(is_occluded(square([0.15, 0.], 0.1, 0.5)));
+    // the occluders beside the light or behind it do not occlude it
+    assert!(!is_occluded(square([2., 0.], 0.1, 0.5)));
+    assert!(!is_occluded(square([0.3, 0.], 2., 1.01)));
+}
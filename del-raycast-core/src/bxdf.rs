@@ -1,15 +1,12 @@
-use crate::sampling;
-use nalgebra::{Vector2, Vector3};
-use std::ops::{BitAnd, BitOr, Mul};
-
-type Real = f32;
-type Vec3f = Vector3<Real>;
-type Vec2f = Vector2<Real>;
+//! pbrt-style BxDF interface shared by all the materials.
+//! A [`crate::material::Material`] is converted to a [`BxDF`] at the hit point by [`crate::material::bxdf`].
+//! As in pbrt, `wo` is the unit vector toward the viewer and `wi` is the one toward the light.
+//! Both are in the local frame whose z-axis is the normal. The values are in RGB.
 
-const N_SPECTRUM_SAMPLES: usize = 4;
-const INV_PI: Real = 1. / std::f32::consts::PI;
+use crate::sampling;
+use std::ops::{BitAnd, BitOr};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BxDFFlags(u32);
 
 impl BxDFFlags {
@@ -19,17 +16,51 @@ impl BxDFFlags {
     pub const DIFFUSE: Self = Self(1 << 3);
     pub const GLOSSY: Self = Self(1 << 4);
     pub const SPECULAR: Self = Self(1 << 5);
-}
 
-#[derive(Debug, Clone, Copy)]
-pub struct BxDFReflTransFlags {
-    _flg: u32,
+    pub fn is_reflective(&self) -> bool {
+        self.0 & Self::REFLECTION.0 != 0
+    }
+
+    pub fn is_transmissive(&self) -> bool {
+        self.0 & Self::TRANSMISSION.0 != 0
+    }
+
+    pub fn is_diffuse(&self) -> bool {
+        self.0 & Self::DIFFUSE.0 != 0
+    }
+
+    pub fn is_glossy(&self) -> bool {
+        self.0 & Self::GLOSSY.0 != 0
+    }
+
+    /// true if there is a delta lobe
+    pub fn is_specular(&self) -> bool {
+        self.0 & Self::SPECULAR.0 != 0
+    }
+
+    /// true if there is a lobe that can be evaluated. The light sampling is useless otherwise
+    pub fn is_non_specular(&self) -> bool {
+        self.0 & (Self::DIFFUSE.0 | Self::GLOSSY.0) != 0
+    }
 }
 
+/// lobes to be sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BxDFReflTransFlags(u32);
+
 impl BxDFReflTransFlags {
-    pub const UNSET: Self = Self { _flg: 0 };
-    pub const REFLECTION: Self = Self { _flg: 1 };
-    pub const TRANSMISSION: Self = Self { _flg: 1 << 2 };
+    pub const UNSET: Self = Self(0);
+    pub const REFLECTION: Self = Self(1);
+    pub const TRANSMISSION: Self = Self(1 << 2);
+    pub const ALL: Self = Self(1 | (1 << 2));
+
+    pub fn has_reflection(&self) -> bool {
+        self.0 & Self::REFLECTION.0 != 0
+    }
+
+    pub fn has_transmission(&self) -> bool {
+        self.0 & Self::TRANSMISSION.0 != 0
+    }
 }
 
 impl BitAnd for BxDFFlags {
@@ -46,82 +77,777 @@ impl BitOr for BxDFFlags {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct SampledSpectrum {
-    pub arr: [Real; N_SPECTRUM_SAMPLES],
-}
-
-impl Mul<Real> for SampledSpectrum {
-    type Output = Self;
-    fn mul(self, rhs: Real) -> Self::Output {
-        let res = self.arr.map(|x| x * rhs);
-        SampledSpectrum { arr: res }
-    }
-}
-
+/// direction sampled by [`BxDF::sample_f`]
 pub struct BSDFSample {
+    pub f: [f32; 3],
+    pub wi: [f32; 3],
+    /// pdf of `wi`. For the delta lobe, the probability to choose the lobe
+    /// (the value and the pdf are the ones divided by the delta function)
+    pub pdf: f32,
     pub flag: BxDFFlags,
-    pub wi: Vec3f,
-    pub pdf: Real,
+    /// true if `pdf` is only proportional to the true one (e.g., the layered BSDF).
+    /// `f / pdf` is still the right weight but [`BxDF::pdf`] should be used for the MIS
+    pub pdf_is_proportional: bool,
 }
 
 impl BSDFSample {
     pub fn has_flag(&self, flag: BxDFFlags) -> bool {
         self.flag.0 & flag.0 != 0
     }
+
+    pub fn is_specular(&self) -> bool {
+        self.flag.is_specular()
+    }
+
+    pub fn is_reflection(&self) -> bool {
+        self.flag.is_reflective()
+    }
+
+    pub fn is_transmission(&self) -> bool {
+        self.flag.is_transmissive()
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TransportMode {
     Radiance,
     Importance,
 }
 
+impl TransportMode {
+    /// the mode of the adjoint transport
+    pub fn flip(&self) -> Self {
+        match self {
+            TransportMode::Radiance => TransportMode::Importance,
+            TransportMode::Importance => TransportMode::Radiance,
+        }
+    }
+}
+
+pub trait BxDF {
+    fn flags(&self) -> BxDFFlags;
+
+    /// value of the BxDF. Zero for the delta lobes
+    fn f(&self, wo: &[f32; 3], wi: &[f32; 3], mode: TransportMode) -> [f32; 3];
+
+    /// sample the direction toward the light
+    /// * `uc` - uniform random number in `[0, 1)` to choose the lobe
+    /// * `u` - uniform random numbers in `[0, 1)` to sample the direction
+    /// * `sample_flags` - the lobes to be sampled
+    fn sample_f(
+        &self,
+        wo: &[f32; 3],
+        uc: f32,
+        u: &[f32; 2],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> Option<BSDFSample>;
+
+    /// pdf of [`BxDF::sample_f`]. Zero for the delta lobes
+    fn pdf(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> f32;
+}
+
+fn same_hemisphere(wo: &[f32; 3], wi: &[f32; 3]) -> bool {
+    wo[2] * wi[2] > 0f32
+}
+
+/// probabilities to choose the reflection and the transmission restricted to the `sample_flags`
+/// # Return
+/// `None` if no lobe can be chosen
+pub(crate) fn lobe_probability(
+    pr: f32,
+    pt: f32,
+    sample_flags: BxDFReflTransFlags,
+) -> Option<(f32, f32)> {
+    let pr = if sample_flags.has_reflection() {
+        pr
+    } else {
+        0f32
+    };
+    let pt = if sample_flags.has_transmission() {
+        pt
+    } else {
+        0f32
+    };
+    if pr + pt <= 0f32 {
+        return None;
+    }
+    Some((pr / (pr + pt), pt / (pr + pt)))
+}
+
+// ----------------------------------------
+
+/// Lambertian reflection
 pub struct DiffuseBxDF {
-    r: SampledSpectrum,
+    pub reflectance: [f32; 3],
 }
 
 impl DiffuseBxDF {
-    pub fn new(r: SampledSpectrum) -> Self {
-        DiffuseBxDF { r }
+    pub fn new(reflectance: [f32; 3]) -> Self {
+        DiffuseBxDF { reflectance }
     }
+}
 
-    fn same_hemisphere(wo: Vec3f, wi: Vec3f) -> bool {
-        wo.z * wi.z > 0.
+impl BxDF for DiffuseBxDF {
+    fn flags(&self) -> BxDFFlags {
+        if self.reflectance == [0f32; 3] {
+            return BxDFFlags::UNSET;
+        }
+        BxDFFlags::DIFFUSE | BxDFFlags::REFLECTION
     }
 
-    pub fn f(&self, wo: Vec3f, wi: Vec3f) -> SampledSpectrum {
-        if !Self::same_hemisphere(wo, wi) {
-            return SampledSpectrum {
-                arr: [0.; N_SPECTRUM_SAMPLES],
-            };
+    fn f(&self, wo: &[f32; 3], wi: &[f32; 3], _mode: TransportMode) -> [f32; 3] {
+        if !same_hemisphere(wo, wi) {
+            return [0f32; 3];
         }
-        self.r * INV_PI
+        self.reflectance.map(|r| r * std::f32::consts::FRAC_1_PI)
     }
 
-    pub fn pdf(&self, wo: Vec3f, wi: Vec3f) -> Real {
-        if !Self::same_hemisphere(wo, wi) {
-            return 0.;
+    fn sample_f(
+        &self,
+        wo: &[f32; 3],
+        _uc: f32,
+        u: &[f32; 2],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> Option<BSDFSample> {
+        if !sample_flags.has_reflection() || wo[2] == 0f32 {
+            return None;
         }
-        sampling::pdf_hemisphere_cos(wi.z.abs())
+        let mut wi = sampling::hemisphere_zup_cos_weighted(u);
+        if wo[2] < 0f32 {
+            wi[2] *= -1f32;
+        }
+        let pdf = sampling::pdf_hemisphere_cos(wi[2].abs());
+        if pdf <= 0f32 {
+            return None;
+        }
+        Some(BSDFSample {
+            f: self.f(wo, &wi, mode),
+            wi,
+            pdf,
+            flag: BxDFFlags::DIFFUSE | BxDFFlags::REFLECTION,
+            pdf_is_proportional: false,
+        })
     }
 
-    /// return incident light of given outgoing light wo
-    pub fn sample_f(&self, wo: Vec3f, u: Vec2f) -> Option<BSDFSample> {
-        let mut wi = sampling::hemisphere_zup_cos_weighted(&[u.x, u.y]);
-        if wo.z < 0. {
-            wi[2] *= -1.;
+    fn pdf(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        _mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> f32 {
+        if !sample_flags.has_reflection() || !same_hemisphere(wo, wi) {
+            return 0f32;
         }
+        sampling::pdf_hemisphere_cos(wi[2].abs())
+    }
+}
+
+// ----------------------------------------
+
+/// Lambertian reflection and transmission such as the leaves and the paper
+pub struct DiffuseTransmissionBxDF {
+    pub reflectance: [f32; 3],
+    pub transmittance: [f32; 3],
+}
+
+impl BxDF for DiffuseTransmissionBxDF {
+    fn flags(&self) -> BxDFFlags {
+        let mut flags = BxDFFlags::UNSET;
+        if self.reflectance != [0f32; 3] {
+            flags = flags | BxDFFlags::REFLECTION | BxDFFlags::DIFFUSE;
+        }
+        if self.transmittance != [0f32; 3] {
+            flags = flags | BxDFFlags::TRANSMISSION | BxDFFlags::DIFFUSE;
+        }
+        flags
+    }
+
+    fn f(&self, wo: &[f32; 3], wi: &[f32; 3], _mode: TransportMode) -> [f32; 3] {
+        if same_hemisphere(wo, wi) {
+            self.reflectance.map(|r| r * std::f32::consts::FRAC_1_PI)
+        } else {
+            self.transmittance.map(|t| t * std::f32::consts::FRAC_1_PI)
+        }
+    }
+
+    fn sample_f(
+        &self,
+        wo: &[f32; 3],
+        uc: f32,
+        u: &[f32; 2],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> Option<BSDFSample> {
+        if wo[2] == 0f32 {
+            return None;
+        }
+        let max = |v: &[f32; 3]| v[0].max(v[1]).max(v[2]);
+        let (pr, pt) = lobe_probability(
+            max(&self.reflectance),
+            max(&self.transmittance),
+            sample_flags,
+        )?;
+        let mut wi = sampling::hemisphere_zup_cos_weighted(u);
+        let is_reflection = uc < pr;
+        // `wi` is on the same side as `wo` for the reflection
+        if (wo[2] < 0f32) == is_reflection {
+            wi[2] *= -1f32;
+        }
+        let pdf = sampling::pdf_hemisphere_cos(wi[2].abs()) * if is_reflection { pr } else { pt };
+        if pdf <= 0f32 {
+            return None;
+        }
+        let flag = if is_reflection {
+            BxDFFlags::DIFFUSE | BxDFFlags::REFLECTION
+        } else {
+            BxDFFlags::DIFFUSE | BxDFFlags::TRANSMISSION
+        };
+        Some(BSDFSample {
+            f: self.f(wo, &wi, mode),
+            wi,
+            pdf,
+            flag,
+            pdf_is_proportional: false,
+        })
+    }
+
+    fn pdf(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        _mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> f32 {
+        let max = |v: &[f32; 3]| v[0].max(v[1]).max(v[2]);
+        let Some((pr, pt)) = lobe_probability(
+            max(&self.reflectance),
+            max(&self.transmittance),
+            sample_flags,
+        ) else {
+            return 0f32;
+        };
         let pdf = sampling::pdf_hemisphere_cos(wi[2].abs());
+        if same_hemisphere(wo, wi) {
+            pdf * pr
+        } else {
+            pdf * pt
+        }
+    }
+}
+
+// ----------------------------------------
 
+/// rough conductor with the Trowbridge-Reitz distribution. Two-sided
+pub struct ConductorBxDF {
+    /// multiplied to the Fresnel reflectance
+    pub reflectance: [f32; 3],
+    pub eta: [f32; 3],
+    pub k: [f32; 3],
+    pub distrib: crate::microfacet::TrowbridgeReitz,
+    /// add the energy of the multiple scattering between the microfacets.
+    /// See [`crate::energy_compensation`]
+    pub multiple_scattering: bool,
+    /// average Fresnel reflectance used by the compensation lobe. Zero if there is no compensation
+    fresnel_avg: [f32; 3],
+}
+
+impl ConductorBxDF {
+    pub fn new(
+        reflectance: [f32; 3],
+        eta: [f32; 3],
+        k: [f32; 3],
+        distrib: crate::microfacet::TrowbridgeReitz,
+        multiple_scattering: bool,
+    ) -> Self {
+        let fresnel_avg = if multiple_scattering && !distrib.effectively_smooth() {
+            crate::energy_compensation::fresnel_average_conductor(&eta, &k)
+        } else {
+            [0f32; 3]
+        };
+        ConductorBxDF {
+            reflectance,
+            eta,
+            k,
+            distrib,
+            multiple_scattering,
+            fresnel_avg,
+        }
+    }
+
+    /// probability to sample the single-scattering lobe rather than the compensation lobe
+    fn prob_single_scattering(&self, wo: &[f32; 3]) -> f32 {
+        if !self.multiple_scattering || self.distrib.effectively_smooth() {
+            return 1f32;
+        }
+        crate::energy_compensation::albedo(wo[2].abs(), &self.distrib).clamp(0.1f32, 1f32)
+    }
+}
+
+/// flip the pair of the directions to the upper hemisphere of `wo`
+fn flip_to_upper(wo: &[f32; 3], wi: &[f32; 3]) -> ([f32; 3], [f32; 3]) {
+    if wo[2] < 0f32 {
+        (wo.map(|v| -v), wi.map(|v| -v))
+    } else {
+        (*wo, *wi)
+    }
+}
+
+impl BxDF for ConductorBxDF {
+    fn flags(&self) -> BxDFFlags {
+        if self.distrib.effectively_smooth() {
+            BxDFFlags::SPECULAR | BxDFFlags::REFLECTION
+        } else {
+            BxDFFlags::GLOSSY | BxDFFlags::REFLECTION
+        }
+    }
+
+    fn f(&self, wo: &[f32; 3], wi: &[f32; 3], _mode: TransportMode) -> [f32; 3] {
+        use del_geo_core::vec3::Vec3;
+        let (wo, wi) = flip_to_upper(wo, wi);
+        let f = crate::material::eval_brdf_rough_conductor(
+            &wo,
+            &wi,
+            &self.reflectance,
+            &self.eta,
+            &self.k,
+            &self.distrib,
+        );
+        if !self.multiple_scattering {
+            return f;
+        }
+        f.add(
+            &crate::energy_compensation::eval_brdf_multiple_scattering_conductor(
+                &wo,
+                &wi,
+                &self.reflectance,
+                &self.fresnel_avg,
+                &self.distrib,
+            ),
+        )
+    }
+
+    fn sample_f(
+        &self,
+        wo: &[f32; 3],
+        uc: f32,
+        u: &[f32; 2],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> Option<BSDFSample> {
+        if !sample_flags.has_reflection() || wo[2] == 0f32 {
+            return None;
+        }
+        let sign = wo[2].signum();
+        let wo_up = wo.map(|v| v * sign);
+        let prob_ss = self.prob_single_scattering(&wo_up);
+        if prob_ss < 1f32 {
+            // the mixture of the single-scattering lobe and the cosine-weighted compensation lobe
+            let wi = if uc < prob_ss {
+                crate::material::sample_brdf_rough_conductor(
+                    &wo_up,
+                    &self.reflectance,
+                    &self.eta,
+                    &self.k,
+                    &self.distrib,
+                    u,
+                )?
+                .0
+            } else {
+                crate::sampling::hemisphere_zup_cos_weighted(u)
+            };
+            let wi = wi.map(|v| v * sign);
+            let pdf = self.pdf(wo, &wi, mode, sample_flags);
+            if pdf <= 0f32 {
+                return None;
+            }
+            return Some(BSDFSample {
+                f: self.f(wo, &wi, mode),
+                wi,
+                pdf,
+                flag: self.flags(),
+                pdf_is_proportional: false,
+            });
+        }
+        let (wi, f, pdf) = crate::material::sample_brdf_rough_conductor(
+            &wo_up,
+            &self.reflectance,
+            &self.eta,
+            &self.k,
+            &self.distrib,
+            u,
+        )?;
         Some(BSDFSample {
-            flag: BxDFFlags::DIFFUSE | BxDFFlags::REFLECTION,
-            wi: Vec3f::new(wi[0], wi[1], wi[2]),
+            f,
+            wi: wi.map(|v| v * sign),
             pdf,
+            flag: self.flags(),
+            pdf_is_proportional: false,
         })
     }
 
-    pub fn get_type(&self) -> BxDFFlags {
-        BxDFFlags::DIFFUSE | BxDFFlags::REFLECTION
+    fn pdf(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        _mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> f32 {
+        if !sample_flags.has_reflection() {
+            return 0f32;
+        }
+        let (wo, wi) = flip_to_upper(wo, wi);
+        let pdf = crate::material::pdf_brdf_rough_conductor(&wo, &wi, &self.distrib);
+        let prob_ss = self.prob_single_scattering(&wo);
+        if prob_ss < 1f32 && wi[2] > 0f32 {
+            prob_ss * pdf + (1f32 - prob_ss) * crate::sampling::pdf_hemisphere_cos(wi[2])
+        } else {
+            pdf
+        }
+    }
+}
+
+// ----------------------------------------
+
+/// dielectric interface with the Trowbridge-Reitz distribution. Smooth if the distribution is effectively smooth.
+/// `eta` is the index of refraction of the inside (below the surface) over the outside.
+/// If the channels of `eta` differ (i.e., dispersion), one channel is sampled with the probability of one third
+pub struct DielectricBxDF {
+    pub eta: [f32; 3],
+    pub distrib: crate::microfacet::TrowbridgeReitz,
+}
+
+impl DielectricBxDF {
+    fn is_smooth(&self) -> bool {
+        self.distrib.effectively_smooth()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.eta[0] != self.eta[1] || self.eta[1] != self.eta[2]
+    }
+
+    /// the radiance is scaled at the refraction but the importance is not
+    fn transmission_scale(eta: f32, wo: &[f32; 3], mode: TransportMode) -> f32 {
+        if mode == TransportMode::Radiance {
+            return 1f32;
+        }
+        let etap = if wo[2] > 0f32 { eta } else { 1f32 / eta };
+        etap * etap
+    }
+
+    fn f_channel(&self, wo: &[f32; 3], wi: &[f32; 3], eta: f32, mode: TransportMode) -> f32 {
+        let f = crate::material::eval_bsdf_rough_dielectric(wo, wi, eta, &self.distrib);
+        if same_hemisphere(wo, wi) {
+            return f;
+        }
+        f * Self::transmission_scale(eta, wo, mode)
+    }
+
+    fn pdf_channel(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        eta: f32,
+        sample_flags: BxDFReflTransFlags,
+    ) -> f32 {
+        use del_geo_core::vec3::Vec3;
+        let Some((m, etap)) = crate::material::rough_dielectric_half_vector(wo, wi, eta) else {
+            return 0f32;
+        };
+        let r = crate::material::fresnel_dielectric(eta, wo.dot(&m));
+        let Some((pr, pt)) = lobe_probability(r, 1f32 - r, sample_flags) else {
+            return 0f32;
+        };
+        // the visible normals from `wo` are sampled
+        let pdf_m = self.distrib.pdf(wo, &m);
+        if same_hemisphere(wo, wi) {
+            return pdf_m * 0.25f32 / wo.dot(&m).abs() * pr;
+        }
+        let denom = wi.dot(&m) + wo.dot(&m) / etap;
+        pdf_m * wi.dot(&m).abs() / (denom * denom) * pt
+    }
+
+    /// # Return
+    /// `(wi, f, pdf, is_reflection)`
+    fn sample_channel(
+        &self,
+        wo: &[f32; 3],
+        eta: f32,
+        uc: f32,
+        u: &[f32; 2],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> Option<([f32; 3], f32, f32, bool)> {
+        use del_geo_core::vec3::Vec3;
+        if self.is_smooth() {
+            let r = crate::material::fresnel_dielectric(eta, wo[2]);
+            let (pr, pt) = lobe_probability(r, 1f32 - r, sample_flags)?;
+            if uc < pr {
+                let wi = [-wo[0], -wo[1], wo[2]];
+                return Some((wi, r / wi[2].abs(), pr, true));
+            }
+            // the normal and the relative index of refraction on the incident side
+            let (n, etap) = if wo[2] > 0f32 {
+                ([0f32, 0f32, 1f32], eta)
+            } else {
+                ([0f32, 0f32, -1f32], 1f32 / eta)
+            };
+            let wi = crate::material::refract(wo, &n, etap)?;
+            if wi[2] == 0f32 {
+                return None;
+            }
+            let f = (1f32 - r) / wi[2].abs() / (etap * etap);
+            return Some((wi, f * Self::transmission_scale(eta, wo, mode), pt, false));
+        }
+        let m = self.distrib.sample_wm(wo, u);
+        let wo_dot_m = wo.dot(&m);
+        let r = crate::material::fresnel_dielectric(eta, wo_dot_m);
+        let (pr, _pt) = lobe_probability(r, 1f32 - r, sample_flags)?;
+        let wi = if uc < pr {
+            let wi = m.scale(2f32 * wo_dot_m).sub(wo);
+            if !same_hemisphere(wo, &wi) {
+                return None;
+            }
+            wi
+        } else {
+            let (n, etap) = if wo_dot_m > 0f32 {
+                (m, eta)
+            } else {
+                (m.scale(-1f32), 1f32 / eta)
+            };
+            let wi = crate::material::refract(wo, &n, etap)?;
+            if wo[2] * wi[2] >= 0f32 {
+                return None;
+            }
+            wi
+        };
+        let pdf = self.pdf_channel(wo, &wi, eta, sample_flags);
+        if pdf <= 0f32 {
+            return None;
+        }
+        let f = self.f_channel(wo, &wi, eta, mode);
+        Some((wi, f, pdf, same_hemisphere(wo, &wi)))
+    }
+}
+
+impl BxDF for DielectricBxDF {
+    fn flags(&self) -> BxDFFlags {
+        let flags = BxDFFlags::REFLECTION | BxDFFlags::TRANSMISSION;
+        if self.is_smooth() {
+            flags | BxDFFlags::SPECULAR
+        } else {
+            flags | BxDFFlags::GLOSSY
+        }
+    }
+
+    fn f(&self, wo: &[f32; 3], wi: &[f32; 3], mode: TransportMode) -> [f32; 3] {
+        if self.is_smooth() {
+            return [0f32; 3];
+        }
+        self.eta.map(|eta| self.f_channel(wo, wi, eta, mode))
+    }
+
+    fn sample_f(
+        &self,
+        wo: &[f32; 3],
+        uc: f32,
+        u: &[f32; 2],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> Option<BSDFSample> {
+        if wo[2] == 0f32 {
+            return None;
+        }
+        let lobe_flag = if self.is_smooth() {
+            BxDFFlags::SPECULAR
+        } else {
+            BxDFFlags::GLOSSY
+        };
+        let to_flag = |is_reflection: bool| {
+            if is_reflection {
+                lobe_flag | BxDFFlags::REFLECTION
+            } else {
+                lobe_flag | BxDFFlags::TRANSMISSION
+            }
+        };
+        if !self.is_dispersive() {
+            let (wi, f, pdf, is_reflection) =
+                self.sample_channel(wo, self.eta[0], uc, u, mode, sample_flags)?;
+            return Some(BSDFSample {
+                f: [f; 3],
+                wi,
+                pdf,
+                flag: to_flag(is_reflection),
+                pdf_is_proportional: false,
+            });
+        }
+        // trace a single channel for the dispersion. `uc` is reused to choose the lobe
+        let i_channel = ((uc * 3f32) as usize).min(2);
+        let uc = (uc * 3f32 - i_channel as f32).clamp(0f32, 1f32 - f32::EPSILON);
+        let (wi, f, pdf, is_reflection) =
+            self.sample_channel(wo, self.eta[i_channel], uc, u, mode, sample_flags)?;
+        let mut f3 = [0f32; 3];
+        f3[i_channel] = f;
+        Some(BSDFSample {
+            f: f3,
+            wi,
+            pdf: pdf / 3f32,
+            flag: to_flag(is_reflection),
+            pdf_is_proportional: !self.is_smooth(),
+        })
+    }
+
+    /// the average of the pdfs of the RGB channels
+    fn pdf(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        _mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> f32 {
+        if self.is_smooth() {
+            return 0f32;
+        }
+        if !self.is_dispersive() {
+            return self.pdf_channel(wo, wi, self.eta[0], sample_flags);
+        }
+        self.eta
+            .iter()
+            .map(|&eta| self.pdf_channel(wo, wi, eta, sample_flags))
+            .sum::<f32>()
+            / 3f32
+    }
+}
+
+// ----------------------------------------
+
+/// infinitely thin dielectric slab. The light passes through without the bending
+pub struct ThinDielectricBxDF {
+    pub eta: [f32; 3],
+}
+
+impl BxDF for ThinDielectricBxDF {
+    fn flags(&self) -> BxDFFlags {
+        BxDFFlags::REFLECTION | BxDFFlags::TRANSMISSION | BxDFFlags::SPECULAR
+    }
+
+    fn f(&self, _wo: &[f32; 3], _wi: &[f32; 3], _mode: TransportMode) -> [f32; 3] {
+        [0f32; 3]
+    }
+
+    fn sample_f(
+        &self,
+        wo: &[f32; 3],
+        uc: f32,
+        _u: &[f32; 2],
+        _mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> Option<BSDFSample> {
+        if wo[2] == 0f32 {
+            return None;
+        }
+        let r = self
+            .eta
+            .map(|eta| crate::material::thin_dielectric_reflectance(eta, wo[2]));
+        // the direction does not depend on the index of refraction, so all the channels share the choice
+        let prob_r = (r[0] + r[1] + r[2]) / 3f32;
+        let (pr, pt) = lobe_probability(prob_r, 1f32 - prob_r, sample_flags)?;
+        let cos = wo[2].abs();
+        if uc < pr {
+            return Some(BSDFSample {
+                f: r.map(|r| r / cos),
+                wi: [-wo[0], -wo[1], wo[2]],
+                pdf: pr,
+                flag: BxDFFlags::SPECULAR | BxDFFlags::REFLECTION,
+                pdf_is_proportional: false,
+            });
+        }
+        Some(BSDFSample {
+            f: r.map(|r| (1f32 - r) / cos),
+            wi: [-wo[0], -wo[1], -wo[2]],
+            pdf: pt,
+            flag: BxDFFlags::SPECULAR | BxDFFlags::TRANSMISSION,
+            pdf_is_proportional: false,
+        })
+    }
+
+    fn pdf(
+        &self,
+        _wo: &[f32; 3],
+        _wi: &[f32; 3],
+        _mode: TransportMode,
+        _sample_flags: BxDFReflTransFlags,
+    ) -> f32 {
+        0f32
+    }
+}
+
+#[test]
+fn test_bxdf_sample_pdf_consistency() {
+    use rand::Rng;
+    use rand::SeedableRng;
+    let bxdfs: Vec<Box<dyn BxDF>> = vec![
+        Box::new(DiffuseBxDF::new([0.5, 0.6, 0.7])),
+        Box::new(DiffuseTransmissionBxDF {
+            reflectance: [0.3, 0.4, 0.2],
+            transmittance: [0.5, 0.1, 0.2],
+        }),
+        Box::new(ConductorBxDF::new(
+            [1.; 3],
+            [0.2, 0.9, 1.1],
+            [3.9, 2.4, 2.1],
+            crate::microfacet::TrowbridgeReitz::new(0.3, 0.2),
+            false,
+        )),
+        Box::new(ConductorBxDF::new(
+            [1.; 3],
+            [0.2, 0.9, 1.1],
+            [3.9, 2.4, 2.1],
+            crate::microfacet::TrowbridgeReitz::new(0.8, 0.6),
+            true,
+        )),
+        Box::new(DielectricBxDF {
+            eta: [1.5; 3],
+            distrib: crate::microfacet::TrowbridgeReitz::new(0.3, 0.15),
+        }),
+    ];
+    let wo = del_geo_core::vec3::normalize(&[0.3, -0.2, 0.8]);
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    for bxdf in bxdfs.iter() {
+        for _ in 0..100 {
+            let Some(bs) = bxdf.sample_f(
+                &wo,
+                rng.random::<f32>(),
+                &[rng.random::<f32>(), rng.random::<f32>()],
+                TransportMode::Radiance,
+                BxDFReflTransFlags::ALL,
+            ) else {
+                continue;
+            };
+            let pdf = bxdf.pdf(
+                &wo,
+                &bs.wi,
+                TransportMode::Radiance,
+                BxDFReflTransFlags::ALL,
+            );
+            assert!(
+                (pdf - bs.pdf).abs() < 1.0e-3 * pdf.max(1.),
+                "{} {}",
+                pdf,
+                bs.pdf
+            );
+            let f = bxdf.f(&wo, &bs.wi, TransportMode::Radiance);
+            for (f, f_sample) in f.iter().zip(bs.f.iter()) {
+                assert!((f - f_sample).abs() < 1.0e-3 * f.max(1.));
+            }
+        }
     }
 }
@@ -0,0 +1,755 @@
+//! Statistical validation of the [`BxDF`]s, which every new material should pass.
+//! * the chi-square goodness-of-fit test of the histogram of the directions sampled by [`BxDF::sample_f`]
+//!   against the one integrated from [`BxDF::pdf`] as in pbrt and Mitsuba
+//! * the white furnace test of the energy conservation, also for the mix material resolved by
+//!   [`crate::material::choose_material`] and the random walk of [`crate::subsurface`]
+//! * the reciprocity of the reflection
+//! * the consistency of [`crate::material::sample_brdf`] with [`crate::material::eval_brdf`] and [`crate::material::pdf_brdf`]
+//!
+//! The histogram is on the grid of `(cos_theta, phi)` over the whole sphere, so every bin has the same solid angle.
+
+use crate::bxdf::{BxDF, BxDFReflTransFlags, TransportMode};
+
+/// number of the bins of `cos_theta` in `[-1, 1]`. Even so that no bin straddles the surface
+const NUM_BIN_COS: usize = 10;
+
+/// number of the bins of `phi` in `[-PI, PI]`
+const NUM_BIN_PHI: usize = 20;
+
+/// resolution of the midpoint quadrature of the pdf inside each bin
+const NUM_QUADRATURE: usize = 16;
+
+/// the bins whose expected count is smaller than this are pooled into one
+const MIN_EXPECTED_COUNT: f64 = 5.;
+
+/// the sampling is rejected if the p-value of the chi-square test is lower than this
+pub const SIGNIFICANCE_LEVEL: f64 = 1.0e-3;
+
+fn index_of_bin(w: &[f32; 3]) -> usize {
+    let cos = w[2].clamp(-1f32, 1f32);
+    let phi = w[1].atan2(w[0]);
+    let i_cos = ((cos + 1f32) * 0.5f32 * NUM_BIN_COS as f32) as usize;
+    let i_phi =
+        ((phi * 0.5f32 * std::f32::consts::FRAC_1_PI + 0.5f32) * NUM_BIN_PHI as f32) as usize;
+    i_cos.min(NUM_BIN_COS - 1) * NUM_BIN_PHI + i_phi.min(NUM_BIN_PHI - 1)
+}
+
+/// histogram of the directions sampled from `wo`
+/// # Return
+/// `None` if a delta lobe is sampled or the pdf is only proportional (e.g., the stochastic layered BxDF),
+/// where the histogram cannot be compared with the pdf
+fn sampled_histogram<RNG>(
+    bxdf: &dyn BxDF,
+    wo: &[f32; 3],
+    num_sample: usize,
+    rng: &mut RNG,
+) -> Option<Vec<f64>>
+where
+    RNG: rand::Rng,
+{
+    let mut hist = vec![0f64; NUM_BIN_COS * NUM_BIN_PHI];
+    for _ in 0..num_sample {
+        let Some(bs) = bxdf.sample_f(
+            wo,
+            rng.random::<f32>(),
+            &[rng.random::<f32>(), rng.random::<f32>()],
+            TransportMode::Radiance,
+            BxDFReflTransFlags::ALL,
+        ) else {
+            continue;
+        };
+        if bs.is_specular() || bs.pdf_is_proportional {
+            return None;
+        }
+        hist[index_of_bin(&bs.wi)] += 1f64;
+    }
+    Some(hist)
+}
+
+/// expected counts of the bins integrated from the pdf. The failed samples are not counted
+/// since the pdf integrates to the probability of the success
+fn expected_histogram(bxdf: &dyn BxDF, wo: &[f32; 3], num_sample: usize) -> Vec<f64> {
+    let d_cos = 2f64 / (NUM_BIN_COS * NUM_QUADRATURE) as f64;
+    let d_phi = 2f64 * std::f64::consts::PI / (NUM_BIN_PHI * NUM_QUADRATURE) as f64;
+    let mut hist = vec![0f64; NUM_BIN_COS * NUM_BIN_PHI];
+    for i_cos in 0..NUM_BIN_COS * NUM_QUADRATURE {
+        let cos = -1f64 + (i_cos as f64 + 0.5) * d_cos;
+        let sin = (1f64 - cos * cos).max(0f64).sqrt();
+        for i_phi in 0..NUM_BIN_PHI * NUM_QUADRATURE {
+            let phi = -std::f64::consts::PI + (i_phi as f64 + 0.5) * d_phi;
+            let wi = [
+                (sin * phi.cos()) as f32,
+                (sin * phi.sin()) as f32,
+                cos as f32,
+            ];
+            let pdf = bxdf.pdf(wo, &wi, TransportMode::Radiance, BxDFReflTransFlags::ALL);
+            let i_bin = (i_cos / NUM_QUADRATURE) * NUM_BIN_PHI + i_phi / NUM_QUADRATURE;
+            hist[i_bin] += pdf as f64 * d_cos * d_phi * num_sample as f64;
+        }
+    }
+    hist
+}
+
+/// `ln(Gamma(x))` for `x > 0` by the Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    let coeffs = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut y = x;
+    let mut ser = 1.000000000190015;
+    for c in coeffs {
+        y += 1.;
+        ser += c / y;
+    }
+    -tmp + ((2f64 * std::f64::consts::PI).sqrt() * ser / x).ln()
+}
+
+/// regularized upper incomplete gamma function `Q(a, x)`.
+/// The series is used for `x < a + 1` and the continued fraction otherwise
+fn regularized_gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0f64 {
+        return 1f64;
+    }
+    let prefactor = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1f64 {
+        let mut ap = a;
+        let mut del = 1f64 / a;
+        let mut sum = del;
+        for _ in 0..1000 {
+            ap += 1f64;
+            del *= x / ap;
+            sum += del;
+            if del.abs() < sum.abs() * 1.0e-12 {
+                break;
+            }
+        }
+        (1f64 - sum * prefactor).max(0f64)
+    } else {
+        // modified Lentz's method
+        let tiny = 1.0e-300;
+        let mut b = x + 1f64 - a;
+        let mut c = 1f64 / tiny;
+        let mut d = 1f64 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2f64;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1f64 / d;
+            let del = d * c;
+            h *= del;
+            if (del - 1f64).abs() < 1.0e-12 {
+                break;
+            }
+        }
+        prefactor * h
+    }
+}
+
+/// p-value of Pearson's chi-square test. The bins with the small expected counts are pooled
+pub fn chi2_p_value(observed: &[f64], expected: &[f64]) -> f64 {
+    assert_eq!(observed.len(), expected.len());
+    let mut chi2 = 0f64;
+    let mut num_cell = 0usize;
+    let (mut pooled_observed, mut pooled_expected) = (0f64, 0f64);
+    for (&o, &e) in observed.iter().zip(expected.iter()) {
+        if e < MIN_EXPECTED_COUNT {
+            pooled_observed += o;
+            pooled_expected += e;
+            continue;
+        }
+        chi2 += (o - e) * (o - e) / e;
+        num_cell += 1;
+    }
+    if pooled_expected > 0f64 {
+        chi2 += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+        num_cell += 1;
+    } else if pooled_observed > 0f64 {
+        // sampled where the pdf is zero
+        return 0f64;
+    }
+    if num_cell < 2 {
+        return 1f64;
+    }
+    regularized_gamma_q((num_cell - 1) as f64 * 0.5, chi2 * 0.5)
+}
+
+/// chi-square test of the directions sampled by [`BxDF::sample_f`] against [`BxDF::pdf`]
+/// * `wo` - unit vector toward the viewer in the local frame
+/// # Return
+/// the p-value, which is lower than [`SIGNIFICANCE_LEVEL`] if the sampling does not follow the pdf.
+/// `None` for the delta lobes and the BxDFs whose pdf is only proportional, where the test is not applicable
+pub fn chi2_test<RNG>(
+    bxdf: &dyn BxDF,
+    wo: &[f32; 3],
+    num_sample: usize,
+    rng: &mut RNG,
+) -> Option<f64>
+where
+    RNG: rand::Rng,
+{
+    let observed = sampled_histogram(bxdf, wo, num_sample, rng)?;
+    let expected = expected_histogram(bxdf, wo, num_sample);
+    Some(chi2_p_value(&observed, &expected))
+}
+
+/// directional albedo `\int f |cos| dw` estimated with the samples of [`BxDF::sample_f`].
+/// It should not exceed one. Use [`TransportMode::Importance`] for the refraction, where the radiance is scaled
+pub fn albedo<RNG>(
+    bxdf: &dyn BxDF,
+    wo: &[f32; 3],
+    mode: TransportMode,
+    num_sample: usize,
+    rng: &mut RNG,
+) -> [f32; 3]
+where
+    RNG: rand::Rng,
+{
+    let mut sum = [0f32; 3];
+    for _ in 0..num_sample {
+        let Some(bs) = bxdf.sample_f(
+            wo,
+            rng.random::<f32>(),
+            &[rng.random::<f32>(), rng.random::<f32>()],
+            mode,
+            BxDFReflTransFlags::ALL,
+        ) else {
+            continue;
+        };
+        let w = bs.wi[2].abs() / (bs.pdf * num_sample as f32);
+        sum = std::array::from_fn(|i| sum[i] + bs.f[i] * w);
+    }
+    sum
+}
+
+/// the largest relative difference between `f(wo, wi)` and `f(wi, wo)` for the pairs of
+/// the random directions in the upper hemisphere
+pub fn reciprocity_error<RNG>(bxdf: &dyn BxDF, num_pair: usize, rng: &mut RNG) -> f32
+where
+    RNG: rand::Rng,
+{
+    let mut max_error = 0f32;
+    for _ in 0..num_pair {
+        let wo = crate::sampling::hemisphere_zup_cos_weighted(&[
+            rng.random::<f32>(),
+            rng.random::<f32>(),
+        ]);
+        let wi = crate::sampling::hemisphere_zup_cos_weighted(&[
+            rng.random::<f32>(),
+            rng.random::<f32>(),
+        ]);
+        if wo[2] < 1.0e-3 || wi[2] < 1.0e-3 {
+            continue;
+        }
+        let a = bxdf.f(&wo, &wi, TransportMode::Radiance);
+        let b = bxdf.f(&wi, &wo, TransportMode::Radiance);
+        for (a, b) in a.iter().zip(b.iter()) {
+            let scale = a.abs().max(b.abs());
+            if scale > 0f32 {
+                max_error = max_error.max((a - b).abs() / scale);
+            }
+        }
+    }
+    max_error
+}
+
+/// the largest relative difference of the values and the pdfs of the samples of [`crate::material::sample_brdf`]
+/// from the ones evaluated by [`crate::material::eval_brdf`] and [`crate::material::pdf_brdf`].
+/// The delta lobes and the samples with the proportional pdf are skipped.
+/// Otherwise, the weight of the path and the MIS weight will be inconsistent
+pub fn sample_eval_error<RNG>(
+    mat: &crate::material::Material,
+    obj_nrm: &[f32; 3],
+    ray_in_outward: &[f32; 3],
+    num_sample: usize,
+    rng: &mut RNG,
+) -> f32
+where
+    RNG: rand::Rng,
+{
+    let relative_error = |a: f32, b: f32| (a - b).abs() / a.abs().max(1f32);
+    let mut max_error = 0f32;
+    for _ in 0..num_sample {
+        let Some(bs) = crate::material::sample_brdf(
+            mat,
+            &[],
+            &[0f32; 2],
+            obj_nrm,
+            None,
+            ray_in_outward,
+            rng,
+            0f32,
+        ) else {
+            continue;
+        };
+        if bs.is_specular() || bs.pdf_is_proportional {
+            continue;
+        }
+        let f = crate::material::eval_brdf(
+            mat,
+            &[],
+            &[0f32; 2],
+            obj_nrm,
+            None,
+            ray_in_outward,
+            &bs.wi,
+            0f32,
+        );
+        let pdf = crate::material::pdf_brdf(
+            mat,
+            &[],
+            &[0f32; 2],
+            obj_nrm,
+            None,
+            ray_in_outward,
+            &bs.wi,
+            0f32,
+        );
+        max_error = max_error.max(relative_error(pdf, bs.pdf));
+        for (a, b) in f.iter().zip(bs.f.iter()) {
+            max_error = max_error.max(relative_error(*a, *b));
+        }
+    }
+    max_error
+}
+
+// ---------------------------
+
+/// materials under the validation
+/// # Return
+/// `(name, material, is_lossless)` where `is_lossless` is true if the albedo is one
+#[cfg(test)]
+fn materials_for_test() -> Vec<(&'static str, crate::material::Material, bool)> {
+    use crate::material::*;
+    let none = usize::MAX;
+    let conductor = |multiple_scattering: bool, eta: [f32; 3], k: [f32; 3]| ConductorMaterial {
+        uroughness: 0.5,
+        vroughness: 0.4,
+        remaproughness: false,
+        reflectance: [1.; 3],
+        k,
+        eta,
+        reflectance_texture: none,
+        uroughness_texture: none,
+        vroughness_texture: none,
+        k_texture: none,
+        eta_texture: none,
+        multiple_scattering,
+    };
+    let dielectric = |uroughness: f32, vroughness: f32| DielectricMaterial {
+        uroughness,
+        vroughness,
+        remaproughness: false,
+        eta: [1.5; 3],
+        uroughness_texture: none,
+        vroughness_texture: none,
+        eta_texture: none,
+    };
+    let measured = {
+        // constant table in the MERL format
+        let n = 90 * 90 * 180;
+        let scale = [1500., 1500. / 1.15, 1500. / 1.66];
+        let table: Vec<f64> = (0..n * 3).map(|i| 0.25 * scale[i / n]).collect();
+        crate::measured_brdf::MerlBrdf::from_table(&table).unwrap()
+    };
+    vec![
+        (
+            "diffuse",
+            Material::Diff(DiffuseMaterial {
+                reflectance: [1.; 3],
+                reflectance_texture: none,
+            }),
+            true,
+        ),
+        (
+            "conductor",
+            Material::Cond(conductor(false, [0.2, 0.9, 1.1], [3.9, 2.4, 2.1])),
+            false,
+        ),
+        (
+            "conductor_multiple_scattering",
+            Material::Cond(conductor(true, [1.; 3], [1.0e3; 3])),
+            true,
+        ),
+        (
+            "rough_dielectric",
+            Material::Diel(dielectric(0.4, 0.4)),
+            false,
+        ),
+        (
+            "anisotropic_rough_dielectric",
+            Material::Diel(dielectric(0.5, 0.1)),
+            false,
+        ),
+        (
+            "smooth_dielectric",
+            Material::Diel(dielectric(0., 0.)),
+            true,
+        ),
+        (
+            "thin_dielectric",
+            Material::ThinDiel(ThinDielectricMaterial { eta: [1.5; 3] }),
+            true,
+        ),
+        (
+            "diffuse_transmission",
+            Material::DiffTrans(DiffuseTransmissionMaterial {
+                reflectance: [0.4; 3],
+                transmittance: [0.6; 3],
+                reflectance_texture: none,
+                transmittance_texture: none,
+            }),
+            true,
+        ),
+        (
+            "coated_diffuse",
+            Material::CoaDiff(CoatedDiffuse {
+                uroughness: 0.3,
+                vroughness: 0.3,
+                reflectance: [0.5, 0.6, 0.7],
+                remaproughness: false,
+                thickness: 0.01,
+                eta: 1.5,
+                albedo: [0.; 3],
+                g: 0.,
+                maxdepth: 10,
+                nsamples: 1,
+                reflectance_texture: none,
+                uroughness_texture: none,
+                vroughness_texture: none,
+            }),
+            false,
+        ),
+        (
+            "coated_conductor",
+            Material::CoaCond(CoatedConductor {
+                interface_uroughness: 0.2,
+                interface_vroughness: 0.2,
+                interface_eta: 1.5,
+                conductor_uroughness: 0.4,
+                conductor_vroughness: 0.4,
+                conductor_eta: [0.2, 0.9, 1.1],
+                k: [3.9, 2.4, 2.1],
+                remaproughness: false,
+                thickness: 0.01,
+                albedo: [0.; 3],
+                g: 0.,
+                maxdepth: 10,
+                nsamples: 1,
+                interface_uroughness_texture: none,
+                interface_vroughness_texture: none,
+                conductor_uroughness_texture: none,
+                conductor_vroughness_texture: none,
+                reflectance_texture: none,
+                conductor_eta_texture: none,
+                k_texture: none,
+            }),
+            false,
+        ),
+        (
+            "measured",
+            Material::Measured(MeasuredMaterial {
+                brdf: std::sync::Arc::new(measured),
+            }),
+            false,
+        ),
+        (
+            "principled",
+            Material::Principled(PrincipledMaterial::from_metallic_roughness(
+                [0.5, 0.6, 0.7],
+                0.,
+                0.6,
+            )),
+            false,
+        ),
+        (
+            "principled_metal",
+            Material::Principled(PrincipledMaterial::from_metallic_roughness(
+                [0.9, 0.7, 0.5],
+                1.,
+                0.6,
+            )),
+            false,
+        ),
+        (
+            "principled_transmission_clearcoat",
+            Material::Principled(PrincipledMaterial {
+                transmission: 0.7,
+                clearcoat: 0.5,
+                clearcoat_gloss: 0.,
+                ..PrincipledMaterial::from_metallic_roughness([0.8; 3], 0., 0.6)
+            }),
+            false,
+        ),
+    ]
+}
+
+/// BxDFs of [`materials_for_test`] and the hair without the absorption
+#[cfg(test)]
+fn bxdfs_for_test() -> Vec<(&'static str, Box<dyn BxDF>, bool)> {
+    let mut bxdfs: Vec<(&'static str, Box<dyn BxDF>, bool)> = materials_for_test()
+        .into_iter()
+        .map(|(name, mat, is_lossless)| {
+            let bxdf = crate::material::bxdf(&mat, &[], &[0.; 2], None, 0.).unwrap();
+            (name, Box::new(bxdf) as Box<dyn BxDF>, is_lossless)
+        })
+        .collect();
+    bxdfs.push((
+        "hair",
+        Box::new(crate::hair_bsdf::HairBxDF::new(
+            0.3, 1.55, [0.; 3], 0.4, 0.4, 2.,
+        )),
+        true,
+    ));
+    bxdfs
+}
+
+#[cfg(test)]
+fn directions_for_test() -> [[f32; 3]; 2] {
+    [
+        del_geo_core::vec3::normalize(&[0.3, -0.2, 0.8]),
+        del_geo_core::vec3::normalize(&[0.8, 0.3, 0.3]),
+    ]
+}
+
+#[test]
+fn test_chi2_p_value() {
+    // Q(1, x) = exp(-x)
+    for x in [0.1, 1., 5.] {
+        assert!((regularized_gamma_q(1., x) - (-x).exp()).abs() < 1.0e-8);
+    }
+    // Q(a, 0) = 1 and the median of the chi-square distribution of 10 degrees of freedom is 9.342
+    assert!((regularized_gamma_q(3., 0.) - 1.).abs() < 1.0e-8);
+    assert!((regularized_gamma_q(5., 9.342 * 0.5) - 0.5).abs() < 1.0e-3);
+    let expected = vec![100.; 10];
+    assert!(chi2_p_value(&expected, &expected) > 0.99);
+    let mut observed = expected.clone();
+    observed[0] = 200.;
+    observed[1] = 0.;
+    assert!(chi2_p_value(&observed, &expected) < SIGNIFICANCE_LEVEL);
+}
+
+#[test]
+fn test_bxdf_chi2() {
+    use rand::SeedableRng;
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    for (name, bxdf, _) in bxdfs_for_test() {
+        for wo in directions_for_test() {
+            match chi2_test(bxdf.as_ref(), &wo, 20000, &mut rng) {
+                Some(p_value) => assert!(p_value > SIGNIFICANCE_LEVEL, "{} {}", name, p_value),
+                None => assert!(
+                    bxdf.flags().is_specular() || name.starts_with("coated"),
+                    "{}",
+                    name
+                ),
+            }
+        }
+    }
+    // the pdf of the diffuse lobe given for the conductor is rejected
+    struct WrongPdf(crate::bxdf::ConductorBxDF);
+    impl BxDF for WrongPdf {
+        fn flags(&self) -> crate::bxdf::BxDFFlags {
+            self.0.flags()
+        }
+        fn f(&self, wo: &[f32; 3], wi: &[f32; 3], mode: TransportMode) -> [f32; 3] {
+            self.0.f(wo, wi, mode)
+        }
+        fn sample_f(
+            &self,
+            wo: &[f32; 3],
+            uc: f32,
+            u: &[f32; 2],
+            mode: TransportMode,
+            sample_flags: BxDFReflTransFlags,
+        ) -> Option<crate::bxdf::BSDFSample> {
+            self.0.sample_f(wo, uc, u, mode, sample_flags)
+        }
+        fn pdf(
+            &self,
+            _wo: &[f32; 3],
+            wi: &[f32; 3],
+            _: TransportMode,
+            _: BxDFReflTransFlags,
+        ) -> f32 {
+            crate::sampling::pdf_hemisphere_cos(wi[2].max(0.))
+        }
+    }
+    let wrong = WrongPdf(crate::bxdf::ConductorBxDF::new(
+        [1.; 3],
+        [0.2, 0.9, 1.1],
+        [3.9, 2.4, 2.1],
+        crate::microfacet::TrowbridgeReitz::new(0.3, 0.3),
+        false,
+    ));
+    let p_value = chi2_test(&wrong, &directions_for_test()[0], 20000, &mut rng).unwrap();
+    assert!(p_value < SIGNIFICANCE_LEVEL, "{}", p_value);
+}
+
+#[test]
+fn test_bxdf_white_furnace() {
+    use rand::SeedableRng;
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    for (name, bxdf, is_lossless) in bxdfs_for_test() {
+        for wo in directions_for_test() {
+            let albedo = albedo(
+                bxdf.as_ref(),
+                &wo,
+                TransportMode::Importance,
+                20000,
+                &mut rng,
+            );
+            for a in albedo {
+                assert!(a < 1.03, "{} {:?}", name, albedo);
+                if is_lossless {
+                    assert!((a - 1.).abs() < 0.03, "{} {:?}", name, albedo);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_bxdf_reciprocity() {
+    use rand::SeedableRng;
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    for (name, bxdf, _) in bxdfs_for_test() {
+        // the layered BxDFs are evaluated stochastically and
+        // the offset `h` of the hair is defined relative to `wo`
+        if name.starts_with("coated") || name == "hair" {
+            continue;
+        }
+        let error = reciprocity_error(bxdf.as_ref(), 1000, &mut rng);
+        assert!(error < 1.0e-3, "{} {}", name, error);
+    }
+}
+
+#[test]
+fn test_material_sample_eval_consistency() {
+    use rand::SeedableRng;
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    let obj_nrm = del_geo_core::vec3::normalize(&[0.2, -0.3, 0.9]);
+    for (name, mat, _) in materials_for_test() {
+        for ray_in in [
+            del_geo_core::vec3::normalize(&[0.5, 0.1, 0.8]),
+            del_geo_core::vec3::normalize(&[-0.6, 0.2, 0.5]),
+        ] {
+            let error = sample_eval_error(&mat, &obj_nrm, &ray_in, 1000, &mut rng);
+            assert!(error < 1.0e-3, "{} {}", name, error);
+        }
+    }
+}
+
+#[test]
+fn test_material_flags() {
+    // the flags computed from the material agree with those of the BxDF
+    for (name, mat, _) in materials_for_test() {
+        let flags = crate::material::flags(std::slice::from_ref(&mat), 0, &[], &[0.; 2], 0.);
+        let bxdf = crate::material::bxdf(&mat, &[], &[0.; 2], None, 0.).unwrap();
+        assert!(flags == bxdf.flags(), "{}", name);
+    }
+}
+
+#[test]
+fn test_mix_white_furnace() {
+    use crate::material::*;
+    use rand::Rng;
+    use rand::SeedableRng;
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    // the mix of the lossless materials chooses the second one with the probability `amount`
+    let amount = 0.3;
+    let materials = [
+        Material::Mix(MixMaterial {
+            materials: [1, 2],
+            amount,
+            amount_texture: usize::MAX,
+        }),
+        Material::Diff(DiffuseMaterial {
+            reflectance: [1.; 3],
+            reflectance_texture: usize::MAX,
+        }),
+        Material::ThinDiel(ThinDielectricMaterial { eta: [1.5; 3] }),
+    ];
+    let num_sample = 20000;
+    let mut num_second = 0;
+    let mut sum = [0f32; 3];
+    for _ in 0..num_sample {
+        // the component is resolved from the position and the direction at the shading point
+        let pos: [f32; 3] = std::array::from_fn(|_| rng.random::<f32>());
+        let obj_nrm = del_geo_core::sphere::sample(&[rng.random::<f32>(), rng.random::<f32>()]);
+        let ray_in = crate::sampling::hemisphere_cos_weighted(
+            &obj_nrm,
+            &[rng.random::<f32>(), rng.random::<f32>()],
+        );
+        let mat = choose_material(&materials, 0, &[], &[0.; 2], &pos, &ray_in);
+        if std::ptr::eq(mat, &materials[2]) {
+            num_second += 1;
+        }
+        let Some(bs) = sample_brdf(mat, &[], &[0.; 2], &obj_nrm, None, &ray_in, &mut rng, 0.)
+        else {
+            continue;
+        };
+        let w = del_geo_core::vec3::dot(&bs.wi, &obj_nrm).abs() / (bs.pdf * num_sample as f32);
+        sum = std::array::from_fn(|i| sum[i] + bs.f[i] * w);
+    }
+    let ratio = num_second as f32 / num_sample as f32;
+    assert!((ratio - amount).abs() < 0.02, "{}", ratio);
+    for a in sum {
+        assert!((a - 1.).abs() < 0.03, "{:?}", sum);
+    }
+}
+
+#[test]
+fn test_subsurface_white_furnace() {
+    use crate::material::{SubsurfaceBoundary, SubsurfaceMaterial};
+    use rand::Rng;
+    use rand::SeedableRng;
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    let se = crate::shape::ShapeEntity::new_untransformed(crate::shape::ShapeType::Sphere {
+        radius: 1.,
+    });
+    let hit_nrm = [0f32, 1., 0.];
+    for boundary in [SubsurfaceBoundary::Diffuse, SubsurfaceBoundary::Dielectric] {
+        for (sigma_a, is_lossless) in [(0., true), (0.3, false)] {
+            let mat = SubsurfaceMaterial {
+                sigma_a: [sigma_a; 3],
+                sigma_s: [2.; 3],
+                g: 0.5,
+                eta: 1.5,
+                boundary,
+            };
+            let num_sample = 2000;
+            let mut sum = [0f32; 3];
+            for _ in 0..num_sample {
+                let ray_in = crate::sampling::hemisphere_cos_weighted(
+                    &hit_nrm,
+                    &[rng.random::<f32>(), rng.random::<f32>()],
+                );
+                let (_, _, _, weight) = crate::subsurface::sample_random_walk(
+                    &se, &mat, &hit_nrm, &hit_nrm, &ray_in, &mut rng,
+                );
+                sum = std::array::from_fn(|i| sum[i] + weight[i] / num_sample as f32);
+            }
+            for a in sum {
+                if is_lossless {
+                    assert!((a - 1.).abs() < 1.0e-3, "{:?} {:?}", boundary, sum);
+                } else {
+                    assert!(a < 0.9, "{:?} {:?}", boundary, sum);
+                }
+            }
+        }
+    }
+}
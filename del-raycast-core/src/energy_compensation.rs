@@ -0,0 +1,218 @@
+//! Energy compensation of the multiple scattering between the microfacets of the rough conductor.
+//! The energy lost by the single-scattering model is added back as the diffuse-like lobe
+//! using the precomputed directional albedo of the Trowbridge-Reitz distribution.
+//! See "Revisiting Physically Based Shading at Imageworks" by Christopher Kulla and Alejandro Conty 2017
+
+/// number of the samples of the cosine and the alpha in the albedo table
+const NUM_DIV: usize = 32;
+
+/// directional albedo of the single-scattering Trowbridge-Reitz BRDF without the Fresnel term
+struct AlbedoTable {
+    /// `e[i_alpha * NUM_DIV + i_mu]`
+    e: Vec<f32>,
+    /// cosine-weighted average of the albedo over the hemisphere for each alpha
+    e_avg: Vec<f32>,
+}
+
+/// `i`-th sample of the cosine or the alpha in the albedo table
+fn table_coordinate(i: usize) -> f32 {
+    i as f32 / (NUM_DIV - 1) as f32
+}
+
+impl AlbedoTable {
+    /// the albedo is integrated with the visible normal sampling on the stratified grid
+    fn new() -> Self {
+        let num_stratum = 32;
+        let mut e = vec![1f32; NUM_DIV * NUM_DIV];
+        // the smooth surface (i_alpha == 0) has the unit albedo
+        for i_alpha in 1..NUM_DIV {
+            let alpha = table_coordinate(i_alpha);
+            let distrib = crate::microfacet::TrowbridgeReitz::new(alpha, alpha);
+            for i_mu in 0..NUM_DIV {
+                let mu = table_coordinate(i_mu).max(1.0e-3);
+                let wo = [(1f32 - mu * mu).sqrt(), 0f32, mu];
+                let g1_o = distrib.g1(&wo);
+                let mut sum = 0f32;
+                for i_u in 0..num_stratum * num_stratum {
+                    let u = [
+                        ((i_u % num_stratum) as f32 + 0.5) / num_stratum as f32,
+                        ((i_u / num_stratum) as f32 + 0.5) / num_stratum as f32,
+                    ];
+                    let wm = distrib.sample_wm(&wo, &u);
+                    let wo_dot_wm = del_geo_core::vec3::dot(&wo, &wm);
+                    let wi =
+                        del_geo_core::vec3::axpy(-1f32, &wo, &wm.map(|v| v * 2f32 * wo_dot_wm));
+                    if wi[2] <= 0f32 {
+                        continue;
+                    }
+                    // f * cos / pdf of the visible normal sampling
+                    sum += distrib.g(&wo, &wi) / g1_o;
+                }
+                e[i_alpha * NUM_DIV + i_mu] = (sum / (num_stratum * num_stratum) as f32).min(1f32);
+            }
+        }
+        let e_avg = (0..NUM_DIV)
+            .map(|i_alpha| {
+                // 2 \int E(mu) mu dmu with the trapezoidal rule
+                let row = &e[i_alpha * NUM_DIV..(i_alpha + 1) * NUM_DIV];
+                let h = 1f32 / (NUM_DIV - 1) as f32;
+                let sum: f32 = (0..NUM_DIV - 1)
+                    .map(|i| {
+                        let (mu0, mu1) = (table_coordinate(i), table_coordinate(i + 1));
+                        0.5f32 * (row[i] * mu0 + row[i + 1] * mu1) * h
+                    })
+                    .sum();
+                (2f32 * sum).min(1f32)
+            })
+            .collect();
+        AlbedoTable { e, e_avg }
+    }
+
+    /// position in the table. The values outside `[0, 1]` are clamped,
+    /// so the alpha larger than one uses the albedo of `alpha == 1` and the compensation of
+    /// such a very rough surface is slightly under-estimated
+    fn locate(v: f32) -> (usize, f32) {
+        let x = v.clamp(0f32, 1f32) * (NUM_DIV - 1) as f32;
+        let i = (x.floor() as usize).min(NUM_DIV - 2);
+        (i, x - i as f32)
+    }
+
+    fn albedo(&self, mu: f32, alpha: f32) -> f32 {
+        let (ia, ra) = Self::locate(alpha);
+        let (im, rm) = Self::locate(mu);
+        let e = |ia: usize, im: usize| self.e[ia * NUM_DIV + im];
+        (1f32 - ra) * ((1f32 - rm) * e(ia, im) + rm * e(ia, im + 1))
+            + ra * ((1f32 - rm) * e(ia + 1, im) + rm * e(ia + 1, im + 1))
+    }
+
+    fn albedo_average(&self, alpha: f32) -> f32 {
+        let (ia, ra) = Self::locate(alpha);
+        (1f32 - ra) * self.e_avg[ia] + ra * self.e_avg[ia + 1]
+    }
+}
+
+fn albedo_table() -> &'static AlbedoTable {
+    static TABLE: std::sync::OnceLock<AlbedoTable> = std::sync::OnceLock::new();
+    TABLE.get_or_init(AlbedoTable::new)
+}
+
+/// alpha of the isotropic distribution that approximates the anisotropic one
+fn isotropic_alpha(distrib: &crate::microfacet::TrowbridgeReitz) -> f32 {
+    (distrib.alpha_x * distrib.alpha_y).sqrt()
+}
+
+/// directional albedo of the single-scattering BRDF without the Fresnel term.
+/// The table covers `alpha` in `[0, 1]` and the larger alpha is clamped to one
+/// * `mu` - cosine of the direction with the normal
+pub fn albedo(mu: f32, distrib: &crate::microfacet::TrowbridgeReitz) -> f32 {
+    albedo_table().albedo(mu, isotropic_alpha(distrib))
+}
+
+/// cosine-weighted average of the Fresnel reflectance of the conductor over the hemisphere.
+/// This is constant for the material, so compute it once when the BxDF is constructed
+pub fn fresnel_average_conductor(eta: &[f32; 3], k: &[f32; 3]) -> [f32; 3] {
+    let num_div = 32;
+    let mut sum = [0f32; 3];
+    for i in 0..num_div {
+        let mu = (i as f32 + 0.5f32) / num_div as f32;
+        let f = crate::material::fresnel_conductor_reflectance_rgb(eta, k, mu);
+        sum = std::array::from_fn(|j| sum[j] + 2f32 * f[j] * mu / num_div as f32);
+    }
+    sum
+}
+
+/// the lobe compensating the energy lost by [`crate::material::eval_brdf_rough_conductor`]
+/// * `wo`, `wi` - unit vectors in the local frame whose z-axis is the normal
+/// * `fresnel_avg` - computed by [`fresnel_average_conductor`]
+pub fn eval_brdf_multiple_scattering_conductor(
+    wo: &[f32; 3],
+    wi: &[f32; 3],
+    reflectance: &[f32; 3],
+    fresnel_avg: &[f32; 3],
+    distrib: &crate::microfacet::TrowbridgeReitz,
+) -> [f32; 3] {
+    if wo[2] <= 0f32 || wi[2] <= 0f32 || distrib.effectively_smooth() {
+        return [0f32; 3];
+    }
+    let table = albedo_table();
+    let alpha = isotropic_alpha(distrib);
+    let e_avg = table.albedo_average(alpha);
+    if e_avg >= 1f32 {
+        return [0f32; 3];
+    }
+    let e_o = table.albedo(wo[2], alpha);
+    let e_i = table.albedo(wi[2], alpha);
+    let f_ms = (1f32 - e_o) * (1f32 - e_i) / (std::f32::consts::PI * (1f32 - e_avg));
+    // the energy reflected after the multiple bounces, each of which is attenuated by the Fresnel
+    std::array::from_fn(|i| {
+        let f_avg = fresnel_avg[i] * reflectance[i];
+        f_ms * f_avg * f_avg * e_avg / (1f32 - f_avg * (1f32 - e_avg))
+    })
+}
+
+#[test]
+fn test_albedo_table() {
+    // the albedo is one for the smooth surface and it decreases as the roughness increases.
+    // At the grazing angles the albedo is not monotonic in the roughness
+    let table = albedo_table();
+    for i_mu in 0..NUM_DIV {
+        let mu = table_coordinate(i_mu);
+        assert!((table.albedo(mu, 0.) - 1.).abs() < 1.0e-5);
+        for i_alpha in 1..NUM_DIV {
+            let e = table.albedo(mu, table_coordinate(i_alpha));
+            assert!((0f32..=1f32).contains(&e));
+        }
+        if mu < 0.5 {
+            continue;
+        }
+        for i_alpha in 1..NUM_DIV {
+            let alpha = table_coordinate(i_alpha);
+            let e0 = table.albedo(mu, table_coordinate(i_alpha - 1));
+            assert!(table.albedo(mu, alpha) <= e0 + 1.0e-2);
+        }
+    }
+    assert!(table.albedo_average(1.) < 0.8);
+    // the alpha larger than one is clamped
+    assert_eq!(table.albedo(0.5, 1.5), table.albedo(0.5, 1.));
+    // the Fresnel of the perfect conductor is one
+    let f_avg = fresnel_average_conductor(&[1.; 3], &[1.0e3; 3]);
+    assert!(f_avg.iter().all(|&v| (v - 1.).abs() < 1.0e-2));
+}
+
+#[test]
+fn test_white_furnace_rough_conductor() {
+    use crate::bxdf::{BxDF, BxDFReflTransFlags, TransportMode};
+    use rand::Rng;
+    use rand::SeedableRng;
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    let wo = del_geo_core::vec3::normalize(&[0.6, 0.2, 0.5]);
+    for multiple_scattering in [false, true] {
+        // the Fresnel reflectance of this conductor is almost one
+        let bxdf = crate::bxdf::ConductorBxDF::new(
+            [1.; 3],
+            [1.; 3],
+            [1.0e3; 3],
+            crate::microfacet::TrowbridgeReitz::new(1.0, 1.0),
+            multiple_scattering,
+        );
+        let num_sample = 100000;
+        let mut sum = 0f32;
+        for _ in 0..num_sample {
+            let Some(bs) = bxdf.sample_f(
+                &wo,
+                rng.random::<f32>(),
+                &[rng.random::<f32>(), rng.random::<f32>()],
+                TransportMode::Radiance,
+                BxDFReflTransFlags::ALL,
+            ) else {
+                continue;
+            };
+            sum += bs.f[0] * bs.wi[2].abs() / bs.pdf / num_sample as f32;
+        }
+        if multiple_scattering {
+            assert!((sum - 1.).abs() < 0.03, "{}", sum);
+        } else {
+            assert!(sum < 0.9, "{}", sum);
+        }
+    }
+}
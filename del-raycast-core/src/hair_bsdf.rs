@@ -0,0 +1,667 @@
+//! Hair scattering model of pbrt-v4 with the longitudinal lobes of d'Eon et al. 2011 and
+//! the azimuthal lobes of "A Practical and Controllable Hair and Fur Model for Production Path Tracing"
+//! by Chiang et al. 2016.
+//! See <https://pbr-book.org/4ed/Reflection_Models/Scattering_from_Hair>
+//!
+//! As in pbrt, `wo` is the unit vector toward the viewer and `wi` is the one toward the light.
+//! Both are in the local frame whose x-axis is the tangent of the fiber and z-axis is the normal.
+
+use crate::bxdf::{BSDFSample, BxDF, BxDFFlags, BxDFReflTransFlags, TransportMode};
+
+/// number of the explicit lobes (R, TT, TRT). The remaining higher order scattering is one lobe
+const P_MAX: usize = 3;
+
+/// absorption coefficients of the eumelanin and the pheomelanin
+const EUMELANIN_SIGMA_A: [f32; 3] = [0.419, 0.697, 1.37];
+const PHEOMELANIN_SIGMA_A: [f32; 3] = [0.187, 0.4, 1.05];
+
+#[derive(Clone, Copy)]
+pub struct HairBxDF {
+    /// offset across the fiber in `[-1, 1]`
+    pub h: f32,
+    /// index of refraction of the fiber
+    pub eta: f32,
+    /// absorption coefficient inside the fiber normalized by the radius
+    pub sigma_a: [f32; 3],
+    /// variances of the longitudinal lobes
+    v: [f32; P_MAX + 1],
+    /// logistic scale of the azimuthal lobes
+    s: f32,
+    /// `sin(2^k alpha)` and `cos(2^k alpha)` for the tilt of the cuticle scales
+    sin_2k_alpha: [f32; 3],
+    cos_2k_alpha: [f32; 3],
+}
+
+/// absorption coefficient from the concentrations of the eumelanin and the pheomelanin
+pub fn sigma_a_from_concentration(ce: f32, cp: f32) -> [f32; 3] {
+    std::array::from_fn(|i| ce * EUMELANIN_SIGMA_A[i] + cp * PHEOMELANIN_SIGMA_A[i])
+}
+
+/// absorption coefficient that gives the color `c` after the multiple scattering
+pub fn sigma_a_from_reflectance(c: &[f32; 3], beta_n: f32) -> [f32; 3] {
+    let b = beta_n;
+    let denom = 5.969f32 - 0.215f32 * b + 2.532f32 * b.powi(2) - 10.73f32 * b.powi(3)
+        + 5.574f32 * b.powi(4)
+        + 0.245f32 * b.powi(5);
+    c.map(|c| {
+        let v = c.max(1.0e-6).ln() / denom;
+        v * v
+    })
+}
+
+/// modified Bessel function of the first kind
+fn bessel_i0(x: f32) -> f32 {
+    let mut val = 0f32;
+    let mut x2i = 1f32;
+    let mut ifact = 1f32;
+    let mut i4 = 1f32;
+    for i in 0..10 {
+        if i > 1 {
+            ifact *= i as f32;
+        }
+        val += x2i / (i4 * ifact * ifact);
+        x2i *= x * x;
+        i4 *= 4f32;
+    }
+    val
+}
+
+fn log_bessel_i0(x: f32) -> f32 {
+    if x > 12f32 {
+        x + 0.5f32 * (-(2f32 * std::f32::consts::PI).ln() + (1f32 / x).ln() + 1f32 / (8f32 * x))
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+/// longitudinal scattering function
+fn mp(cos_theta_i: f32, cos_theta_o: f32, sin_theta_i: f32, sin_theta_o: f32, v: f32) -> f32 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1f32 {
+        (log_bessel_i0(a) - b - 1f32 / v + std::f32::consts::LN_2 + (1f32 / (2f32 * v)).ln()).exp()
+    } else {
+        (-b).exp() * bessel_i0(a) / ((1f32 / v).sinh() * 2f32 * v)
+    }
+}
+
+/// attenuation of each lobe by the Fresnel reflection and the absorption
+fn ap(cos_theta_o: f32, eta: f32, h: f32, t: &[f32; 3]) -> [[f32; 3]; P_MAX + 1] {
+    let cos_gamma_o = (1f32 - h * h).max(0f32).sqrt();
+    let cos_theta = cos_theta_o * cos_gamma_o;
+    let f = crate::material::fresnel_dielectric(eta, cos_theta);
+    let mut ap = [[0f32; 3]; P_MAX + 1];
+    ap[0] = [f; 3];
+    ap[1] = t.map(|t| (1f32 - f) * (1f32 - f) * t);
+    for p in 2..P_MAX {
+        ap[p] = std::array::from_fn(|i| ap[p - 1][i] * t[i] * f);
+    }
+    ap[P_MAX] = std::array::from_fn(|i| {
+        let denom = 1f32 - t[i] * f;
+        if denom > 0f32 {
+            ap[P_MAX - 1][i] * f * t[i] / denom
+        } else {
+            0f32
+        }
+    });
+    ap
+}
+
+/// azimuthal deflection of the `p`-th lobe
+fn phi(p: usize, gamma_o: f32, gamma_t: f32) -> f32 {
+    2f32 * p as f32 * gamma_t - 2f32 * gamma_o + p as f32 * std::f32::consts::PI
+}
+
+fn logistic(x: f32, s: f32) -> f32 {
+    let x = x.abs();
+    let e = (-x / s).exp();
+    e / (s * (1f32 + e) * (1f32 + e))
+}
+
+fn logistic_cdf(x: f32, s: f32) -> f32 {
+    1f32 / (1f32 + (-x / s).exp())
+}
+
+/// logistic distribution restricted to `[-PI, PI]`
+fn trimmed_logistic(x: f32, s: f32) -> f32 {
+    use std::f32::consts::PI;
+    logistic(x, s) / (logistic_cdf(PI, s) - logistic_cdf(-PI, s))
+}
+
+fn sample_trimmed_logistic(u: f32, s: f32) -> f32 {
+    use std::f32::consts::PI;
+    let k = logistic_cdf(PI, s) - logistic_cdf(-PI, s);
+    let x = -s * (1f32 / (u * k + logistic_cdf(-PI, s)) - 1f32).ln();
+    x.clamp(-PI, PI)
+}
+
+/// azimuthal scattering function
+fn np(phi_: f32, p: usize, s: f32, gamma_o: f32, gamma_t: f32) -> f32 {
+    use std::f32::consts::PI;
+    let mut dphi = phi_ - phi(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2f32 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2f32 * PI;
+    }
+    trimmed_logistic(dphi, s)
+}
+
+fn safe_asin(x: f32) -> f32 {
+    x.clamp(-1f32, 1f32).asin()
+}
+
+fn safe_sqrt(x: f32) -> f32 {
+    x.max(0f32).sqrt()
+}
+
+impl HairBxDF {
+    /// * `h` - offset across the fiber in `[-1, 1]`
+    /// * `beta_m` - longitudinal roughness
+    /// * `beta_n` - azimuthal roughness
+    /// * `alpha` - tilt of the cuticle scales in degree
+    pub fn new(h: f32, eta: f32, sigma_a: [f32; 3], beta_m: f32, beta_n: f32, alpha: f32) -> Self {
+        let v0 = {
+            let v = 0.726f32 * beta_m + 0.812f32 * beta_m * beta_m + 3.7f32 * beta_m.powi(20);
+            v * v
+        };
+        let v = [v0, 0.25f32 * v0, 4f32 * v0, 4f32 * v0];
+        let s = (std::f32::consts::PI / 8f32).sqrt()
+            * (0.265f32 * beta_n + 1.194f32 * beta_n * beta_n + 5.372f32 * beta_n.powi(22));
+        let mut sin_2k_alpha = [0f32; 3];
+        let mut cos_2k_alpha = [0f32; 3];
+        sin_2k_alpha[0] = alpha.to_radians().sin();
+        cos_2k_alpha[0] = safe_sqrt(1f32 - sin_2k_alpha[0] * sin_2k_alpha[0]);
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2f32 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1] * cos_2k_alpha[i - 1]
+                - sin_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+        }
+        HairBxDF {
+            h: h.clamp(-1f32, 1f32),
+            eta,
+            sigma_a,
+            v,
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    /// BxDF of the material at the offset `h` across the fiber
+    pub fn from_material(mat: &crate::material::HairMaterial, h: f32) -> Self {
+        Self::new(h, mat.eta, mat.sigma_a, mat.beta_m, mat.beta_n, mat.alpha)
+    }
+
+    /// longitudinal angle of `wo` tilted by the cuticle scales for the `p`-th lobe
+    /// # Return
+    /// `(sin_theta, cos_theta)`
+    fn tilt(&self, p: usize, sin_theta_o: f32, cos_theta_o: f32) -> (f32, f32) {
+        let (s, c) = (self.sin_2k_alpha, self.cos_2k_alpha);
+        let (sin_thetap_o, cos_thetap_o) = match p {
+            0 => (
+                sin_theta_o * c[1] - cos_theta_o * s[1],
+                cos_theta_o * c[1] + sin_theta_o * s[1],
+            ),
+            1 => (
+                sin_theta_o * c[0] + cos_theta_o * s[0],
+                cos_theta_o * c[0] - sin_theta_o * s[0],
+            ),
+            2 => (
+                sin_theta_o * c[2] + cos_theta_o * s[2],
+                cos_theta_o * c[2] - sin_theta_o * s[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin_thetap_o, cos_thetap_o.abs())
+    }
+
+    /// `(gamma_t, transmittance for the single pass inside the fiber)`
+    fn refracted(&self, sin_theta_o: f32, cos_theta_o: f32) -> (f32, [f32; 3]) {
+        let etap = safe_sqrt(self.eta * self.eta - sin_theta_o * sin_theta_o) / cos_theta_o;
+        let sin_gamma_t = self.h / etap;
+        let cos_gamma_t = safe_sqrt(1f32 - sin_gamma_t * sin_gamma_t);
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1f32 - sin_theta_t * sin_theta_t);
+        let t = self
+            .sigma_a
+            .map(|sa| (-sa * (2f32 * cos_gamma_t / cos_theta_t)).exp());
+        (safe_asin(sin_gamma_t), t)
+    }
+
+    /// probabilities to choose the lobes
+    fn ap_pdf(&self, sin_theta_o: f32, cos_theta_o: f32) -> [f32; P_MAX + 1] {
+        let (_, t) = self.refracted(sin_theta_o, cos_theta_o);
+        let ap = ap(cos_theta_o, self.eta, self.h, &t);
+        let ap = ap.map(|a| (a[0] + a[1] + a[2]) / 3f32);
+        let sum = ap.iter().sum::<f32>();
+        if sum <= 0f32 {
+            return [1f32 / (P_MAX + 1) as f32; P_MAX + 1];
+        }
+        ap.map(|a| a / sum)
+    }
+}
+
+impl BxDF for HairBxDF {
+    fn flags(&self) -> BxDFFlags {
+        BxDFFlags::GLOSSY | BxDFFlags::REFLECTION | BxDFFlags::TRANSMISSION
+    }
+
+    fn f(&self, wo: &[f32; 3], wi: &[f32; 3], _mode: TransportMode) -> [f32; 3] {
+        let sin_theta_o = wo[0];
+        let cos_theta_o = safe_sqrt(1f32 - sin_theta_o * sin_theta_o);
+        let phi_o = wo[2].atan2(wo[1]);
+        let gamma_o = safe_asin(self.h);
+        let sin_theta_i = wi[0];
+        let cos_theta_i = safe_sqrt(1f32 - sin_theta_i * sin_theta_i);
+        let phi_i = wi[2].atan2(wi[1]);
+        let (gamma_t, t) = self.refracted(sin_theta_o, cos_theta_o);
+        let phi_ = phi_i - phi_o;
+        let ap = ap(cos_theta_o, self.eta, self.h, &t);
+        let mut fsum = [0f32; 3];
+        for (p, ap) in ap.iter().enumerate().take(P_MAX) {
+            let (sin_thetap_o, cos_thetap_o) = self.tilt(p, sin_theta_o, cos_theta_o);
+            let w = mp(
+                cos_theta_i,
+                cos_thetap_o,
+                sin_theta_i,
+                sin_thetap_o,
+                self.v[p],
+            ) * np(phi_, p, self.s, gamma_o, gamma_t);
+            for i in 0..3 {
+                fsum[i] += w * ap[i];
+            }
+        }
+        let w = mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        ) / (2f32 * std::f32::consts::PI);
+        for i in 0..3 {
+            fsum[i] += w * ap[P_MAX][i];
+        }
+        if wi[2].abs() > 0f32 {
+            fsum = fsum.map(|v| v / wi[2].abs());
+        }
+        fsum
+    }
+
+    fn sample_f(
+        &self,
+        wo: &[f32; 3],
+        uc: f32,
+        u: &[f32; 2],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> Option<BSDFSample> {
+        if sample_flags != BxDFReflTransFlags::ALL {
+            return None;
+        }
+        let sin_theta_o = wo[0];
+        let cos_theta_o = safe_sqrt(1f32 - sin_theta_o * sin_theta_o);
+        let phi_o = wo[2].atan2(wo[1]);
+        let gamma_o = safe_asin(self.h);
+        // choose the lobe and remap `uc` for the azimuthal sampling
+        let ap_pdf = self.ap_pdf(sin_theta_o, cos_theta_o);
+        let mut uc = uc;
+        let mut p = P_MAX;
+        for (i, &pdf) in ap_pdf.iter().enumerate() {
+            if uc < pdf || i == P_MAX {
+                p = i;
+                uc = (uc / pdf).clamp(0f32, 1f32 - f32::EPSILON);
+                break;
+            }
+            uc -= pdf;
+        }
+        // sample the longitudinal angle
+        let (sin_thetap_o, cos_thetap_o) = self.tilt(p, sin_theta_o, cos_theta_o);
+        let v = self.v[p];
+        let cos_theta = 1f32 + v * (u[0].max(1.0e-5) + (1f32 - u[0]) * (-2f32 / v).exp()).ln();
+        let sin_theta = safe_sqrt(1f32 - cos_theta * cos_theta);
+        let cos_phi = (2f32 * std::f32::consts::PI * u[1]).cos();
+        let sin_theta_i = -cos_theta * sin_thetap_o + sin_theta * cos_phi * cos_thetap_o;
+        let cos_theta_i = safe_sqrt(1f32 - sin_theta_i * sin_theta_i);
+        // sample the azimuthal angle
+        let (gamma_t, _) = self.refracted(sin_theta_o, cos_theta_o);
+        let dphi = if p < P_MAX {
+            phi(p, gamma_o, gamma_t) + sample_trimmed_logistic(uc, self.s)
+        } else {
+            2f32 * std::f32::consts::PI * uc
+        };
+        let phi_i = phi_o + dphi;
+        let wi = [
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        ];
+        let pdf = self.pdf(wo, &wi, mode, sample_flags);
+        if pdf <= 0f32 {
+            return None;
+        }
+        let flag = if wo[2] * wi[2] < 0f32 {
+            BxDFFlags::GLOSSY | BxDFFlags::TRANSMISSION
+        } else {
+            BxDFFlags::GLOSSY | BxDFFlags::REFLECTION
+        };
+        Some(BSDFSample {
+            f: self.f(wo, &wi, mode),
+            wi,
+            pdf,
+            flag,
+            pdf_is_proportional: false,
+        })
+    }
+
+    fn pdf(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        _mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> f32 {
+        if sample_flags != BxDFReflTransFlags::ALL {
+            return 0f32;
+        }
+        let sin_theta_o = wo[0];
+        let cos_theta_o = safe_sqrt(1f32 - sin_theta_o * sin_theta_o);
+        let phi_o = wo[2].atan2(wo[1]);
+        let gamma_o = safe_asin(self.h);
+        let sin_theta_i = wi[0];
+        let cos_theta_i = safe_sqrt(1f32 - sin_theta_i * sin_theta_i);
+        let phi_i = wi[2].atan2(wi[1]);
+        let (gamma_t, _) = self.refracted(sin_theta_o, cos_theta_o);
+        let ap_pdf = self.ap_pdf(sin_theta_o, cos_theta_o);
+        let phi_ = phi_i - phi_o;
+        let mut pdf = 0f32;
+        for (p, &ap_pdf) in ap_pdf.iter().enumerate().take(P_MAX) {
+            let (sin_thetap_o, cos_thetap_o) = self.tilt(p, sin_theta_o, cos_theta_o);
+            pdf += mp(
+                cos_theta_i,
+                cos_thetap_o,
+                sin_theta_i,
+                sin_thetap_o,
+                self.v[p],
+            ) * ap_pdf
+                * np(phi_, p, self.s, gamma_o, gamma_t);
+        }
+        pdf += mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        ) * ap_pdf[P_MAX]
+            / (2f32 * std::f32::consts::PI);
+        pdf
+    }
+}
+
+// ----------------------------------------
+
+/// offset `h` across the cylindrical fiber at the point seen from `wo`.
+/// `wo` is in the local frame whose x-axis is the tangent and z-axis is the normal
+pub fn offset_from_wo(wo: &[f32; 3]) -> f32 {
+    let r = (wo[1] * wo[1] + wo[2] * wo[2]).sqrt();
+    if r < 1.0e-6 {
+        return 0f32;
+    }
+    (-wo[1] / r).clamp(-1f32, 1f32)
+}
+
+/// shading frame of the fiber and the offset across the fiber
+/// * `nrm` - unit normal of the fiber at the hit point
+/// * `tangent` - direction of the fiber
+/// * `wo` - unit vector toward the viewer
+/// # Return
+/// `(transform_lcl2world, h)`
+pub fn frame_and_offset(nrm: &[f32; 3], tangent: &[f32; 3], wo: &[f32; 3]) -> ([f32; 9], f32) {
+    use del_geo_core::mat3_col_major;
+    let transform_lcl2world =
+        crate::microfacet::transform_lcl2world_given_normal_tangent(nrm, tangent);
+    let wo_lcl = mat3_col_major::mult_vec(&mat3_col_major::transpose(&transform_lcl2world), wo);
+    (transform_lcl2world, offset_from_wo(&wo_lcl))
+}
+
+/// [`HairBxDF`] on the cylindrical fiber whose offset `h` is given by `wo` (see [`offset_from_wo`]).
+/// This is the hair BxDF of [`crate::material::bxdf`] where the local x-axis is the tangent
+pub struct FiberBxDF(pub HairBxDF);
+
+impl FiberBxDF {
+    pub fn from_material(mat: &crate::material::HairMaterial) -> Self {
+        FiberBxDF(HairBxDF::from_material(mat, 0f32))
+    }
+
+    fn at(&self, wo: &[f32; 3]) -> HairBxDF {
+        HairBxDF {
+            h: offset_from_wo(wo),
+            ..self.0
+        }
+    }
+}
+
+impl BxDF for FiberBxDF {
+    fn flags(&self) -> BxDFFlags {
+        self.0.flags()
+    }
+
+    fn f(&self, wo: &[f32; 3], wi: &[f32; 3], mode: TransportMode) -> [f32; 3] {
+        self.at(wo).f(wo, wi, mode)
+    }
+
+    fn sample_f(
+        &self,
+        wo: &[f32; 3],
+        uc: f32,
+        u: &[f32; 2],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> Option<BSDFSample> {
+        self.at(wo).sample_f(wo, uc, u, mode, sample_flags)
+    }
+
+    fn pdf(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> f32 {
+        self.at(wo).pdf(wo, wi, mode, sample_flags)
+    }
+}
+
+/// sample the direction toward the light in the world coordinate
+/// * `nrm` - unit normal of the fiber at the hit point
+/// * `tangent` - direction of the fiber at the hit point (see [`crate::shape::tangent_at`])
+/// * `ray_in_outward` - unit vector toward the viewer
+pub fn sample_hair<RNG>(
+    mat: &crate::material::HairMaterial,
+    nrm: &[f32; 3],
+    tangent: &[f32; 3],
+    ray_in_outward: &[f32; 3],
+    rng: &mut RNG,
+) -> Option<BSDFSample>
+where
+    RNG: rand::Rng,
+{
+    use del_geo_core::mat3_col_major;
+    let (transform_lcl2world, h) = frame_and_offset(nrm, tangent, ray_in_outward);
+    let transform_world2lcl = mat3_col_major::transpose(&transform_lcl2world);
+    let wo = mat3_col_major::mult_vec(&transform_world2lcl, ray_in_outward);
+    let mut bs = HairBxDF::from_material(mat, h).sample_f(
+        &wo,
+        rng.random::<f32>(),
+        &[rng.random::<f32>(), rng.random::<f32>()],
+        TransportMode::Radiance,
+        BxDFReflTransFlags::ALL,
+    )?;
+    bs.wi = mat3_col_major::mult_vec(&transform_lcl2world, &bs.wi);
+    Some(bs)
+}
+
+/// BSDF for the pair of the directions in the world coordinate. See [`sample_hair`] for the arguments
+pub fn eval_hair(
+    mat: &crate::material::HairMaterial,
+    nrm: &[f32; 3],
+    tangent: &[f32; 3],
+    ray_in_outward: &[f32; 3],
+    ray_out: &[f32; 3],
+) -> [f32; 3] {
+    use del_geo_core::mat3_col_major;
+    let (transform_lcl2world, h) = frame_and_offset(nrm, tangent, ray_in_outward);
+    let transform_world2lcl = mat3_col_major::transpose(&transform_lcl2world);
+    let wo = mat3_col_major::mult_vec(&transform_world2lcl, ray_in_outward);
+    let wi = mat3_col_major::mult_vec(&transform_world2lcl, ray_out);
+    HairBxDF::from_material(mat, h).f(&wo, &wi, TransportMode::Radiance)
+}
+
+/// pdf of the direction sampled by [`sample_hair`] on the unit sphere
+pub fn pdf_hair(
+    mat: &crate::material::HairMaterial,
+    nrm: &[f32; 3],
+    tangent: &[f32; 3],
+    ray_in_outward: &[f32; 3],
+    ray_out: &[f32; 3],
+) -> f32 {
+    use del_geo_core::mat3_col_major;
+    let (transform_lcl2world, h) = frame_and_offset(nrm, tangent, ray_in_outward);
+    let transform_world2lcl = mat3_col_major::transpose(&transform_lcl2world);
+    let wo = mat3_col_major::mult_vec(&transform_world2lcl, ray_in_outward);
+    let wi = mat3_col_major::mult_vec(&transform_world2lcl, ray_out);
+    HairBxDF::from_material(mat, h).pdf(&wo, &wi, TransportMode::Radiance, BxDFReflTransFlags::ALL)
+}
+
+#[test]
+fn test_hair_bsdf() {
+    use rand::Rng;
+    use rand::SeedableRng;
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    let uniform_sphere = |rng: &mut rand_chacha::ChaChaRng| {
+        let z = 1f32 - 2f32 * rng.random::<f32>();
+        let r = safe_sqrt(1f32 - z * z);
+        let phi = 2f32 * std::f32::consts::PI * rng.random::<f32>();
+        [r * phi.cos(), r * phi.sin(), z]
+    };
+    for beta_m in [0.2f32, 0.5] {
+        for beta_n in [0.3f32, 0.7] {
+            let h = -1f32 + 2f32 * rng.random::<f32>();
+            let wo = uniform_sphere(&mut rng);
+            // white furnace: no energy is lost without the absorption
+            let bxdf = HairBxDF::new(h, 1.55, [0f32; 3], beta_m, beta_n, 2.);
+            let num_sample = 300000;
+            let mut sum_uniform = 0f32;
+            let mut sum_is = 0f32;
+            for _ in 0..num_sample {
+                let wi = uniform_sphere(&mut rng);
+                let f = bxdf.f(&wo, &wi, TransportMode::Radiance)[0];
+                sum_uniform += f * wi[2].abs() * 4f32 * std::f32::consts::PI / num_sample as f32;
+                let Some(bs) = bxdf.sample_f(
+                    &wo,
+                    rng.random::<f32>(),
+                    &[rng.random::<f32>(), rng.random::<f32>()],
+                    TransportMode::Radiance,
+                    BxDFReflTransFlags::ALL,
+                ) else {
+                    continue;
+                };
+                sum_is += bs.f[0] * bs.wi[2].abs() / bs.pdf / num_sample as f32;
+            }
+            assert!((sum_uniform - 1f32).abs() < 0.05, "{}", sum_uniform);
+            assert!((sum_is - 1f32).abs() < 0.02, "{}", sum_is);
+        }
+    }
+}
+
+#[test]
+fn test_hair_material_with_tangent() {
+    use del_geo_core::vec3::Vec3;
+    use rand::Rng;
+    use rand::SeedableRng;
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    let mat = crate::material::Material::Hair(crate::material::HairMaterial {
+        sigma_a: sigma_a_from_reflectance(&[0.6, 0.4, 0.2], 0.3),
+        eta: 1.55,
+        beta_m: 0.3,
+        beta_n: 0.3,
+        alpha: 2.,
+    });
+    let crate::material::Material::Hair(hair) = &mat else {
+        unreachable!()
+    };
+    // the surface without the tangent of the curve has no hair BxDF
+    assert!(crate::material::bxdf(&mat, &[], &[0f32; 2], None, 0f32).is_none());
+    let tangent = [1f32, 2., 0.5].normalize();
+    let nrm = [0f32, 0.5, -2.];
+    let nrm = nrm.sub(&tangent.scale(tangent.dot(&nrm))).normalize();
+    for _ in 0..20 {
+        let wo = [
+            rng.random::<f32>() - 0.5,
+            rng.random::<f32>() - 0.5,
+            rng.random::<f32>(),
+        ];
+        let wo = wo.add(&nrm.scale(0.5)).normalize();
+        {
+            // the offset in the local frame is the one across the fiber seen from `wo`
+            let wo_perp = wo.sub(&tangent.scale(tangent.dot(&wo))).normalize();
+            let h = tangent.dot(&nrm.cross(&wo_perp));
+            assert!((frame_and_offset(&nrm, &tangent, &wo).1 - h).abs() < 1.0e-5);
+        }
+        let Some(bs) = crate::material::sample_brdf(
+            &mat,
+            &[],
+            &[0f32; 2],
+            &nrm,
+            Some(&tangent),
+            &wo,
+            &mut rng,
+            0f32,
+        ) else {
+            continue;
+        };
+        let f0 = crate::material::eval_brdf(
+            &mat,
+            &[],
+            &[0f32; 2],
+            &nrm,
+            Some(&tangent),
+            &wo,
+            &bs.wi,
+            0f32,
+        );
+        let f1 = eval_hair(hair, &nrm, &tangent, &wo, &bs.wi);
+        let pdf0 = crate::material::pdf_brdf(
+            &mat,
+            &[],
+            &[0f32; 2],
+            &nrm,
+            Some(&tangent),
+            &wo,
+            &bs.wi,
+            0f32,
+        );
+        let pdf1 = pdf_hair(hair, &nrm, &tangent, &wo, &bs.wi);
+        for i in 0..3 {
+            assert!(
+                (f0[i] - f1[i]).abs() <= 1.0e-4 * f1[i].max(1.0),
+                "{:?} {:?}",
+                f0,
+                f1
+            );
+        }
+        assert!(
+            (pdf0 - pdf1).abs() <= 1.0e-4 * pdf1.max(1.0),
+            "{} {}",
+            pdf0,
+            pdf1
+        );
+        assert!(
+            (pdf0 - bs.pdf).abs() <= 1.0e-3 * pdf0.max(1.0),
+            "{} {}",
+            pdf0,
+            bs.pdf
+        );
+    }
+}
@@ -1,41 +1,591 @@
-use crate::material::CoatedDiffuse;
-
-#[allow(unused_mut)]
-#[allow(unused_variables)]
-pub fn sample_brdf_coated_diffuse<RNG>(
-    wo: &[f32; 3],
-    nsamples: i32,
-    mdepth: i32,
-    thickness: f32,
-    mat: &CoatedDiffuse,
-    rng: &mut RNG,
-) -> Option<([f32; 3], [f32; 3], f32)>
-where
-    RNG: rand::Rng,
-{
-    let mut m_pdf = 0.;
-    for isample in 0..nsamples {
-        for idepth in 0..mdepth {
-            todo!();
-        }
-    }
-
-    // refl, brdf, pdf
-    todo!()
-}
-
-#[allow(unused_variables)]
-pub fn eval_brdf_coated_diffuse<RNG>(
-    wi: &[f32; 3],
-    wo: &[f32; 3],
-    nsamples: i32,
-    mdepth: i32,
-    thickness: f32,
-    mat: &CoatedDiffuse,
-    rng: &mut RNG,
-) -> [f32; 3]
-where
-    RNG: rand::Rng,
-{
-    todo!()
+//! Stochastic layered BSDF of pbrt-v4 (`LayeredBxDF`): an interface on the top,
+//! a base at the bottom, and an optional scattering medium with the thickness between them.
+//! The value, the sample and the pdf are estimated by the random walk between the two interfaces.
+//! See <https://pbr-book.org/4ed/Reflection_Models/Scattering_from_Layered_Surfaces>
+//!
+//! As in pbrt, `wo` is the unit direction toward the viewer and `wi` is the one toward the light.
+//! Both are in the local frame whose z-axis is the normal. The layered surfaces are two-sided.
+
+use crate::bxdf::{
+    BSDFSample, BxDF, BxDFFlags, BxDFReflTransFlags, ConductorBxDF, DielectricBxDF, DiffuseBxDF,
+    TransportMode,
+};
+use crate::material::{CoatedConductor, CoatedDiffuse};
+
+/// parameters of the random walk between the layers
+#[derive(Debug, Clone, Copy)]
+pub struct LayeredConfig {
+    /// thickness of the medium between the layers with the unit extinction coefficient
+    pub thickness: f32,
+    /// single-scattering albedo of the medium. No scattering if zero
+    pub albedo: [f32; 3],
+    /// asymmetry parameter of the Henyey-Greenstein phase function of the medium
+    pub g: f32,
+    /// maximum number of the scattering events in the random walk
+    pub max_depth: usize,
+    /// number of the random walks to estimate the value and the pdf
+    pub num_sample: usize,
+}
+
+/// transmittance through the medium for the vertical distance `dz` in the direction `w`
+fn transmittance(dz: f32, w: &[f32; 3]) -> f32 {
+    if dz.abs() <= f32::MIN_POSITIVE {
+        return 1f32;
+    }
+    (-(dz / w[2]).abs()).exp()
+}
+
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denom = 1f32 + g * g + 2f32 * g * cos_theta;
+    0.25f32 * std::f32::consts::FRAC_1_PI * (1f32 - g * g) / (denom * denom.max(0f32).sqrt())
+}
+
+/// sample the Henyey-Greenstein phase function for the outgoing direction `wo`
+/// # Return
+/// `(wi, pdf)` where the value of the phase function equals the pdf
+pub(crate) fn sample_henyey_greenstein(wo: &[f32; 3], g: f32, u: &[f32; 2]) -> ([f32; 3], f32) {
+    let cos_theta = if g.abs() < 1.0e-3 {
+        1f32 - 2f32 * u[0]
+    } else {
+        let tmp = (1f32 - g * g) / (1f32 + g - 2f32 * g * u[0]);
+        -(1f32 + g * g - tmp * tmp) / (2f32 * g)
+    };
+    let cos_theta = cos_theta.clamp(-1f32, 1f32);
+    let sin_theta = (1f32 - cos_theta * cos_theta).max(0f32).sqrt();
+    let phi = 2f32 * std::f32::consts::PI * u[1];
+    let transform = del_geo_core::mat3_col_major::transform_lcl2world_given_local_z(wo);
+    let wi = del_geo_core::mat3_col_major::mult_vec(
+        &transform,
+        &[sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta],
+    );
+    (wi, henyey_greenstein(cos_theta, g))
+}
+
+fn power_heuristic(pdf_f: f32, pdf_g: f32) -> f32 {
+    let (f, g) = (pdf_f * pdf_f, pdf_g * pdf_g);
+    if f.is_infinite() {
+        return 1f32;
+    }
+    if f + g == 0f32 {
+        return 0f32;
+    }
+    f / (f + g)
+}
+
+/// deterministic random number generator for the pair of directions,
+/// so that the estimates of the value and the pdf are consistent for the same query
+fn rng_from_directions(a: &[f32; 3], b: &[f32; 3]) -> rand_chacha::ChaChaRng {
+    use rand::SeedableRng;
+    let seed = a.iter().chain(b.iter()).fold(0u64, |h, v| {
+        (h.rotate_left(17) ^ v.to_bits() as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    });
+    rand_chacha::ChaChaRng::seed_from_u64(seed)
+}
+
+fn is_valid(s: &BSDFSample) -> bool {
+    s.f != [0f32; 3] && s.pdf > 0f32 && s.wi[2] != 0f32
+}
+
+fn neg(v: &[f32; 3]) -> [f32; 3] {
+    [-v[0], -v[1], -v[2]]
+}
+
+/// two interfaces and the medium between them
+pub struct LayeredBxDF<TOP: BxDF, BOTTOM: BxDF> {
+    pub top: TOP,
+    pub bottom: BOTTOM,
+    pub config: LayeredConfig,
+}
+
+pub type CoatedDiffuseBxDF = LayeredBxDF<DielectricBxDF, DiffuseBxDF>;
+
+pub type CoatedConductorBxDF = LayeredBxDF<DielectricBxDF, ConductorBxDF>;
+
+impl CoatedDiffuseBxDF {
+    pub fn coated_diffuse(mat: &CoatedDiffuse, min_roughness: f32) -> Self {
+        let top = DielectricBxDF {
+            eta: [mat.eta; 3],
+            distrib: crate::microfacet::TrowbridgeReitz::from_roughness(
+                mat.uroughness.max(min_roughness),
+                mat.vroughness.max(min_roughness),
+                mat.remaproughness,
+            ),
+        };
+        let bottom = DiffuseBxDF::new(mat.reflectance);
+        let config = LayeredConfig {
+            thickness: mat.thickness,
+            albedo: mat.albedo,
+            g: mat.g,
+            max_depth: mat.maxdepth,
+            num_sample: mat.nsamples,
+        };
+        LayeredBxDF {
+            top,
+            bottom,
+            config,
+        }
+    }
+}
+
+impl CoatedConductorBxDF {
+    pub fn coated_conductor(mat: &CoatedConductor, min_roughness: f32) -> Self {
+        let top = DielectricBxDF {
+            eta: [mat.interface_eta; 3],
+            distrib: crate::microfacet::TrowbridgeReitz::from_roughness(
+                mat.interface_uroughness.max(min_roughness),
+                mat.interface_vroughness.max(min_roughness),
+                mat.remaproughness,
+            ),
+        };
+        // the conductor is in contact with the coat, not with the air
+        let bottom = ConductorBxDF::new(
+            [1f32; 3],
+            mat.conductor_eta.map(|v| v / mat.interface_eta),
+            mat.k.map(|v| v / mat.interface_eta),
+            crate::microfacet::TrowbridgeReitz::from_roughness(
+                mat.conductor_uroughness.max(min_roughness),
+                mat.conductor_vroughness.max(min_roughness),
+                mat.remaproughness,
+            ),
+            false,
+        );
+        let config = LayeredConfig {
+            thickness: mat.thickness,
+            albedo: mat.albedo,
+            g: mat.g,
+            max_depth: mat.maxdepth,
+            num_sample: mat.nsamples,
+        };
+        LayeredBxDF {
+            top,
+            bottom,
+            config,
+        }
+    }
+}
+
+/// lobes of the layered BxDF from the lobes of the interfaces and the albedo of the medium in between
+pub fn layered_flags(
+    flags_top: BxDFFlags,
+    flags_bottom: BxDFFlags,
+    albedo: &[f32; 3],
+) -> BxDFFlags {
+    let mut flags = BxDFFlags::REFLECTION;
+    if flags_top.is_specular() {
+        flags = flags | BxDFFlags::SPECULAR;
+    }
+    if flags_top.is_diffuse() || flags_bottom.is_diffuse() || *albedo != [0f32; 3] {
+        flags = flags | BxDFFlags::DIFFUSE;
+    } else if flags_top.is_glossy() || flags_bottom.is_glossy() {
+        flags = flags | BxDFFlags::GLOSSY;
+    }
+    if flags_top.is_transmissive() && flags_bottom.is_transmissive() {
+        flags = flags | BxDFFlags::TRANSMISSION;
+    }
+    flags
+}
+
+impl<TOP: BxDF, BOTTOM: BxDF> BxDF for LayeredBxDF<TOP, BOTTOM> {
+    fn flags(&self) -> BxDFFlags {
+        layered_flags(self.top.flags(), self.bottom.flags(), &self.config.albedo)
+    }
+
+    /// stochastic estimate of the value
+    fn f(&self, wo: &[f32; 3], wi: &[f32; 3], mode: TransportMode) -> [f32; 3] {
+        use del_geo_core::vec3::Vec3;
+        use rand::Rng;
+        let config = &self.config;
+        let (wo, wi) = if wo[2] < 0f32 {
+            (neg(wo), neg(wi))
+        } else {
+            (*wo, *wi)
+        };
+        let thickness = config.thickness.max(f32::MIN_POSITIVE);
+        let num_sample = config.num_sample.max(1);
+        let is_same_hemisphere = wo[2] * wi[2] > 0f32;
+        // the light exits from the top for the reflection
+        let (exit, non_exit, z_exit): (&dyn BxDF, &dyn BxDF, f32) = if is_same_hemisphere {
+            (&self.top, &self.bottom, thickness)
+        } else {
+            (&self.bottom, &self.top, 0f32)
+        };
+        let is_exit_specular = exit.flags().is_specular();
+        let is_non_exit_specular = non_exit.flags().is_specular();
+        let mut f = if is_same_hemisphere {
+            self.top.f(&wo, &wi, mode).scale(num_sample as f32)
+        } else {
+            [0f32; 3]
+        };
+        let has_medium = config.albedo != [0f32; 3];
+        let mut rng = rng_from_directions(&wo, &wi);
+        let mut r = || rng.random::<f32>();
+        let trans = BxDFReflTransFlags::TRANSMISSION;
+        let refl = BxDFReflTransFlags::REFLECTION;
+        let all = BxDFReflTransFlags::ALL;
+        for _i_sample in 0..num_sample {
+            // enter the layers
+            let Some(wos) = self.top.sample_f(&wo, r(), &[r(), r()], mode, trans) else {
+                continue;
+            };
+            if !is_valid(&wos) {
+                continue;
+            }
+            // the virtual light from `wi` entering the layers
+            let Some(wis) = exit.sample_f(&wi, r(), &[r(), r()], mode.flip(), trans) else {
+                continue;
+            };
+            if !is_valid(&wis) {
+                continue;
+            }
+            let mut beta = wos.f.scale(wos.wi[2].abs() / wos.pdf);
+            let mut z = thickness;
+            let mut w = wos.wi;
+            for i_depth in 0..config.max_depth {
+                // russian roulette
+                let beta_max = beta[0].max(beta[1]).max(beta[2]);
+                if i_depth > 3 && beta_max < 0.25f32 {
+                    let q = (1f32 - beta_max).max(0f32);
+                    if r() < q {
+                        break;
+                    }
+                    beta = beta.scale(1f32 / (1f32 - q));
+                }
+                if !has_medium {
+                    z = if z == thickness { 0f32 } else { thickness };
+                    beta = beta.scale(transmittance(thickness, &w));
+                } else {
+                    let dz = -(1f32 - r()).ln() * w[2].abs();
+                    let zp = if w[2] > 0f32 { z + dz } else { z - dz };
+                    if 0f32 < zp && zp < thickness {
+                        // scattering in the medium toward the virtual light
+                        let wt = if is_exit_specular {
+                            1f32
+                        } else {
+                            power_heuristic(wis.pdf, henyey_greenstein(w.dot(&wis.wi), config.g))
+                        };
+                        let contrib = beta
+                            .element_wise_mult(&config.albedo)
+                            .element_wise_mult(&wis.f)
+                            .scale(
+                                henyey_greenstein(w.dot(&wis.wi), config.g)
+                                    * wt
+                                    * transmittance(zp - z_exit, &wis.wi)
+                                    / wis.pdf,
+                            );
+                        f = f.add(&contrib);
+                        let (w_next, pdf_phase) =
+                            sample_henyey_greenstein(&neg(&w), config.g, &[r(), r()]);
+                        if pdf_phase == 0f32 || w_next[2] == 0f32 {
+                            continue;
+                        }
+                        beta = beta.element_wise_mult(&config.albedo);
+                        w = w_next;
+                        z = zp;
+                        if ((z < z_exit && w[2] > 0f32) || (z > z_exit && w[2] < 0f32))
+                            && !is_exit_specular
+                        {
+                            let f_exit = exit.f(&neg(&w), &wi, mode);
+                            if f_exit != [0f32; 3] {
+                                let pdf_exit = exit.pdf(&neg(&w), &wi, mode, trans);
+                                let wt = power_heuristic(pdf_phase, pdf_exit);
+                                let contrib = beta
+                                    .element_wise_mult(&f_exit)
+                                    .scale(transmittance(zp - z_exit, &w) * wt);
+                                f = f.add(&contrib);
+                            }
+                        }
+                        continue;
+                    }
+                    z = zp.clamp(0f32, thickness);
+                }
+                if z == z_exit {
+                    // reflection at the exit interface
+                    let Some(bs) = exit.sample_f(&neg(&w), r(), &[r(), r()], mode, refl) else {
+                        break;
+                    };
+                    if !is_valid(&bs) {
+                        break;
+                    }
+                    beta = beta.element_wise_mult(&bs.f.scale(bs.wi[2].abs() / bs.pdf));
+                    w = bs.wi;
+                    continue;
+                }
+                if !is_non_exit_specular {
+                    // next event estimation toward the virtual light
+                    let wt = if is_exit_specular {
+                        1f32
+                    } else {
+                        power_heuristic(wis.pdf, non_exit.pdf(&neg(&w), &neg(&wis.wi), mode, all))
+                    };
+                    let contrib = beta
+                        .element_wise_mult(&non_exit.f(&neg(&w), &neg(&wis.wi), mode))
+                        .element_wise_mult(&wis.f)
+                        .scale(wis.wi[2].abs() * wt * transmittance(thickness, &wis.wi) / wis.pdf);
+                    f = f.add(&contrib);
+                }
+                let Some(bs) = non_exit.sample_f(&neg(&w), r(), &[r(), r()], mode, refl) else {
+                    break;
+                };
+                if !is_valid(&bs) {
+                    break;
+                }
+                beta = beta.element_wise_mult(&bs.f.scale(bs.wi[2].abs() / bs.pdf));
+                w = bs.wi;
+                if !is_exit_specular {
+                    let f_exit = exit.f(&neg(&w), &wi, mode);
+                    if f_exit != [0f32; 3] {
+                        let wt = if is_non_exit_specular {
+                            1f32
+                        } else {
+                            power_heuristic(bs.pdf, exit.pdf(&neg(&w), &wi, mode, trans))
+                        };
+                        let contrib = beta
+                            .element_wise_mult(&f_exit)
+                            .scale(transmittance(thickness, &bs.wi) * wt);
+                        f = f.add(&contrib);
+                    }
+                }
+            }
+        }
+        f.scale(1f32 / num_sample as f32)
+    }
+
+    /// sample by the random walk between the layers.
+    /// The pdf is only proportional to the true one but `f / pdf` is the right weight
+    fn sample_f(
+        &self,
+        wo: &[f32; 3],
+        uc: f32,
+        u: &[f32; 2],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> Option<BSDFSample> {
+        use del_geo_core::vec3::Vec3;
+        use rand::Rng;
+        if sample_flags != BxDFReflTransFlags::ALL {
+            return None;
+        }
+        let config = &self.config;
+        let is_flip = wo[2] < 0f32;
+        let wo = if is_flip { neg(wo) } else { *wo };
+        let thickness = config.thickness.max(f32::MIN_POSITIVE);
+        let flip = |w: [f32; 3]| if is_flip { neg(&w) } else { w };
+        let all = BxDFReflTransFlags::ALL;
+        let bs = self.top.sample_f(&wo, uc, u, mode, all)?;
+        if !is_valid(&bs) {
+            return None;
+        }
+        if bs.is_reflection() {
+            return Some(BSDFSample {
+                wi: flip(bs.wi),
+                pdf_is_proportional: true,
+                ..bs
+            });
+        }
+        let mut rng = rng_from_directions(&wo, &[uc, u[0], u[1]]);
+        let mut r = || rng.random::<f32>();
+        let mut is_specular_path = bs.is_specular();
+        let mut w = bs.wi;
+        let mut f = bs.f.scale(bs.wi[2].abs());
+        let mut pdf = bs.pdf;
+        let mut z = thickness;
+        let has_medium = config.albedo != [0f32; 3];
+        for i_depth in 0..config.max_depth {
+            // russian roulette
+            let rr_beta = f[0].max(f[1]).max(f[2]) / pdf;
+            if i_depth > 3 && rr_beta < 0.25f32 {
+                let q = (1f32 - rr_beta).max(0f32);
+                if r() < q {
+                    return None;
+                }
+                pdf *= 1f32 - q;
+            }
+            if w[2] == 0f32 {
+                return None;
+            }
+            if has_medium {
+                let dz = -(1f32 - r()).ln() * w[2].abs();
+                let zp = if w[2] > 0f32 { z + dz } else { z - dz };
+                if zp == z {
+                    return None;
+                }
+                if 0f32 < zp && zp < thickness {
+                    let (w_next, p) = sample_henyey_greenstein(&neg(&w), config.g, &[r(), r()]);
+                    if p == 0f32 || w_next[2] == 0f32 {
+                        return None;
+                    }
+                    f = f.element_wise_mult(&config.albedo).scale(p);
+                    pdf *= p;
+                    is_specular_path = false;
+                    w = w_next;
+                    z = zp;
+                    continue;
+                }
+                z = zp.clamp(0f32, thickness);
+            } else {
+                z = if z == thickness { 0f32 } else { thickness };
+                f = f.scale(transmittance(thickness, &w));
+            }
+            let interface: &dyn BxDF = if z == 0f32 { &self.bottom } else { &self.top };
+            let bs = interface.sample_f(&neg(&w), r(), &[r(), r()], mode, all)?;
+            if !is_valid(&bs) {
+                return None;
+            }
+            f = f.element_wise_mult(&bs.f);
+            pdf *= bs.pdf;
+            is_specular_path &= bs.is_specular();
+            w = bs.wi;
+            if bs.is_transmission() {
+                // left the layers
+                let flag = if wo[2] * w[2] > 0f32 {
+                    BxDFFlags::REFLECTION
+                } else {
+                    BxDFFlags::TRANSMISSION
+                };
+                let flag = if is_specular_path {
+                    flag | BxDFFlags::SPECULAR
+                } else {
+                    flag | BxDFFlags::GLOSSY
+                };
+                return Some(BSDFSample {
+                    f,
+                    wi: flip(w),
+                    pdf,
+                    flag,
+                    pdf_is_proportional: true,
+                });
+            }
+            f = f.scale(bs.wi[2].abs());
+        }
+        None
+    }
+
+    /// stochastic estimate of the pdf mixed with the uniform sphere for the robustness as in pbrt
+    fn pdf(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> f32 {
+        use rand::Rng;
+        if sample_flags != BxDFReflTransFlags::ALL {
+            return 0f32;
+        }
+        let (top, bottom) = (&self.top, &self.bottom);
+        let (wo, wi) = if wo[2] < 0f32 {
+            (neg(wo), neg(wi))
+        } else {
+            (*wo, *wi)
+        };
+        let num_sample = self.config.num_sample.max(1);
+        let mut rng = rng_from_directions(&wi, &wo);
+        let mut r = || rng.random::<f32>();
+        let is_top_specular = top.flags().is_specular();
+        let is_bottom_specular = bottom.flags().is_specular();
+        let trans = BxDFReflTransFlags::TRANSMISSION;
+        let all = BxDFReflTransFlags::ALL;
+        let is_same_hemisphere = wo[2] * wi[2] > 0f32;
+        let mut pdf_sum = 0f32;
+        if is_same_hemisphere {
+            pdf_sum += num_sample as f32 * top.pdf(&wo, &wi, mode, BxDFReflTransFlags::REFLECTION);
+        }
+        for _i_sample in 0..num_sample {
+            if is_same_hemisphere {
+                // transmission, reflection at the bottom, and transmission
+                let wos = top.sample_f(&wo, r(), &[r(), r()], mode, trans);
+                let wis = top.sample_f(&wi, r(), &[r(), r()], mode.flip(), trans);
+                let (Some(wos), Some(wis)) = (wos, wis) else {
+                    continue;
+                };
+                if !is_valid(&wos) || !is_valid(&wis) {
+                    continue;
+                }
+                if is_top_specular {
+                    pdf_sum += bottom.pdf(&neg(&wos.wi), &neg(&wis.wi), mode, all);
+                    continue;
+                }
+                let Some(rs) = bottom.sample_f(&neg(&wos.wi), r(), &[r(), r()], mode, all) else {
+                    continue;
+                };
+                if !is_valid(&rs) {
+                    continue;
+                }
+                if is_bottom_specular {
+                    pdf_sum += top.pdf(&neg(&rs.wi), &wi, mode, all);
+                    continue;
+                }
+                let pdf_r = bottom.pdf(&neg(&wos.wi), &neg(&wis.wi), mode, all);
+                pdf_sum += power_heuristic(wis.pdf, pdf_r) * pdf_r;
+                let pdf_t = top.pdf(&neg(&rs.wi), &wi, mode, all);
+                pdf_sum += power_heuristic(rs.pdf, pdf_t) * pdf_t;
+            } else {
+                // transmission through the both interfaces
+                let Some(wos) = top.sample_f(&wo, r(), &[r(), r()], mode, all) else {
+                    continue;
+                };
+                if !is_valid(&wos) || wos.is_reflection() {
+                    continue;
+                }
+                let Some(wis) = bottom.sample_f(&wi, r(), &[r(), r()], mode.flip(), all) else {
+                    continue;
+                };
+                if !is_valid(&wis) || wis.is_reflection() {
+                    continue;
+                }
+                if is_top_specular {
+                    pdf_sum += bottom.pdf(&neg(&wos.wi), &wi, mode, all);
+                } else if is_bottom_specular {
+                    pdf_sum += top.pdf(&wo, &neg(&wis.wi), mode, all);
+                } else {
+                    pdf_sum += 0.5f32
+                        * (top.pdf(&wo, &neg(&wis.wi), mode, all)
+                            + bottom.pdf(&neg(&wos.wi), &wi, mode, all));
+                }
+            }
+        }
+        let pdf_uniform = 0.25f32 * std::f32::consts::FRAC_1_PI;
+        0.1f32 * pdf_uniform + 0.9f32 * pdf_sum / num_sample as f32
+    }
+}
+
+#[test]
+fn test_coated_diffuse_energy() {
+    use rand::Rng;
+    use rand::SeedableRng;
+    let mat = CoatedDiffuse {
+        uroughness: 0.,
+        vroughness: 0.,
+        reflectance: [1.; 3],
+        remaproughness: false,
+        thickness: 1.0e-4,
+        eta: 1.5,
+        albedo: [0.; 3],
+        g: 0.,
+        maxdepth: 100,
+        nsamples: 1,
+        reflectance_texture: usize::MAX,
+        uroughness_texture: usize::MAX,
+        vroughness_texture: usize::MAX,
+    };
+    let bxdf = CoatedDiffuseBxDF::coated_diffuse(&mat, 0.);
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    let wo = del_geo_core::vec3::normalize(&[0.3, 0.2, 1.0]);
+    let num_sample = 10000;
+    let mut sum = [0f32; 3];
+    for _ in 0..num_sample {
+        let Some(bs) = bxdf.sample_f(
+            &wo,
+            rng.random::<f32>(),
+            &[rng.random::<f32>(), rng.random::<f32>()],
+            TransportMode::Radiance,
+            BxDFReflTransFlags::ALL,
+        ) else {
+            continue;
+        };
+        assert!(bs.wi[2] > 0.);
+        sum = del_geo_core::vec3::axpy(bs.wi[2].abs() / bs.pdf / num_sample as f32, &bs.f, &sum);
+    }
+    // the white base under the clear and thin coat does not absorb the light
+    assert!((sum[0] - 1.).abs() < 0.03, "{}", sum[0]);
 }
@@ -1,16 +1,30 @@
 pub mod area_light;
 pub mod bxdf;
+pub mod bxdf_validation;
 pub mod cam2;
 pub mod cam3;
 pub mod cam_pbrt;
+pub mod energy_compensation;
 pub mod env_map;
+pub mod hair_bsdf;
 pub mod io_pfm;
 pub mod layered_material;
+pub mod lightmap;
 pub mod material;
+pub mod measured_brdf;
+pub mod microfacet;
 pub mod monte_carlo_integrator;
 pub mod parse_pbrt;
+pub mod polygonal_light;
+pub mod principled_bsdf;
+pub mod radiosity;
 pub mod raycast_trimesh3;
 pub mod sampling;
+pub mod shading_normal;
 pub mod shape;
 pub mod silhouette;
+pub mod spectrum;
+pub mod spherical_harmonics;
+pub mod subsurface;
 pub mod textures;
+pub mod vertex_bake;
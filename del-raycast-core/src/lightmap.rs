@@ -0,0 +1,703 @@
+//! Baking the lighting into a texture indexed by the uv coordinates of a triangle mesh (lightmap).
+//! The texel `(iw, ih)` of a texture with the shape `(w, h)` covers
+//! `u in [iw/w, (iw+1)/w]` and `v in [1-(ih+1)/h, 1-ih/h]`, which is the same convention as
+//! [`crate::raycast_trimesh3::render_texture_from_pix2tri`]
+
+/// uv coordinate at the center of the texel
+pub fn texel_center_uv(i_texel: usize, tex_shape: (usize, usize)) -> [f32; 2] {
+    let (iw, ih) = (i_texel % tex_shape.0, i_texel / tex_shape.0);
+    [
+        (iw as f32 + 0.5) / tex_shape.0 as f32,
+        1f32 - (ih as f32 + 0.5) / tex_shape.1 as f32,
+    ]
+}
+
+/// barycentric coordinates of the point in the triangle nearest to `q` in the uv space
+fn nearest_barycentric_coords(
+    uv0: &[f32; 2],
+    uv1: &[f32; 2],
+    uv2: &[f32; 2],
+    q: &[f32; 2],
+) -> [f32; 3] {
+    let e1 = [uv1[0] - uv0[0], uv1[1] - uv0[1]];
+    let e2 = [uv2[0] - uv0[0], uv2[1] - uv0[1]];
+    let det = e1[0] * e2[1] - e1[1] * e2[0];
+    let d = [q[0] - uv0[0], q[1] - uv0[1]];
+    let r1 = (d[0] * e2[1] - d[1] * e2[0]) / det;
+    let r2 = (e1[0] * d[1] - e1[1] * d[0]) / det;
+    let r0 = 1f32 - r1 - r2;
+    if r0 >= 0f32 && r1 >= 0f32 && r2 >= 0f32 {
+        return [r0, r1, r2];
+    }
+    // nearest point on the edges
+    let node2uv = [uv0, uv1, uv2];
+    let mut dist_min = f32::INFINITY;
+    let mut bc_min = [1f32, 0f32, 0f32];
+    for i_node in 0..3 {
+        let j_node = (i_node + 1) % 3;
+        let (a, b) = (node2uv[i_node], node2uv[j_node]);
+        let ab = [b[0] - a[0], b[1] - a[1]];
+        let aq = [q[0] - a[0], q[1] - a[1]];
+        let len2 = ab[0] * ab[0] + ab[1] * ab[1];
+        let t = if len2 > 0f32 {
+            ((aq[0] * ab[0] + aq[1] * ab[1]) / len2).clamp(0f32, 1f32)
+        } else {
+            0f32
+        };
+        let dist = (aq[0] - t * ab[0]).powi(2) + (aq[1] - t * ab[1]).powi(2);
+        if dist < dist_min {
+            dist_min = dist;
+            bc_min = [0f32; 3];
+            bc_min[i_node] = 1f32 - t;
+            bc_min[j_node] = t;
+        }
+    }
+    bc_min
+}
+
+/// rasterize the triangles in the uv space.
+/// The texel whose center is not covered by any triangle falls back to the triangle overlapping the texel,
+/// so the thin triangles and the chart boundaries are not dropped.
+/// # Return
+/// `(texel2tri, texel2bc)`
+/// * `texel2tri` - index of the triangle covering the texel center, otherwise the triangle whose nearest point
+///   to the texel center is inside the texel. `usize::MAX` if there is no such triangle
+/// * `texel2bc` - barycentric coordinates of the texel center (or its nearest point) in the triangle
+pub fn rasterize_uv(
+    tri2vtx: &[usize],
+    vtx2uv: &[f32],
+    tex_shape: (usize, usize),
+) -> (Vec<usize>, Vec<[f32; 3]>) {
+    let num_texel = tex_shape.0 * tex_shape.1;
+    let mut texel2tri = vec![usize::MAX; num_texel];
+    let mut texel2bc = vec![[0f32; 3]; num_texel];
+    // squared distance from the texel center to the triangle. Zero if the center is covered
+    let mut texel2dist = vec![f32::INFINITY; num_texel];
+    let half_texel = [0.5 / tex_shape.0 as f32, 0.5 / tex_shape.1 as f32];
+    for (i_tri, node2vtx) in tri2vtx.chunks(3).enumerate() {
+        let uv0 = arrayref::array_ref![vtx2uv, node2vtx[0] * 2, 2];
+        let uv1 = arrayref::array_ref![vtx2uv, node2vtx[1] * 2, 2];
+        let uv2 = arrayref::array_ref![vtx2uv, node2vtx[2] * 2, 2];
+        let e1 = [uv1[0] - uv0[0], uv1[1] - uv0[1]];
+        let e2 = [uv2[0] - uv0[0], uv2[1] - uv0[1]];
+        let det = e1[0] * e2[1] - e1[1] * e2[0];
+        if det.abs() < f32::EPSILON {
+            continue; // degenerated in the uv space
+        }
+        // bounding box in the texel coordinate
+        let (u_min, u_max) = (
+            uv0[0].min(uv1[0]).min(uv2[0]),
+            uv0[0].max(uv1[0]).max(uv2[0]),
+        );
+        let (v_min, v_max) = (
+            uv0[1].min(uv1[1]).min(uv2[1]),
+            uv0[1].max(uv1[1]).max(uv2[1]),
+        );
+        let to_texel =
+            |t: f32, ext: usize| (t * ext as f32).floor().clamp(0f32, (ext - 1) as f32) as usize;
+        let (iw_min, iw_max) = (to_texel(u_min, tex_shape.0), to_texel(u_max, tex_shape.0));
+        let (ih_min, ih_max) = (
+            to_texel(1f32 - v_max, tex_shape.1),
+            to_texel(1f32 - v_min, tex_shape.1),
+        );
+        for ih in ih_min..=ih_max {
+            for iw in iw_min..=iw_max {
+                let i_texel = ih * tex_shape.0 + iw;
+                let uv = texel_center_uv(i_texel, tex_shape);
+                let bc = nearest_barycentric_coords(uv0, uv1, uv2, &uv);
+                let p = [
+                    bc[0] * uv0[0] + bc[1] * uv1[0] + bc[2] * uv2[0],
+                    bc[0] * uv0[1] + bc[1] * uv1[1] + bc[2] * uv2[1],
+                ];
+                let d = [p[0] - uv[0], p[1] - uv[1]];
+                if d[0].abs() > half_texel[0] || d[1].abs() > half_texel[1] {
+                    continue; // the triangle does not overlap the texel
+                }
+                let is_center = bc.iter().all(|&r| r > 0f32);
+                let dist = if is_center {
+                    0f32
+                } else {
+                    d[0] * d[0] + d[1] * d[1]
+                };
+                if dist > 0f32 && dist >= texel2dist[i_texel] {
+                    continue;
+                }
+                texel2tri[i_texel] = i_tri;
+                texel2bc[i_texel] = bc;
+                texel2dist[i_texel] = dist;
+            }
+        }
+    }
+    (texel2tri, texel2bc)
+}
+
+/// position and unit normal in the world coordinate at the barycentric coordinates of a triangle.
+/// The normal is interpolated from `vtx2nrm` if it is not empty.
+pub fn position_normal_at(
+    tri2vtx: &[usize],
+    vtx2xyz: &[f32],
+    vtx2nrm: &[f32],
+    transform_objlcl2world: &[f32; 16],
+    i_tri: usize,
+    bc: &[f32; 3],
+) -> ([f32; 3], [f32; 3]) {
+    use del_geo_core::mat4_col_major;
+    use del_geo_core::vec3::Vec3;
+    let (p0, p1, p2) = del_msh_cpu::trimesh3::to_corner_points(tri2vtx, vtx2xyz, i_tri);
+    let pos = del_geo_core::tri3::position_from_barycentric_coords(&p0, &p1, &p2, bc);
+    let nrm = if vtx2nrm.is_empty() {
+        p1.sub(&p0).cross(&p2.sub(&p0))
+    } else {
+        let mut nrm = [0f32; 3];
+        for (i_node, &w) in bc.iter().enumerate() {
+            let n = arrayref::array_ref![vtx2nrm, tri2vtx[i_tri * 3 + i_node] * 3, 3];
+            nrm = del_geo_core::vec3::axpy(w, n, &nrm);
+        }
+        nrm
+    };
+    let pos = mat4_col_major::transform_homogeneous(transform_objlcl2world, &pos).unwrap();
+    let nrm = mat4_col_major::transform_direction(transform_objlcl2world, &nrm).normalize();
+    (pos, nrm)
+}
+
+/// bake the irradiance `E = \int L_i cos dw` at each texel of the `i_shape_entity`-th shape entity.
+/// The direct lighting is estimated by sampling the light at the texel, and the indirect lighting
+/// by gathering the radiance of the cosine-weighted rays with [`crate::monte_carlo_integrator::radiance_nee`].
+/// `max_depth == 1` bakes the direct lighting only, and larger `max_depth` bakes the global illumination.
+/// Multiplying the diffuse reflectance divided by pi gives the outgoing radiance.
+/// # Return
+/// `tex_data` with the size of `tex_shape.0 * tex_shape.1 * 3`. Zero for the empty texels
+#[allow(clippy::too_many_arguments)]
+pub fn bake_irradiance<SCENE>(
+    scene: &SCENE,
+    i_shape_entity: usize,
+    tri2vtx: &[usize],
+    vtx2xyz: &[f32],
+    vtx2nrm: &[f32],
+    transform_objlcl2world: &[f32; 16],
+    texel2tri: &[usize],
+    texel2bc: &[[f32; 3]],
+    num_sample: usize,
+    max_depth: usize,
+) -> Vec<f32>
+where
+    SCENE: crate::monte_carlo_integrator::Scene + Sync,
+{
+    use crate::monte_carlo_integrator::offset_ray_origin;
+    use del_geo_core::vec3::Vec3;
+    let gather = |i_texel: usize, pix: &mut [f32]| {
+        let i_tri = texel2tri[i_texel];
+        if i_tri == usize::MAX {
+            return;
+        }
+        let (pos, nrm) = position_normal_at(
+            tri2vtx,
+            vtx2xyz,
+            vtx2nrm,
+            transform_objlcl2world,
+            i_tri,
+            &texel2bc[i_texel],
+        );
+        let pos_offset = offset_ray_origin(&pos, &nrm, &nrm);
+        use rand::Rng;
+        use rand::SeedableRng;
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(i_texel as u64);
+        let mut irradiance = [0f32; 3];
+        for _i_sample in 0..num_sample {
+            // direct lighting
+            if let Some((li_light, pdf_light, uvec_hit2light)) =
+                scene.sample_light(&pos_offset, i_shape_entity, &mut rng)
+            {
+                let cos = uvec_hit2light.dot(&nrm);
+                if cos > 0f32 && pdf_light > 0f32 {
+                    irradiance = del_geo_core::vec3::axpy(cos / pdf_light, &li_light, &irradiance);
+                }
+            }
+            if max_depth <= 1 {
+                continue;
+            }
+            // indirect lighting. The pdf of the cosine-weighted sampling is cos/pi
+            let ray_dir = crate::sampling::hemisphere_cos_weighted(
+                &nrm,
+                &[rng.random::<f32>(), rng.random::<f32>()],
+            );
+            let ray_org = offset_ray_origin(&pos, &nrm, &ray_dir);
+            let Some((_, _, hit_emission, _, _, _, _, _)) = scene
+                .hit_position_normal_emission_roughness_at_ray_intersection(&ray_org, &ray_dir)
+            else {
+                continue;
+            };
+            let rad = crate::monte_carlo_integrator::radiance_nee(
+                &ray_org,
+                &ray_dir,
+                scene,
+                max_depth - 1,
+                &mut rng,
+                false,
+            );
+            // the emission of the first hit is already counted by the light sampling
+            let rad = rad.sub(&hit_emission);
+            irradiance = del_geo_core::vec3::axpy(std::f32::consts::PI, &rad, &irradiance);
+        }
+        let irradiance = del_geo_core::vec3::scale(&irradiance, 1f32 / num_sample as f32);
+        pix.copy_from_slice(&irradiance);
+    };
+    let mut tex_data = vec![0f32; texel2tri.len() * 3];
+    use rayon::prelude::*;
+    tex_data
+        .par_chunks_mut(3)
+        .enumerate()
+        .for_each(|(i_texel, pix)| gather(i_texel, pix));
+    tex_data
+}
+
+/// fill the empty texels around the uv charts with the average of the filled neighbours
+/// to avoid the dark seams from the bilinear interpolation or the mip-mapping.
+/// The dilation is repeated `num_iteration` times, each of which grows the charts by one texel
+pub fn dilate(
+    tex_data: &mut [f32],
+    tex_shape: (usize, usize),
+    num_channel: usize,
+    texel2tri: &[usize],
+    num_iteration: usize,
+) {
+    let (w, h) = tex_shape;
+    assert_eq!(tex_data.len(), w * h * num_channel);
+    assert_eq!(texel2tri.len(), w * h);
+    let mut texel2flag: Vec<bool> = texel2tri.iter().map(|&i_tri| i_tri != usize::MAX).collect();
+    for _iter in 0..num_iteration {
+        let mut texel2flag_new = texel2flag.clone();
+        for ih in 0..h {
+            for iw in 0..w {
+                let i_texel = ih * w + iw;
+                if texel2flag[i_texel] {
+                    continue;
+                }
+                let mut sum = vec![0f32; num_channel];
+                let mut cnt = 0;
+                for jh in ih.saturating_sub(1)..(ih + 2).min(h) {
+                    for jw in iw.saturating_sub(1)..(iw + 2).min(w) {
+                        let j_texel = jh * w + jw;
+                        if !texel2flag[j_texel] {
+                            continue;
+                        }
+                        for (i_ch, v) in sum.iter_mut().enumerate() {
+                            *v += tex_data[j_texel * num_channel + i_ch];
+                        }
+                        cnt += 1;
+                    }
+                }
+                if cnt == 0 {
+                    continue;
+                }
+                for (i_ch, v) in sum.iter().enumerate() {
+                    tex_data[i_texel * num_channel + i_ch] = v / cnt as f32;
+                }
+                texel2flag_new[i_texel] = true;
+            }
+        }
+        texel2flag = texel2flag_new;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_scene {
+    use crate::bxdf::{BSDFSample, BxDFFlags};
+    use crate::shape::{ShapeEntity, ShapeType};
+    use del_geo_core::vec3::Vec3;
+
+    /// diffuse floor `[0,1]^2` at `z=0` lit by the square light facing down.
+    /// Optionally the diffuse wall at `x=1` reflects the light to the floor (see [`FloorUnderLight::with_wall`])
+    pub struct FloorUnderLight {
+        pub shape_entities: Vec<ShapeEntity>,
+        pub reflectance: f32,
+        pub emission: [f32; 3],
+        /// integrate the direct light analytically. See [`crate::monte_carlo_integrator::Scene::direct_light_analytic`]
+        pub is_direct_light_analytic: bool,
+    }
+
+    pub const LIGHT_VTX2XYZ: [f32; 12] = [
+        0.25, 0.25, 0.5, 0.75, 0.25, 0.5, 0.75, 0.75, 0.5, 0.25, 0.75, 0.5,
+    ];
+    pub const LIGHT_TRI2VTX: [usize; 6] = [0, 2, 1, 0, 3, 2];
+
+    fn trimesh(tri2vtx: Vec<usize>, vtx2xyz: Vec<f32>) -> ShapeEntity {
+        ShapeEntity::new_untransformed(ShapeType::TriangleMesh {
+            tri2vtx,
+            vtx2xyz,
+            vtx2nrm: vec![],
+            vtx2uv: vec![],
+            tri2cumsumarea: None,
+        })
+    }
+
+    impl FloorUnderLight {
+        pub fn new(reflectance: f32, emission: [f32; 3]) -> Self {
+            let floor = trimesh(
+                vec![0, 1, 2, 0, 2, 3],
+                vec![0., 0., 0., 1., 0., 0., 1., 1., 0., 0., 1., 0.],
+            );
+            let light = trimesh(LIGHT_TRI2VTX.to_vec(), LIGHT_VTX2XYZ.to_vec());
+            FloorUnderLight {
+                shape_entities: vec![floor, light],
+                reflectance,
+                emission,
+                is_direct_light_analytic: false,
+            }
+        }
+
+        /// the scene with the unit square wall at `x=1` facing the floor as the third shape entity
+        pub fn with_wall(reflectance: f32, emission: [f32; 3]) -> Self {
+            let mut scene = Self::new(reflectance, emission);
+            let wall = trimesh(
+                vec![0, 2, 1, 0, 3, 2],
+                vec![1., 0., 0., 1., 1., 0., 1., 1., 1., 1., 0., 1.],
+            );
+            scene.shape_entities.push(wall);
+            scene
+        }
+
+        fn normal(i_shape_entity: usize) -> [f32; 3] {
+            match i_shape_entity {
+                0 => [0., 0., 1.],
+                1 => [0., 0., -1.],
+                _ => [-1., 0., 0.],
+            }
+        }
+    }
+
+    impl crate::monte_carlo_integrator::Scene for FloorUnderLight {
+        fn hit_position_normal_emission_roughness_at_ray_intersection(
+            &self,
+            ray_org: &[f32; 3],
+            ray_dir: &[f32; 3],
+        ) -> Option<(
+            [f32; 3],
+            [f32; 3],
+            [f32; 3],
+            f32,
+            usize,
+            [f32; 2],
+            [f32; 3],
+            Option<[f32; 3]>,
+        )> {
+            let (t, i_shape_entity, _i_tri) =
+                crate::shape::intersection_ray_against_shape_entities(
+                    ray_org,
+                    ray_dir,
+                    &self.shape_entities,
+                )?;
+            let pos = del_geo_core::vec3::axpy(t, ray_dir, ray_org);
+            let nrm = Self::normal(i_shape_entity);
+            let emission = if i_shape_entity == 1 && ray_dir.dot(&nrm) < 0f32 {
+                self.emission
+            } else {
+                [0f32; 3]
+            };
+            Some((
+                pos,
+                nrm,
+                emission,
+                1f32,
+                i_shape_entity,
+                [0f32; 2],
+                nrm,
+                None,
+            ))
+        }
+
+        fn eval_brdf(
+            &self,
+            i_shape_entity: usize,
+            _hit_pos: &[f32; 3],
+            _hit_uv: &[f32; 2],
+            obj_nrm: &[f32; 3],
+            _tangent: Option<&[f32; 3]>,
+            ray_in_outward_normlized: &[f32; 3],
+            ray_out_normalized: &[f32; 3],
+            _minimum_roughness: f32,
+        ) -> [f32; 3] {
+            if i_shape_entity == 1
+                || obj_nrm.dot(ray_in_outward_normlized) <= 0f32
+                || obj_nrm.dot(ray_out_normalized) <= 0f32
+            {
+                return [0f32; 3];
+            }
+            [self.reflectance * std::f32::consts::FRAC_1_PI; 3]
+        }
+
+        fn sample_brdf<Rng: rand::Rng>(
+            &self,
+            obj_nrm: &[f32; 3],
+            _tangent: Option<&[f32; 3]>,
+            _uvec_ray_in_outward: &[f32; 3],
+            i_shape_entity: usize,
+            _hit_pos: &[f32; 3],
+            _hit_uv: &[f32; 2],
+            rng: &mut Rng,
+            _minimum_roughness: f32,
+        ) -> Option<BSDFSample> {
+            if i_shape_entity == 1 {
+                return None;
+            }
+            let wi = crate::sampling::hemisphere_cos_weighted(
+                obj_nrm,
+                &[rng.random::<f32>(), rng.random::<f32>()],
+            );
+            let pdf = wi.dot(obj_nrm) * std::f32::consts::FRAC_1_PI;
+            Some(BSDFSample {
+                f: [self.reflectance * std::f32::consts::FRAC_1_PI; 3],
+                wi,
+                pdf,
+                flag: BxDFFlags::DIFFUSE | BxDFFlags::REFLECTION,
+                pdf_is_proportional: false,
+            })
+        }
+
+        fn pdf_brdf(
+            &self,
+            _i_shape_entity: usize,
+            _hit_pos: &[f32; 3],
+            _hit_uv: &[f32; 2],
+            obj_nrm: &[f32; 3],
+            _tangent: Option<&[f32; 3]>,
+            _ray_in_outward_normlized: &[f32; 3],
+            ray_out_normalized: &[f32; 3],
+            _minimum_roughness: f32,
+        ) -> f32 {
+            obj_nrm.dot(ray_out_normalized).max(0f32) * std::f32::consts::FRAC_1_PI
+        }
+
+        fn bxdf_flags(
+            &self,
+            i_shape_entity: usize,
+            _hit_uv: &[f32; 2],
+            _minimum_roughness: f32,
+        ) -> BxDFFlags {
+            if i_shape_entity != 1 {
+                BxDFFlags::DIFFUSE | BxDFFlags::REFLECTION
+            } else {
+                BxDFFlags::UNSET
+            }
+        }
+
+        fn sample_light<Rng: rand::Rng>(
+            &self,
+            pos_observeffset: &[f32; 3],
+            _i_shape_entity_observe: usize,
+            rng: &mut Rng,
+        ) -> Option<([f32; 3], f32, [f32; 3])> {
+            // uniform sampling on the square light. Nothing occludes it from the floor and the wall
+            let pos_light = [
+                0.25 + 0.5 * rng.random::<f32>(),
+                0.25 + 0.5 * rng.random::<f32>(),
+                0.5,
+            ];
+            let d = pos_light.sub(pos_observeffset);
+            let uvec_hit2light = d.normalize();
+            let cos_light = -uvec_hit2light.dot(&Self::normal(1));
+            if cos_light <= 0f32 {
+                return None;
+            }
+            let pdf = d.dot(&d) / (cos_light * 0.25);
+            Some((self.emission, pdf, uvec_hit2light))
+        }
+
+        fn direct_light_analytic(
+            &self,
+            hit_pos: &[f32; 3],
+            hit_nrm: &[f32; 3],
+            uvec_ray_in_outward: &[f32; 3],
+            i_shape_entity: usize,
+            _hit_uv: &[f32; 2],
+        ) -> Option<[f32; 3]> {
+            // nothing occludes the light from the floor
+            if !self.is_direct_light_analytic || i_shape_entity != 0 {
+                return None;
+            }
+            Some(crate::polygonal_light::direct_radiance_trimesh(
+                &crate::polygonal_light::Receiver::Diffuse([self.reflectance; 3]),
+                &LIGHT_TRI2VTX,
+                &LIGHT_VTX2XYZ,
+                &self.emission,
+                false,
+                hit_pos,
+                hit_nrm,
+                uvec_ray_in_outward,
+            ))
+        }
+
+        fn pdf_light(
+            &self,
+            pos_observe: &[f32; 3],
+            pos_light: &[f32; 3],
+            nrm_light: &[f32; 3],
+            _uv_light: &[f32; 2],
+            _i_shape_entity: usize,
+        ) -> f32 {
+            let d = pos_light.sub(pos_observe);
+            let cos_light = -d.normalize().dot(nrm_light);
+            if cos_light <= 0f32 {
+                return 0f32;
+            }
+            d.dot(&d) / (cos_light * 0.25)
+        }
+    }
+}
+
+#[test]
+fn test_bake_irradiance_under_light() {
+    let scene = test_scene::FloorUnderLight::new(0.5, [1., 2., 3.]);
+    let crate::shape::ShapeType::TriangleMesh {
+        tri2vtx, vtx2xyz, ..
+    } = &scene.shape_entities[0].shape
+    else {
+        panic!();
+    };
+    // the uv coordinates are the xy coordinates of the floor
+    let vtx2uv: Vec<f32> = vtx2xyz.chunks(3).flat_map(|xyz| [xyz[0], xyz[1]]).collect();
+    let tex_shape = (8, 8);
+    let (texel2tri, texel2bc) = rasterize_uv(tri2vtx, &vtx2uv, tex_shape);
+    assert!(texel2tri.iter().all(|&i_tri| i_tri != usize::MAX));
+    let transform = scene.shape_entities[0].transform_objlcl2world;
+    let bake = |max_depth: usize| {
+        bake_irradiance(
+            &scene,
+            0,
+            tri2vtx,
+            vtx2xyz,
+            &[],
+            &transform,
+            &texel2tri,
+            &texel2bc,
+            1000,
+            max_depth,
+        )
+    };
+    let direct = bake(1);
+    // the floor cannot see itself, so the global illumination is the same as the direct lighting
+    let global = bake(3);
+    for i_texel in 0..tex_shape.0 * tex_shape.1 {
+        let uv = texel_center_uv(i_texel, tex_shape);
+        let ff = crate::polygonal_light::form_factor_trimesh(
+            &test_scene::LIGHT_TRI2VTX,
+            &test_scene::LIGHT_VTX2XYZ,
+            &[uv[0], uv[1], 0.],
+            &[0., 0., 1.],
+            false,
+        );
+        for (i_ch, emission) in scene.emission.iter().enumerate() {
+            let expected = std::f32::consts::PI * emission * ff;
+            let v0 = direct[i_texel * 3 + i_ch];
+            let v1 = global[i_texel * 3 + i_ch];
+            assert!(
+                (v0 - expected).abs() < 0.05 * expected,
+                "{} {}",
+                v0,
+                expected
+            );
+            assert!(
+                (v1 - expected).abs() < 0.05 * expected,
+                "{} {}",
+                v1,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn test_rasterize_thin_triangle() {
+    // the sliver triangle does not cover any texel center
+    let vtx2uv = [0.1f32, 0.33, 0.9, 0.331, 0.9, 0.332];
+    let tex_shape = (8, 8);
+    let (texel2tri, texel2bc) = rasterize_uv(&[0, 1, 2], &vtx2uv, tex_shape);
+    let num_covered = texel2tri.iter().filter(|&&i_tri| i_tri == 0).count();
+    assert!(num_covered >= 6, "{}", num_covered);
+    for (i_texel, bc) in texel2bc.iter().enumerate() {
+        if texel2tri[i_texel] == usize::MAX {
+            continue;
+        }
+        assert!(bc.iter().all(|&r| r >= 0f32));
+        assert!((bc.iter().sum::<f32>() - 1f32).abs() < 1.0e-5);
+    }
+}
+
+#[test]
+fn test_dilate() {
+    // the chart of 2x2 texels at the center of the 6x6 texture
+    let tex_shape = (6, 6);
+    let mut texel2tri = vec![usize::MAX; 36];
+    let mut tex_data = vec![0f32; 36 * 2];
+    for (i_texel, v) in [(14, 1f32), (15, 2.), (20, 3.), (21, 4.)] {
+        texel2tri[i_texel] = 0;
+        tex_data[i_texel * 2] = v;
+        tex_data[i_texel * 2 + 1] = -v;
+    }
+    let tex_data_ini = tex_data.clone();
+    dilate(&mut tex_data, tex_shape, 2, &texel2tri, 1);
+    // the texels in the chart stay unchanged
+    for i_texel in [14, 15, 20, 21] {
+        assert_eq!(
+            tex_data[i_texel * 2..i_texel * 2 + 2],
+            tex_data_ini[i_texel * 2..i_texel * 2 + 2]
+        );
+    }
+    // the average of the neighbours in the chart: diagonal to the texel 14,
+    // above the texels 14 and 15, and left of the texels 14 and 20
+    assert_eq!(tex_data[7 * 2..7 * 2 + 2], [1., -1.]);
+    assert_eq!(tex_data[8 * 2..8 * 2 + 2], [1.5, -1.5]);
+    assert_eq!(tex_data[13 * 2..13 * 2 + 2], [2., -2.]);
+    // the corner texel two texels away is filled by the second iteration from the texel 7
+    assert_eq!(tex_data[0..2], [0., 0.]);
+    let mut tex_data = tex_data_ini.clone();
+    dilate(&mut tex_data, tex_shape, 2, &texel2tri, 2);
+    assert_eq!(tex_data[0..2], [1., -1.]);
+    assert_eq!(tex_data[14 * 2..14 * 2 + 2], [1., -1.]);
+}
+
+#[test]
+fn test_bake_irradiance_with_wall() {
+    let scene = test_scene::FloorUnderLight::with_wall(0.8, [1., 1., 1.]);
+    let crate::shape::ShapeType::TriangleMesh {
+        tri2vtx, vtx2xyz, ..
+    } = &scene.shape_entities[0].shape
+    else {
+        panic!();
+    };
+    let vtx2uv: Vec<f32> = vtx2xyz.chunks(3).flat_map(|xyz| [xyz[0], xyz[1]]).collect();
+    let tex_shape = (4, 4);
+    let (texel2tri, texel2bc) = rasterize_uv(tri2vtx, &vtx2uv, tex_shape);
+    let transform = scene.shape_entities[0].transform_objlcl2world;
+    let bake = |max_depth: usize| {
+        bake_irradiance(
+            &scene,
+            0,
+            tri2vtx,
+            vtx2xyz,
+            &[],
+            &transform,
+            &texel2tri,
+            &texel2bc,
+            1000,
+            max_depth,
+        )
+    };
+    let direct = bake(1);
+    let global = bake(3);
+    // the wall lit by the light reflects it to the floor, most strongly near the wall
+    let ratio = |iw: usize| {
+        let (v0, v1): (f32, f32) = (0..tex_shape.1)
+            .map(|ih| ih * tex_shape.0 + iw)
+            .fold((0., 0.), |(a, b), i| (a + direct[i * 3], b + global[i * 3]));
+        v1 / v0
+    };
+    let (sum_direct, sum_global) = (direct.iter().sum::<f32>(), global.iter().sum::<f32>());
+    assert!(
+        sum_global > 1.05 * sum_direct,
+        "{} {}",
+        sum_global,
+        sum_direct
+    );
+    assert!(ratio(3) > ratio(0) + 0.1, "{} {}", ratio(3), ratio(0));
+}
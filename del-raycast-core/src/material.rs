@@ -4,6 +4,15 @@ pub enum Material {
     Diff(DiffuseMaterial),
     Cond(ConductorMaterial),
     CoaDiff(CoatedDiffuse),
+    CoaCond(CoatedConductor),
+    Diel(DielectricMaterial),
+    ThinDiel(ThinDielectricMaterial),
+    DiffTrans(DiffuseTransmissionMaterial),
+    Mix(MixMaterial),
+    Measured(MeasuredMaterial),
+    Principled(PrincipledMaterial),
+    Hair(HairMaterial),
+    Subsurface(SubsurfaceMaterial),
 }
 
 #[derive(Debug)]
@@ -16,9 +25,19 @@ pub struct DiffuseMaterial {
 pub struct ConductorMaterial {
     pub uroughness: f32,
     pub vroughness: f32,
+    pub remaproughness: bool,
     pub reflectance: [f32; 3],
     pub k: [f32; 3],
     pub eta: [f32; 3],
+    /// reflectance at the normal incidence from which `eta` and `k` are derived as in pbrt-v4.
+    /// `eta`, `k` and `reflectance` are ignored where it is valid (i.e., != usize::MAX)
+    pub reflectance_texture: usize,
+    pub uroughness_texture: usize, // valid if != usize:MAX
+    pub vroughness_texture: usize, // valid if != usize:MAX
+    pub k_texture: usize,          // valid if != usize:MAX
+    pub eta_texture: usize,        // valid if != usize:MAX
+    /// compensate the energy lost at the high roughness. See [`crate::energy_compensation`]
+    pub multiple_scattering: bool,
 }
 
 #[derive(Debug)]
@@ -27,37 +46,225 @@ pub struct DielectricMaterial {
     pub vroughness: f32,
     pub remaproughness: bool,
     pub eta: [f32; 3],
+    pub uroughness_texture: usize, // valid if != usize:MAX
+    pub vroughness_texture: usize, // valid if != usize:MAX
+    pub eta_texture: usize,        // valid if != usize:MAX
+}
+
+/// diffuse reflection and transmission such as the leaves, the paper and the lampshade
+#[derive(Debug)]
+pub struct DiffuseTransmissionMaterial {
+    pub reflectance: [f32; 3],
+    pub transmittance: [f32; 3],
+    pub reflectance_texture: usize,   // valid if != usize:MAX
+    pub transmittance_texture: usize, // valid if != usize:MAX
+}
+
+/// blend of two materials. One of them is chosen stochastically at each shading point (see [`choose_material`])
+#[derive(Debug)]
+pub struct MixMaterial {
+    /// indices of the two materials
+    pub materials: [usize; 2],
+    /// probability to choose the second material
+    pub amount: f32,
+    pub amount_texture: usize, // valid if != usize:MAX
+}
+
+/// BRDF measured by the MERL database
+#[derive(Debug)]
+pub struct MeasuredMaterial {
+    pub brdf: std::sync::Arc<crate::measured_brdf::MerlBrdf>,
+}
+
+/// Disney's principled material. See [`crate::principled_bsdf`]
+#[derive(Debug, Clone)]
+pub struct PrincipledMaterial {
+    pub base_color: [f32; 3],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// reflectance at the normal incidence of the non-metal part in the unit of `0.08`
+    pub specular: f32,
+    pub specular_tint: f32,
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    pub clearcoat: f32,
+    pub clearcoat_gloss: f32,
+    pub transmission: f32,
+    /// index of refraction of the inside for the transmission
+    pub eta: f32,
 }
 
+impl PrincipledMaterial {
+    /// metallic-roughness parameters such as the ones of the glTF
+    pub fn from_metallic_roughness(base_color: [f32; 3], metallic: f32, roughness: f32) -> Self {
+        PrincipledMaterial {
+            base_color,
+            metallic,
+            roughness,
+            specular: 0.5,
+            specular_tint: 0.,
+            sheen: 0.,
+            sheen_tint: 0.5,
+            clearcoat: 0.,
+            clearcoat_gloss: 1.,
+            transmission: 0.,
+            eta: 1.5,
+        }
+    }
+
+    /// parameters of the Wavefront MTL file
+    /// * `kd` - diffuse reflectance
+    /// * `ks` - specular reflectance
+    /// * `ns` - specular exponent of the Phong model
+    /// * `d` - opacity
+    /// * `ni` - index of refraction
+    pub fn from_wavefront_mtl(kd: [f32; 3], ks: [f32; 3], ns: f32, d: f32, ni: f32) -> Self {
+        // the Phong exponent to the alpha of the microfacet distribution, and the alpha to the roughness
+        let alpha = (2f32 / (ns.max(0f32) + 2f32)).sqrt();
+        let ks = (ks[0] + ks[1] + ks[2]) / 3f32;
+        PrincipledMaterial {
+            specular: (ks / 0.08f32).min(1f32),
+            transmission: (1f32 - d).clamp(0f32, 1f32),
+            eta: if ni > 0f32 { ni } else { 1.5 },
+            ..Self::from_metallic_roughness(kd, 0f32, alpha.sqrt())
+        }
+    }
+}
+
+/// fiber such as the hair and the fur on the [`crate::shape::ShapeType::Curves`]. See [`crate::hair_bsdf`]
+#[derive(Debug)]
+pub struct HairMaterial {
+    /// absorption coefficient inside the fiber normalized by the radius
+    pub sigma_a: [f32; 3],
+    pub eta: f32,
+    /// longitudinal roughness
+    pub beta_m: f32,
+    /// azimuthal roughness
+    pub beta_n: f32,
+    /// tilt of the cuticle scales in degree
+    pub alpha: f32,
+}
+
+/// interface of the [`SubsurfaceMaterial`] where the random walk enters and leaves the medium
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubsurfaceBoundary {
+    /// the path enters and leaves in the cosine-weighted direction
+    Diffuse,
+    /// the smooth interface with the Fresnel reflection and the refraction
+    Dielectric,
+}
+
+/// translucent medium such as the skin, the wax and the marble inside the closed shape.
+/// The light transport is simulated by the random walk. See [`crate::subsurface`]
+#[derive(Debug)]
+pub struct SubsurfaceMaterial {
+    /// absorption coefficient in the inverse of the scene unit
+    pub sigma_a: [f32; 3],
+    /// scattering coefficient in the inverse of the scene unit
+    pub sigma_s: [f32; 3],
+    /// asymmetry parameter of the Henyey-Greenstein phase function
+    pub g: f32,
+    pub eta: f32,
+    pub boundary: SubsurfaceBoundary,
+}
+
+impl SubsurfaceMaterial {
+    /// medium from the mean free path `mfp` and the single-scattering albedo `albedo` per channel
+    pub fn from_mfp_albedo(
+        mfp: &[f32; 3],
+        albedo: &[f32; 3],
+        eta: f32,
+        boundary: SubsurfaceBoundary,
+    ) -> Self {
+        let sigma_t = mfp.map(|v| 1f32 / v);
+        let sigma_s: [f32; 3] = std::array::from_fn(|i| albedo[i] * sigma_t[i]);
+        SubsurfaceMaterial {
+            sigma_a: std::array::from_fn(|i| sigma_t[i] - sigma_s[i]),
+            sigma_s,
+            g: 0f32,
+            eta,
+            boundary,
+        }
+    }
+}
+
+/// infinitely thin dielectric slab such as the window glass. The ray passes through without the bending
+#[derive(Debug)]
+pub struct ThinDielectricMaterial {
+    pub eta: [f32; 3],
+}
+
+/// diffuse base under the dielectric coat. See [`crate::layered_material`]
 #[derive(Debug)]
 pub struct CoatedDiffuse {
     pub uroughness: f32,
     pub vroughness: f32,
     pub reflectance: [f32; 3],
     pub remaproughness: bool,
+    pub thickness: f32,
+    /// index of refraction of the coat
+    pub eta: f32,
+    /// single-scattering albedo of the medium in the coat
+    pub albedo: [f32; 3],
+    /// asymmetry parameter of the phase function of the medium in the coat
+    pub g: f32,
+    pub maxdepth: usize,
+    pub nsamples: usize,
+    pub reflectance_texture: usize, // valid if != usize:MAX
+    pub uroughness_texture: usize,  // valid if != usize:MAX
+    pub vroughness_texture: usize,  // valid if != usize:MAX
 }
 
-pub fn sample_brdf_diffuse<RNG>(reflectance: &[f32; 3], rng: &mut RNG) -> ([f32; 3], [f32; 3], f32)
-where
-    RNG: rand::Rng,
-{
-    let ray_dir_next =
-        crate::sampling::hemisphere_zup_cos_weighted(&[rng.random::<f32>(), rng.random::<f32>()]);
-    use del_geo_core::vec3::Vec3;
-    let brdf = reflectance.scale(std::f32::consts::FRAC_1_PI);
-    let cos_hit = ray_dir_next[2].clamp(f32::EPSILON, 1f32);
-    let pdf = cos_hit * std::f32::consts::FRAC_1_PI;
-    (ray_dir_next, brdf, pdf)
-}
-
-pub fn eval_brdf_diffuse(reflectance: &[f32; 3]) -> [f32; 3] {
-    del_geo_core::vec3::scale(reflectance, 1. / std::f32::consts::PI)
+/// rough conductor under the dielectric coat such as the car paint. See [`crate::layered_material`]
+#[derive(Debug)]
+pub struct CoatedConductor {
+    pub interface_uroughness: f32,
+    pub interface_vroughness: f32,
+    /// index of refraction of the coat
+    pub interface_eta: f32,
+    pub conductor_uroughness: f32,
+    pub conductor_vroughness: f32,
+    pub conductor_eta: [f32; 3],
+    pub k: [f32; 3],
+    pub remaproughness: bool,
+    pub thickness: f32,
+    /// single-scattering albedo of the medium in the coat
+    pub albedo: [f32; 3],
+    /// asymmetry parameter of the phase function of the medium in the coat
+    pub g: f32,
+    pub maxdepth: usize,
+    pub nsamples: usize,
+    pub interface_uroughness_texture: usize, // valid if != usize:MAX
+    pub interface_vroughness_texture: usize, // valid if != usize:MAX
+    pub conductor_uroughness_texture: usize, // valid if != usize:MAX
+    pub conductor_vroughness_texture: usize, // valid if != usize:MAX
+    pub conductor_eta_texture: usize,        // valid if != usize:MAX
+    pub k_texture: usize,                    // valid if != usize:MAX
+    /// reflectance of the conductor at the normal incidence from which `conductor_eta` and `k`
+    /// are derived as in pbrt-v4. They are ignored where it is valid (i.e., != usize::MAX)
+    pub reflectance_texture: usize,
 }
 
 // --------------------------
 
+/// `(eta, k)` of the conductor whose reflectance at the normal incidence is `r` as in pbrt-v4.
+/// The reflectance is matched with eta = 1
+pub fn conductor_eta_k_from_reflectance(r: &[f32; 3]) -> ([f32; 3], [f32; 3]) {
+    let k = r.map(|r| {
+        let r = r.clamp(0.0, 0.9999);
+        2.0 * r.sqrt() / (1.0 - r).sqrt()
+    });
+    ([1.0; 3], k)
+}
+
+/// map the perceptually linear roughness to the alpha of the Beckmann distribution as in pbrt-v3
 pub fn microfacet_beckmann_roughness_to_alpha(roughness: f32) -> f32 {
-    roughness
+    let x = roughness.max(1.0e-3).ln();
+    1.62142f32
+        + 0.819955f32 * x
+        + 0.1734f32 * x * x
+        + 0.0171201f32 * x * x * x
+        + 0.000640711f32 * x * x * x * x
 }
 
 pub fn microfacet_beckmann_sample(alpha: f32, xi: &[f32; 2]) -> [f32; 3] {
@@ -89,7 +296,7 @@ pub fn microfacet_distribution_g(alpha: f32, i: &[f32; 3], o: &[f32; 3], m: &[f3
     g1i * g1o
 }
 
-fn microfacet_beckmann_d(alpha: f32, m: &[f32; 3]) -> f32 {
+pub fn microfacet_beckmann_d(alpha: f32, m: &[f32; 3]) -> f32 {
     if m[2] <= 0f32 {
         return 0f32;
     }
@@ -133,7 +340,11 @@ pub fn fresnel_conductor_reflectance(eta: f32, k: f32, cos_theta_i: f32) -> f32
     0.5f32 * (rs + rs * rp)
 }
 
-fn fresnel_conductor_reflectance_rgb(eta: &[f32; 3], k: &[f32; 3], cos_theta_i: f32) -> [f32; 3] {
+pub fn fresnel_conductor_reflectance_rgb(
+    eta: &[f32; 3],
+    k: &[f32; 3],
+    cos_theta_i: f32,
+) -> [f32; 3] {
     [
         fresnel_conductor_reflectance(eta[0], k[0], cos_theta_i),
         fresnel_conductor_reflectance(eta[1], k[1], cos_theta_i),
@@ -141,47 +352,46 @@ fn fresnel_conductor_reflectance_rgb(eta: &[f32; 3], k: &[f32; 3], cos_theta_i:
     ]
 }
 
-pub fn sample_brdf_rough_conductor<RNG>(
+/// sample the rough conductor with the visible normals of the Trowbridge-Reitz distribution.
+/// The perfect mirror is sampled if the distribution is effectively smooth
+/// * `wi` - unit vector toward the viewer in the local frame whose z-axis is the normal
+/// * `u` - uniform random numbers in `[0, 1)`
+/// # Return
+/// `(wo, brdf, pdf)`
+pub fn sample_brdf_rough_conductor(
     wi: &[f32; 3],
     reflectance: &[f32; 3],
     eta: &[f32; 3],
     k: &[f32; 3],
-    roughness: f32,
-    rng: &mut RNG,
-) -> Option<([f32; 3], [f32; 3], f32)>
-where
-    RNG: rand::Rng,
-{
+    distrib: &crate::microfacet::TrowbridgeReitz,
+    u: &[f32; 2],
+) -> Option<([f32; 3], [f32; 3], f32)> {
     use del_geo_core::vec3::Vec3;
-    if wi[2] < 0f32 {
+    if wi[2] <= 0f32 {
         return None;
     }
-    let sample_roughness: f32 = roughness;
-    let alpha = microfacet_beckmann_roughness_to_alpha(roughness);
-    let sample_alpha = microfacet_beckmann_roughness_to_alpha(sample_roughness);
-    // sampling microfacet normal
-    let m = microfacet_beckmann_sample(sample_alpha, &[rng.random::<f32>(), rng.random::<f32>()]);
-    assert!(!m[0].is_nan() && !m[1].is_nan() && !m[2].is_nan());
-    // microfacet normal PDF
-    let m_pdf = microfacet_beckmann_pdf(sample_alpha, &m);
-    assert!(!m_pdf.is_nan());
-    //
+    if distrib.effectively_smooth() {
+        let wo = [-wi[0], -wi[1], wi[2]];
+        let brdf = fresnel_conductor_reflectance_rgb(eta, k, wi[2])
+            .scale(1f32 / wo[2])
+            .element_wise_mult(reflectance);
+        return Some((wo, brdf, 1f32));
+    }
+    let m = distrib.sample_wm(wi, u);
     let wi_dot_m = wi.dot(&m);
     let wo = m.scale(2f32 * wi_dot_m).sub(wi);
     if wi_dot_m <= 0f32 || wo[2] <= 0f32 {
         return None;
     }
-    // the masking shadow function
-    let g = microfacet_distribution_g(alpha, wi, &wo, &m);
-    assert!(!g.is_nan());
-    let d = microfacet_beckmann_d(alpha, &m);
-    assert!(!d.is_nan());
-    let pdf = m_pdf * 0.25f32 / wi_dot_m; // compute `pdf of wo` from `pdf of m`
-    let f = fresnel_conductor_reflectance_rgb(eta, k, wi_dot_m);
-    let brdf = f
-        .scale(g * d * 0.25f32 / (wi[2] * wo[2]))
-        .element_wise_mult(reflectance); // 0.00281
-    Some((wo, brdf, pdf))
+    let pdf = distrib.pdf(wi, &m) * 0.25f32 / wi_dot_m; // compute `pdf of wo` from `pdf of m`
+    if pdf <= 0f32 {
+        return None;
+    }
+    Some((
+        wo,
+        eval_brdf_rough_conductor(wi, &wo, reflectance, eta, k, distrib),
+        pdf,
+    ))
 }
 
 pub fn eval_brdf_rough_conductor(
@@ -190,82 +400,147 @@ pub fn eval_brdf_rough_conductor(
     reflectance: &[f32; 3],
     eta: &[f32; 3],
     k: &[f32; 3],
-    roughness: f32,
+    distrib: &crate::microfacet::TrowbridgeReitz,
 ) -> [f32; 3] {
     use del_geo_core::vec3::Vec3;
-    if wi[2] <= 0f32 || wo[2] <= 0f32 {
+    if wi[2] <= 0f32 || wo[2] <= 0f32 || distrib.effectively_smooth() {
         return [0f32; 3];
     }
-    let alpha = microfacet_beckmann_roughness_to_alpha(roughness);
-    let m = wi.add(wo).normalize();
-    let wi_dot_m = wi.dot(&m);
-    // the masking shadow function
-    let g = microfacet_distribution_g(alpha, wi, wo, &m);
-    let d = microfacet_beckmann_d(alpha, &m);
-    let f = fresnel_conductor_reflectance_rgb(eta, k, wi_dot_m);
-    f.scale(g * d * 0.25f32 / (wi[2] * wo[2]))
+    let m = wi.add(wo);
+    if m.norm() < f32::EPSILON {
+        return [0f32; 3];
+    }
+    let m = m.normalize();
+    let f = fresnel_conductor_reflectance_rgb(eta, k, wi.dot(&m).abs());
+    f.scale(distrib.d(&m) * distrib.g(wi, wo) * 0.25f32 / (wi[2] * wo[2]))
         .element_wise_mult(reflectance)
 }
 
-#[allow(unused_variables)]
-pub fn sample_brdf_dielectric<RNG>(
+/// pdf of the direction sampled by [`sample_brdf_rough_conductor`]
+pub fn pdf_brdf_rough_conductor(
     wi: &[f32; 3],
-    reflectance: &[f32; 3],
-    eta: &[f32; 3],
-    uroughness: f32,
-    vroughness: f32,
-    rng: &mut RNG,
-) -> Option<([f32; 3], [f32; 3], f32)>
-where
-    RNG: rand::Rng,
-{
-    let smooth = |x: f32, y: f32| x.max(y) < 1e-3;
-    // specular
-    if eta.iter().all(|&x| x == 1.) || (smooth(uroughness, vroughness)) {
-        let cos_theta_i = wi[2];
-        // reflectance
-        let r = fresnel_dielectric_reflectance_rgb(eta, cos_theta_i);
-        // transmission
-        let t = [1. - r[0], 1. - r[1], 1. - r[2]];
-
-        let pr = r;
-        let pt = t;
-
-        let rdms = [
-            rng.random::<f32>(),
-            rng.random::<f32>(),
-            rng.random::<f32>(),
-        ];
-        //TODO: sample btdf or brdf
+    wo: &[f32; 3],
+    distrib: &crate::microfacet::TrowbridgeReitz,
+) -> f32 {
+    use del_geo_core::vec3::Vec3;
+    if wi[2] <= 0f32 || wo[2] <= 0f32 || distrib.effectively_smooth() {
+        return 0f32;
+    }
+    let m = wi.add(wo);
+    if m.norm() < f32::EPSILON {
+        return 0f32;
+    }
+    let m = m.normalize();
+    distrib.pdf(wi, &m) * 0.25f32 / wi.dot(&m)
+}
+
+/// refraction of the direction `wi` pointing away from the surface
+/// * `n` - unit normal on the same side as `wi`
+/// * `eta` - relative index of refraction (the transmitted side over the incident side)
+/// # Return
+/// `None` for the total internal reflection
+pub fn refract(wi: &[f32; 3], n: &[f32; 3], eta: f32) -> Option<[f32; 3]> {
+    use del_geo_core::vec3::Vec3;
+    let cos_theta_i = n.dot(wi);
+    let sin_theta_i_sq = (1f32 - cos_theta_i * cos_theta_i).max(0f32);
+    let sin_theta_t_sq = sin_theta_i_sq / (eta * eta);
+    if sin_theta_t_sq >= 1f32 {
+        return None;
+    }
+    let cos_theta_t = (1f32 - sin_theta_t_sq).sqrt();
+    Some(
+        wi.scale(-1f32 / eta)
+            .add(&n.scale(cos_theta_i / eta - cos_theta_t)),
+    )
+}
+
+/// microfacet normal of the rough dielectric for the pair of the directions (the generalized half vector)
+/// # Return
+/// `Some((m, etap))` where `m` is in the upper hemisphere and `etap` is the relative index of refraction
+/// (one for the reflection). `None` for the degenerated or back-facing configurations
+pub fn rough_dielectric_half_vector(
+    wi: &[f32; 3],
+    wo: &[f32; 3],
+    eta: f32,
+) -> Option<([f32; 3], f32)> {
+    use del_geo_core::vec3::Vec3;
+    let is_reflect = wi[2] * wo[2] > 0f32;
+    let etap = if is_reflect {
+        1f32
+    } else if wi[2] > 0f32 {
+        eta
     } else {
-        // TODO: roughness sampling
+        1f32 / eta
+    };
+    let m = wo.scale(etap).add(wi);
+    if wi[2] == 0f32 || wo[2] == 0f32 || m.norm() < f32::EPSILON {
+        return None;
+    }
+    let m = m.normalize();
+    let m = if m[2] < 0f32 { m.scale(-1f32) } else { m };
+    // the microfacets facing backward to the directions are discarded
+    if m.dot(wo) * wo[2] < 0f32 || m.dot(wi) * wi[2] < 0f32 {
+        return None;
     }
-    todo!();
+    Some((m, etap))
 }
 
-pub fn fresnel_dielectric_reflectance_rgb(eta: &[f32; 3], mut cos_theta_i: f32) -> [f32; 3] {
+/// BSDF of the rough dielectric interface with the Trowbridge-Reitz distribution
+/// from "Microfacet Models for Refraction through Rough Surfaces" by Bruce Walter et al. 2007
+/// * `wi` - unit vector toward the viewer in the local frame whose z-axis is the outward normal
+/// * `wo` - unit vector toward the light. Below the surface for the transmission
+pub fn eval_bsdf_rough_dielectric(
+    wi: &[f32; 3],
+    wo: &[f32; 3],
+    eta: f32,
+    distrib: &crate::microfacet::TrowbridgeReitz,
+) -> f32 {
+    use del_geo_core::vec3::Vec3;
+    let Some((m, etap)) = rough_dielectric_half_vector(wi, wo, eta) else {
+        return 0f32;
+    };
+    let d = distrib.d(&m);
+    let g = distrib.g(wi, wo);
+    let f = fresnel_dielectric(eta, wi.dot(&m));
+    if wi[2] * wo[2] > 0f32 {
+        return d * g * f / (4f32 * wi[2] * wo[2]).abs();
+    }
+    let denom = wo.dot(&m) + wi.dot(&m) / etap;
+    let denom = denom * denom * wi[2] * wo[2];
+    // the radiance is compressed (or expanded) by the change of the solid angle
+    d * g * (1f32 - f) * (wo.dot(&m) * wi.dot(&m) / denom).abs() / (etap * etap)
+}
+
+/// reflectance of the thin dielectric slab including the inter-reflection between the two interfaces
+pub fn thin_dielectric_reflectance(eta: f32, cos_theta_i: f32) -> f32 {
+    let r = fresnel_dielectric(eta, cos_theta_i.abs());
+    if r >= 1f32 {
+        return r;
+    }
+    // geometric series of the light bouncing inside the slab
+    let t = 1f32 - r;
+    r + t * t * r / (1f32 - r * r)
+}
+
+pub fn fresnel_dielectric_reflectance_rgb(eta: &[f32; 3], cos_theta_i: f32) -> [f32; 3] {
+    eta.map(|e| fresnel_dielectric(e, cos_theta_i))
+}
+
+/// Fresnel reflectance of the dielectric interface
+/// * `eta` - index of refraction of the inside over the outside
+/// * `cos_theta_i` - cosine of the incident angle. Negative if the light comes from the inside
+pub fn fresnel_dielectric(mut eta: f32, mut cos_theta_i: f32) -> f32 {
     cos_theta_i = cos_theta_i.clamp(-1., 1.);
-    let mut eta_local = *eta;
-    // potential filp
     if cos_theta_i < 0. {
+        eta = 1. / eta;
         cos_theta_i = -cos_theta_i;
-        eta_local = [1. / eta[0], 1. / eta[1], 1. / eta[2]];
     }
-
-    let sqr_sin_theta_i = (1. - cos_theta_i * cos_theta_i).sqrt();
-    let cos_theta_t: Vec<f32> = eta_local
-        .iter()
-        .map(|i_eta| {
-            let sqr_eta = i_eta * i_eta;
-            (1. - sqr_sin_theta_i / sqr_eta).sqrt()
-        })
-        .collect();
-
-    [
-        fresnel_dielectric_reflectance(eta_local[0], cos_theta_i, cos_theta_t[0]),
-        fresnel_dielectric_reflectance(eta_local[1], cos_theta_i, cos_theta_t[1]),
-        fresnel_dielectric_reflectance(eta_local[2], cos_theta_i, cos_theta_t[2]),
-    ]
+    let sin_theta_t_sq = (1. - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin_theta_t_sq >= 1. {
+        return 1.; // total internal reflection
+    }
+    let cos_theta_t = (1. - sin_theta_t_sq).max(0.).sqrt();
+    fresnel_dielectric_reflectance(eta, cos_theta_i, cos_theta_t)
 }
 
 // <https://pbr-book.org/4ed/Reflection_Models/Specular_Reflection_and_Transmission.html#FrDielectric >
@@ -283,56 +558,509 @@ pub fn fresnel_dielectric_reflectance(mut eta: f32, mut cos_theta_i: f32, cos_th
     (r_parl * r_parl + r_perp * r_perp) / 2.
 }
 
+/// true if the material transmits the light.
+/// For such materials, `obj_nrm` given to [`sample_brdf`] and [`eval_brdf`] should be the outward normal
+/// (not flipped toward the ray) because it distinguishes the inside from the outside
+pub fn is_transmissive(mat: &Material) -> bool {
+    match mat {
+        Material::Diel(_)
+        | Material::ThinDiel(_)
+        | Material::DiffTrans(_)
+        | Material::Hair(_)
+        | Material::Subsurface(_) => true,
+        Material::Principled(p) => p.transmission > 0f32 && p.metallic < 1f32,
+        _ => false,
+    }
+}
+
+/// deterministic random number in `[0, 1)` for the shading point,
+/// so that the same material is chosen for the evaluation and the sampling
+fn hash_float(values: &[f32]) -> f32 {
+    let h = values.iter().fold(0u64, |h, v| {
+        (h.rotate_left(17) ^ v.to_bits() as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    });
+    (h >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// true if the mix material `i_material` refers to itself directly or through the other mix materials.
+/// Such a material cannot be resolved by [`choose_material`]
+pub fn is_mix_cyclic(materials: &[Material], i_material: usize) -> bool {
+    fn visit(materials: &[Material], i_material: usize, path: &mut Vec<usize>) -> bool {
+        let Material::Mix(mix) = &materials[i_material] else {
+            return false;
+        };
+        if path.contains(&i_material) {
+            return true;
+        }
+        path.push(i_material);
+        let is_cyclic = mix.materials.iter().any(|&i| visit(materials, i, path));
+        path.pop();
+        is_cyclic
+    }
+    visit(materials, i_material, &mut vec![])
+}
+
+/// the material used at the shading point. The mix materials are resolved to one of their components
+/// * `uv` - texture coordinate of the shading point to evaluate [`MixMaterial::amount_texture`]
+/// * `pos` - position of the shading point. The choice is hashed from it and `ray_in_outward` as in pbrt-v4,
+///   so the parallel rays hitting a flat surface get the blend of the components
+pub fn choose_material<'a>(
+    materials: &'a [Material],
+    i_material: usize,
+    textures: &[crate::textures::Texture],
+    uv: &[f32; 2],
+    pos: &[f32; 3],
+    ray_in_outward: &[f32; 3],
+) -> &'a Material {
+    let mut mat = &materials[i_material];
+    let mut depth = 0;
+    while let Material::Mix(mix) = mat {
+        // the chain of the mix materials longer than the number of materials has a cycle
+        depth += 1;
+        assert!(
+            depth <= materials.len(),
+            "the mix material {} refers to itself",
+            i_material
+        );
+        let amount = crate::textures::eval_float(textures, mix.amount_texture, mix.amount, uv);
+        let i = if amount <= 0f32 {
+            0
+        } else if amount >= 1f32 {
+            1
+        } else {
+            let (m0, m1) = (mix.materials[0] as f32, mix.materials[1] as f32);
+            let u = hash_float(&[
+                pos[0],
+                pos[1],
+                pos[2],
+                ray_in_outward[0],
+                ray_in_outward[1],
+                ray_in_outward[2],
+                m0,
+                m1,
+            ]);
+            if amount < u {
+                0
+            } else {
+                1
+            }
+        };
+        mat = &materials[mix.materials[i]];
+    }
+    mat
+}
+
+/// BxDF of the material at the hit point. The BxDF is held by value so building it does not allocate
+pub enum MaterialBxDF {
+    Diffuse(crate::bxdf::DiffuseBxDF),
+    Conductor(crate::bxdf::ConductorBxDF),
+    CoatedDiffuse(crate::layered_material::CoatedDiffuseBxDF),
+    CoatedConductor(crate::layered_material::CoatedConductorBxDF),
+    Dielectric(crate::bxdf::DielectricBxDF),
+    ThinDielectric(crate::bxdf::ThinDielectricBxDF),
+    DiffuseTransmission(crate::bxdf::DiffuseTransmissionBxDF),
+    Measured(crate::measured_brdf::MeasuredBxDF),
+    Principled(crate::principled_bsdf::PrincipledBxDF),
+    Hair(crate::hair_bsdf::FiberBxDF),
+}
+
+impl MaterialBxDF {
+    fn as_bxdf(&self) -> &dyn crate::bxdf::BxDF {
+        match self {
+            MaterialBxDF::Diffuse(b) => b,
+            MaterialBxDF::Conductor(b) => b,
+            MaterialBxDF::CoatedDiffuse(b) => b,
+            MaterialBxDF::CoatedConductor(b) => b,
+            MaterialBxDF::Dielectric(b) => b,
+            MaterialBxDF::ThinDielectric(b) => b,
+            MaterialBxDF::DiffuseTransmission(b) => b,
+            MaterialBxDF::Measured(b) => b,
+            MaterialBxDF::Principled(b) => b,
+            MaterialBxDF::Hair(b) => b,
+        }
+    }
+}
+
+impl crate::bxdf::BxDF for MaterialBxDF {
+    fn flags(&self) -> crate::bxdf::BxDFFlags {
+        self.as_bxdf().flags()
+    }
+
+    fn f(&self, wo: &[f32; 3], wi: &[f32; 3], mode: crate::bxdf::TransportMode) -> [f32; 3] {
+        self.as_bxdf().f(wo, wi, mode)
+    }
+
+    fn sample_f(
+        &self,
+        wo: &[f32; 3],
+        uc: f32,
+        u: &[f32; 2],
+        mode: crate::bxdf::TransportMode,
+        sample_flags: crate::bxdf::BxDFReflTransFlags,
+    ) -> Option<crate::bxdf::BSDFSample> {
+        self.as_bxdf().sample_f(wo, uc, u, mode, sample_flags)
+    }
+
+    fn pdf(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        mode: crate::bxdf::TransportMode,
+        sample_flags: crate::bxdf::BxDFReflTransFlags,
+    ) -> f32 {
+        self.as_bxdf().pdf(wo, wi, mode, sample_flags)
+    }
+}
+
+/// BxDF of the material at the hit point
+/// * `uv` - texture coordinate of the hit point where the textured parameters are evaluated
+/// * `tangent` - tangent of the curve (see [`crate::shape::tangent_at`]), which is the x-axis of the local frame
+///   given by [`local_frame`]. Required by the hair material
+/// # Return
+/// `None` for [`Material::None`] and for the hair material without `tangent`.
+/// `None` also for the subsurface material, which is shaded by [`crate::subsurface`] in
+/// [`crate::monte_carlo_integrator::Scene::sample_subsurface`], and for the mix material,
+/// which should be resolved by [`choose_material`] beforehand. The surface without the BxDF absorbs the light
+pub fn bxdf(
+    mat: &Material,
+    textures: &[crate::textures::Texture],
+    uv: &[f32; 2],
+    tangent: Option<&[f32; 3]>,
+    min_roughness: f32,
+) -> Option<MaterialBxDF> {
+    use crate::bxdf::{
+        ConductorBxDF, DielectricBxDF, DiffuseBxDF, DiffuseTransmissionBxDF, ThinDielectricBxDF,
+    };
+    use crate::layered_material::{CoatedConductorBxDF, CoatedDiffuseBxDF};
+    use crate::textures::{eval_float, eval_spectrum};
+    let bxdf = match mat {
+        Material::Diff(a) => MaterialBxDF::Diffuse(DiffuseBxDF::new(eval_spectrum(
+            textures,
+            a.reflectance_texture,
+            &a.reflectance,
+            uv,
+        ))),
+        Material::Cond(b) => {
+            let (reflectance, eta, k) = if b.reflectance_texture != usize::MAX {
+                let r = eval_spectrum(textures, b.reflectance_texture, &b.reflectance, uv);
+                let (eta, k) = conductor_eta_k_from_reflectance(&r);
+                ([1f32; 3], eta, k)
+            } else {
+                (
+                    b.reflectance,
+                    eval_spectrum(textures, b.eta_texture, &b.eta, uv),
+                    eval_spectrum(textures, b.k_texture, &b.k, uv),
+                )
+            };
+            MaterialBxDF::Conductor(ConductorBxDF::new(
+                reflectance,
+                eta,
+                k,
+                crate::microfacet::TrowbridgeReitz::from_roughness(
+                    eval_float(textures, b.uroughness_texture, b.uroughness, uv).max(min_roughness),
+                    eval_float(textures, b.vroughness_texture, b.vroughness, uv).max(min_roughness),
+                    b.remaproughness,
+                ),
+                b.multiple_scattering,
+            ))
+        }
+        Material::CoaDiff(c) => {
+            let c = CoatedDiffuse {
+                reflectance: eval_spectrum(textures, c.reflectance_texture, &c.reflectance, uv),
+                uroughness: eval_float(textures, c.uroughness_texture, c.uroughness, uv),
+                vroughness: eval_float(textures, c.vroughness_texture, c.vroughness, uv),
+                ..*c
+            };
+            MaterialBxDF::CoatedDiffuse(CoatedDiffuseBxDF::coated_diffuse(&c, min_roughness))
+        }
+        Material::CoaCond(c) => {
+            let (conductor_eta, k) = if c.reflectance_texture != usize::MAX {
+                let r = eval_spectrum(textures, c.reflectance_texture, &[1f32; 3], uv);
+                conductor_eta_k_from_reflectance(&r)
+            } else {
+                (
+                    eval_spectrum(textures, c.conductor_eta_texture, &c.conductor_eta, uv),
+                    eval_spectrum(textures, c.k_texture, &c.k, uv),
+                )
+            };
+            let c = CoatedConductor {
+                interface_uroughness: eval_float(
+                    textures,
+                    c.interface_uroughness_texture,
+                    c.interface_uroughness,
+                    uv,
+                ),
+                interface_vroughness: eval_float(
+                    textures,
+                    c.interface_vroughness_texture,
+                    c.interface_vroughness,
+                    uv,
+                ),
+                conductor_uroughness: eval_float(
+                    textures,
+                    c.conductor_uroughness_texture,
+                    c.conductor_uroughness,
+                    uv,
+                ),
+                conductor_vroughness: eval_float(
+                    textures,
+                    c.conductor_vroughness_texture,
+                    c.conductor_vroughness,
+                    uv,
+                ),
+                conductor_eta,
+                k,
+                ..*c
+            };
+            MaterialBxDF::CoatedConductor(CoatedConductorBxDF::coated_conductor(&c, min_roughness))
+        }
+        Material::Diel(c) => MaterialBxDF::Dielectric(DielectricBxDF {
+            eta: eval_spectrum(textures, c.eta_texture, &c.eta, uv),
+            distrib: crate::microfacet::TrowbridgeReitz::from_roughness(
+                eval_float(textures, c.uroughness_texture, c.uroughness, uv).max(min_roughness),
+                eval_float(textures, c.vroughness_texture, c.vroughness, uv).max(min_roughness),
+                c.remaproughness,
+            ),
+        }),
+        Material::ThinDiel(c) => MaterialBxDF::ThinDielectric(ThinDielectricBxDF { eta: c.eta }),
+        Material::DiffTrans(d) => MaterialBxDF::DiffuseTransmission(DiffuseTransmissionBxDF {
+            reflectance: eval_spectrum(textures, d.reflectance_texture, &d.reflectance, uv),
+            transmittance: eval_spectrum(textures, d.transmittance_texture, &d.transmittance, uv),
+        }),
+        Material::Measured(m) => MaterialBxDF::Measured(crate::measured_brdf::MeasuredBxDF {
+            brdf: m.brdf.clone(),
+        }),
+        Material::Principled(p) => MaterialBxDF::Principled(
+            crate::principled_bsdf::PrincipledBxDF::new(p, min_roughness),
+        ),
+        Material::Hair(h) => {
+            // the fiber frame is not defined on the surface without the tangent of the curve
+            tangent?;
+            MaterialBxDF::Hair(crate::hair_bsdf::FiberBxDF::from_material(h))
+        }
+        // the subsurface material is shaded by the random walk of `crate::subsurface`
+        // and the mix material is resolved by `choose_material` beforehand
+        Material::Subsurface(_) | Material::Mix(_) | Material::None => return None,
+    };
+    Some(bxdf)
+}
+
+/// lobes of the material computed from its parameters without building the BxDF.
+/// The integrators skip the light sampling for the purely specular materials.
+/// For the mix material, the union of the lobes of the components
+pub fn flags(
+    materials: &[Material],
+    i_material: usize,
+    textures: &[crate::textures::Texture],
+    uv: &[f32; 2],
+    min_roughness: f32,
+) -> crate::bxdf::BxDFFlags {
+    flags_nested(materials, i_material, textures, uv, min_roughness, 0)
+}
+
+/// [`flags`] of the material nested in `depth` mix materials
+fn flags_nested(
+    materials: &[Material],
+    i_material: usize,
+    textures: &[crate::textures::Texture],
+    uv: &[f32; 2],
+    min_roughness: f32,
+    depth: usize,
+) -> crate::bxdf::BxDFFlags {
+    use crate::bxdf::BxDFFlags;
+    use crate::textures::{eval_float, eval_spectrum};
+    // the lobe of the microfacet interface
+    let interface = |i_textures: [usize; 2], uroughness: f32, vroughness: f32, remap: bool| {
+        let distrib = crate::microfacet::TrowbridgeReitz::from_roughness(
+            eval_float(textures, i_textures[0], uroughness, uv).max(min_roughness),
+            eval_float(textures, i_textures[1], vroughness, uv).max(min_roughness),
+            remap,
+        );
+        if distrib.effectively_smooth() {
+            BxDFFlags::SPECULAR
+        } else {
+            BxDFFlags::GLOSSY
+        }
+    };
+    let is_black = |i_texture: usize, value: &[f32; 3]| {
+        eval_spectrum(textures, i_texture, value, uv) == [0f32; 3]
+    };
+    match &materials[i_material] {
+        Material::Mix(mix) => {
+            assert!(
+                depth < materials.len(),
+                "the mix material {} refers to itself",
+                i_material
+            );
+            let flags =
+                |i: usize| flags_nested(materials, i, textures, uv, min_roughness, depth + 1);
+            flags(mix.materials[0]) | flags(mix.materials[1])
+        }
+        Material::Diff(a) => {
+            if is_black(a.reflectance_texture, &a.reflectance) {
+                BxDFFlags::UNSET
+            } else {
+                BxDFFlags::DIFFUSE | BxDFFlags::REFLECTION
+            }
+        }
+        Material::Cond(b) => {
+            BxDFFlags::REFLECTION
+                | interface(
+                    [b.uroughness_texture, b.vroughness_texture],
+                    b.uroughness,
+                    b.vroughness,
+                    b.remaproughness,
+                )
+        }
+        Material::CoaDiff(c) => {
+            let top = BxDFFlags::REFLECTION
+                | BxDFFlags::TRANSMISSION
+                | interface(
+                    [c.uroughness_texture, c.vroughness_texture],
+                    c.uroughness,
+                    c.vroughness,
+                    c.remaproughness,
+                );
+            let bottom = if is_black(c.reflectance_texture, &c.reflectance) {
+                BxDFFlags::UNSET
+            } else {
+                BxDFFlags::DIFFUSE | BxDFFlags::REFLECTION
+            };
+            crate::layered_material::layered_flags(top, bottom, &c.albedo)
+        }
+        Material::CoaCond(c) => {
+            let top = BxDFFlags::REFLECTION
+                | BxDFFlags::TRANSMISSION
+                | interface(
+                    [
+                        c.interface_uroughness_texture,
+                        c.interface_vroughness_texture,
+                    ],
+                    c.interface_uroughness,
+                    c.interface_vroughness,
+                    c.remaproughness,
+                );
+            let bottom = BxDFFlags::REFLECTION
+                | interface(
+                    [
+                        c.conductor_uroughness_texture,
+                        c.conductor_vroughness_texture,
+                    ],
+                    c.conductor_uroughness,
+                    c.conductor_vroughness,
+                    c.remaproughness,
+                );
+            crate::layered_material::layered_flags(top, bottom, &c.albedo)
+        }
+        Material::Diel(c) => {
+            BxDFFlags::REFLECTION
+                | BxDFFlags::TRANSMISSION
+                | interface(
+                    [c.uroughness_texture, c.vroughness_texture],
+                    c.uroughness,
+                    c.vroughness,
+                    c.remaproughness,
+                )
+        }
+        Material::ThinDiel(_) => {
+            BxDFFlags::REFLECTION | BxDFFlags::TRANSMISSION | BxDFFlags::SPECULAR
+        }
+        Material::DiffTrans(d) => {
+            let mut flags = BxDFFlags::UNSET;
+            if !is_black(d.reflectance_texture, &d.reflectance) {
+                flags = flags | BxDFFlags::REFLECTION | BxDFFlags::DIFFUSE;
+            }
+            if !is_black(d.transmittance_texture, &d.transmittance) {
+                flags = flags | BxDFFlags::TRANSMISSION | BxDFFlags::DIFFUSE;
+            }
+            flags
+        }
+        Material::Measured(_) => BxDFFlags::REFLECTION | BxDFFlags::GLOSSY,
+        Material::Principled(p) => {
+            let mut flags = BxDFFlags::REFLECTION | BxDFFlags::GLOSSY;
+            if (1f32 - p.metallic) * (1f32 - p.transmission) > 0f32 {
+                flags = flags | BxDFFlags::DIFFUSE;
+            }
+            if (1f32 - p.metallic) * p.transmission > 0f32 {
+                flags = flags | BxDFFlags::TRANSMISSION;
+            }
+            flags
+        }
+        Material::Hair(_) => BxDFFlags::GLOSSY | BxDFFlags::REFLECTION | BxDFFlags::TRANSMISSION,
+        Material::Subsurface(_) => {
+            BxDFFlags::SPECULAR | BxDFFlags::REFLECTION | BxDFFlags::TRANSMISSION
+        }
+        Material::None => BxDFFlags::UNSET,
+    }
+}
+
+/// transformation from the local frame of [`bxdf`] to the world.
+/// The z-axis is the normal and the x-axis is the tangent if it is given
+pub fn local_frame(obj_nrm: &[f32; 3], tangent: Option<&[f32; 3]>) -> [f32; 9] {
+    match tangent {
+        Some(tangent) => {
+            crate::microfacet::transform_lcl2world_given_normal_tangent(obj_nrm, tangent)
+        }
+        None => del_geo_core::mat3_col_major::transform_lcl2world_given_local_z(obj_nrm),
+    }
+}
+
+/// sample the outgoing direction
+/// # Return
+/// the sample whose `wi` is the outgoing direction in the world coordinate.
+/// `wi` is below the surface for the transmission
+/// * `uv` - texture coordinate of the hit point. See [`bxdf`]
+/// * `obj_nrm` - unit normal of the local frame, perturbed by [`crate::shading_normal`] if any
+/// * `tangent` - tangent of the curve for the hair material, otherwise `None`. See [`bxdf`]
+#[allow(clippy::too_many_arguments)]
 pub fn sample_brdf<RNG>(
     mat: &Material,
+    textures: &[crate::textures::Texture],
+    uv: &[f32; 2],
     obj_nrm: &[f32; 3],
+    tangent: Option<&[f32; 3]>,
     ray_in_outward_world: &[f32; 3],
     rng: &mut RNG,
     min_roughness: f32,
-) -> Option<([f32; 3], [f32; 3], f32)>
+) -> Option<crate::bxdf::BSDFSample>
 where
     RNG: rand::Rng,
 {
+    use crate::bxdf::BxDF;
     use del_geo_core::mat3_col_major;
     use del_geo_core::vec3;
     debug_assert!((vec3::norm(obj_nrm) - 1f32).abs() < 1.0e-5);
     debug_assert!((vec3::norm(ray_in_outward_world) - 1f32).abs() < 1.0e-5);
-    let transform_objlcl2world = mat3_col_major::transform_lcl2world_given_local_z(obj_nrm);
+    let bxdf = bxdf(mat, textures, uv, tangent, min_roughness)?;
+    let transform_objlcl2world = local_frame(obj_nrm, tangent);
     let transform_world2objlcl = mat3_col_major::transpose(&transform_objlcl2world);
-    let (ray_out_objlcl, brdf, pdf) = match mat {
-        Material::Diff(a) => sample_brdf_diffuse(&a.reflectance, rng),
-        Material::Cond(b) => {
-            let ray_in_objlcl =
-                mat3_col_major::mult_vec(&transform_world2objlcl, ray_in_outward_world);
-            sample_brdf_rough_conductor(
-                &ray_in_objlcl,
-                &b.reflectance,
-                &b.eta,
-                &b.k,
-                b.uroughness.max(min_roughness),
-                rng,
-            )?
-        }
-        Material::CoaDiff(_) => {
-            eprintln!("Not implement sample CoaDiff");
-            return None;
-        }
-        Material::None => return None,
-    };
-    debug_assert!((vec3::norm(&ray_out_objlcl) - 1f32).abs() < 1.0e-5);
-    debug_assert!(ray_out_objlcl[2] >= 0f32);
-    assert!(!brdf[0].is_nan() && !brdf[1].is_nan() && !brdf[2].is_nan());
-    let ray_out_world = mat3_col_major::mult_vec(&transform_objlcl2world, &ray_out_objlcl);
-    Some((ray_out_world, brdf, pdf))
+    let ray_in_objlcl = mat3_col_major::mult_vec(&transform_world2objlcl, ray_in_outward_world);
+    let mut bs = bxdf.sample_f(
+        &ray_in_objlcl,
+        rng.random::<f32>(),
+        &[rng.random::<f32>(), rng.random::<f32>()],
+        crate::bxdf::TransportMode::Radiance,
+        crate::bxdf::BxDFReflTransFlags::ALL,
+    )?;
+    debug_assert!((vec3::norm(&bs.wi) - 1f32).abs() < 1.0e-4);
+    debug_assert!(bs.wi[2] >= 0f32 || bxdf.flags().is_transmissive() || ray_in_objlcl[2] < 0f32);
+    assert!(!bs.f[0].is_nan() && !bs.f[1].is_nan() && !bs.f[2].is_nan());
+    bs.wi = mat3_col_major::mult_vec(&transform_objlcl2world, &bs.wi);
+    Some(bs)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn eval_brdf(
     mat: &Material,
+    textures: &[crate::textures::Texture],
+    uv: &[f32; 2],
     obj_nrm: &[f32; 3],
+    tangent: Option<&[f32; 3]>,
     ray_in_outward_normalized: &[f32; 3],
     ray_out: &[f32; 3],
     minimum_roughness: f32,
 ) -> [f32; 3] {
+    use crate::bxdf::BxDF;
     use del_geo_core::mat3_col_major;
     use del_geo_core::vec3;
     assert!(
@@ -345,31 +1073,111 @@ pub fn eval_brdf(
         "{}",
         vec3::norm(ray_in_outward_normalized)
     );
-    let transform_objlcl2world = mat3_col_major::transform_lcl2world_given_local_z(obj_nrm);
+    let Some(bxdf) = bxdf(mat, textures, uv, tangent, minimum_roughness) else {
+        return [0f32; 3];
+    };
+    let transform_objlcl2world = local_frame(obj_nrm, tangent);
     let transform_world2objlcl = mat3_col_major::transpose(&transform_objlcl2world);
-    match mat {
-        Material::Diff(a) => eval_brdf_diffuse(&a.reflectance),
-        Material::Cond(b) => {
-            let ray_in_objlcl =
-                mat3_col_major::mult_vec(&transform_world2objlcl, ray_in_outward_normalized);
-            assert!((vec3::norm(&ray_in_objlcl) - 1.).abs() < 1.0e-3);
-            let ray_out_objlcl = mat3_col_major::mult_vec(&transform_world2objlcl, ray_out);
-            assert!((vec3::norm(&ray_out_objlcl) - 1.).abs() < 1.0e-3);
-            eval_brdf_rough_conductor(
-                &ray_in_objlcl,
-                &ray_out_objlcl,
-                &b.reflectance,
-                &b.eta,
-                &b.k,
-                b.uroughness.max(minimum_roughness),
-            )
-            // eval_brdf_diffuse(&b.reflectance)
-        }
-        Material::CoaDiff(_) => {
-            eprintln!("Not implement eval CoaDiff");
-            [0f32; 3]
-        }
+    let ray_in_objlcl =
+        mat3_col_major::mult_vec(&transform_world2objlcl, ray_in_outward_normalized);
+    let ray_out_objlcl = mat3_col_major::mult_vec(&transform_world2objlcl, ray_out);
+    bxdf.f(
+        &ray_in_objlcl,
+        &ray_out_objlcl,
+        crate::bxdf::TransportMode::Radiance,
+    )
+}
 
-        Material::None => [0f32; 3],
-    }
+/// pdf of the direction sampled by [`sample_brdf`] on the unit sphere.
+/// Zero for the delta lobes (e.g., the smooth dielectric) that cannot be hit by the other sampling strategies.
+/// For the dispersive dielectric, the average of the pdfs of the RGB channels is returned
+#[allow(clippy::too_many_arguments)]
+pub fn pdf_brdf(
+    mat: &Material,
+    textures: &[crate::textures::Texture],
+    uv: &[f32; 2],
+    obj_nrm: &[f32; 3],
+    tangent: Option<&[f32; 3]>,
+    ray_in_outward_normalized: &[f32; 3],
+    ray_out: &[f32; 3],
+    minimum_roughness: f32,
+) -> f32 {
+    use crate::bxdf::BxDF;
+    use del_geo_core::mat3_col_major;
+    let Some(bxdf) = bxdf(mat, textures, uv, tangent, minimum_roughness) else {
+        return 0f32;
+    };
+    let transform_objlcl2world = local_frame(obj_nrm, tangent);
+    let transform_world2objlcl = mat3_col_major::transpose(&transform_objlcl2world);
+    let wo = mat3_col_major::mult_vec(&transform_world2objlcl, ray_in_outward_normalized);
+    let wi = mat3_col_major::mult_vec(&transform_world2objlcl, ray_out);
+    bxdf.pdf(
+        &wo,
+        &wi,
+        crate::bxdf::TransportMode::Radiance,
+        crate::bxdf::BxDFReflTransFlags::ALL,
+    )
+}
+
+#[test]
+fn test_mix_cycle() {
+    let mix = |materials: [usize; 2]| {
+        Material::Mix(MixMaterial {
+            materials,
+            amount: 0.5,
+            amount_texture: usize::MAX,
+        })
+    };
+    let diffuse = Material::Diff(DiffuseMaterial {
+        reflectance: [0.5; 3],
+        reflectance_texture: usize::MAX,
+    });
+    // 0 -> (1, 2) -> 3 is acyclic but 4 -> 5 -> 4 is cyclic
+    let materials = [
+        mix([1, 2]),
+        mix([3, 3]),
+        diffuse,
+        mix([2, 2]),
+        mix([2, 5]),
+        mix([4, 2]),
+    ];
+    assert!(!is_mix_cyclic(&materials, 0));
+    assert!(!is_mix_cyclic(&materials, 2));
+    assert!(is_mix_cyclic(&materials, 4));
+    assert!(is_mix_cyclic(&materials, 5));
+    let flags = std::panic::catch_unwind(|| flags(&materials, 4, &[], &[0.; 2], 0.));
+    assert!(flags.is_err());
+}
+
+#[test]
+fn test_mix_parallel_rays() {
+    let diffuse = |r: f32| {
+        Material::Diff(DiffuseMaterial {
+            reflectance: [r; 3],
+            reflectance_texture: usize::MAX,
+        })
+    };
+    let materials = [
+        Material::Mix(MixMaterial {
+            materials: [1, 2],
+            amount: 0.3,
+            amount_texture: usize::MAX,
+        }),
+        diffuse(0.2),
+        diffuse(0.8),
+    ];
+    // the parallel rays hitting the plane at the different points blend the components
+    let ray_in = [0f32, 0.6, 0.8];
+    let num_point = 1000;
+    let num_second = (0..num_point)
+        .filter(|&i| {
+            let pos = [(i % 40) as f32 * 0.1, (i / 40) as f32 * 0.1, 0.];
+            let mat = choose_material(&materials, 0, &[], &[0.; 2], &pos, &ray_in);
+            std::ptr::eq(mat, &materials[2])
+        })
+        .count();
+    let ratio = num_second as f32 / num_point as f32;
+    assert!((ratio - 0.3).abs() < 0.05, "{}", ratio);
+    // the unresolved mix material has no BxDF
+    assert!(bxdf(&materials[0], &[], &[0.; 2], None, 0.).is_none());
 }
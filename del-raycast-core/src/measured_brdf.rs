@@ -0,0 +1,316 @@
+//! Isotropic BRDF measured by the MERL database (`.binary` file).
+//! The table is parameterized by the half and the difference angles of
+//! "A Data-Driven Reflectance Model" by Wojciech Matusik et al. 2003.
+//! See <https://www.merl.com/brdf/>
+//!
+//! As in pbrt, `wo` is the unit vector toward the viewer and `wi` is the one toward the light.
+//! Both are in the local frame whose z-axis is the normal.
+
+const NUM_THETA_H: usize = 90;
+const NUM_THETA_D: usize = 90;
+const NUM_PHI_D: usize = 180;
+
+/// scales of the RGB channels in the MERL table
+const SCALE_RGB: [f32; 3] = [1f32 / 1500f32, 1.15f32 / 1500f32, 1.66f32 / 1500f32];
+
+/// resolution of the azimuth of the half vector in the distribution for the importance sampling
+const NUM_PHI_H: usize = 32;
+
+pub struct MerlBrdf {
+    /// the scaled values of the RGB channels. The index is `((i_theta_h * NUM_THETA_D + i_theta_d) * NUM_PHI_D + i_phi_d) * 3 + i_channel`
+    pub data: Vec<f32>,
+    /// distribution of the half vector in the coordinate `(sqrt(theta_h / (PI/2)), phi_h / (2 PI))`
+    pub distrib_half: crate::sampling::PiecewiseConstant2D,
+}
+
+impl std::fmt::Debug for MerlBrdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MerlBrdf")
+            .field("num_data", &self.data.len())
+            .finish()
+    }
+}
+
+/// half and difference angles `(theta_h, theta_d, phi_d)` of the pair of the directions.
+/// `phi_d` is in `[0, PI)` using the reciprocity
+fn half_diff_angles(wo: &[f32; 3], wi: &[f32; 3]) -> Option<(f32, f32, f32)> {
+    use del_geo_core::vec3::Vec3;
+    let h = wo.add(wi);
+    if h.norm() < f32::EPSILON {
+        return None;
+    }
+    let h = h.normalize();
+    let theta_h = h[2].clamp(-1f32, 1f32).acos();
+    let phi_h = h[1].atan2(h[0]);
+    // rotate `wi` so that the half vector becomes the z-axis
+    let (cp, sp) = (phi_h.cos(), phi_h.sin());
+    let d = [cp * wi[0] + sp * wi[1], -sp * wi[0] + cp * wi[1], wi[2]];
+    let (ct, st) = (theta_h.cos(), theta_h.sin());
+    let d = [ct * d[0] - st * d[2], d[1], st * d[0] + ct * d[2]];
+    let theta_d = d[2].clamp(-1f32, 1f32).acos();
+    let mut phi_d = d[1].atan2(d[0]);
+    if phi_d < 0f32 {
+        phi_d += std::f32::consts::PI;
+    }
+    Some((theta_h, theta_d, phi_d))
+}
+
+impl MerlBrdf {
+    /// * `table` - the raw values in the file (the red, green and blue blocks in this order)
+    pub fn from_table(table: &[f64]) -> anyhow::Result<Self> {
+        let n = NUM_THETA_H * NUM_THETA_D * NUM_PHI_D;
+        if table.len() != n * 3 {
+            return Err(anyhow::anyhow!("invalid size of the MERL table"));
+        }
+        let mut data = vec![0f32; n * 3];
+        for i in 0..n {
+            for i_channel in 0..3 {
+                // the negative values mark the missing measurements
+                data[i * 3 + i_channel] =
+                    (table[i_channel * n + i] as f32).max(0f32) * SCALE_RGB[i_channel];
+            }
+        }
+        let distrib_half = Self::distribution_of_half_vector(&data);
+        Ok(MerlBrdf { data, distrib_half })
+    }
+
+    pub fn read_from<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() < 12 {
+            return Err(anyhow::anyhow!("invalid header of the MERL file"));
+        }
+        let dims: Vec<usize> = bytes[0..12]
+            .chunks(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .collect();
+        if dims != [NUM_THETA_H, NUM_THETA_D, NUM_PHI_D] {
+            return Err(anyhow::anyhow!("unsupported dimensions of the MERL file"));
+        }
+        let table: Vec<f64> = bytes[12..]
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        Self::from_table(&table)
+    }
+
+    /// the density of the half vector proportional to the average value at each `theta_h` times the cosine
+    fn distribution_of_half_vector(data: &[f32]) -> crate::sampling::PiecewiseConstant2D {
+        let mut func = vec![0f32; NUM_THETA_H * NUM_PHI_H];
+        for i_theta_h in 0..NUM_THETA_H {
+            let u = (i_theta_h as f32 + 0.5) / NUM_THETA_H as f32;
+            let theta_h = u * u * std::f32::consts::FRAC_PI_2;
+            let n = NUM_THETA_D * NUM_PHI_D;
+            let avg = data[i_theta_h * n * 3..(i_theta_h + 1) * n * 3]
+                .iter()
+                .sum::<f32>()
+                / (n * 3) as f32;
+            // Jacobian from the solid angle to `(u, v)`
+            let jacobian = std::f32::consts::PI * u * 2f32 * std::f32::consts::PI * theta_h.sin();
+            for i_phi_h in 0..NUM_PHI_H {
+                func[i_phi_h * NUM_THETA_H + i_theta_h] = avg * theta_h.cos() * jacobian;
+            }
+        }
+        // small floor so that every direction can be sampled
+        let max = func.iter().fold(0f32, |a, &b| a.max(b));
+        func.iter_mut().for_each(|v| *v = v.max(1.0e-3 * max));
+        crate::sampling::PiecewiseConstant2D::new(&func, NUM_THETA_H, NUM_PHI_H)
+    }
+
+    /// value at the continuous index with the trilinear interpolation
+    fn lookup(&self, theta_h: f32, theta_d: f32, phi_d: f32) -> [f32; 3] {
+        // `theta_h` is sampled densely around the specular peak
+        let x_h = (theta_h / std::f32::consts::FRAC_PI_2).max(0f32).sqrt() * NUM_THETA_H as f32;
+        let x_d = theta_d / std::f32::consts::FRAC_PI_2 * NUM_THETA_D as f32;
+        let x_p = phi_d / std::f32::consts::PI * NUM_PHI_D as f32;
+        let split = |x: f32, n: usize| {
+            let x = x.clamp(0f32, (n - 1) as f32);
+            let i0 = (x.floor() as usize).min(n - 1);
+            (i0, (i0 + 1).min(n - 1), x - i0 as f32)
+        };
+        let (h0, h1, th) = split(x_h, NUM_THETA_H);
+        let (d0, d1, td) = split(x_d, NUM_THETA_D);
+        // `phi_d` is periodic with `PI`
+        let x_p = x_p.rem_euclid(NUM_PHI_D as f32);
+        let p0 = (x_p.floor() as usize).min(NUM_PHI_D - 1);
+        let (p1, tp) = ((p0 + 1) % NUM_PHI_D, x_p - p0 as f32);
+        let mut val = [0f32; 3];
+        for (ih, wh) in [(h0, 1f32 - th), (h1, th)] {
+            for (id, wd) in [(d0, 1f32 - td), (d1, td)] {
+                for (ip, wp) in [(p0, 1f32 - tp), (p1, tp)] {
+                    let w = wh * wd * wp;
+                    if w == 0f32 {
+                        continue;
+                    }
+                    let i = ((ih * NUM_THETA_D + id) * NUM_PHI_D + ip) * 3;
+                    for (v, d) in val.iter_mut().zip(&self.data[i..i + 3]) {
+                        *v += w * d;
+                    }
+                }
+            }
+        }
+        val
+    }
+
+    pub fn eval(&self, wo: &[f32; 3], wi: &[f32; 3]) -> [f32; 3] {
+        if wo[2] <= 0f32 || wi[2] <= 0f32 {
+            return [0f32; 3];
+        }
+        let Some((theta_h, theta_d, phi_d)) = half_diff_angles(wo, wi) else {
+            return [0f32; 3];
+        };
+        self.lookup(theta_h, theta_d, phi_d)
+    }
+
+    /// pdf of the direction sampled by [`MerlBrdf::sample`]
+    pub fn pdf(&self, wo: &[f32; 3], wi: &[f32; 3]) -> f32 {
+        use del_geo_core::vec3::Vec3;
+        if wo[2] <= 0f32 || wi[2] <= 0f32 {
+            return 0f32;
+        }
+        let h = wo.add(wi);
+        if h.norm() < f32::EPSILON {
+            return 0f32;
+        }
+        let h = h.normalize();
+        let theta_h = h[2].clamp(-1f32, 1f32).acos();
+        let mut phi_h = h[1].atan2(h[0]);
+        if phi_h < 0f32 {
+            phi_h += 2f32 * std::f32::consts::PI;
+        }
+        let u = (theta_h / std::f32::consts::FRAC_PI_2).sqrt();
+        let v = phi_h / (2f32 * std::f32::consts::PI);
+        let jacobian = std::f32::consts::PI * u * 2f32 * std::f32::consts::PI * theta_h.sin();
+        if jacobian <= 0f32 {
+            return 0f32;
+        }
+        let pdf_h = self.distrib_half.pdf(&[u.min(1f32 - f32::EPSILON), v]) / jacobian;
+        pdf_h / (4f32 * wo.dot(&h))
+    }
+
+    /// sample the direction toward the light by reflecting `wo` with the sampled half vector
+    /// * `u` - uniform random numbers in `[0, 1)`
+    /// # Return
+    /// `(wi, brdf, pdf)`
+    pub fn sample(&self, wo: &[f32; 3], u: &[f32; 2]) -> Option<([f32; 3], [f32; 3], f32)> {
+        use del_geo_core::vec3::Vec3;
+        if wo[2] <= 0f32 {
+            return None;
+        }
+        let (uv, _pdf_uv) = self.distrib_half.sample(u);
+        let theta_h = uv[0] * uv[0] * std::f32::consts::FRAC_PI_2;
+        let phi_h = uv[1] * 2f32 * std::f32::consts::PI;
+        let h = [
+            theta_h.sin() * phi_h.cos(),
+            theta_h.sin() * phi_h.sin(),
+            theta_h.cos(),
+        ];
+        let wo_dot_h = wo.dot(&h);
+        if wo_dot_h <= 0f32 {
+            return None;
+        }
+        let wi = h.scale(2f32 * wo_dot_h).sub(wo);
+        if wi[2] <= 0f32 {
+            return None;
+        }
+        let pdf = self.pdf(wo, &wi);
+        if pdf <= 0f32 {
+            return None;
+        }
+        Some((wi, self.eval(wo, &wi), pdf))
+    }
+}
+
+/// measured BRDF shared among the materials
+pub struct MeasuredBxDF {
+    pub brdf: std::sync::Arc<MerlBrdf>,
+}
+
+impl crate::bxdf::BxDF for MeasuredBxDF {
+    fn flags(&self) -> crate::bxdf::BxDFFlags {
+        crate::bxdf::BxDFFlags::REFLECTION | crate::bxdf::BxDFFlags::GLOSSY
+    }
+
+    fn f(&self, wo: &[f32; 3], wi: &[f32; 3], _mode: crate::bxdf::TransportMode) -> [f32; 3] {
+        self.brdf.eval(wo, wi)
+    }
+
+    fn sample_f(
+        &self,
+        wo: &[f32; 3],
+        _uc: f32,
+        u: &[f32; 2],
+        _mode: crate::bxdf::TransportMode,
+        sample_flags: crate::bxdf::BxDFReflTransFlags,
+    ) -> Option<crate::bxdf::BSDFSample> {
+        if !sample_flags.has_reflection() {
+            return None;
+        }
+        let (wi, f, pdf) = self.brdf.sample(wo, u)?;
+        Some(crate::bxdf::BSDFSample {
+            f,
+            wi,
+            pdf,
+            flag: self.flags(),
+            pdf_is_proportional: false,
+        })
+    }
+
+    fn pdf(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        _mode: crate::bxdf::TransportMode,
+        sample_flags: crate::bxdf::BxDFReflTransFlags,
+    ) -> f32 {
+        if !sample_flags.has_reflection() {
+            return 0f32;
+        }
+        self.brdf.pdf(wo, wi)
+    }
+}
+
+#[test]
+fn test_merl_brdf() {
+    use rand::Rng;
+    use rand::SeedableRng;
+    // synthetic table peaked around the specular direction
+    let n = NUM_THETA_H * NUM_THETA_D * NUM_PHI_D;
+    let table: Vec<f64> = (0..n * 3)
+        .map(|i| {
+            let i_theta_h = (i % n) / (NUM_THETA_D * NUM_PHI_D);
+            1500. * (0.1 + 10. * (-(i_theta_h as f64) * 0.2).exp())
+        })
+        .collect();
+    let brdf = MerlBrdf::from_table(&table).unwrap();
+    // the value at the grid point is reproduced
+    let f = brdf.lookup(0., 0.3, 0.2);
+    assert!((f[0] - 10.1).abs() < 1.0e-3, "{}", f[0]);
+    assert!((f[2] - 10.1 * 1.66).abs() < 1.0e-2, "{}", f[2]);
+    let wo = del_geo_core::vec3::normalize(&[0.3, -0.2, 0.8]);
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    let num_sample = 20000;
+    let (mut sum_pdf, mut sum_is, mut sum_uniform) = (0f32, 0f32, 0f32);
+    for _ in 0..num_sample {
+        if let Some((wi, f, pdf)) = brdf.sample(&wo, &[rng.random::<f32>(), rng.random::<f32>()]) {
+            assert!((brdf.pdf(&wo, &wi) - pdf).abs() < 1.0e-3 * pdf);
+            sum_is += f[0] * wi[2] / pdf / num_sample as f32;
+        }
+        // cosine-weighted hemisphere sampling for the reference
+        let wi = crate::sampling::hemisphere_zup_cos_weighted(&[
+            rng.random::<f32>(),
+            rng.random::<f32>(),
+        ]);
+        let pdf_cos = wi[2] * std::f32::consts::FRAC_1_PI;
+        sum_pdf += brdf.pdf(&wo, &wi) / pdf_cos / num_sample as f32;
+        sum_uniform += brdf.eval(&wo, &wi)[0] * wi[2] / pdf_cos / num_sample as f32;
+    }
+    // the pdf integrates to at most one (the half vectors reflecting below the horizon are lost)
+    assert!(sum_pdf < 1.05 && sum_pdf > 0.5, "{}", sum_pdf);
+    // the importance sampling agrees with the cosine-weighted sampling
+    assert!(
+        (sum_is - sum_uniform).abs() < 0.1 * sum_uniform,
+        "{} {}",
+        sum_is,
+        sum_uniform
+    );
+}
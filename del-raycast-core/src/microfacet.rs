@@ -0,0 +1,198 @@
+//! Anisotropic Trowbridge-Reitz (GGX) microfacet distribution with the sampling of the visible normals.
+//! The directions are in the local frame whose z-axis is the normal and x-axis is the tangent
+//! along which the roughness is `alpha_x`.
+//! See <https://pbr-book.org/4ed/Reflection_Models/Roughness_Using_Microfacet_Theory> and
+//! "Sampling the GGX Distribution of Visible Normals" by Eric Heitz 2018
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrowbridgeReitz {
+    pub alpha_x: f32,
+    pub alpha_y: f32,
+}
+
+fn cos_sin_phi(w: &[f32; 3]) -> (f32, f32) {
+    let sin_theta = (w[0] * w[0] + w[1] * w[1]).sqrt();
+    if sin_theta == 0f32 {
+        return (1f32, 0f32);
+    }
+    (
+        (w[0] / sin_theta).clamp(-1f32, 1f32),
+        (w[1] / sin_theta).clamp(-1f32, 1f32),
+    )
+}
+
+fn tan2_theta(w: &[f32; 3]) -> f32 {
+    let cos2_theta = w[2] * w[2];
+    (1f32 - cos2_theta).max(0f32) / cos2_theta
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f32, alpha_y: f32) -> Self {
+        // too small alpha causes the numerical issues
+        let (alpha_x, alpha_y) = (
+            if alpha_x < 1.0e-4 { 0f32 } else { alpha_x },
+            if alpha_y < 1.0e-4 { 0f32 } else { alpha_y },
+        );
+        TrowbridgeReitz { alpha_x, alpha_y }
+    }
+
+    /// distribution from the roughness parameters of the material.
+    /// The roughness is used as alpha as it is if `remaproughness` is false
+    pub fn from_roughness(uroughness: f32, vroughness: f32, remaproughness: bool) -> Self {
+        if remaproughness {
+            Self::new(
+                Self::roughness_to_alpha(uroughness),
+                Self::roughness_to_alpha(vroughness),
+            )
+        } else {
+            Self::new(uroughness, vroughness)
+        }
+    }
+
+    /// map the perceptually linear roughness to alpha as in pbrt-v4
+    pub fn roughness_to_alpha(roughness: f32) -> f32 {
+        roughness.sqrt()
+    }
+
+    /// true if the surface should be treated as the perfect mirror (delta lobe)
+    pub fn effectively_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1.0e-3
+    }
+
+    /// distribution of the microfacet normals `wm` in the upper hemisphere
+    pub fn d(&self, wm: &[f32; 3]) -> f32 {
+        let tan2_theta = tan2_theta(wm);
+        if tan2_theta.is_infinite() || tan2_theta.is_nan() {
+            return 0f32;
+        }
+        let cos4_theta = (wm[2] * wm[2]) * (wm[2] * wm[2]);
+        if cos4_theta < 1.0e-16 {
+            return 0f32;
+        }
+        let (cos_phi, sin_phi) = cos_sin_phi(wm);
+        let e = tan2_theta
+            * ((cos_phi / self.alpha_x) * (cos_phi / self.alpha_x)
+                + (sin_phi / self.alpha_y) * (sin_phi / self.alpha_y));
+        1f32 / (std::f32::consts::PI
+            * self.alpha_x
+            * self.alpha_y
+            * cos4_theta
+            * (1f32 + e)
+            * (1f32 + e))
+    }
+
+    /// Smith's auxiliary function
+    pub fn lambda(&self, w: &[f32; 3]) -> f32 {
+        let tan2_theta = tan2_theta(w);
+        if tan2_theta.is_infinite() || tan2_theta.is_nan() {
+            return 0f32;
+        }
+        let (cos_phi, sin_phi) = cos_sin_phi(w);
+        let alpha2 = (cos_phi * self.alpha_x) * (cos_phi * self.alpha_x)
+            + (sin_phi * self.alpha_y) * (sin_phi * self.alpha_y);
+        ((1f32 + alpha2 * tan2_theta).sqrt() - 1f32) * 0.5f32
+    }
+
+    /// masking function
+    pub fn g1(&self, w: &[f32; 3]) -> f32 {
+        1f32 / (1f32 + self.lambda(w))
+    }
+
+    /// height-correlated masking-shadowing function
+    pub fn g(&self, wo: &[f32; 3], wi: &[f32; 3]) -> f32 {
+        1f32 / (1f32 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// distribution of the normals visible from the direction `w`
+    pub fn d_visible(&self, w: &[f32; 3], wm: &[f32; 3]) -> f32 {
+        use del_geo_core::vec3::Vec3;
+        if w[2] == 0f32 {
+            return 0f32;
+        }
+        self.g1(w) / w[2].abs() * self.d(wm) * w.dot(wm).abs()
+    }
+
+    /// pdf of the microfacet normal sampled by [`TrowbridgeReitz::sample_wm`]
+    pub fn pdf(&self, w: &[f32; 3], wm: &[f32; 3]) -> f32 {
+        self.d_visible(w, wm)
+    }
+
+    /// sample the microfacet normal visible from the direction `w`
+    /// * `u` - uniform random numbers in `[0, 1)`
+    pub fn sample_wm(&self, w: &[f32; 3], u: &[f32; 2]) -> [f32; 3] {
+        use del_geo_core::vec3::Vec3;
+        // transform to the hemispherical configuration
+        let wh = [self.alpha_x * w[0], self.alpha_y * w[1], w[2]].normalize();
+        let wh = if wh[2] < 0f32 { wh.scale(-1f32) } else { wh };
+        let t1 = if wh[2] < 0.99999f32 {
+            [0f32, 0f32, 1f32].cross(&wh).normalize()
+        } else {
+            [1f32, 0f32, 0f32]
+        };
+        let t2 = wh.cross(&t1);
+        // uniformly distributed point on the disk
+        let r = u[0].sqrt();
+        let phi = 2f32 * std::f32::consts::PI * u[1];
+        let (px, py) = (r * phi.cos(), r * phi.sin());
+        // warp to the projection of the hemisphere
+        let h = (1f32 - px * px).max(0f32).sqrt();
+        let s = 0.5f32 * (1f32 + wh[2]);
+        let py = (1f32 - s) * h + s * py;
+        let pz = (1f32 - px * px - py * py).max(0f32).sqrt();
+        let nh = t1.scale(px).add(&t2.scale(py)).add(&wh.scale(pz));
+        // back to the ellipsoid configuration
+        [
+            self.alpha_x * nh[0],
+            self.alpha_y * nh[1],
+            nh[2].max(1.0e-6),
+        ]
+        .normalize()
+    }
+}
+
+/// transformation from the local frame to the world given the normal (local z-axis)
+/// and the tangent (local x-axis). The tangent is orthogonalized against the normal
+pub fn transform_lcl2world_given_normal_tangent(nrm: &[f32; 3], tangent: &[f32; 3]) -> [f32; 9] {
+    use del_geo_core::vec3::Vec3;
+    let t = tangent.sub(&nrm.scale(nrm.dot(tangent)));
+    if t.norm() < 1.0e-6 {
+        return del_geo_core::mat3_col_major::transform_lcl2world_given_local_z(nrm);
+    }
+    let t = t.normalize();
+    let b = nrm.cross(&t);
+    [t[0], t[1], t[2], b[0], b[1], b[2], nrm[0], nrm[1], nrm[2]]
+}
+
+#[test]
+fn test_trowbridge_reitz() {
+    use del_geo_core::vec3::Vec3;
+    use rand::Rng;
+    use rand::SeedableRng;
+    let distrib = TrowbridgeReitz::new(0.5, 0.25);
+    let w = [0.5f32, 0.2, 0.7].normalize();
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    // `\int D(wm) cos(wm) dwm = 1` and `\int D_w(wm) dwm = 1` with the uniform hemisphere sampling
+    let num_sample = 200000;
+    let (mut sum_d, mut sum_dv) = (0f32, 0f32);
+    for _ in 0..num_sample {
+        let (u0, u1) = (rng.random::<f32>(), rng.random::<f32>());
+        let z = u0;
+        let r = (1f32 - z * z).max(0f32).sqrt();
+        let phi = 2f32 * std::f32::consts::PI * u1;
+        let wm = [r * phi.cos(), r * phi.sin(), z];
+        let weight = 2f32 * std::f32::consts::PI / num_sample as f32;
+        sum_d += distrib.d(&wm) * wm[2] * weight;
+        if w.dot(&wm) > 0f32 {
+            sum_dv += distrib.d_visible(&w, &wm) * weight;
+        }
+    }
+    assert!((sum_d - 1.).abs() < 0.05, "{}", sum_d);
+    assert!((sum_dv - 1.).abs() < 0.05, "{}", sum_dv);
+    // the sampled normals are the visible ones
+    for _ in 0..1000 {
+        let wm = distrib.sample_wm(&w, &[rng.random::<f32>(), rng.random::<f32>()]);
+        assert!((wm.norm() - 1.).abs() < 1.0e-4);
+        assert!(wm[2] > 0. && w.dot(&wm) > -1.0e-4);
+        assert!(distrib.pdf(&w, &wm) > 0.);
+    }
+}
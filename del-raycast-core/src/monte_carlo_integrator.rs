@@ -1,29 +1,79 @@
 pub trait Scene {
+    /// # Return
+    /// `Some((position, normal, emission, roughness, i_shape_entity, uv, shading_normal, tangent))`
+    /// where `uv` is the texture coordinate and `shading_normal` is the normal perturbed
+    /// by the bump or the normal map (see [`crate::shading_normal`]).
+    /// The materials are evaluated with `shading_normal` while `normal` offsets the next ray.
+    /// `tangent` is the tangent of the curve (see [`crate::shape::tangent_at`]) and `None` for the surfaces
     #[allow(clippy::type_complexity)]
     fn hit_position_normal_emission_roughness_at_ray_intersection(
         &self,
         ray_org: &[f32; 3],
         ray_dir: &[f32; 3],
-    ) -> Option<([f32; 3], [f32; 3], [f32; 3], f32, usize)>;
+    ) -> Option<(
+        [f32; 3],
+        [f32; 3],
+        [f32; 3],
+        f32,
+        usize,
+        [f32; 2],
+        [f32; 3],
+        Option<[f32; 3]>,
+    )>;
 
+    /// * `hit_pos` - position of the hit point where the mix material is resolved. See [`crate::material::choose_material`]
+    /// * `tangent` - tangent of the curve at the hit point. See [`crate::material::bxdf`]
+    #[allow(clippy::too_many_arguments)]
     fn eval_brdf(
         &self,
         itrimsh: usize,
+        hit_pos: &[f32; 3],
+        hit_uv: &[f32; 2],
         obj_nrm: &[f32; 3],
+        tangent: Option<&[f32; 3]>,
         ray_in_outward_normlized: &[f32; 3],
         ray_out_normalized: &[f32; 3],
         minimum_roughness: f32,
     ) -> [f32; 3];
 
-    /// `uvec_ray_in_outward` should be facing outward (same direction as `obj_nrm`)
+    /// `uvec_ray_in_outward` should be facing outward (same direction as `obj_nrm`).
+    /// For the transmissive materials, `obj_nrm` is the outward normal of the object and
+    /// `uvec_ray_in_outward` is below the surface if the ray comes from the inside.
+    /// The sampled direction is below the surface for the transmission
+    #[allow(clippy::too_many_arguments)]
     fn sample_brdf<Rng: rand::Rng>(
         &self,
         obj_nrm: &[f32; 3],
+        tangent: Option<&[f32; 3]>,
         uvec_ray_in_outward: &[f32; 3],
         i_shape_entity: usize,
+        hit_pos: &[f32; 3],
+        hit_uv: &[f32; 2],
         rng: &mut Rng,
         minimum_roughness: f32,
-    ) -> Option<([f32; 3], [f32; 3], f32)>;
+    ) -> Option<crate::bxdf::BSDFSample>;
+
+    /// pdf of the direction sampled by [`Scene::sample_brdf`] on the unit sphere. Zero for the delta lobes
+    #[allow(clippy::too_many_arguments)]
+    fn pdf_brdf(
+        &self,
+        i_shape_entity: usize,
+        hit_pos: &[f32; 3],
+        hit_uv: &[f32; 2],
+        obj_nrm: &[f32; 3],
+        tangent: Option<&[f32; 3]>,
+        ray_in_outward_normlized: &[f32; 3],
+        ray_out_normalized: &[f32; 3],
+        minimum_roughness: f32,
+    ) -> f32;
+
+    /// lobes of the material of the shape entity
+    fn bxdf_flags(
+        &self,
+        i_shape_entity: usize,
+        hit_uv: &[f32; 2],
+        minimum_roughness: f32,
+    ) -> crate::bxdf::BxDFFlags;
 
     /// # Return
     /// - `Some(radiance: [f32;3], pdf: f32, uvec_hit2light:[f32;3])`
@@ -36,14 +86,90 @@ pub trait Scene {
         rng: &mut Rng,
     ) -> Option<([f32; 3], f32, [f32; 3])>;
 
+    /// direct light reflected at the hit point toward `uvec_ray_in_outward`, integrated analytically
+    /// (e.g., by [`crate::area_light::direct_radiance_analytic`]) in place of [`Scene::sample_light`]
+    /// in [`radiance_nee`]. The analytic integral has no shadow, so return `None` where the light may be occluded
+    /// (e.g., where [`crate::area_light::is_triangle_overlapping_tetrahedron`] detects the occluder)
+    /// # Return
+    /// `None` to sample the light, which is the default
+    fn direct_light_analytic(
+        &self,
+        _hit_pos: &[f32; 3],
+        _hit_nrm: &[f32; 3],
+        _uvec_ray_in_outward: &[f32; 3],
+        _i_shape_entity: usize,
+        _hit_uv: &[f32; 2],
+    ) -> Option<[f32; 3]> {
+        None
+    }
+
     /// pdf should be the density on the unit sphere around the `pos_observe`
+    /// * `uv_light` - texture coordinate of `pos_light` for the textured emission
     fn pdf_light(
         &self,
         pos_observe: &[f32; 3],
         pos_light: &[f32; 3],
         nrm_light: &[f32; 3],
+        uv_light: &[f32; 2],
         i_shape_entity: usize,
     ) -> f32;
+
+    /// random walk inside the shape entity of the subsurface material. See [`crate::subsurface`]
+    /// # Return
+    /// - `Some(pos: [f32;3], nrm: [f32;3], dir: [f32;3], weight: [f32;3])` the ray leaving the surface
+    /// - `None` if the material of the shape entity is not the subsurface material
+    #[allow(clippy::type_complexity)]
+    fn sample_subsurface<Rng: rand::Rng>(
+        &self,
+        _hit_pos: &[f32; 3],
+        _hit_nrm: &[f32; 3],
+        _uvec_ray_in_outward: &[f32; 3],
+        _i_shape_entity: usize,
+        _hit_uv: &[f32; 2],
+        _rng: &mut Rng,
+    ) -> Option<([f32; 3], [f32; 3], [f32; 3], [f32; 3])> {
+        None
+    }
+}
+
+/// origin of the next ray offset from the surface to the side of `ray_dir_next`
+/// (i.e., below the surface for the transmission) to avoid the self intersection
+pub fn offset_ray_origin(
+    hit_pos: &[f32; 3],
+    hit_nrm: &[f32; 3],
+    ray_dir_next: &[f32; 3],
+) -> [f32; 3] {
+    let sign = del_geo_core::vec3::dot(ray_dir_next, hit_nrm).signum();
+    del_geo_core::vec3::axpy(1.0e-3 * sign, hit_nrm, hit_pos)
+}
+
+/// origin of the shadow ray for the light sampling offset from the surface.
+/// The light on the other side is occluded by the surface itself, so for the transmissive material
+/// either side is chosen with the equal probability
+/// # Return
+/// `(origin, probability of the side)`
+fn light_sample_origin<RNG>(
+    hit_pos: &[f32; 3],
+    hit_nrm: &[f32; 3],
+    is_transmissive: bool,
+    rng: &mut RNG,
+) -> ([f32; 3], f32)
+where
+    RNG: rand::Rng,
+{
+    if is_transmissive {
+        let sign = if rng.random::<f32>() < 0.5 {
+            1f32
+        } else {
+            -1f32
+        };
+        (
+            del_geo_core::vec3::axpy(1.0e-3 * sign, hit_nrm, hit_pos),
+            0.5,
+        )
+    } else {
+        (del_geo_core::vec3::axpy(1.0e-3, hit_nrm, hit_pos), 1f32)
+    }
 }
 
 pub fn radiance_pt<RNG, SCENE>(
@@ -63,24 +189,52 @@ where
     let mut ray_org: [f32; 3] = ray_org_ini.to_owned();
     let mut ray_dir: [f32; 3] = ray_dir_ini.to_owned();
     for _i_depth in 0..max_depth {
-        let Some((hit_pos, hit_nrm, hit_emission, _hit_roughness, hit_itrimsh)) =
-            scene.hit_position_normal_emission_roughness_at_ray_intersection(&ray_org, &ray_dir)
+        let Some((
+            hit_pos,
+            hit_nrm,
+            hit_emission,
+            _hit_roughness,
+            hit_itrimsh,
+            hit_uv,
+            hit_shading_nrm,
+            hit_tangent,
+        )) = scene.hit_position_normal_emission_roughness_at_ray_intersection(&ray_org, &ray_dir)
         else {
             break;
         };
         rad_out = rad_out.add(&hit_emission.element_wise_mult(&throughput));
         //
-        let Some((ray_dir_next, brdf, pdf)) = scene.sample_brdf(
+        if let Some((pos, nrm, dir, weight)) = scene.sample_subsurface(
+            &hit_pos,
             &hit_nrm,
             &ray_dir.scale(-1f32).normalize(),
             hit_itrimsh,
+            &hit_uv,
+            rng,
+        ) {
+            throughput = throughput.element_wise_mult(&weight);
+            if throughput == [0f32; 3] {
+                break;
+            }
+            ray_org = offset_ray_origin(&pos, &nrm, &dir);
+            ray_dir = dir;
+            continue;
+        }
+        let Some(bs) = scene.sample_brdf(
+            &hit_shading_nrm,
+            hit_tangent.as_ref(),
+            &ray_dir.scale(-1f32).normalize(),
+            hit_itrimsh,
+            &hit_pos,
+            &hit_uv,
             rng,
             0.0,
         ) else {
             break;
         };
-        let cos_hit = ray_dir_next.dot(&hit_nrm);
-        throughput = throughput.element_wise_mult(&brdf.scale(cos_hit / pdf));
+        let ray_dir_next = bs.wi;
+        let cos_hit = ray_dir_next.dot(&hit_shading_nrm).abs();
+        throughput = throughput.element_wise_mult(&bs.f.scale(cos_hit / bs.pdf));
         {
             let russian_roulette_prob = throughput.iter().fold(f32::NAN, |a, b| a.max(*b));
             if rng.random::<f32>() < russian_roulette_prob {
@@ -89,8 +243,7 @@ where
                 break; // terminate ray
             }
         }
-        let hit_pos_w_offset = del_geo_core::vec3::axpy(1.0e-3, &hit_nrm, &hit_pos);
-        ray_org = hit_pos_w_offset;
+        ray_org = offset_ray_origin(&hit_pos, &hit_nrm, &ray_dir_next);
         ray_dir = ray_dir_next;
     }
     rad_out
@@ -114,10 +267,20 @@ where
     let mut ray_org: [f32; 3] = ray_org_ini.to_owned();
     let mut ray_dir: [f32; 3] = ray_dir_ini.to_owned();
     let mut max_roughness = 0f32;
+    // the light hit after the specular bounce is not counted by the light sampling
+    let mut is_prev_specular = false;
     for i_depth in 0..max_depth {
         use del_geo_core::vec3;
-        let Some((hit_pos, hit_nrm, hit_emission, hit_roughness, hit_i_shape_entity)) =
-            scene.hit_position_normal_emission_roughness_at_ray_intersection(&ray_org, &ray_dir)
+        let Some((
+            hit_pos,
+            hit_nrm,
+            hit_emission,
+            hit_roughness,
+            hit_i_shape_entity,
+            hit_uv,
+            hit_shading_nrm,
+            hit_tangent,
+        )) = scene.hit_position_normal_emission_roughness_at_ray_intersection(&ray_org, &ray_dir)
         else {
             break;
         };
@@ -126,44 +289,82 @@ where
         }
         // println!("{} {} {}", i_depth, hit_roughness, max_roughness);
         // ------------
-        if i_depth == 0 {
+        if i_depth == 0 || is_prev_specular {
             rad_out = rad_out.add(&hit_emission.element_wise_mult(&throughput));
         };
-        let hit_pos_w_offset = vec3::axpy(1.0e-3, &hit_nrm, &hit_pos);
-        if hit_emission == [0f32; 3] {
-            // sample light
-            if let Some((li_light, pdf_light, uvec_hit2light)) =
-                scene.sample_light(&hit_pos_w_offset, hit_i_shape_entity, rng)
-            {
-                let brdf_hit = scene.eval_brdf(
-                    hit_i_shape_entity,
-                    &hit_nrm,
-                    &ray_dir.scale(-1.).normalize(),
-                    &uvec_hit2light,
-                    max_roughness,
-                );
-                let cos_hit = vec3::dot(&uvec_hit2light, &hit_nrm);
-                let lo_light =
-                    vec3::element_wise_mult(&brdf_hit, &li_light.scale(cos_hit / pdf_light));
-                rad_out = rad_out.add(&lo_light.element_wise_mult(&throughput));
+        if let Some((pos, nrm, dir, weight)) = scene.sample_subsurface(
+            &hit_pos,
+            &hit_nrm,
+            &ray_dir.scale(-1f32).normalize(),
+            hit_i_shape_entity,
+            &hit_uv,
+            rng,
+        ) {
+            throughput = throughput.element_wise_mult(&weight);
+            if throughput == [0f32; 3] {
+                break;
+            }
+            // the light hit by the exiting ray is not counted by the light sampling
+            is_prev_specular = true;
+            ray_org = offset_ray_origin(&pos, &nrm, &dir);
+            ray_dir = dir;
+            continue;
+        }
+        let flags = scene.bxdf_flags(hit_i_shape_entity, &hit_uv, max_roughness);
+        if hit_emission == [0f32; 3] && flags.is_non_specular() {
+            if let Some(lo_analytic) = scene.direct_light_analytic(
+                &hit_pos,
+                &hit_shading_nrm,
+                &ray_dir.scale(-1.).normalize(),
+                hit_i_shape_entity,
+                &hit_uv,
+            ) {
+                rad_out = rad_out.add(&lo_analytic.element_wise_mult(&throughput));
+            } else {
+                // sample light
+                let (hit_pos_w_offset, prob_side) =
+                    light_sample_origin(&hit_pos, &hit_nrm, flags.is_transmissive(), rng);
+                if let Some((li_light, pdf_light, uvec_hit2light)) =
+                    scene.sample_light(&hit_pos_w_offset, hit_i_shape_entity, rng)
+                {
+                    let brdf_hit = scene.eval_brdf(
+                        hit_i_shape_entity,
+                        &hit_pos,
+                        &hit_uv,
+                        &hit_shading_nrm,
+                        hit_tangent.as_ref(),
+                        &ray_dir.scale(-1.).normalize(),
+                        &uvec_hit2light,
+                        max_roughness,
+                    );
+                    let cos_hit = vec3::dot(&uvec_hit2light, &hit_shading_nrm).abs();
+                    let pdf_light = pdf_light * prob_side;
+                    let lo_light =
+                        vec3::element_wise_mult(&brdf_hit, &li_light.scale(cos_hit / pdf_light));
+                    rad_out = rad_out.add(&lo_light.element_wise_mult(&throughput));
+                }
             }
         }
         if i_depth == max_depth - 1 {
             break;
         }
         let ray_dir_next = {
-            let Some((ray_dir_next, brdf, pdf)) = scene.sample_brdf(
-                &hit_nrm,
+            let Some(bs) = scene.sample_brdf(
+                &hit_shading_nrm,
+                hit_tangent.as_ref(),
                 &ray_dir.scale(-1f32).normalize(),
                 hit_i_shape_entity,
+                &hit_pos,
+                &hit_uv,
                 rng,
                 max_roughness,
             ) else {
                 break;
             };
-            let cos_hit = ray_dir_next.dot(&hit_nrm); //.clamp(f32::EPSILON, 1f32);
-            throughput = throughput.element_wise_mult(&brdf.scale(cos_hit / pdf));
-            ray_dir_next
+            let cos_hit = bs.wi.dot(&hit_shading_nrm).abs();
+            throughput = throughput.element_wise_mult(&bs.f.scale(cos_hit / bs.pdf));
+            is_prev_specular = bs.is_specular();
+            bs.wi
         };
         {
             // russian roulette
@@ -177,7 +378,7 @@ where
                 break; // terminate ray
             }
         }
-        ray_org = hit_pos_w_offset;
+        ray_org = offset_ray_origin(&hit_pos, &hit_nrm, &ray_dir_next);
         ray_dir = ray_dir_next;
     }
     rad_out
@@ -201,10 +402,19 @@ where
     let mut ray_org: [f32; 3] = ray_org_ini.to_owned();
     let mut ray_dir: [f32; 3] = ray_dir_ini.to_owned();
     let mut max_roughness = 0f32;
+    let mut is_prev_subsurface = false;
     for i_depth in 0..max_depth {
         use del_geo_core::vec3;
-        let Some((hit_pos, hit_nrm, hit_emission, hit_roughness, hit_i_shape_entity)) =
-            scene.hit_position_normal_emission_roughness_at_ray_intersection(&ray_org, &ray_dir)
+        let Some((
+            hit_pos,
+            hit_nrm,
+            hit_emission,
+            hit_roughness,
+            hit_i_shape_entity,
+            hit_uv,
+            hit_shading_nrm,
+            hit_tangent,
+        )) = scene.hit_position_normal_emission_roughness_at_ray_intersection(&ray_org, &ray_dir)
         else {
             break;
         };
@@ -212,24 +422,59 @@ where
             max_roughness = max_roughness.max(hit_roughness);
         }
         // ------------
-        if i_depth == 0 {
+        if i_depth == 0 || is_prev_subsurface {
             rad_out = rad_out.add(&hit_emission.element_wise_mult(&throughput));
         };
-        let hit_pos_w_offset = vec3::axpy(1.0e-3, &hit_nrm, &hit_pos);
-        if hit_emission == [0f32; 3] {
+        is_prev_subsurface = false;
+        if let Some((pos, nrm, dir, weight)) = scene.sample_subsurface(
+            &hit_pos,
+            &hit_nrm,
+            &ray_dir.scale(-1f32).normalize(),
+            hit_i_shape_entity,
+            &hit_uv,
+            rng,
+        ) {
+            throughput = throughput.element_wise_mult(&weight);
+            if throughput == [0f32; 3] {
+                break;
+            }
+            // the light hit by the exiting ray is counted neither by the light sampling nor the material sampling
+            is_prev_subsurface = true;
+            ray_org = offset_ray_origin(&pos, &nrm, &dir);
+            ray_dir = dir;
+            continue;
+        }
+        let flags = scene.bxdf_flags(hit_i_shape_entity, &hit_uv, max_roughness);
+        let prob_side = if flags.is_transmissive() { 0.5 } else { 1f32 };
+        if hit_emission == [0f32; 3] && flags.is_non_specular() {
             // sample light seeking for direct light
+            let (hit_pos_w_offset, _) =
+                light_sample_origin(&hit_pos, &hit_nrm, flags.is_transmissive(), rng);
             if let Some((li_light, pdf_light, uvec_hit2light)) =
                 scene.sample_light(&hit_pos_w_offset, hit_i_shape_entity, rng)
             {
                 let brdf_hit = scene.eval_brdf(
                     hit_i_shape_entity,
-                    &hit_nrm,
+                    &hit_pos,
+                    &hit_uv,
+                    &hit_shading_nrm,
+                    hit_tangent.as_ref(),
+                    &ray_dir.scale(-1.).normalize(),
+                    &uvec_hit2light,
+                    max_roughness,
+                );
+                let cos_hit = vec3::dot(&uvec_hit2light, &hit_shading_nrm).abs();
+                let pdf_light = pdf_light * prob_side;
+                let pdf_brdf = scene.pdf_brdf(
+                    hit_i_shape_entity,
+                    &hit_pos,
+                    &hit_uv,
+                    &hit_shading_nrm,
+                    hit_tangent.as_ref(),
                     &ray_dir.scale(-1.).normalize(),
                     &uvec_hit2light,
                     max_roughness,
                 );
-                let cos_hit = vec3::dot(&uvec_hit2light, &hit_nrm);
-                let pdf_brdf = cos_hit * std::f32::consts::FRAC_1_PI;
                 let mis_weight_light = pdf_light / (pdf_light + pdf_brdf);
                 let lo_light = vec3::element_wise_mult(
                     &brdf_hit,
@@ -240,55 +485,88 @@ where
         }
         if hit_emission == [0f32; 3] {
             // sample material seeking for direct light
-            let Some((ray_dir_brdf, brdf, pdf_brdf)) = scene.sample_brdf(
-                &hit_nrm,
+            let Some(bs) = scene.sample_brdf(
+                &hit_shading_nrm,
+                hit_tangent.as_ref(),
                 &ray_dir.scale(-1f32).normalize(),
                 hit_i_shape_entity,
+                &hit_pos,
+                &hit_uv,
                 rng,
                 max_roughness,
             ) else {
                 break;
             };
+            let ray_dir_brdf = bs.wi;
             if let Some((
                 hit_pos_light,
                 hit_nrm_light,
                 hit_emission_light,
                 _hit_roughness,
                 hit_i_shape_entity_light,
+                hit_uv_light,
+                _hit_shading_nrm_light,
+                _hit_tangent_light,
             )) = scene.hit_position_normal_emission_roughness_at_ray_intersection(
-                &hit_pos_w_offset,
+                &offset_ray_origin(&hit_pos, &hit_nrm, &ray_dir_brdf),
                 &ray_dir_brdf,
             ) {
                 // the material-sampled ray hit light
                 if hit_emission_light != [0f32; 3] {
-                    let cos_hit = ray_dir_brdf.dot(&hit_nrm).clamp(f32::EPSILON, 1f32);
+                    let cos_hit = ray_dir_brdf
+                        .dot(&hit_shading_nrm)
+                        .abs()
+                        .clamp(f32::EPSILON, 1f32);
                     let pdf_light = scene.pdf_light(
                         &hit_pos,
                         &hit_pos_light,
                         &hit_nrm_light,
+                        &hit_uv_light,
                         hit_i_shape_entity_light,
-                    );
-                    let mis_weight_brdf = pdf_brdf / (pdf_brdf + pdf_light);
+                    ) * prob_side;
+                    // the delta lobe cannot be hit by the light sampling
+                    let mis_weight_brdf = if bs.is_specular() {
+                        1f32
+                    } else {
+                        let pdf_brdf = if bs.pdf_is_proportional {
+                            scene.pdf_brdf(
+                                hit_i_shape_entity,
+                                &hit_pos,
+                                &hit_uv,
+                                &hit_shading_nrm,
+                                hit_tangent.as_ref(),
+                                &ray_dir.scale(-1f32).normalize(),
+                                &ray_dir_brdf,
+                                max_roughness,
+                            )
+                        } else {
+                            bs.pdf
+                        };
+                        pdf_brdf / (pdf_brdf + pdf_light)
+                    };
                     let lo_brdf = hit_emission_light
-                        .element_wise_mult(&brdf.scale(cos_hit / pdf_brdf * mis_weight_brdf));
+                        .element_wise_mult(&bs.f.scale(cos_hit / bs.pdf * mis_weight_brdf));
                     rad_out = rad_out.add(&lo_brdf.element_wise_mult(&throughput));
                 }
             }
         }
         let ray_dir_next = {
             // update throughput
-            let Some((ray_dir_next, brdf, pdf_brdf)) = scene.sample_brdf(
-                &hit_nrm,
+            let Some(bs) = scene.sample_brdf(
+                &hit_shading_nrm,
+                hit_tangent.as_ref(),
                 &ray_dir.scale(-1f32).normalize(),
                 hit_i_shape_entity,
+                &hit_pos,
+                &hit_uv,
                 rng,
                 max_roughness,
             ) else {
                 break;
             };
-            let cosine = ray_dir_next.dot(&hit_nrm).clamp(f32::EPSILON, 1f32);
-            throughput = throughput.element_wise_mult(&brdf.scale(cosine / pdf_brdf));
-            ray_dir_next
+            let cosine = bs.wi.dot(&hit_shading_nrm).abs().clamp(f32::EPSILON, 1f32);
+            throughput = throughput.element_wise_mult(&bs.f.scale(cosine / bs.pdf));
+            bs.wi
         };
         {
             let &russian_roulette_prob = throughput
@@ -301,8 +579,35 @@ where
                 break; // terminate ray
             }
         }
-        ray_org = hit_pos_w_offset;
+        ray_org = offset_ray_origin(&hit_pos, &hit_nrm, &ray_dir_next);
         ray_dir = ray_dir_next;
     }
     rad_out
 }
+
+#[test]
+fn test_radiance_nee_analytic_direct_light() {
+    use del_geo_core::vec3::Vec3;
+    use rand::SeedableRng;
+    let mut scene = crate::lightmap::test_scene::FloorUnderLight::new(0.5, [1., 2., 3.]);
+    // the ray from below the light hitting the floor at `(0.3, 0.6, 0)`
+    let ray_org = [0.1f32, 0.1, 0.4];
+    let ray_dir = [0.3f32, 0.6, 0.].sub(&ray_org).normalize();
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    let num_sample = 20000;
+    let mut sampled = [0f32; 3];
+    for _ in 0..num_sample {
+        let rad = radiance_nee(&ray_org, &ray_dir, &scene, 1, &mut rng, false);
+        sampled = del_geo_core::vec3::axpy(1f32 / num_sample as f32, &rad, &sampled);
+    }
+    scene.is_direct_light_analytic = true;
+    let analytic = radiance_nee(&ray_org, &ray_dir, &scene, 1, &mut rng, false);
+    for i_ch in 0..3 {
+        assert!(
+            (analytic[i_ch] - sampled[i_ch]).abs() < 0.02 * analytic[i_ch],
+            "{:?} {:?}",
+            analytic,
+            sampled
+        );
+    }
+}
@@ -133,18 +133,15 @@ pub fn trimesh3_from_shape_entity(
     }
 }
 
+/// `None` if the spectrum is not given (e.g., the emission is given by the image)
 pub fn spectrum_from_light_entity(area_light_entity: &pbrt4::types::AreaLight) -> Option<[f32; 3]> {
     match area_light_entity {
-        pbrt4::types::AreaLight::Diffuse {
-            filename: _filename,
-            two_sided: _two_sided,
-            spectrum,
-            scale: _scale,
-        } => match spectrum.unwrap().to_owned() {
-            pbrt4::param::Spectrum::Rgb(rgb) => Some(rgb),
-            _ => {
-                panic!();
+        pbrt4::types::AreaLight::Diffuse { spectrum, .. } => match spectrum {
+            Some(pbrt4::param::Spectrum::Rgb(rgb)) => Some(*rgb),
+            Some(pbrt4::param::Spectrum::Blackbody(temperature)) => {
+                Some(crate::spectrum::blackbody_rgb(*temperature as f32))
             }
+            None => None,
         },
     }
 }
@@ -155,6 +152,9 @@ pub fn get_f32_array3_from_params(
 ) -> Option<[f32; 3]> {
     let mp = dict_mp.get(key)?;
     assert_eq!(mp.1, key.to_string());
+    if mp.0 == pbrt4::param::ParamType::Texture {
+        return None; // see `get_texture_index_from_material_param`
+    }
     let res: [f32; 3] =
         mp.2.split_whitespace()
             .map(|v| v.parse::<f32>().unwrap())
@@ -187,6 +187,35 @@ pub fn get_f32_from_params(
     Ok(mp.2.parse::<f32>()?)
 }
 
+/// the spectrum given as a float, RGB or the named spectrum (e.g., "glass-BK7") in RGB.
+/// `default` if missing or given by the texture
+fn get_spectrum_from_params(
+    key: &str,
+    dict_mp: &std::collections::HashMap<String, (pbrt4::param::ParamType, String, String)>,
+    default: [f32; 3],
+) -> [f32; 3] {
+    if let Ok(v) = get_f32_from_params(key, dict_mp) {
+        return [v; 3];
+    }
+    match dict_mp.get(key) {
+        Some(mp) if mp.0 == pbrt4::param::ParamType::Texture => default,
+        Some(mp) if mp.2.starts_with('"') => {
+            let name = mp.2.trim_matches('"');
+            crate::spectrum::named_spectrum_rgb(name)
+                .unwrap_or_else(|| panic!("unknown named spectrum: {}", name))
+        }
+        Some(_) => get_f32_array3_from_params(key, dict_mp).unwrap(),
+        None => default,
+    }
+}
+
+/// the index of refraction given as a float or RGB. 1.5 (glass) by default
+fn get_eta_from_params(
+    dict_mp: &std::collections::HashMap<String, (pbrt4::param::ParamType, String, String)>,
+) -> [f32; 3] {
+    get_spectrum_from_params("eta", dict_mp, [1.5; 3])
+}
+
 fn get_texture_index_from_material_param(
     key: &str,
     dict_mp: &std::collections::HashMap<String, (pbrt4::param::ParamType, String, String)>,
@@ -208,85 +237,421 @@ fn get_texture_index_from_material_param(
     None
 }
 
-pub fn parse_material(scene: &pbrt4::Scene) -> Vec<crate::material::Material> {
+/// index of the material given by the name in `MakeNamedMaterial`
+fn get_material_index_from_name(scene: &pbrt4::Scene, name: &str) -> Option<usize> {
+    scene.materials.iter().position(|mat| mat.name == name)
+}
+
+/// * `path_file` - path of the pbrt file. The files referred by the materials are relative to it
+///
+/// The mix material referring to itself is rejected.
+/// The `measured` material not in the MERL format is replaced by the gray diffuse material with a warning
+pub fn parse_material(scene: &pbrt4::Scene, path_file: &str) -> Vec<crate::material::Material> {
     let mut materials = Vec::<crate::material::Material>::with_capacity(scene.materials.len());
     for mat in scene.materials.iter() {
+        // `usize::MAX` if the parameter is not given by the texture
+        let texture_index = |key: &str| {
+            get_texture_index_from_material_param(key, &mat.params, &scene.textures)
+                .unwrap_or(usize::MAX)
+        };
+        // textures of `{prefix}uroughness` and `{prefix}vroughness`, falling back to `{prefix}roughness`
+        let roughness_textures = |prefix: &str| {
+            let i_texture = texture_index(&format!("{prefix}roughness"));
+            [format!("{prefix}uroughness"), format!("{prefix}vroughness")].map(|key| {
+                match texture_index(&key) {
+                    usize::MAX => i_texture,
+                    i => i,
+                }
+            })
+        };
         match mat.attributes.as_str() {
             "diffuse" => {
                 let diff = crate::material::DiffuseMaterial {
                     reflectance: mat.reflectance.get_rgb(),
-                    reflectance_texture: get_texture_index_from_material_param(
-                        "reflectance",
-                        &mat.params,
-                        &scene.textures,
-                    )
-                    .unwrap_or(usize::MAX),
+                    reflectance_texture: texture_index("reflectance"),
                 };
                 materials.push(crate::material::Material::Diff(diff));
             }
             "conductor" => {
-                let uroughness = get_f32_from_params("uroughness", &mat.params).unwrap();
-                let vroughness = get_f32_from_params("vroughness", &mat.params).unwrap();
-                let reflectance = get_f32_array3_from_params("reflectance", &mat.params)
-                    .unwrap_or([1.0, 1.0, 1.0]);
-                let k = get_f32_array3_from_params("k", &mat.params).unwrap();
-                let eta = get_f32_array3_from_params("eta", &mat.params).unwrap();
+                let roughness = get_f32_from_params("roughness", &mat.params).unwrap_or(0.0);
+                let uroughness =
+                    get_f32_from_params("uroughness", &mat.params).unwrap_or(roughness);
+                let vroughness =
+                    get_f32_from_params("vroughness", &mat.params).unwrap_or(roughness);
+                // copper by default as in pbrt-v4.
+                // The textured reflectance is converted to eta and k at each lookup (see `material::bxdf`)
+                let (eta, k) = match get_f32_array3_from_params("reflectance", &mat.params) {
+                    Some(r) => crate::material::conductor_eta_k_from_reflectance(&r),
+                    None => (
+                        get_spectrum_from_params(
+                            "eta",
+                            &mat.params,
+                            crate::spectrum::named_spectrum_rgb("metal-Cu-eta").unwrap(),
+                        ),
+                        get_spectrum_from_params(
+                            "k",
+                            &mat.params,
+                            crate::spectrum::named_spectrum_rgb("metal-Cu-k").unwrap(),
+                        ),
+                    ),
+                };
+                let remaproughness =
+                    get_bool_from_params("remaproughness", &mat.params).unwrap_or(true);
                 let mat = crate::material::ConductorMaterial {
                     uroughness,
                     vroughness,
-                    reflectance,
+                    remaproughness,
+                    reflectance: [1.0; 3],
                     k,
                     eta,
+                    reflectance_texture: texture_index("reflectance"),
+                    uroughness_texture: roughness_textures("")[0],
+                    vroughness_texture: roughness_textures("")[1],
+                    k_texture: texture_index("k"),
+                    eta_texture: texture_index("eta"),
+                    multiple_scattering: get_bool_from_params("multiplescattering", &mat.params)
+                        .unwrap_or(false),
                 };
                 materials.push(crate::material::Material::Cond(mat))
             }
             "coateddiffuse" => {
-                let uroughness = get_f32_from_params("uroughness", &mat.params).unwrap();
-                let vroughness = get_f32_from_params("vroughness", &mat.params).unwrap();
+                let roughness = get_f32_from_params("roughness", &mat.params).unwrap_or(0.0);
+                let uroughness =
+                    get_f32_from_params("uroughness", &mat.params).unwrap_or(roughness);
+                let vroughness =
+                    get_f32_from_params("vroughness", &mat.params).unwrap_or(roughness);
                 let reflectance = get_f32_array3_from_params("reflectance", &mat.params)
                     .unwrap_or([1.0, 1.0, 1.0]);
-                let remaproughness = get_bool_from_params("remaproughness", &mat.params).unwrap();
-
+                let remaproughness =
+                    get_bool_from_params("remaproughness", &mat.params).unwrap_or(true);
+                let thickness = get_f32_from_params("thickness", &mat.params).unwrap_or(0.01);
+                let albedo = get_spectrum_from_params("albedo", &mat.params, [0.0; 3]);
+                let g = get_f32_from_params("g", &mat.params).unwrap_or(0.0);
+                // the integer parameters
+                let maxdepth = get_f32_from_params("maxdepth", &mat.params).unwrap_or(10.0);
+                let nsamples = get_f32_from_params("nsamples", &mat.params).unwrap_or(1.0);
                 let coadiff = crate::material::CoatedDiffuse {
                     uroughness,
                     vroughness,
                     reflectance,
                     remaproughness,
+                    thickness,
+                    eta: get_eta_from_params(&mat.params)[0],
+                    albedo,
+                    g,
+                    maxdepth: maxdepth.max(0.0) as usize,
+                    nsamples: nsamples.max(1.0) as usize,
+                    reflectance_texture: texture_index("reflectance"),
+                    uroughness_texture: roughness_textures("")[0],
+                    vroughness_texture: roughness_textures("")[1],
                 };
                 materials.push(crate::material::Material::CoaDiff(coadiff))
             }
+            "coatedconductor" => {
+                let get_roughness = |prefix: &str| {
+                    let roughness =
+                        get_f32_from_params(&format!("{prefix}.roughness"), &mat.params)
+                            .unwrap_or(0.0);
+                    (
+                        get_f32_from_params(&format!("{prefix}.uroughness"), &mat.params)
+                            .unwrap_or(roughness),
+                        get_f32_from_params(&format!("{prefix}.vroughness"), &mat.params)
+                            .unwrap_or(roughness),
+                    )
+                };
+                let (interface_uroughness, interface_vroughness) = get_roughness("interface");
+                let (conductor_uroughness, conductor_vroughness) = get_roughness("conductor");
+                let interface_eta =
+                    get_spectrum_from_params("interface.eta", &mat.params, [1.5; 3])[0];
+                // copper by default as in pbrt-v4.
+                // The textured reflectance is converted to eta and k at each lookup (see `material::bxdf`)
+                let (conductor_eta, k) =
+                    match get_f32_array3_from_params("reflectance", &mat.params) {
+                        Some(r) => crate::material::conductor_eta_k_from_reflectance(&r),
+                        None => (
+                            get_spectrum_from_params(
+                                "conductor.eta",
+                                &mat.params,
+                                crate::spectrum::named_spectrum_rgb("metal-Cu-eta").unwrap(),
+                            ),
+                            get_spectrum_from_params(
+                                "conductor.k",
+                                &mat.params,
+                                crate::spectrum::named_spectrum_rgb("metal-Cu-k").unwrap(),
+                            ),
+                        ),
+                    };
+                let remaproughness =
+                    get_bool_from_params("remaproughness", &mat.params).unwrap_or(true);
+                let thickness = get_f32_from_params("thickness", &mat.params).unwrap_or(0.01);
+                let albedo = get_spectrum_from_params("albedo", &mat.params, [0.0; 3]);
+                let g = get_f32_from_params("g", &mat.params).unwrap_or(0.0);
+                // the integer parameters
+                let maxdepth = get_f32_from_params("maxdepth", &mat.params).unwrap_or(10.0);
+                let nsamples = get_f32_from_params("nsamples", &mat.params).unwrap_or(1.0);
+                let coacond = crate::material::CoatedConductor {
+                    interface_uroughness,
+                    interface_vroughness,
+                    interface_eta,
+                    conductor_uroughness,
+                    conductor_vroughness,
+                    conductor_eta,
+                    k,
+                    remaproughness,
+                    thickness,
+                    albedo,
+                    g,
+                    maxdepth: maxdepth.max(0.0) as usize,
+                    nsamples: nsamples.max(1.0) as usize,
+                    interface_uroughness_texture: roughness_textures("interface.")[0],
+                    interface_vroughness_texture: roughness_textures("interface.")[1],
+                    conductor_uroughness_texture: roughness_textures("conductor.")[0],
+                    conductor_vroughness_texture: roughness_textures("conductor.")[1],
+                    conductor_eta_texture: texture_index("conductor.eta"),
+                    k_texture: texture_index("conductor.k"),
+                    reflectance_texture: texture_index("reflectance"),
+                };
+                materials.push(crate::material::Material::CoaCond(coacond))
+            }
+            "dielectric" => {
+                let roughness = get_f32_from_params("roughness", &mat.params).unwrap_or(0.0);
+                let uroughness =
+                    get_f32_from_params("uroughness", &mat.params).unwrap_or(roughness);
+                let vroughness =
+                    get_f32_from_params("vroughness", &mat.params).unwrap_or(roughness);
+                let remaproughness =
+                    get_bool_from_params("remaproughness", &mat.params).unwrap_or(true);
+                let eta = get_eta_from_params(&mat.params);
+                let diel = crate::material::DielectricMaterial {
+                    uroughness,
+                    vroughness,
+                    remaproughness,
+                    eta,
+                    uroughness_texture: roughness_textures("")[0],
+                    vroughness_texture: roughness_textures("")[1],
+                    eta_texture: texture_index("eta"),
+                };
+                materials.push(crate::material::Material::Diel(diel))
+            }
+            "thindielectric" => {
+                let eta = get_eta_from_params(&mat.params);
+                let thindiel = crate::material::ThinDielectricMaterial { eta };
+                materials.push(crate::material::Material::ThinDiel(thindiel))
+            }
+            "measured" => {
+                let filename = mat
+                    .params
+                    .get("filename")
+                    .expect("the measured material needs the filename")
+                    .2
+                    .trim_matches('"')
+                    .to_string();
+                let path = std::path::Path::new(path_file)
+                    .parent()
+                    .unwrap()
+                    .join(&filename);
+                if !filename.ends_with(".binary") {
+                    // e.g., the RGL format (.bsdf) is not supported
+                    eprintln!(
+                        "warning: only the MERL format (.binary) is supported. {} is replaced by the diffuse material",
+                        filename
+                    );
+                    let diff = crate::material::DiffuseMaterial {
+                        reflectance: [0.5; 3],
+                        reflectance_texture: usize::MAX,
+                    };
+                    materials.push(crate::material::Material::Diff(diff));
+                    continue;
+                }
+                let brdf = crate::measured_brdf::MerlBrdf::read_from(&path)
+                    .unwrap_or_else(|e| panic!("failed to load {}: {}", path.display(), e));
+                let measured = crate::material::MeasuredMaterial {
+                    brdf: std::sync::Arc::new(brdf),
+                };
+                materials.push(crate::material::Material::Measured(measured))
+            }
+            "mix" => {
+                let names: Vec<&str> = mat
+                    .params
+                    .get("materials")
+                    .expect("the mix material needs two materials")
+                    .2
+                    .split('"')
+                    .map(|v| v.trim())
+                    .filter(|v| !v.is_empty())
+                    .collect();
+                assert_eq!(names.len(), 2, "the mix material needs two materials");
+                let i_materials = [names[0], names[1]].map(|name| {
+                    get_material_index_from_name(scene, name)
+                        .unwrap_or_else(|| panic!("unknown material: {}", name))
+                });
+                let mix = crate::material::MixMaterial {
+                    materials: i_materials,
+                    amount: get_f32_from_params("amount", &mat.params).unwrap_or(0.5),
+                    amount_texture: texture_index("amount"),
+                };
+                materials.push(crate::material::Material::Mix(mix))
+            }
+            "diffusetransmission" => {
+                let scale = get_f32_from_params("scale", &mat.params).unwrap_or(1.0);
+                let reflectance = get_spectrum_from_params("reflectance", &mat.params, [0.25; 3]);
+                let transmittance =
+                    get_spectrum_from_params("transmittance", &mat.params, [0.25; 3]);
+                let difftrans = crate::material::DiffuseTransmissionMaterial {
+                    reflectance: reflectance.map(|v| v * scale),
+                    transmittance: transmittance.map(|v| v * scale),
+                    reflectance_texture: texture_index("reflectance"),
+                    transmittance_texture: texture_index("transmittance"),
+                };
+                materials.push(crate::material::Material::DiffTrans(difftrans))
+            }
+            "hair" => {
+                let beta_n = get_f32_from_params("beta_n", &mat.params).unwrap_or(0.3);
+                // the absorption is given directly, by the color, or by the melanin concentrations in this priority
+                let sigma_a = if mat.params.contains_key("sigma_a") {
+                    get_spectrum_from_params("sigma_a", &mat.params, [0f32; 3])
+                } else if mat.params.contains_key("reflectance") {
+                    let c = get_spectrum_from_params("reflectance", &mat.params, [0f32; 3]);
+                    crate::hair_bsdf::sigma_a_from_reflectance(&c, beta_n)
+                } else {
+                    let ce = get_f32_from_params("eumelanin", &mat.params).unwrap_or(1.3);
+                    let cp = get_f32_from_params("pheomelanin", &mat.params).unwrap_or(0.0);
+                    crate::hair_bsdf::sigma_a_from_concentration(ce, cp)
+                };
+                let hair = crate::material::HairMaterial {
+                    sigma_a,
+                    eta: get_f32_from_params("eta", &mat.params).unwrap_or(1.55),
+                    beta_m: get_f32_from_params("beta_m", &mat.params).unwrap_or(0.3),
+                    beta_n,
+                    alpha: get_f32_from_params("alpha", &mat.params).unwrap_or(2.0),
+                };
+                materials.push(crate::material::Material::Hair(hair))
+            }
+            "subsurface" => {
+                // the surface roughness is ignored and the boundary is always smooth
+                let scale = get_f32_from_params("scale", &mat.params).unwrap_or(1.0);
+                let eta = get_f32_from_params("eta", &mat.params).unwrap_or(1.33);
+                let boundary = crate::material::SubsurfaceBoundary::Dielectric;
+                let mut ss = if mat.params.contains_key("sigma_a") {
+                    let sigma_a = get_spectrum_from_params("sigma_a", &mat.params, [0f32; 3]);
+                    let sigma_s = get_spectrum_from_params("sigma_s", &mat.params, [0f32; 3]);
+                    crate::material::SubsurfaceMaterial {
+                        sigma_a: sigma_a.map(|v| v * scale),
+                        sigma_s: sigma_s.map(|v| v * scale),
+                        g: 0f32,
+                        eta,
+                        boundary,
+                    }
+                } else if mat.params.contains_key("reflectance") {
+                    let r = get_spectrum_from_params("reflectance", &mat.params, [1f32; 3]);
+                    let mfp = get_spectrum_from_params("mfp", &mat.params, [1f32; 3]);
+                    crate::material::SubsurfaceMaterial::from_mfp_albedo(
+                        &mfp.map(|v| v / scale),
+                        &r.map(crate::subsurface::single_scattering_albedo_from_reflectance),
+                        eta,
+                        boundary,
+                    )
+                } else {
+                    // skin-like default of pbrt-v4 in mm^-1
+                    crate::material::SubsurfaceMaterial {
+                        sigma_a: [0.0011, 0.0024, 0.014].map(|v| v * scale),
+                        sigma_s: [2.55, 3.21, 3.77].map(|v| v * scale),
+                        g: 0f32,
+                        eta,
+                        boundary,
+                    }
+                };
+                ss.g = get_f32_from_params("g", &mat.params).unwrap_or(0.0);
+                materials.push(crate::material::Material::Subsurface(ss))
+            }
             _ => {
                 dbg!(&mat.attributes);
                 panic!("Material paser not support");
             }
         }
     }
+    // the cyclic mix materials would never be resolved at the shading point
+    for (i_material, mat) in scene.materials.iter().enumerate() {
+        assert!(
+            !crate::material::is_mix_cyclic(&materials, i_material),
+            "the mix material refers to itself: {}",
+            mat.name
+        );
+    }
     materials
 }
 
-pub fn parse_area_light(scene: &pbrt4::Scene) -> Vec<AreaLight> {
+/// the bump map (`displacement`) and the normal map (`normalmap`) of the materials
+/// in the same order as [`parse_material`]
+/// * `path_file` - path of the pbrt file. The normal map is relative to it
+pub fn parse_normal_perturbation(
+    scene: &pbrt4::Scene,
+    path_file: &str,
+) -> Vec<crate::shading_normal::NormalPerturbation> {
+    let mut perturbations = Vec::with_capacity(scene.materials.len());
+    for mat in scene.materials.iter() {
+        let normal_map = mat.params.get("normalmap").map(|mp| {
+            let path = std::path::Path::new(path_file)
+                .parent()
+                .unwrap()
+                .join(mp.2.trim_matches('"'));
+            let map = crate::shading_normal::NormalMap::read_from(&path)
+                .unwrap_or_else(|e| panic!("failed to load {}: {}", path.display(), e));
+            std::sync::Arc::new(map)
+        });
+        perturbations.push(crate::shading_normal::NormalPerturbation {
+            displacement_texture: get_texture_index_from_material_param(
+                "displacement",
+                &mat.params,
+                &scene.textures,
+            )
+            .unwrap_or(usize::MAX),
+            normal_map,
+        });
+    }
+    perturbations
+}
+
+/// * `path_file` - path of the pbrt file. The emission image is relative to it
+pub fn parse_area_light(scene: &pbrt4::Scene, path_file: &str) -> Vec<AreaLight> {
     let mut area_lights = Vec::<AreaLight>::new();
-    for area_light in &scene.area_lights {
+    for (i_area_light, area_light) in scene.area_lights.iter().enumerate() {
         match area_light {
             pbrt4::types::AreaLight::Diffuse {
-                filename: _filename,
+                filename,
                 two_sided,
-                spectrum,
-                scale: _scale,
+                scale,
+                ..
             } => {
-                let spectrum_rgb = if let Some(spectrum) = spectrum {
-                    match spectrum {
-                        pbrt4::param::Spectrum::Rgb(rgb) => Some(rgb),
-                        pbrt4::param::Spectrum::Blackbody(_i) => {
-                            todo!()
+                let image = filename.as_ref().map(|filename| {
+                    let path = std::path::Path::new(path_file)
+                        .parent()
+                        .unwrap()
+                        .join(filename.trim_matches('"'));
+                    crate::area_light::EmissionImage::read_from(&path)
+                        .unwrap_or_else(|e| panic!("failed to load {}: {}", path.display(), e))
+                });
+                // the image is importance sampled only on the single triangle mesh of the light
+                let mut shapes = scene
+                    .shapes
+                    .iter()
+                    .filter(|shape| shape.area_light_index == Some(i_area_light));
+                let uv_grid = match (&image, shapes.next(), shapes.next()) {
+                    (Some(_), Some(shape), None) => match &shape.params {
+                        pbrt4::types::Shape::TriangleMesh { indices, uvs, .. } => {
+                            let tri2vtx: Vec<usize> = indices.iter().map(|&v| v as usize).collect();
+                            crate::area_light::UvTriangleGrid::new(&tri2vtx, uvs)
                         }
-                    }
-                } else {
-                    None
+                        _ => None,
+                    },
+                    _ => None,
                 };
                 let al = AreaLight {
-                    spectrum_rgb: spectrum_rgb.copied(),
+                    spectrum_rgb: spectrum_from_light_entity(area_light),
                     two_sided: *two_sided,
+                    scale: *scale,
+                    image,
+                    uv_grid,
                 };
                 area_lights.push(al);
             }
@@ -303,6 +668,7 @@ pub fn parse_shapes(scene: &pbrt4::Scene) -> Vec<ShapeEntity> {
                 indices,
                 positions,
                 normals,
+                uvs,
                 ..
             } => {
                 let tri2vtx: Vec<usize> = indices.iter().map(|&v| v as usize).collect();
@@ -318,12 +684,31 @@ pub fn parse_shapes(scene: &pbrt4::Scene) -> Vec<ShapeEntity> {
                     tri2vtx,
                     vtx2xyz,
                     vtx2nrm: normals.clone(),
+                    vtx2uv: uvs.clone(),
                     tri2cumsumarea,
                 }
             }
             pbrt4::types::Shape::Sphere { radius, .. } => {
                 crate::shape::ShapeType::Sphere { radius: *radius }
             }
+            pbrt4::types::Shape::Curve {
+                p, width0, width1, ..
+            } => {
+                // the cubic Bezier curve (the default basis of pbrt-v4) as a polyline of cylinders
+                let vtx2xyz = crate::shape::polyline_from_cubic_bezier(p, 8);
+                let edge2vtx = crate::shape::edge2vtx_from_polylines(&[0, vtx2xyz.len() / 3]);
+                let edge2cumsumlength = if shape_entity.area_light_index.is_some() {
+                    Some(crate::shape::edge2cumsumlength(&edge2vtx, &vtx2xyz))
+                } else {
+                    None
+                };
+                crate::shape::ShapeType::Curves {
+                    edge2vtx,
+                    vtx2xyz,
+                    radius: (width0 + width1) * 0.25,
+                    edge2cumsumlength,
+                }
+            }
             _ => {
                 dbg!(&shape_entity.params);
                 panic!("Parse unsupported shape")
@@ -349,10 +734,11 @@ pub fn parse_texture(scene: &pbrt4::Scene) -> Vec<crate::textures::Texture> {
     for tex in scene.textures.iter() {
         match tex.class.as_str() {
             "checkerboard" => {
-                let tex1 = get_f32_array3_from_params("tex1", &tex.params).unwrap();
-                let tex2 = get_f32_array3_from_params("tex2", &tex.params).unwrap();
-                let uscale = get_f32_from_params("uscale", &tex.params).unwrap();
-                let vscale = get_f32_from_params("vscale", &tex.params).unwrap();
+                // the float texture has the same value in the RGB channels
+                let tex1 = get_spectrum_from_params("tex1", &tex.params, [1.0; 3]);
+                let tex2 = get_spectrum_from_params("tex2", &tex.params, [0.0; 3]);
+                let uscale = get_f32_from_params("uscale", &tex.params).unwrap_or(1.0);
+                let vscale = get_f32_from_params("vscale", &tex.params).unwrap_or(1.0);
 
                 let checker = crate::textures::CheckerBoardTexture {
                     uscale,
@@ -370,3 +756,61 @@ pub fn parse_texture(scene: &pbrt4::Scene) -> Vec<crate::textures::Texture> {
     }
     textures
 }
+
+#[test]
+fn test_parse_conductor_reflectance() {
+    let path_file = std::env::temp_dir().join("del_raycast_test_parse_conductor.pbrt");
+    std::fs::write(
+        &path_file,
+        r#"Camera "perspective" "float fov" [ 45 ]
+WorldBegin
+MakeNamedMaterial "Gold"
+    "string type" [ "conductor" ]
+    "rgb reflectance" [ 0.9 0.6 0.2 ]
+MakeNamedMaterial "Copper"
+    "string type" [ "conductor" ]
+"#,
+    )
+    .unwrap();
+    let path_file = path_file.to_str().unwrap();
+    let scene = pbrt4::Scene::from_file(path_file).unwrap();
+    let materials = parse_material(&scene, path_file);
+    let crate::material::Material::Cond(gold) = &materials[0] else {
+        panic!("the conductor is not parsed")
+    };
+    // the reflectance is given by eta and k instead of tinting the copper
+    assert_eq!(gold.reflectance, [1.0; 3]);
+    assert_eq!(gold.eta, [1.0; 3]);
+    for (i, r) in [0.9f32, 0.6, 0.2].iter().enumerate() {
+        let f = crate::material::fresnel_conductor_reflectance(gold.eta[i], gold.k[i], 1.0);
+        assert!((f - r).abs() < 1.0e-4, "{} {}", f, r);
+    }
+    let crate::material::Material::Cond(copper) = &materials[1] else {
+        panic!("the conductor is not parsed")
+    };
+    assert_eq!(
+        copper.eta,
+        crate::spectrum::named_spectrum_rgb("metal-Cu-eta").unwrap()
+    );
+    assert_eq!(copper.reflectance, [1.0; 3]);
+}
+
+#[test]
+fn test_parse_measured_not_merl() {
+    let path_file = std::env::temp_dir().join("del_raycast_test_parse_measured.pbrt");
+    std::fs::write(
+        &path_file,
+        r#"Camera "perspective" "float fov" [ 45 ]
+WorldBegin
+MakeNamedMaterial "Paint"
+    "string type" [ "measured" ]
+    "string filename" [ "paint.bsdf" ]
+"#,
+    )
+    .unwrap();
+    let path_file = path_file.to_str().unwrap();
+    let scene = pbrt4::Scene::from_file(path_file).unwrap();
+    // the RGL file is replaced by the diffuse material without being read
+    let materials = parse_material(&scene, path_file);
+    assert!(matches!(materials[0], crate::material::Material::Diff(_)));
+}
@@ -0,0 +1,685 @@
+//! Analytic direct lighting from planar polygonal area lights (without the shadow).
+//! The diffuse shading is the edge integral of the form factor (Lambert's formula), and
+//! the glossy shading approximates the microfacet BRDF with the linearly transformed cosines (LTC).
+//! See "Real-Time Polygonal-Light Shading with Linearly Transformed Cosines" by Eric Heitz et al. 2016
+//!
+//! The front side of a polygon is the side from which its vertices are seen counter-clockwise.
+
+use num_traits::AsPrimitive;
+
+/// clip the polygon with the horizon `z = 0` and keep the part with `z >= 0`
+pub fn clip_polygon_by_horizon<T>(polygon: &[[T; 3]]) -> Vec<[T; 3]>
+where
+    T: num_traits::Float,
+{
+    let num_vtx = polygon.len();
+    let mut clipped = Vec::with_capacity(num_vtx + 1);
+    for (i0, p0) in polygon.iter().enumerate() {
+        let p1 = &polygon[(i0 + 1) % num_vtx];
+        let (is_above0, is_above1) = (p0[2] >= T::zero(), p1[2] >= T::zero());
+        if is_above0 {
+            clipped.push(*p0);
+        }
+        if is_above0 != is_above1 {
+            let r = p0[2] / (p0[2] - p1[2]);
+            clipped.push([
+                p0[0] + r * (p1[0] - p0[0]),
+                p0[1] + r * (p1[1] - p0[1]),
+                T::zero(),
+            ]);
+        }
+    }
+    clipped
+}
+
+/// z-component of `acos(v0.v1) * normalize(v0 x v1)` for the unit vectors `v0` and `v1`
+fn integrate_edge_z<T>(v0: &[T; 3], v1: &[T; 3]) -> T
+where
+    T: num_traits::Float + Copy + 'static,
+    f64: AsPrimitive<T>,
+{
+    use del_geo_core::vec3::Vec3;
+    let cos_theta = v0.dot(v1).max(-T::one()).min(T::one());
+    let theta = cos_theta.acos();
+    let sin_theta = (T::one() - cos_theta * cos_theta).max(T::zero()).sqrt();
+    // theta / sin(theta) goes to one at the limit of theta -> 0
+    let ratio = if sin_theta > 1.0e-7.as_() {
+        theta / sin_theta
+    } else {
+        T::one()
+    };
+    v0.cross(v1)[2] * ratio
+}
+
+/// form factor `\int_P max(cos, 0) / pi dw` of the polygon `P` seen from the origin
+/// where the normal is in the z direction.
+/// * `is_two_sided` - if false, the polygon whose back side faces the origin gives zero
+pub fn form_factor_local<T>(polygon: &[[T; 3]], is_two_sided: bool) -> T
+where
+    T: num_traits::Float + Copy + 'static,
+    f64: AsPrimitive<T>,
+{
+    use del_geo_core::vec3::Vec3;
+    let polygon = clip_polygon_by_horizon(polygon);
+    if polygon.len() < 3 {
+        return T::zero();
+    }
+    let mut sum = T::zero();
+    for (i0, p0) in polygon.iter().enumerate() {
+        let p1 = &polygon[(i0 + 1) % polygon.len()];
+        sum = sum + integrate_edge_z(&p0.normalize(), &p1.normalize());
+    }
+    // the sum is negative if the front side faces the origin
+    let sum = if is_two_sided {
+        sum.abs()
+    } else {
+        (-sum).max(T::zero())
+    };
+    sum / (2f64 * std::f64::consts::PI).as_()
+}
+
+/// orthonormal frame `[ex, ey, ez]` with `ez = nrm` and `ex` orthogonal to `nrm` in the direction of `dir`.
+/// An arbitrary `ex` is used if `dir` is parallel to `nrm`
+fn local_frame<T>(nrm: &[T; 3], dir: Option<&[T; 3]>) -> [[T; 3]; 3]
+where
+    T: num_traits::Float + Copy + 'static,
+    f64: AsPrimitive<T>,
+{
+    use del_geo_core::vec3::Vec3;
+    if let Some(dir) = dir {
+        let ex = dir.sub(&nrm.scale(nrm.dot(dir)));
+        if ex.norm() > 1.0e-5.as_() {
+            let ex = ex.normalize();
+            return [ex, nrm.cross(&ex), *nrm];
+        }
+    }
+    let t = if nrm[0].abs() > 0.1.as_() {
+        [T::zero(), T::one(), T::zero()]
+    } else {
+        [T::one(), T::zero(), T::zero()]
+    };
+    let ex = t.cross(nrm).normalize();
+    [ex, nrm.cross(&ex), *nrm]
+}
+
+/// vertices of the polygon relative to `pos` in the local frame
+fn polygon_in_local_frame<T>(vtx2xyz: &[T], pos: &[T; 3], frame: &[[T; 3]; 3]) -> Vec<[T; 3]>
+where
+    T: num_traits::Float + Copy + 'static,
+    f64: AsPrimitive<T>,
+{
+    use del_geo_core::vec3::Vec3;
+    vtx2xyz
+        .chunks(3)
+        .map(|p| {
+            let d = [p[0] - pos[0], p[1] - pos[1], p[2] - pos[2]];
+            [d.dot(&frame[0]), d.dot(&frame[1]), d.dot(&frame[2])]
+        })
+        .collect()
+}
+
+/// form factor of the planar polygon light at the point `pos` with the unit normal `nrm`.
+/// The Lambertian surface with the reflectance `rho` lit by the polygon with the radiance `L`
+/// reflects the radiance `rho * L * form_factor`.
+/// * `vtx2xyz` - coordinates of the polygon's vertices
+pub fn form_factor<T>(vtx2xyz: &[T], pos: &[T; 3], nrm: &[T; 3], is_two_sided: bool) -> T
+where
+    T: num_traits::Float + Copy + 'static,
+    f64: AsPrimitive<T>,
+{
+    let frame = local_frame(nrm, None);
+    let polygon = polygon_in_local_frame(vtx2xyz, pos, &frame);
+    form_factor_local(&polygon, is_two_sided)
+}
+
+/// sum of the form factors of the triangles (e.g., a quad made of two triangles) of an emissive mesh
+pub fn form_factor_trimesh<T>(
+    tri2vtx: &[usize],
+    vtx2xyz: &[T],
+    pos: &[T; 3],
+    nrm: &[T; 3],
+    is_two_sided: bool,
+) -> T
+where
+    T: num_traits::Float + Copy + 'static,
+    f64: AsPrimitive<T>,
+{
+    let frame = local_frame(nrm, None);
+    let vtx2lcl = polygon_in_local_frame(vtx2xyz, pos, &frame);
+    tri2vtx.chunks(3).fold(T::zero(), |sum, node2vtx| {
+        let tri = [
+            vtx2lcl[node2vtx[0]],
+            vtx2lcl[node2vtx[1]],
+            vtx2lcl[node2vtx[2]],
+        ];
+        sum + form_factor_local(&tri, is_two_sided)
+    })
+}
+
+/// integral of the LTC distribution over the polygon given in the local frame
+/// * `m_inv` - inverse of the LTC matrix in the column-major order
+pub fn ltc_integral_local<T>(m_inv: &[T; 9], polygon: &[[T; 3]], is_two_sided: bool) -> T
+where
+    T: num_traits::Float + Copy + 'static,
+    f64: AsPrimitive<T>,
+{
+    let polygon: Vec<[T; 3]> = polygon
+        .iter()
+        .map(|p| del_geo_core::mat3_col_major::mult_vec(m_inv, p))
+        .collect();
+    form_factor_local(&polygon, is_two_sided)
+}
+
+// ----------------------------------------
+// fitting the LTC to the microfacet BRDFs
+
+/// microfacet distributions approximated by the LTC
+#[derive(Clone, Copy, Debug)]
+pub enum Microfacet {
+    Beckmann,
+    Ggx,
+}
+
+impl Microfacet {
+    /// isotropic Trowbridge-Reitz distribution used for [`Microfacet::Ggx`]
+    fn trowbridge_reitz(alpha: f32) -> crate::microfacet::TrowbridgeReitz {
+        crate::microfacet::TrowbridgeReitz::new(alpha, alpha)
+    }
+
+    /// `brdf * cos` of the microfacet reflection with the Fresnel term of one
+    pub fn eval_brdf_cos(&self, alpha: f32, wo: &[f32; 3], wi: &[f32; 3]) -> f32 {
+        use del_geo_core::vec3::Vec3;
+        if wo[2] <= 0f32 || wi[2] <= 0f32 {
+            return 0f32;
+        }
+        let h = wo.add(wi).normalize();
+        let (d, g) = match self {
+            Microfacet::Beckmann => (
+                crate::material::microfacet_beckmann_d(alpha, &h),
+                crate::material::microfacet_distribution_g(alpha, wo, wi, &h),
+            ),
+            Microfacet::Ggx => {
+                let distrib = Self::trowbridge_reitz(alpha);
+                (distrib.d(&h), distrib.g(wo, wi))
+            }
+        };
+        d * g / (4f32 * wo[2])
+    }
+
+    /// sample the incoming direction by reflecting `wo` with the sampled microfacet normal.
+    /// The visible normals are sampled for [`Microfacet::Ggx`]
+    pub fn sample_wi(&self, alpha: f32, wo: &[f32; 3], xi: &[f32; 2]) -> [f32; 3] {
+        use del_geo_core::vec3::Vec3;
+        let m = match self {
+            Microfacet::Beckmann => crate::material::microfacet_beckmann_sample(alpha, xi),
+            Microfacet::Ggx => Self::trowbridge_reitz(alpha).sample_wm(wo, xi),
+        };
+        m.scale(2f32 * wo.dot(&m)).sub(wo)
+    }
+
+    /// pdf of the direction sampled by [`Microfacet::sample_wi`]
+    pub fn pdf_wi(&self, alpha: f32, wo: &[f32; 3], wi: &[f32; 3]) -> f32 {
+        use del_geo_core::vec3::Vec3;
+        let h = wo.add(wi);
+        if h.norm() < f32::EPSILON {
+            return 0f32;
+        }
+        let h = h.normalize();
+        let dot = wo.dot(&h).abs();
+        if dot < f32::EPSILON {
+            return 0f32;
+        }
+        let pdf_h = match self {
+            Microfacet::Beckmann => crate::material::microfacet_beckmann_pdf(alpha, &h),
+            Microfacet::Ggx => Self::trowbridge_reitz(alpha).pdf(wo, &h),
+        };
+        pdf_h / (4f32 * dot)
+    }
+}
+
+/// value of the LTC distribution in the direction `w`
+fn ltc_eval(m_inv: &[f32; 9], det_m_inv: f32, w: &[f32; 3]) -> f32 {
+    use del_geo_core::vec3::Vec3;
+    let w_orig = del_geo_core::mat3_col_major::mult_vec(m_inv, w);
+    let len = w_orig.norm();
+    let d_orig = (w_orig[2] / len).max(0f32) * std::f32::consts::FRAC_1_PI;
+    d_orig * det_m_inv.abs() / (len * len * len)
+}
+
+/// minimize the function with the Nelder-Mead method starting from the simplex around `start`
+fn nelder_mead<F>(func: F, start: [f32; 3], delta: f32, tolerance: f32, max_iter: usize) -> [f32; 3]
+where
+    F: Fn(&[f32; 3]) -> f32,
+{
+    // point `c + t * (p - c)`
+    let lerp = |c: &[f32; 3], p: &[f32; 3], t: f32| -> [f32; 3] {
+        [
+            c[0] + t * (p[0] - c[0]),
+            c[1] + t * (p[1] - c[1]),
+            c[2] + t * (p[2] - c[2]),
+        ]
+    };
+    let mut pts = [start; 4];
+    pts[1][0] += delta;
+    pts[2][1] += delta;
+    pts[3][2] += delta;
+    let mut vals = pts.map(|p| func(&p));
+    for _iter in 0..max_iter {
+        let mut idx = [0, 1, 2, 3];
+        idx.sort_by(|&i, &j| vals[i].total_cmp(&vals[j]));
+        pts = idx.map(|i| pts[i]);
+        vals = idx.map(|i| vals[i]);
+        if (vals[3] - vals[0]).abs() < tolerance {
+            break;
+        }
+        let c = [
+            (pts[0][0] + pts[1][0] + pts[2][0]) / 3f32,
+            (pts[0][1] + pts[1][1] + pts[2][1]) / 3f32,
+            (pts[0][2] + pts[1][2] + pts[2][2]) / 3f32,
+        ];
+        let p_r = lerp(&c, &pts[3], -1f32);
+        let v_r = func(&p_r);
+        if v_r < vals[0] {
+            let p_e = lerp(&c, &pts[3], -2f32);
+            let v_e = func(&p_e);
+            (pts[3], vals[3]) = if v_e < v_r { (p_e, v_e) } else { (p_r, v_r) };
+            continue;
+        }
+        if v_r < vals[2] {
+            (pts[3], vals[3]) = (p_r, v_r);
+            continue;
+        }
+        let p_c = if v_r < vals[3] {
+            lerp(&c, &pts[3], -0.5f32)
+        } else {
+            lerp(&c, &pts[3], 0.5f32)
+        };
+        let v_c = func(&p_c);
+        if v_c < vals[3].min(v_r) {
+            (pts[3], vals[3]) = (p_c, v_c);
+            continue;
+        }
+        // shrink toward the best point
+        let p0 = pts[0];
+        for (p, v) in pts.iter_mut().zip(vals.iter_mut()).skip(1) {
+            *p = lerp(&p0, p, 0.5f32);
+            *v = func(p);
+        }
+    }
+    let i_best = (0..4).min_by(|&i, &j| vals[i].total_cmp(&vals[j])).unwrap();
+    pts[i_best]
+}
+
+/// fit the LTC to `brdf * cos` for the view direction `(sin(theta), 0, cos(theta))`.
+/// The LTC matrix is `[X, Y, Z] * [[m11, 0, m13], [0, m22, 0], [0, 0, 1]]` where
+/// `Z` is the average direction of the BRDF lobe and `(m11, m22, m13)` are optimized.
+/// # Return
+/// `(m_inv, magnitude, (m11, m22, m13))`
+fn fit_ltc(
+    microfacet: Microfacet,
+    alpha: f32,
+    theta: f32,
+    num_sample: usize,
+    params_init: &[f32; 3],
+) -> ([f32; 9], f32, [f32; 3]) {
+    use del_geo_core::mat3_col_major;
+    use del_geo_core::vec3::Vec3;
+    let wo = [theta.sin(), 0f32, theta.cos()];
+    let xis: Vec<[f32; 2]> = (0..num_sample)
+        .map(|i| {
+            [
+                (i as f32 + 0.5f32) / num_sample as f32,
+                crate::sampling::radical_inverse(i, 2),
+            ]
+        })
+        .collect();
+    // albedo and the average direction of the lobe
+    let mut magnitude = 0f32;
+    let mut dir_avg = [0f32; 3];
+    for xi in xis.iter() {
+        let wi = microfacet.sample_wi(alpha, &wo, xi);
+        let pdf = microfacet.pdf_wi(alpha, &wo, &wi);
+        if pdf <= 0f32 {
+            continue;
+        }
+        let w = microfacet.eval_brdf_cos(alpha, &wo, &wi) / pdf;
+        magnitude += w;
+        dir_avg = del_geo_core::vec3::axpy(w, &wi, &dir_avg);
+    }
+    magnitude /= num_sample as f32;
+    // the lobe is symmetric about the xz-plane
+    let ez = [dir_avg[0], 0f32, dir_avg[2]];
+    let ez = if ez.norm() > f32::EPSILON {
+        ez.normalize()
+    } else {
+        [0f32, 0f32, 1f32]
+    };
+    let ex = [ez[2], 0f32, -ez[0]];
+    let ey = [0f32, 1f32, 0f32];
+    let to_m = |p: &[f32; 3]| -> [f32; 9] {
+        let c0 = ex.scale(p[0].abs());
+        let c1 = ey.scale(p[1].abs());
+        let c2 = ex.scale(p[2]).add(&ez);
+        [
+            c0[0], c0[1], c0[2], c1[0], c1[1], c1[2], c2[0], c2[1], c2[2],
+        ]
+    };
+    let error = |p: &[f32; 3]| -> f32 {
+        let m = to_m(p);
+        let Some(m_inv) = mat3_col_major::try_inverse(&m) else {
+            return f32::MAX;
+        };
+        let det_m_inv = mat3_col_major::determinant(&m_inv);
+        let mut error = 0f32;
+        for xi in xis.iter() {
+            let w_ltc =
+                mat3_col_major::mult_vec(&m, &crate::sampling::hemisphere_zup_cos_weighted(xi))
+                    .normalize();
+            let w_brdf = microfacet.sample_wi(alpha, &wo, xi);
+            for w in [w_ltc, w_brdf] {
+                let eval_ltc = ltc_eval(&m_inv, det_m_inv, &w);
+                let eval_brdf = microfacet.eval_brdf_cos(alpha, &wo, &w);
+                let pdf_brdf = microfacet.pdf_wi(alpha, &wo, &w);
+                let diff = (eval_brdf - magnitude * eval_ltc).abs();
+                // the balance heuristic of the two sampling strategies
+                error += diff * diff * diff / (eval_ltc + pdf_brdf).max(f32::EPSILON);
+            }
+        }
+        error / (2 * num_sample) as f32
+    };
+    let params = nelder_mead(error, *params_init, 0.05f32, 1.0e-5f32, 100);
+    let m_inv = mat3_col_major::try_inverse(&to_m(&params))
+        .unwrap_or([1f32, 0f32, 0f32, 0f32, 1f32, 0f32, 0f32, 0f32, 1f32]);
+    // the LTC is invariant to the scaling. normalize for the interpolation
+    let m_inv = m_inv.map(|v| v / m_inv[4]);
+    (m_inv, magnitude, params)
+}
+
+/// the smallest `alpha` in [`LtcTable`]
+pub const LTC_MIN_ALPHA: f32 = 1.0e-3;
+
+/// the largest angle between the normal and the view direction in [`LtcTable`]
+const LTC_MAX_THETA: f32 = 1.57f32;
+
+/// table of the LTC fitted to a microfacet BRDF
+/// parameterized by `sqrt(alpha)` and the angle between the normal and the view direction
+pub struct LtcTable {
+    pub num_alpha: usize,
+    pub num_theta: usize,
+    /// inverse LTC matrix in the column-major order at `i_alpha * num_theta + i_theta`
+    pub minv: Vec<[f32; 9]>,
+    /// albedo `\int brdf * cos dw` with the Fresnel term of one
+    pub magnitude: Vec<f32>,
+}
+
+impl LtcTable {
+    /// fit the LTC for each entry of the table with `num_sample` samples.
+    /// `(32, 32, 256)` takes a few seconds
+    pub fn new(
+        microfacet: Microfacet,
+        num_alpha: usize,
+        num_theta: usize,
+        num_sample: usize,
+    ) -> Self {
+        assert!(num_alpha >= 2 && num_theta >= 2);
+        let mut minv = vec![[0f32; 9]; num_alpha * num_theta];
+        let mut magnitude = vec![0f32; num_alpha * num_theta];
+        // from the rough to the smooth so that the previous fit is a good initial guess
+        let mut params_alpha = [1f32, 1f32, 0f32];
+        for i_alpha in (0..num_alpha).rev() {
+            let r = i_alpha as f32 / (num_alpha - 1) as f32;
+            let alpha = (r * r).max(LTC_MIN_ALPHA);
+            let mut params = [params_alpha[0].min(alpha), params_alpha[1].min(alpha), 0f32];
+            for i_theta in 0..num_theta {
+                let theta = LTC_MAX_THETA * i_theta as f32 / (num_theta - 1) as f32;
+                let (m_inv, mag, params_fit) =
+                    fit_ltc(microfacet, alpha, theta, num_sample, &params);
+                minv[i_alpha * num_theta + i_theta] = m_inv;
+                magnitude[i_alpha * num_theta + i_theta] = mag;
+                params = params_fit;
+                if i_theta == 0 {
+                    params_alpha = params_fit;
+                }
+            }
+        }
+        LtcTable {
+            num_alpha,
+            num_theta,
+            minv,
+            magnitude,
+        }
+    }
+
+    /// bilinearly interpolated inverse LTC matrix and the magnitude
+    pub fn lookup(&self, alpha: f32, cos_theta: f32) -> ([f32; 9], f32) {
+        let ta = alpha.max(0f32).sqrt().min(1f32) * (self.num_alpha - 1) as f32;
+        let tt = (cos_theta.clamp(0f32, 1f32).acos() / LTC_MAX_THETA).min(1f32)
+            * (self.num_theta - 1) as f32;
+        let (ia, it) = (
+            (ta as usize).min(self.num_alpha - 2),
+            (tt as usize).min(self.num_theta - 2),
+        );
+        let (ra, rt) = (ta - ia as f32, tt - it as f32);
+        let mut m_inv = [0f32; 9];
+        let mut magnitude = 0f32;
+        for (ja, wa) in [(ia, 1f32 - ra), (ia + 1, ra)] {
+            for (jt, wt) in [(it, 1f32 - rt), (it + 1, rt)] {
+                let idx = ja * self.num_theta + jt;
+                for (v, &w) in m_inv.iter_mut().zip(self.minv[idx].iter()) {
+                    *v += wa * wt * w;
+                }
+                magnitude += wa * wt * self.magnitude[idx];
+            }
+        }
+        (m_inv, magnitude)
+    }
+}
+
+/// outgoing radiance of the glossy microfacet surface lit by the planar polygon light with the unit radiance,
+/// with the Fresnel term of one. Multiply the radiance of the light and the specular color (e.g., Fresnel reflectance)
+/// * `dir_out` - unit vector from `pos` toward the viewer
+#[allow(clippy::too_many_arguments)]
+pub fn ltc_glossy(
+    table: &LtcTable,
+    vtx2xyz: &[f32],
+    pos: &[f32; 3],
+    nrm: &[f32; 3],
+    dir_out: &[f32; 3],
+    alpha: f32,
+    is_two_sided: bool,
+) -> f32 {
+    let cos_theta = del_geo_core::vec3::dot(nrm, dir_out);
+    if cos_theta <= 0f32 {
+        return 0f32;
+    }
+    let frame = local_frame(nrm, Some(dir_out));
+    let polygon = polygon_in_local_frame(vtx2xyz, pos, &frame);
+    let (m_inv, magnitude) = table.lookup(alpha, cos_theta);
+    magnitude * ltc_integral_local(&m_inv, &polygon, is_two_sided)
+}
+
+/// [`ltc_glossy`] for the triangles of an emissive mesh
+#[allow(clippy::too_many_arguments)]
+pub fn ltc_glossy_trimesh(
+    table: &LtcTable,
+    tri2vtx: &[usize],
+    vtx2xyz: &[f32],
+    pos: &[f32; 3],
+    nrm: &[f32; 3],
+    dir_out: &[f32; 3],
+    alpha: f32,
+    is_two_sided: bool,
+) -> f32 {
+    let cos_theta = del_geo_core::vec3::dot(nrm, dir_out);
+    if cos_theta <= 0f32 {
+        return 0f32;
+    }
+    let frame = local_frame(nrm, Some(dir_out));
+    let vtx2lcl = polygon_in_local_frame(vtx2xyz, pos, &frame);
+    let (m_inv, magnitude) = table.lookup(alpha, cos_theta);
+    tri2vtx.chunks(3).fold(0f32, |sum, node2vtx| {
+        let tri = [
+            vtx2lcl[node2vtx[0]],
+            vtx2lcl[node2vtx[1]],
+            vtx2lcl[node2vtx[2]],
+        ];
+        sum + magnitude * ltc_integral_local(&m_inv, &tri, is_two_sided)
+    })
+}
+
+/// surface lit by the emitter in [`direct_radiance_trimesh`]
+pub enum Receiver<'a> {
+    /// Lambertian surface with the reflectance
+    Diffuse([f32; 3]),
+    /// microfacet surface with the roughness `alpha` whose BRDF is fitted by `table` (e.g., GGX).
+    /// `specular` is multiplied as the Fresnel term (e.g., the reflectance at the normal incidence)
+    Glossy {
+        table: &'a LtcTable,
+        alpha: f32,
+        specular: [f32; 3],
+    },
+}
+
+/// radiance reflected toward `dir_out` at `pos` lit by the emissive triangle mesh with the uniform radiance `emission`.
+/// The light is integrated over the mesh analytically with the form factor for the diffuse receiver
+/// and with the LTC for the glossy receiver.
+/// The visibility is not taken into account: the occluders between `pos` and the mesh are ignored
+#[allow(clippy::too_many_arguments)]
+pub fn direct_radiance_trimesh(
+    receiver: &Receiver,
+    tri2vtx: &[usize],
+    vtx2xyz: &[f32],
+    emission: &[f32; 3],
+    is_two_sided: bool,
+    pos: &[f32; 3],
+    nrm: &[f32; 3],
+    dir_out: &[f32; 3],
+) -> [f32; 3] {
+    let color = match receiver {
+        Receiver::Diffuse(reflectance) => {
+            let ff = form_factor_trimesh(tri2vtx, vtx2xyz, pos, nrm, is_two_sided);
+            reflectance.map(|v| v * ff)
+        }
+        Receiver::Glossy {
+            table,
+            alpha,
+            specular,
+        } => {
+            let g = ltc_glossy_trimesh(
+                table,
+                tri2vtx,
+                vtx2xyz,
+                pos,
+                nrm,
+                dir_out,
+                *alpha,
+                is_two_sided,
+            );
+            specular.map(|v| v * g)
+        }
+    };
+    std::array::from_fn(|i| color[i] * emission[i])
+}
+
+#[test]
+fn test_form_factor() {
+    // unit square facing down above the origin at the height of one
+    let vtx2xyz: Vec<f64> = vec![-1., -1., 1., -1., 1., 1., 1., 1., 1., 1., -1., 1.];
+    let ff = form_factor(&vtx2xyz, &[0.; 3], &[0., 0., 1.], false);
+    // analytic form factor from a differential area to a parallel rectangle
+    let corner = |a: f64, b: f64| {
+        let (sa, sb) = ((1. + a * a).sqrt(), (1. + b * b).sqrt());
+        (a / sa * (b / sa).atan() + b / sb * (a / sb).atan()) * 0.5 * std::f64::consts::FRAC_1_PI
+    };
+    assert!((ff - 4. * corner(1., 1.)).abs() < 1.0e-10);
+    // the back side
+    let vtx2xyz_rev: Vec<f64> = vtx2xyz.chunks(3).rev().flatten().cloned().collect();
+    assert_eq!(
+        form_factor(&vtx2xyz_rev, &[0.; 3], &[0., 0., 1.], false),
+        0.
+    );
+    let ff_rev = form_factor(&vtx2xyz_rev, &[0.; 3], &[0., 0., 1.], true);
+    assert!((ff - ff_rev).abs() < 1.0e-10);
+    // the same square as two triangles in f32
+    let vtx2xyz: Vec<f32> = vtx2xyz.iter().map(|&v| v as f32).collect();
+    let ff_tri = form_factor_trimesh(
+        &[0, 1, 2, 0, 2, 3],
+        &vtx2xyz,
+        &[0.; 3],
+        &[0., 0., 1.],
+        false,
+    );
+    assert!((ff as f32 - ff_tri).abs() < 1.0e-5);
+    // the polygon below the horizon is clipped
+    let ff_tilt = form_factor(&vtx2xyz, &[0., 0., 0.], &[1., 0., 0.], false);
+    assert!(ff_tilt > 0f32 && ff_tilt < ff_tri * 0.5);
+    // the LTC with the identity matrix is the clamped cosine
+    let polygon: Vec<[f32; 3]> = vtx2xyz.chunks(3).map(|p| [p[0], p[1], p[2]]).collect();
+    let identity = [1., 0., 0., 0., 1., 0., 0., 0., 1.];
+    assert!((ltc_integral_local(&identity, &polygon, false) - ff_tri).abs() < 1.0e-5);
+}
+
+#[test]
+fn test_ltc_glossy() {
+    use del_geo_core::vec3::Vec3;
+    use rand::Rng;
+    use rand::SeedableRng;
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    // the LTC is close to the GGX distribution, while the Gaussian-like
+    // Beckmann distribution is approximated with the wider lobe
+    for (microfacet, tolerance) in [(Microfacet::Ggx, 0.08f32), (Microfacet::Beckmann, 0.2)] {
+        let table = LtcTable::new(microfacet, 6, 6, 128);
+        // on and between the entries of the table
+        for (alpha, r_theta) in [
+            (0.16f32, 0.2f32),
+            (0.36, 0.4),
+            (0.64, 0.2),
+            (0.05, 0.3),
+            (0.1, 0.1),
+            (0.25, 0.5),
+        ] {
+            let theta = r_theta * LTC_MAX_THETA;
+            let dir_out = [theta.sin(), 0f32, theta.cos()];
+            // unit square at the height of one around the mirror direction of the viewer
+            let c = -theta.tan();
+            let vtx2xyz: Vec<f32> = [[-0.5, -0.5], [0.5, -0.5], [0.5, 0.5], [-0.5, 0.5]]
+                .iter()
+                .flat_map(|p| [c + p[0], p[1], 1.])
+                .collect();
+            let ltc = ltc_glossy(
+                &table,
+                &vtx2xyz,
+                &[0f32; 3],
+                &[0., 0., 1.],
+                &dir_out,
+                alpha,
+                true,
+            );
+            // Monte Carlo integration of `brdf * cos` over the square
+            let num_sample = 100000;
+            let mut sum = 0f32;
+            for _ in 0..num_sample {
+                let wi = microfacet.sample_wi(alpha, &dir_out, &[rng.random(), rng.random()]);
+                if wi[2] <= 0f32 {
+                    continue;
+                }
+                let p = wi.scale(1f32 / wi[2]);
+                if (p[0] - c).abs() > 0.5 || p[1].abs() > 0.5 {
+                    continue;
+                }
+                let pdf = microfacet.pdf_wi(alpha, &dir_out, &wi);
+                sum += microfacet.eval_brdf_cos(alpha, &dir_out, &wi) / pdf;
+            }
+            let mc = sum / num_sample as f32;
+            assert!(
+                (ltc - mc).abs() < tolerance * mc,
+                "{:?} {} {} {} {}",
+                microfacet,
+                alpha,
+                theta,
+                ltc,
+                mc
+            );
+        }
+    }
+}
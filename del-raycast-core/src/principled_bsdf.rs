@@ -0,0 +1,466 @@
+//! Principled BSDF of "Physically-Based Shading at Disney" by Brent Burley 2012 and
+//! "Extending the Disney BRDF to a BSDF with Integrated Subsurface Scattering" by Brent Burley 2015.
+//! The opaque base has the diffuse, the sheen, the specular and the clearcoat lobes.
+//! The transmission is the rough dielectric interface. The microfacet lobes use the isotropic Trowbridge-Reitz distribution.
+//!
+//! As in pbrt, `wo` is the unit vector toward the viewer and `wi` is the one toward the light.
+//! Both are in the local frame whose z-axis is the normal.
+//! The opaque lobes are two-sided while the transmission distinguishes the inside (`z < 0`) from the outside.
+
+use crate::bxdf::{BSDFSample, BxDF, BxDFFlags, BxDFReflTransFlags, TransportMode};
+
+pub struct PrincipledBxDF {
+    pub base_color: [f32; 3],
+    pub metallic: f32,
+    /// roughness for the retro-reflection of the diffuse lobe
+    pub roughness: f32,
+    pub specular: f32,
+    pub specular_tint: f32,
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    pub clearcoat: f32,
+    pub clearcoat_gloss: f32,
+    pub transmission: f32,
+    /// index of refraction of the inside over the outside
+    pub eta: f32,
+    pub distrib: crate::microfacet::TrowbridgeReitz,
+}
+
+fn luminance(c: &[f32; 3]) -> f32 {
+    0.2126f32 * c[0] + 0.7152f32 * c[1] + 0.0722f32 * c[2]
+}
+
+fn lerp3(a: &[f32; 3], b: &[f32; 3], t: f32) -> [f32; 3] {
+    std::array::from_fn(|i| a[i] * (1f32 - t) + b[i] * t)
+}
+
+/// `(1 - cos)^5` of the Schlick's approximation
+fn schlick_weight(cos: f32) -> f32 {
+    let m = (1f32 - cos).clamp(0f32, 1f32);
+    (m * m) * (m * m) * m
+}
+
+/// Smith's masking of the GGX divided by `2 cos`. The product for the pair of the directions
+/// is the masking-shadowing divided by `4 cos_o cos_i`
+fn smith_g_ggx_separable(cos: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let c2 = cos * cos;
+    1f32 / (cos + (a2 + c2 - a2 * c2).sqrt())
+}
+
+/// GTR1 (Berry) distribution of the clearcoat
+fn gtr1_d(cos_theta_h: f32, alpha: f32) -> f32 {
+    if alpha >= 1f32 {
+        return std::f32::consts::FRAC_1_PI;
+    }
+    let a2 = alpha * alpha;
+    let t = 1f32 + (a2 - 1f32) * cos_theta_h * cos_theta_h;
+    (a2 - 1f32) / (std::f32::consts::PI * a2.ln() * t)
+}
+
+/// sample the half vector of the GTR1 distribution. The pdf is [`gtr1_d`] multiplied by `cos_theta_h`
+fn gtr1_sample(alpha: f32, u: &[f32; 2]) -> [f32; 3] {
+    let a2 = alpha * alpha;
+    let cos_theta = ((1f32 - a2.powf(1f32 - u[0])) / (1f32 - a2))
+        .clamp(0f32, 1f32)
+        .sqrt();
+    let sin_theta = (1f32 - cos_theta * cos_theta).max(0f32).sqrt();
+    let phi = 2f32 * std::f32::consts::PI * u[1];
+    [sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta]
+}
+
+impl PrincipledBxDF {
+    pub fn new(mat: &crate::material::PrincipledMaterial, min_roughness: f32) -> Self {
+        let roughness = mat.roughness.max(min_roughness);
+        // the specular lobe is always glossy to keep the lobe selection simple
+        let alpha = (roughness * roughness).max(1.0e-3);
+        PrincipledBxDF {
+            base_color: mat.base_color,
+            metallic: mat.metallic,
+            roughness,
+            specular: mat.specular,
+            specular_tint: mat.specular_tint,
+            sheen: mat.sheen,
+            sheen_tint: mat.sheen_tint,
+            clearcoat: mat.clearcoat,
+            clearcoat_gloss: mat.clearcoat_gloss,
+            transmission: mat.transmission,
+            eta: mat.eta,
+            distrib: crate::microfacet::TrowbridgeReitz::new(alpha, alpha),
+        }
+    }
+
+    /// hue and saturation of the base color
+    fn tint(&self) -> [f32; 3] {
+        let lum = luminance(&self.base_color);
+        if lum > 0f32 {
+            self.base_color.map(|v| v / lum)
+        } else {
+            [1f32; 3]
+        }
+    }
+
+    /// specular reflectance at the normal incidence
+    fn specular_f0(&self) -> [f32; 3] {
+        let dielectric = lerp3(&[1f32; 3], &self.tint(), self.specular_tint)
+            .map(|v| v * 0.08f32 * self.specular);
+        lerp3(&dielectric, &self.base_color, self.metallic)
+    }
+
+    fn clearcoat_alpha(&self) -> f32 {
+        0.1f32 * (1f32 - self.clearcoat_gloss) + 0.001f32 * self.clearcoat_gloss
+    }
+
+    /// weights of the diffuse (with the sheen), the specular and the transmission
+    fn weights(&self) -> (f32, f32, f32) {
+        let w_transmission = (1f32 - self.metallic) * self.transmission;
+        let w_diffuse = (1f32 - self.metallic) * (1f32 - self.transmission);
+        (w_diffuse, 1f32 - w_transmission, w_transmission)
+    }
+
+    /// probabilities to choose the diffuse, the specular, the clearcoat and the transmission lobes
+    fn lobe_probabilities(&self, sample_flags: BxDFReflTransFlags) -> [f32; 4] {
+        let (w_diffuse, w_specular, w_transmission) = self.weights();
+        let f0 = luminance(&self.specular_f0());
+        let mut p = [
+            w_diffuse,
+            w_specular * (0.25f32 + 0.75f32 * f0),
+            0.25f32 * self.clearcoat,
+            w_transmission,
+        ];
+        if !sample_flags.has_reflection() {
+            p[0] = 0f32;
+            p[1] = 0f32;
+            p[2] = 0f32;
+        }
+        let sum = p.iter().sum::<f32>();
+        if sum <= 0f32 {
+            return [0f32; 4];
+        }
+        p.map(|v| v / sum)
+    }
+
+    /// opaque lobes for the directions in the upper hemisphere
+    fn f_opaque(&self, wo: &[f32; 3], wi: &[f32; 3]) -> [f32; 3] {
+        use del_geo_core::vec3::Vec3;
+        if wo[2] <= 0f32 || wi[2] <= 0f32 {
+            return [0f32; 3];
+        }
+        let h = wo.add(wi);
+        if h.norm() < f32::EPSILON {
+            return [0f32; 3];
+        }
+        let h = h.normalize();
+        let cos_d = wi.dot(&h);
+        let fh = schlick_weight(cos_d);
+        let (w_diffuse, w_specular, _) = self.weights();
+        // diffuse with the retro-reflection
+        let fd90 = 0.5f32 + 2f32 * self.roughness * cos_d * cos_d;
+        let fd = (1f32 + (fd90 - 1f32) * schlick_weight(wi[2]))
+            * (1f32 + (fd90 - 1f32) * schlick_weight(wo[2]));
+        let sheen = lerp3(&[1f32; 3], &self.tint(), self.sheen_tint);
+        // specular
+        let f0 = self.specular_f0();
+        let ds = self.distrib.d(&h) * self.distrib.g(wo, wi) / (4f32 * wo[2] * wi[2]);
+        // clearcoat
+        let dr = gtr1_d(h[2], self.clearcoat_alpha());
+        let fr = 0.04f32 + 0.96f32 * fh;
+        let gr = smith_g_ggx_separable(wo[2], 0.25) * smith_g_ggx_separable(wi[2], 0.25);
+        let clearcoat = 0.25f32 * self.clearcoat * dr * fr * gr;
+        std::array::from_fn(|i| {
+            let diffuse =
+                self.base_color[i] * std::f32::consts::FRAC_1_PI * fd + self.sheen * sheen[i] * fh;
+            let fs = f0[i] + (1f32 - f0[i]) * fh;
+            w_diffuse * diffuse + w_specular * ds * fs + clearcoat
+        })
+    }
+
+    /// pdf of the opaque lobes for the directions in the upper hemisphere
+    fn pdf_opaque(&self, wo: &[f32; 3], wi: &[f32; 3], prob: &[f32; 4]) -> f32 {
+        use del_geo_core::vec3::Vec3;
+        if wo[2] <= 0f32 || wi[2] <= 0f32 {
+            return 0f32;
+        }
+        let h = wo.add(wi);
+        if h.norm() < f32::EPSILON {
+            return 0f32;
+        }
+        let h = h.normalize();
+        let pdf_diffuse = crate::sampling::pdf_hemisphere_cos(wi[2]);
+        let pdf_specular = self.distrib.pdf(wo, &h) / (4f32 * wo.dot(&h));
+        let pdf_clearcoat = gtr1_d(h[2], self.clearcoat_alpha()) * h[2] / (4f32 * wo.dot(&h));
+        prob[0] * pdf_diffuse + prob[1] * pdf_specular + prob[2] * pdf_clearcoat
+    }
+
+    /// generalized half vector of the dielectric interface
+    /// # Return
+    /// `Some((wm, etap))` where `wm` is in the upper hemisphere and `etap` is the relative index of refraction
+    /// (one for the reflection). `None` for the degenerated or back-facing configurations
+    fn dielectric_half_vector(&self, wo: &[f32; 3], wi: &[f32; 3]) -> Option<([f32; 3], f32)> {
+        use del_geo_core::vec3::Vec3;
+        let (cos_o, cos_i) = (wo[2], wi[2]);
+        if cos_o == 0f32 || cos_i == 0f32 {
+            return None;
+        }
+        let etap = if cos_o * cos_i > 0f32 {
+            1f32
+        } else if cos_o > 0f32 {
+            self.eta
+        } else {
+            1f32 / self.eta
+        };
+        let wm = wi.scale(etap).add(wo);
+        if wm.norm() < f32::EPSILON {
+            return None;
+        }
+        let wm = wm.normalize();
+        let wm = if wm[2] < 0f32 { wm.scale(-1f32) } else { wm };
+        if wm.dot(wi) * cos_i < 0f32 || wm.dot(wo) * cos_o < 0f32 {
+            return None;
+        }
+        Some((wm, etap))
+    }
+
+    fn f_dielectric(&self, wo: &[f32; 3], wi: &[f32; 3], mode: TransportMode) -> [f32; 3] {
+        use del_geo_core::vec3::Vec3;
+        let Some((wm, etap)) = self.dielectric_half_vector(wo, wi) else {
+            return [0f32; 3];
+        };
+        let r = crate::material::fresnel_dielectric(self.eta, wo.dot(&wm));
+        let dg = self.distrib.d(&wm) * self.distrib.g(wo, wi);
+        if wo[2] * wi[2] > 0f32 {
+            return [dg * r / (4f32 * wo[2] * wi[2]).abs(); 3];
+        }
+        let denom = wi.dot(&wm) + wo.dot(&wm) / etap;
+        let mut ft =
+            dg * (1f32 - r) * (wi.dot(&wm) * wo.dot(&wm) / (wi[2] * wo[2] * denom * denom)).abs();
+        if mode == TransportMode::Radiance {
+            ft /= etap * etap;
+        }
+        self.base_color.map(|v| v * ft)
+    }
+
+    fn pdf_dielectric(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        sample_flags: BxDFReflTransFlags,
+    ) -> f32 {
+        use del_geo_core::vec3::Vec3;
+        let Some((wm, etap)) = self.dielectric_half_vector(wo, wi) else {
+            return 0f32;
+        };
+        let r = crate::material::fresnel_dielectric(self.eta, wo.dot(&wm));
+        let Some((pr, pt)) = crate::bxdf::lobe_probability(r, 1f32 - r, sample_flags) else {
+            return 0f32;
+        };
+        if wo[2] * wi[2] > 0f32 {
+            return self.distrib.pdf(wo, &wm) / (4f32 * wo.dot(&wm).abs()) * pr;
+        }
+        let denom = wi.dot(&wm) + wo.dot(&wm) / etap;
+        self.distrib.pdf(wo, &wm) * wi.dot(&wm).abs() / (denom * denom) * pt
+    }
+
+    fn sample_dielectric(
+        &self,
+        wo: &[f32; 3],
+        uc: f32,
+        u: &[f32; 2],
+        sample_flags: BxDFReflTransFlags,
+    ) -> Option<[f32; 3]> {
+        use del_geo_core::vec3::Vec3;
+        let wm = self.distrib.sample_wm(wo, u);
+        let wo_dot_m = wo.dot(&wm);
+        let r = crate::material::fresnel_dielectric(self.eta, wo_dot_m);
+        let (pr, _pt) = crate::bxdf::lobe_probability(r, 1f32 - r, sample_flags)?;
+        if uc < pr {
+            let wi = wm.scale(2f32 * wo_dot_m).sub(wo);
+            return if wo[2] * wi[2] > 0f32 { Some(wi) } else { None };
+        }
+        let (n, etap) = if wo_dot_m > 0f32 {
+            (wm, self.eta)
+        } else {
+            (wm.scale(-1f32), 1f32 / self.eta)
+        };
+        let wi = crate::material::refract(wo, &n, etap)?;
+        if wo[2] * wi[2] >= 0f32 {
+            return None;
+        }
+        Some(wi)
+    }
+}
+
+impl BxDF for PrincipledBxDF {
+    fn flags(&self) -> BxDFFlags {
+        let (w_diffuse, _, w_transmission) = self.weights();
+        let mut flags = BxDFFlags::REFLECTION | BxDFFlags::GLOSSY;
+        if w_diffuse > 0f32 {
+            flags = flags | BxDFFlags::DIFFUSE;
+        }
+        if w_transmission > 0f32 {
+            flags = flags | BxDFFlags::TRANSMISSION;
+        }
+        flags
+    }
+
+    fn f(&self, wo: &[f32; 3], wi: &[f32; 3], mode: TransportMode) -> [f32; 3] {
+        let sign = if wo[2] < 0f32 { -1f32 } else { 1f32 };
+        let f_opaque = self.f_opaque(&wo.map(|v| v * sign), &wi.map(|v| v * sign));
+        let (_, _, w_transmission) = self.weights();
+        if w_transmission == 0f32 {
+            return f_opaque;
+        }
+        let f_dielectric = self.f_dielectric(wo, wi, mode);
+        std::array::from_fn(|i| f_opaque[i] + w_transmission * f_dielectric[i])
+    }
+
+    fn sample_f(
+        &self,
+        wo: &[f32; 3],
+        uc: f32,
+        u: &[f32; 2],
+        mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> Option<BSDFSample> {
+        use del_geo_core::vec3::Vec3;
+        if wo[2] == 0f32 {
+            return None;
+        }
+        let prob = self.lobe_probabilities(sample_flags);
+        let sign = wo[2].signum();
+        let wo_up = wo.map(|v| v * sign);
+        let (i_lobe, wi) = if uc < prob[0] {
+            let wi = crate::sampling::hemisphere_zup_cos_weighted(u);
+            (0, wi.map(|v| v * sign))
+        } else if uc < prob[0] + prob[1] {
+            let wm = self.distrib.sample_wm(&wo_up, u);
+            let wi = wm.scale(2f32 * wo_up.dot(&wm)).sub(&wo_up);
+            (1, wi.map(|v| v * sign))
+        } else if uc < prob[0] + prob[1] + prob[2] {
+            let h = gtr1_sample(self.clearcoat_alpha(), u);
+            if wo_up.dot(&h) <= 0f32 {
+                return None;
+            }
+            let wi = h.scale(2f32 * wo_up.dot(&h)).sub(&wo_up);
+            (2, wi.map(|v| v * sign))
+        } else {
+            if prob[3] == 0f32 {
+                return None;
+            }
+            // `uc` is reused to choose the reflection or the transmission
+            let p = prob[0] + prob[1] + prob[2];
+            let uc = ((uc - p) / prob[3]).clamp(0f32, 1f32 - f32::EPSILON);
+            (3, self.sample_dielectric(wo, uc, u, sample_flags)?)
+        };
+        // the samples of the opaque lobes below the horizon have zero density
+        if i_lobe != 3 && wi[2] * wo[2] <= 0f32 {
+            return None;
+        }
+        let pdf = self.pdf(wo, &wi, mode, sample_flags);
+        if pdf <= 0f32 {
+            return None;
+        }
+        let flag = if wo[2] * wi[2] < 0f32 {
+            BxDFFlags::TRANSMISSION | BxDFFlags::GLOSSY
+        } else if i_lobe == 0 {
+            BxDFFlags::REFLECTION | BxDFFlags::DIFFUSE
+        } else {
+            BxDFFlags::REFLECTION | BxDFFlags::GLOSSY
+        };
+        Some(BSDFSample {
+            f: self.f(wo, &wi, mode),
+            wi,
+            pdf,
+            flag,
+            pdf_is_proportional: false,
+        })
+    }
+
+    fn pdf(
+        &self,
+        wo: &[f32; 3],
+        wi: &[f32; 3],
+        _mode: TransportMode,
+        sample_flags: BxDFReflTransFlags,
+    ) -> f32 {
+        let prob = self.lobe_probabilities(sample_flags);
+        let sign = if wo[2] < 0f32 { -1f32 } else { 1f32 };
+        let pdf_opaque = self.pdf_opaque(&wo.map(|v| v * sign), &wi.map(|v| v * sign), &prob);
+        if prob[3] == 0f32 {
+            return pdf_opaque;
+        }
+        pdf_opaque + prob[3] * self.pdf_dielectric(wo, wi, sample_flags)
+    }
+}
+
+#[test]
+fn test_principled_bsdf() {
+    use rand::Rng;
+    use rand::SeedableRng;
+    let mats = [
+        crate::material::PrincipledMaterial::from_metallic_roughness([0.8, 0.5, 0.3], 0.0, 0.5),
+        crate::material::PrincipledMaterial::from_metallic_roughness([0.9, 0.8, 0.5], 1.0, 0.5),
+        crate::material::PrincipledMaterial {
+            sheen: 1.0,
+            clearcoat: 1.0,
+            clearcoat_gloss: 0.5,
+            ..crate::material::PrincipledMaterial::from_metallic_roughness(
+                [0.2, 0.3, 0.8],
+                0.3,
+                0.6,
+            )
+        },
+        crate::material::PrincipledMaterial {
+            transmission: 0.7,
+            ..crate::material::PrincipledMaterial::from_metallic_roughness([1.0; 3], 0.0, 0.5)
+        },
+    ];
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    for mat in mats.iter() {
+        let bxdf = PrincipledBxDF::new(mat, 0.);
+        for wo in [[0.3, -0.2, 0.8], [0.1, 0.7, 0.2], [0.2, 0.3, -0.7]] {
+            let wo = del_geo_core::vec3::normalize(&wo);
+            let num_sample = 50000;
+            let (mut sum_is, mut sum_uniform) = ([0f32; 3], [0f32; 3]);
+            for _ in 0..num_sample {
+                if let Some(bs) = bxdf.sample_f(
+                    &wo,
+                    rng.random::<f32>(),
+                    &[rng.random::<f32>(), rng.random::<f32>()],
+                    TransportMode::Radiance,
+                    BxDFReflTransFlags::ALL,
+                ) {
+                    let pdf = bxdf.pdf(
+                        &wo,
+                        &bs.wi,
+                        TransportMode::Radiance,
+                        BxDFReflTransFlags::ALL,
+                    );
+                    assert!((pdf - bs.pdf).abs() < 1.0e-3 * pdf.max(1.));
+                    for (sum, f) in sum_is.iter_mut().zip(bs.f.iter()) {
+                        *sum += f * bs.wi[2].abs() / bs.pdf / num_sample as f32;
+                    }
+                }
+                // uniform sphere sampling for the reference
+                let z = 1f32 - 2f32 * rng.random::<f32>();
+                let r = (1f32 - z * z).max(0f32).sqrt();
+                let phi = 2f32 * std::f32::consts::PI * rng.random::<f32>();
+                let wi = [r * phi.cos(), r * phi.sin(), z];
+                let f = bxdf.f(&wo, &wi, TransportMode::Radiance);
+                for i in 0..3 {
+                    sum_uniform[i] +=
+                        f[i] * z.abs() * 4f32 * std::f32::consts::PI / num_sample as f32;
+                }
+            }
+            // the importance sampling agrees with the uniform sampling
+            for i in 0..3 {
+                assert!(
+                    (sum_is[i] - sum_uniform[i]).abs() < 0.05 * sum_uniform[i].max(0.2),
+                    "{:?} {:?}",
+                    sum_is,
+                    sum_uniform
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,606 @@
+//! Finite-element radiosity for scenes composed of diffuse triangle meshes.
+//! Each triangle is a patch with constant radiance. The form factors are estimated by
+//! casting cosine-weighted rays against the shape entities (visibility included)
+
+use crate::area_light::AreaLight;
+use crate::material::Material;
+use crate::shape::{ShapeEntity, ShapeType};
+
+/// triangle patches of the radiosity system in the world coordinate
+pub struct Patches {
+    /// patches of the `i_shape_entity`-th shape entity are `shape2idx[i_shape_entity]..shape2idx[i_shape_entity+1]`.
+    /// The patch index is `shape2idx[i_shape_entity] + i_tri`
+    pub shape2idx: Vec<usize>,
+    /// corner points of the triangle in the world coordinate
+    pub patch2xyz: Vec<[[f32; 3]; 3]>,
+    /// unit normal in the world coordinate. The radiosity is computed only for this side
+    pub patch2nrm: Vec<[f32; 3]>,
+    pub patch2area: Vec<f32>,
+    /// texture coordinates of the corner points. Zero if the mesh has no texture coordinates
+    pub patch2uv: Vec<[[f32; 2]; 3]>,
+}
+
+impl Patches {
+    pub fn from_shape_entities(shape_entities: &[ShapeEntity]) -> Self {
+        use del_geo_core::vec3::Vec3;
+        let mut shape2idx = vec![0usize; shape_entities.len() + 1];
+        let mut patch2xyz: Vec<[[f32; 3]; 3]> = vec![];
+        let mut patch2nrm: Vec<[f32; 3]> = vec![];
+        let mut patch2area: Vec<f32> = vec![];
+        let mut patch2uv: Vec<[[f32; 2]; 3]> = vec![];
+        for (i_shape_entity, se) in shape_entities.iter().enumerate() {
+            if let ShapeType::TriangleMesh {
+                tri2vtx,
+                vtx2xyz,
+                vtx2uv,
+                ..
+            } = &se.shape
+            {
+                let vtx2xyz_world = del_msh_cpu::vtx2xyz::transform_homogeneous(
+                    vtx2xyz,
+                    &se.transform_objlcl2world,
+                );
+                for node2vtx in tri2vtx.chunks(3) {
+                    let p0 = *arrayref::array_ref![vtx2xyz_world, node2vtx[0] * 3, 3];
+                    let p1 = *arrayref::array_ref![vtx2xyz_world, node2vtx[1] * 3, 3];
+                    let p2 = *arrayref::array_ref![vtx2xyz_world, node2vtx[2] * 3, 3];
+                    let n = p1.sub(&p0).cross(&p2.sub(&p0));
+                    let area = n.norm() * 0.5;
+                    patch2xyz.push([p0, p1, p2]);
+                    patch2nrm.push(n.normalize());
+                    patch2area.push(area);
+                    patch2uv.push(if vtx2uv.is_empty() {
+                        [[0f32; 2]; 3]
+                    } else {
+                        std::array::from_fn(|i| *arrayref::array_ref![vtx2uv, node2vtx[i] * 2, 2])
+                    });
+                }
+            }
+            shape2idx[i_shape_entity + 1] = patch2xyz.len();
+        }
+        Patches {
+            shape2idx,
+            patch2xyz,
+            patch2nrm,
+            patch2area,
+            patch2uv,
+        }
+    }
+
+    pub fn num_patch(&self) -> usize {
+        self.patch2area.len()
+    }
+
+    /// # Return
+    /// `Some(i_patch)` if the shape entity is a triangle mesh, otherwise `None`
+    pub fn patch_index(&self, i_shape_entity: usize, i_tri: usize) -> Option<usize> {
+        let i_patch = self.shape2idx[i_shape_entity] + i_tri;
+        if i_patch < self.shape2idx[i_shape_entity + 1] {
+            Some(i_patch)
+        } else {
+            None
+        }
+    }
+}
+
+/// diffuse reflectance of the material at the texture coordinate `uv` regarded as a Lambertian surface.
+/// The diffuse-like materials are approximated by the closed-form albedo at the normal incidence
+/// and the transmission of the diffuse transmission is ignored because the patch has only one side
+/// # Return
+/// `None` if the material is not diffuse-like (e.g., the conductor, the dielectric, and the mix material)
+pub fn diffuse_albedo(
+    mat: &Material,
+    textures: &[crate::textures::Texture],
+    uv: &[f32; 2],
+) -> Option<[f32; 3]> {
+    use crate::textures::eval_spectrum;
+    match mat {
+        Material::None => Some([0f32; 3]),
+        Material::Diff(diff) => Some(eval_spectrum(
+            textures,
+            diff.reflectance_texture,
+            &diff.reflectance,
+            uv,
+        )),
+        Material::DiffTrans(difftrans) => Some(eval_spectrum(
+            textures,
+            difftrans.reflectance_texture,
+            &difftrans.reflectance,
+            uv,
+        )),
+        Material::CoaDiff(coadiff) => {
+            // the smooth coat over the Lambertian base, whose inter-reflection is summed as the geometric series.
+            // The absorption in the coat is ignored
+            let r = eval_spectrum(
+                textures,
+                coadiff.reflectance_texture,
+                &coadiff.reflectance,
+                uv,
+            );
+            let f0 = crate::material::fresnel_dielectric(coadiff.eta, 1f32);
+            let fdr_int = fresnel_average_dielectric(1f32 / coadiff.eta);
+            Some(r.map(|r| f0 + (1f32 - f0) * r * (1f32 - fdr_int) / (1f32 - r * fdr_int)))
+        }
+        Material::Principled(principled) if !crate::material::is_transmissive(mat) => {
+            // the specular reflectance at the normal incidence and the diffuse lobe under it
+            let f0_dielectric = 0.08f32 * principled.specular;
+            Some(principled.base_color.map(|c| {
+                let f0 = f0_dielectric + (c - f0_dielectric) * principled.metallic;
+                f0 + (1f32 - principled.metallic) * (1f32 - f0_dielectric) * c
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// cosine-weighted average of the Fresnel reflectance of the dielectric over the hemisphere
+/// * `eta` - relative index of refraction of the other side (e.g., `1/eta` inside the coat)
+fn fresnel_average_dielectric(eta: f32) -> f32 {
+    let num_div = 32;
+    (0..num_div)
+        .map(|i| {
+            let mu = (i as f32 + 0.5f32) / num_div as f32;
+            2f32 * crate::material::fresnel_dielectric(eta, mu) * mu / num_div as f32
+        })
+        .sum()
+}
+
+/// true if the reflectance of the material varies with the texture coordinate
+fn is_textured(mat: &Material) -> bool {
+    match mat {
+        Material::Diff(diff) => diff.reflectance_texture != usize::MAX,
+        Material::DiffTrans(difftrans) => difftrans.reflectance_texture != usize::MAX,
+        Material::CoaDiff(coadiff) => {
+            coadiff.reflectance_texture != usize::MAX
+                || coadiff.uroughness_texture != usize::MAX
+                || coadiff.vroughness_texture != usize::MAX
+        }
+        _ => false,
+    }
+}
+
+/// # Return
+/// `(patch2emission, patch2reflectance)`
+/// * `patch2emission` - emitted radiance of the patch
+/// * `patch2reflectance` - diffuse reflectance of the patch given by [`diffuse_albedo`].
+///   The textured reflectance is averaged over the patch. Zero for the shape entity without the material.
+///   Zero for the material that is not diffuse-like, so that such patches are black occluders
+pub fn emission_and_reflectance(
+    patches: &Patches,
+    shape_entities: &[ShapeEntity],
+    materials: &[Material],
+    textures: &[crate::textures::Texture],
+    area_lights: &[AreaLight],
+) -> (Vec<[f32; 3]>, Vec<[f32; 3]>) {
+    let num_patch = patches.num_patch();
+    let mut patch2emission = vec![[0f32; 3]; num_patch];
+    let mut patch2reflectance = vec![[0f32; 3]; num_patch];
+    // points inside the patch where the textured reflectance is averaged
+    const BARYCENTRIC_COORDS: [[f32; 3]; 4] = [
+        [1. / 3., 1. / 3., 1. / 3.],
+        [2. / 3., 1. / 6., 1. / 6.],
+        [1. / 6., 2. / 3., 1. / 6.],
+        [1. / 6., 1. / 6., 2. / 3.],
+    ];
+    for (i_shape_entity, se) in shape_entities.iter().enumerate() {
+        if patches.shape2idx[i_shape_entity] == patches.shape2idx[i_shape_entity + 1] {
+            continue; // not a triangle mesh
+        }
+        // the textured emission is averaged over the shape
+        let emission = se
+            .area_light_index
+            .map(|i_area_light| area_lights[i_area_light].average_emission())
+            .unwrap_or([0f32; 3]);
+        // the patch that does not reflect diffusely only blocks the light
+        let albedo = |i_material: usize, uv: &[f32; 2]| {
+            diffuse_albedo(&materials[i_material], textures, uv).unwrap_or([0f32; 3])
+        };
+        let reflectance = match se.material_index {
+            Some(i_material) if !is_textured(&materials[i_material]) => {
+                Some(albedo(i_material, &[0f32; 2]))
+            }
+            Some(_) => None,
+            None => Some([0f32; 3]),
+        };
+        for i_patch in patches.shape2idx[i_shape_entity]..patches.shape2idx[i_shape_entity + 1] {
+            patch2emission[i_patch] = emission;
+            patch2reflectance[i_patch] = reflectance.unwrap_or_else(|| {
+                let [q0, q1, q2] = &patches.patch2uv[i_patch];
+                let i_material = se.material_index.unwrap();
+                let sum = BARYCENTRIC_COORDS.iter().fold([0f32; 3], |sum, bc| {
+                    let uv = std::array::from_fn(|i| bc[0] * q0[i] + bc[1] * q1[i] + bc[2] * q2[i]);
+                    del_geo_core::vec3::add(&sum, &albedo(i_material, &uv))
+                });
+                del_geo_core::vec3::scale(&sum, 1f32 / BARYCENTRIC_COORDS.len() as f32)
+            });
+        }
+    }
+    (patch2emission, patch2reflectance)
+}
+
+/// Monte-Carlo estimation of the form factors.
+/// `num_sample` cosine-weighted rays are cast from random points on each patch and
+/// the form factor `F_ij` is the ratio of the rays from the patch `i` hitting the front side of the patch `j`.
+/// # Return
+/// sparse rows of the form factor matrix `patch2ff[i] = [(j, F_ij), ...]`
+pub fn form_factors(
+    patches: &Patches,
+    shape_entities: &[ShapeEntity],
+    num_sample: usize,
+) -> Vec<Vec<(usize, f32)>> {
+    let row = |i_patch: usize| -> Vec<(usize, f32)> {
+        use rand::Rng;
+        use rand::SeedableRng;
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(i_patch as u64);
+        let [p0, p1, p2] = &patches.patch2xyz[i_patch];
+        let nrm = &patches.patch2nrm[i_patch];
+        let mut ff = std::collections::BTreeMap::<usize, f32>::new();
+        let weight = 1f32 / num_sample as f32;
+        for _i_sample in 0..num_sample {
+            let (r0, r1) = {
+                let (r0, r1) = (rng.random::<f32>(), rng.random::<f32>());
+                if r0 + r1 > 1f32 {
+                    (1f32 - r0, 1f32 - r1)
+                } else {
+                    (r0, r1)
+                }
+            };
+            let pos = del_geo_core::tri3::position_from_barycentric_coords(
+                p0,
+                p1,
+                p2,
+                &[1f32 - r0 - r1, r0, r1],
+            );
+            let ray_org = del_geo_core::vec3::axpy(1.0e-3, nrm, &pos);
+            let ray_dir = crate::sampling::hemisphere_cos_weighted(
+                nrm,
+                &[rng.random::<f32>(), rng.random::<f32>()],
+            );
+            let Some((_t, i_shape_entity, i_tri)) =
+                crate::shape::intersection_ray_against_shape_entities(
+                    &ray_org,
+                    &ray_dir,
+                    shape_entities,
+                )
+            else {
+                continue;
+            };
+            let Some(j_patch) = patches.patch_index(i_shape_entity, i_tri) else {
+                continue;
+            };
+            if del_geo_core::vec3::dot(&ray_dir, &patches.patch2nrm[j_patch]) >= 0f32 {
+                continue; // hit the back side
+            }
+            *ff.entry(j_patch).or_insert(0f32) += weight;
+        }
+        ff.into_iter().collect()
+    };
+    use rayon::prelude::*;
+    (0..patches.num_patch()).into_par_iter().map(row).collect()
+}
+
+/// solve `L_i = Le_i + rho_i * sum_j F_ij L_j` with the Gauss-Seidel iteration.
+/// # Return
+/// radiance of each patch
+pub fn solve_gauss_seidel(
+    patch2ff: &[Vec<(usize, f32)>],
+    patch2emission: &[[f32; 3]],
+    patch2reflectance: &[[f32; 3]],
+    max_iteration: usize,
+    tolerance: f32,
+) -> Vec<[f32; 3]> {
+    use del_geo_core::vec3::Vec3;
+    let num_patch = patch2ff.len();
+    assert_eq!(patch2emission.len(), num_patch);
+    assert_eq!(patch2reflectance.len(), num_patch);
+    let mut patch2radiance = patch2emission.to_vec();
+    for _iter in 0..max_iteration {
+        let mut max_diff = 0f32;
+        for (i_patch, ff_row) in patch2ff.iter().enumerate() {
+            let mut gather = [0f32; 3];
+            for &(j_patch, ff) in ff_row.iter() {
+                gather = del_geo_core::vec3::axpy(ff, &patch2radiance[j_patch], &gather);
+            }
+            let rad =
+                patch2emission[i_patch].add(&patch2reflectance[i_patch].element_wise_mult(&gather));
+            let diff = rad.sub(&patch2radiance[i_patch]);
+            max_diff = diff.iter().fold(max_diff, |a, b| a.max(b.abs()));
+            patch2radiance[i_patch] = rad;
+        }
+        if max_diff < tolerance {
+            break;
+        }
+    }
+    patch2radiance
+}
+
+/// area-weighted average of the patch radiance around each vertex of the `i_shape_entity`-th shape entity
+/// # Return
+/// `vtx2radiance` with the size of `num_vtx * 3`
+pub fn vtx2radiance(
+    patches: &Patches,
+    shape_entities: &[ShapeEntity],
+    i_shape_entity: usize,
+    patch2radiance: &[[f32; 3]],
+) -> Vec<f32> {
+    let ShapeType::TriangleMesh {
+        tri2vtx, vtx2xyz, ..
+    } = &shape_entities[i_shape_entity].shape
+    else {
+        return vec![];
+    };
+    let num_vtx = vtx2xyz.len() / 3;
+    let mut vtx2radiance = vec![0f32; num_vtx * 3];
+    let mut vtx2area = vec![0f32; num_vtx];
+    for (i_tri, node2vtx) in tri2vtx.chunks(3).enumerate() {
+        let i_patch = patches.patch_index(i_shape_entity, i_tri).unwrap();
+        let area = patches.patch2area[i_patch];
+        for &i_vtx in node2vtx {
+            let rad = arrayref::array_mut_ref![vtx2radiance, i_vtx * 3, 3];
+            *rad = del_geo_core::vec3::axpy(area, &patch2radiance[i_patch], rad);
+            vtx2area[i_vtx] += area;
+        }
+    }
+    for (rad, &area) in vtx2radiance.chunks_mut(3).zip(vtx2area.iter()) {
+        if area == 0f32 {
+            continue;
+        }
+        rad.iter_mut().for_each(|v| *v /= area);
+    }
+    vtx2radiance
+}
+
+/// 1-to-4 subdivision of a triangle mesh by inserting the middle points of the edges
+/// # Return
+/// `(tri2vtx, edge2vtx)`
+/// * `edge2vtx` - `edge2vtx[i_edge]` is the end points of the new vertex `num_vtx + i_edge`
+pub fn subdivide_trimesh(tri2vtx: &[usize], num_vtx: usize) -> (Vec<usize>, Vec<[usize; 2]>) {
+    let mut edge2vtx: Vec<[usize; 2]> = vec![];
+    let mut map = std::collections::HashMap::<[usize; 2], usize>::new();
+    let mut vtx_middle = |i0: usize, i1: usize| -> usize {
+        let key = if i0 < i1 { [i0, i1] } else { [i1, i0] };
+        *map.entry(key).or_insert_with(|| {
+            edge2vtx.push(key);
+            num_vtx + edge2vtx.len() - 1
+        })
+    };
+    let mut tri2vtx_new = Vec::<usize>::with_capacity(tri2vtx.len() * 4);
+    for node2vtx in tri2vtx.chunks(3) {
+        let (i0, i1, i2) = (node2vtx[0], node2vtx[1], node2vtx[2]);
+        let i01 = vtx_middle(i0, i1);
+        let i12 = vtx_middle(i1, i2);
+        let i20 = vtx_middle(i2, i0);
+        tri2vtx_new.extend_from_slice(&[i0, i01, i20, i1, i12, i01, i2, i20, i12, i01, i12, i20]);
+    }
+    (tri2vtx_new, edge2vtx)
+}
+
+/// append the values at the middle points of the edges computed by [`subdivide_trimesh`]
+pub fn append_middle_points(vtx2val: &mut Vec<f32>, num_dim: usize, edge2vtx: &[[usize; 2]]) {
+    for &[i0, i1] in edge2vtx {
+        for i_dim in 0..num_dim {
+            let v = 0.5 * (vtx2val[i0 * num_dim + i_dim] + vtx2val[i1 * num_dim + i_dim]);
+            vtx2val.push(v);
+        }
+    }
+}
+
+/// refine the triangle mesh of the shape entity `num_level` times with the 1-to-4 subdivision.
+/// The radiosity is constant inside a patch, so the patches need to be small enough
+pub fn refine_shape_entity(se: &mut ShapeEntity, num_level: usize) {
+    let ShapeType::TriangleMesh {
+        tri2vtx,
+        vtx2xyz,
+        vtx2nrm,
+        vtx2uv,
+        tri2cumsumarea,
+    } = &mut se.shape
+    else {
+        return;
+    };
+    for _i_level in 0..num_level {
+        let (tri2vtx_new, edge2vtx) = subdivide_trimesh(tri2vtx, vtx2xyz.len() / 3);
+        append_middle_points(vtx2xyz, 3, &edge2vtx);
+        if !vtx2nrm.is_empty() {
+            append_middle_points(vtx2nrm, 3, &edge2vtx);
+        }
+        if !vtx2uv.is_empty() {
+            append_middle_points(vtx2uv, 2, &edge2vtx);
+        }
+        *tri2vtx = tri2vtx_new;
+    }
+    if tri2cumsumarea.is_some() {
+        *tri2cumsumarea = Some(del_msh_cpu::trimesh::tri2cumsumarea(tri2vtx, vtx2xyz, 3));
+    }
+}
+
+#[cfg(test)]
+fn trimesh_shape_entity(tri2vtx: Vec<usize>, vtx2xyz: Vec<f32>) -> ShapeEntity {
+    ShapeEntity::new_untransformed(ShapeType::TriangleMesh {
+        tri2vtx,
+        vtx2xyz,
+        vtx2nrm: vec![],
+        vtx2uv: vec![],
+        tri2cumsumarea: None,
+    })
+}
+
+#[test]
+fn test_closed_enclosure() {
+    use del_geo_core::vec3::Vec3;
+    // the inside of the sphere. The triangles are flipped so that the normals face the center
+    let (tri2vtx, vtx2xyz) = del_msh_cpu::trimesh3_primitive::sphere_yup::<usize, f32>(1., 8, 8);
+    let tri2vtx: Vec<usize> = tri2vtx
+        .chunks(3)
+        .flat_map(|node2vtx| [node2vtx[0], node2vtx[2], node2vtx[1]])
+        .collect();
+    let shape_entities = vec![trimesh_shape_entity(tri2vtx, vtx2xyz)];
+    let patches = Patches::from_shape_entities(&shape_entities);
+    for (xyz, nrm) in patches.patch2xyz.iter().zip(patches.patch2nrm.iter()) {
+        assert!(xyz[0].add(&xyz[1]).add(&xyz[2]).dot(nrm) < 0f32);
+    }
+    let patch2ff = form_factors(&patches, &shape_entities, 1000);
+    // every ray leaving a patch hits the enclosure
+    for ff_row in patch2ff.iter() {
+        let sum = ff_row.iter().map(|v| v.1).sum::<f32>();
+        assert!((sum - 1f32).abs() < 1.0e-3, "{}", sum);
+    }
+    // the constant emission `E` and reflectance `rho` converge to `E / (1 - rho)`
+    let num_patch = patches.num_patch();
+    let emission = [1f32, 0.5, 0.2];
+    let reflectance = [0.5f32, 0.8, 0.2];
+    let patch2radiance = solve_gauss_seidel(
+        &patch2ff,
+        &vec![emission; num_patch],
+        &vec![reflectance; num_patch],
+        1000,
+        1.0e-6,
+    );
+    for rad in patch2radiance.iter() {
+        for i in 0..3 {
+            let expected = emission[i] / (1f32 - reflectance[i]);
+            assert!((rad[i] - expected).abs() < 1.0e-3 * expected, "{:?}", rad);
+        }
+    }
+}
+
+#[test]
+fn test_form_factor_reciprocity() {
+    // two unit squares facing each other with the distance one
+    let square = |z: f32, is_up: bool| {
+        let vtx2xyz = vec![0., 0., z, 1., 0., z, 1., 1., z, 0., 1., z];
+        let tri2vtx = if is_up {
+            vec![0, 1, 2, 0, 2, 3]
+        } else {
+            vec![0, 2, 1, 0, 3, 2]
+        };
+        trimesh_shape_entity(tri2vtx, vtx2xyz)
+    };
+    let shape_entities = vec![square(0., true), square(1., false)];
+    let patches = Patches::from_shape_entities(&shape_entities);
+    let patch2ff = form_factors(&patches, &shape_entities, 100000);
+    // `A_i F_ij = A_j F_ji` for each pair of the patches
+    for i_patch in 0..2 {
+        for j_patch in 2..4 {
+            let ff = |i: usize, j: usize| {
+                patch2ff[i]
+                    .iter()
+                    .find(|v| v.0 == j)
+                    .map(|v| v.1)
+                    .unwrap_or(0f32)
+            };
+            let a_ij = patches.patch2area[i_patch] * ff(i_patch, j_patch);
+            let a_ji = patches.patch2area[j_patch] * ff(j_patch, i_patch);
+            assert!(a_ij > 0f32);
+            assert!((a_ij - a_ji).abs() < 0.05 * a_ij, "{} {}", a_ij, a_ji);
+        }
+    }
+    // analytic form factor between the parallel unit squares at the unit distance
+    let ff_square = (0..2)
+        .flat_map(|i| patch2ff[i].iter().map(move |v| 0.5 * v.1))
+        .sum::<f32>();
+    assert!((ff_square - 0.19982).abs() < 5.0e-3, "{}", ff_square);
+}
+
+#[test]
+fn test_diffuse_albedo() {
+    use crate::material::{ConductorMaterial, DiffuseMaterial};
+    let diff = Material::Diff(DiffuseMaterial {
+        reflectance: [0.5, 0.2, 0.1],
+        reflectance_texture: usize::MAX,
+    });
+    assert_eq!(diffuse_albedo(&diff, &[], &[0.; 2]), Some([0.5, 0.2, 0.1]));
+    // the coat reflects a part of the light before it reaches the diffuse base
+    let coadiff = Material::CoaDiff(crate::material::CoatedDiffuse {
+        uroughness: 0.,
+        vroughness: 0.,
+        reflectance: [0.5, 0.2, 0.1],
+        remaproughness: false,
+        thickness: 0.01,
+        eta: 1.5,
+        albedo: [0.; 3],
+        g: 0.,
+        maxdepth: 10,
+        nsamples: 1,
+        reflectance_texture: usize::MAX,
+        uroughness_texture: usize::MAX,
+        vroughness_texture: usize::MAX,
+    });
+    let albedo = diffuse_albedo(&coadiff, &[], &[0.; 2]).unwrap();
+    for (i, &r) in [0.5f32, 0.2, 0.1].iter().enumerate() {
+        assert!(albedo[i] > 0.04 && albedo[i] < r + 0.04, "{:?}", albedo);
+    }
+    let cond = Material::Cond(ConductorMaterial {
+        uroughness: 0.1,
+        vroughness: 0.1,
+        remaproughness: false,
+        reflectance: [0.; 3],
+        k: [3.; 3],
+        eta: [0.2; 3],
+        reflectance_texture: usize::MAX,
+        uroughness_texture: usize::MAX,
+        vroughness_texture: usize::MAX,
+        k_texture: usize::MAX,
+        eta_texture: usize::MAX,
+        multiple_scattering: false,
+    });
+    assert!(diffuse_albedo(&cond, &[], &[0.; 2]).is_none());
+    // the conductor patch is a black occluder in the radiosity system
+    let vtx2xyz = vec![0., 0., 0., 1., 0., 0., 1., 1., 0.];
+    let mut se = trimesh_shape_entity(vec![0, 1, 2], vtx2xyz);
+    se.material_index = Some(0);
+    let shape_entities = vec![se];
+    let patches = Patches::from_shape_entities(&shape_entities);
+    let (_, patch2reflectance) =
+        emission_and_reflectance(&patches, &shape_entities, &[cond], &[], &[]);
+    assert_eq!(patch2reflectance, vec![[0f32; 3]]);
+}
+
+#[test]
+fn test_textured_reflectance() {
+    use crate::textures::{CheckerBoardTexture, Texture};
+    let (tex1, tex2) = ([0.8f32, 0.2, 0.1], [0.1f32, 0.3, 0.5]);
+    let textures = [Texture::Checkerboard(CheckerBoardTexture {
+        uscale: 2.,
+        vscale: 2.,
+        tex1,
+        tex2,
+    })];
+    let materials = [Material::Diff(crate::material::DiffuseMaterial {
+        reflectance: [1.; 3],
+        reflectance_texture: 0,
+    })];
+    // unit square whose texture coordinates are the same as the positions
+    let square = || {
+        let vtx2xyz = vec![0., 0., 0., 1., 0., 0., 1., 1., 0., 0., 1., 0.];
+        let mut se = trimesh_shape_entity(vec![0, 1, 2, 0, 2, 3], vtx2xyz);
+        if let ShapeType::TriangleMesh { vtx2uv, .. } = &mut se.shape {
+            *vtx2uv = vec![0., 0., 1., 0., 1., 1., 0., 1.];
+        }
+        se.material_index = Some(0);
+        se
+    };
+    // the triangle over the four checkers gets the average
+    let shape_entities = vec![square()];
+    let patches = Patches::from_shape_entities(&shape_entities);
+    let (_, patch2reflectance) =
+        emission_and_reflectance(&patches, &shape_entities, &materials, &textures, &[]);
+    for reflectance in patch2reflectance.iter() {
+        for i in 0..3 {
+            assert!((reflectance[i] - 0.5 * (tex1[i] + tex2[i])).abs() < 1.0e-5);
+        }
+    }
+    // each of the refined triangles is in one checker
+    let mut se = square();
+    refine_shape_entity(&mut se, 2);
+    let shape_entities = vec![se];
+    let patches = Patches::from_shape_entities(&shape_entities);
+    let (_, patch2reflectance) =
+        emission_and_reflectance(&patches, &shape_entities, &materials, &textures, &[]);
+    for (reflectance, uv) in patch2reflectance.iter().zip(patches.patch2uv.iter()) {
+        let cog = [0, 1].map(|i| (uv[0][i] + uv[1][i] + uv[2][i]) / 3.);
+        let expected = crate::textures::sample_checkerboard(&cog, 2., 2., &tex1, &tex2);
+        assert_eq!(*reflectance, expected);
+    }
+    assert!(patch2reflectance.contains(&tex1) && patch2reflectance.contains(&tex2));
+}
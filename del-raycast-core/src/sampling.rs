@@ -78,3 +78,129 @@ where
     let inv = Real::from(rev_digits).unwrap() * inv_base_m;
     Real::min(inv, Real::from(ONE_MINUS_EPSILON).unwrap())
 }
+
+/// piecewise-constant 1D distribution on `[0, 1)`
+#[derive(Debug, Clone)]
+pub struct PiecewiseConstant1D {
+    pub func: Vec<f32>,
+    /// cumulative distribution with `func.len() + 1` entries
+    pub cdf: Vec<f32>,
+    /// integral of `func` over `[0, 1)`
+    pub integral: f32,
+}
+
+impl PiecewiseConstant1D {
+    /// the negative values are treated as zero. Uniform if `func` is zero everywhere
+    pub fn new(func: &[f32]) -> Self {
+        let n = func.len();
+        assert!(n > 0);
+        let func: Vec<f32> = func.iter().map(|v| v.max(0f32)).collect();
+        let mut cdf = vec![0f32; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i] / n as f32;
+        }
+        let integral = cdf[n];
+        if integral == 0f32 {
+            cdf.iter_mut()
+                .enumerate()
+                .for_each(|(i, v)| *v = i as f32 / n as f32);
+        } else {
+            cdf.iter_mut().for_each(|v| *v /= integral);
+        }
+        PiecewiseConstant1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    /// # Return
+    /// `(x, pdf, i_bin)` where `x` is in `[0, 1)`
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.func.len();
+        // the last bin whose cdf is less than or equal to `u`
+        let i_bin = self.cdf.partition_point(|&c| c <= u).clamp(1, n) - 1;
+        let du = u - self.cdf[i_bin];
+        let width = self.cdf[i_bin + 1] - self.cdf[i_bin];
+        let du = if width > 0f32 { du / width } else { 0f32 };
+        let x = ((i_bin as f32 + du) / n as f32).min(1f32 - f32::EPSILON);
+        let pdf = if self.integral == 0f32 {
+            1f32
+        } else {
+            self.func[i_bin] / self.integral
+        };
+        (x, pdf, i_bin)
+    }
+
+    pub fn pdf(&self, x: f32) -> f32 {
+        let n = self.func.len();
+        let i_bin = ((x * n as f32) as usize).min(n - 1);
+        if self.integral == 0f32 {
+            return 1f32;
+        }
+        self.func[i_bin] / self.integral
+    }
+}
+
+/// piecewise-constant 2D distribution on `[0, 1)^2`.
+/// The marginal distribution of `v` and the conditional distributions of `u`
+#[derive(Debug, Clone)]
+pub struct PiecewiseConstant2D {
+    pub conditional: Vec<PiecewiseConstant1D>,
+    pub marginal: PiecewiseConstant1D,
+}
+
+impl PiecewiseConstant2D {
+    /// * `func` - the values of the `nu x nv` grid where `u` is the fastest index
+    pub fn new(func: &[f32], nu: usize, nv: usize) -> Self {
+        assert_eq!(func.len(), nu * nv);
+        let conditional: Vec<PiecewiseConstant1D> =
+            func.chunks(nu).map(PiecewiseConstant1D::new).collect();
+        let marginal: Vec<f32> = conditional.iter().map(|c| c.integral).collect();
+        PiecewiseConstant2D {
+            conditional,
+            marginal: PiecewiseConstant1D::new(&marginal),
+        }
+    }
+
+    /// # Return
+    /// `(uv, pdf)`
+    pub fn sample(&self, u: &[f32; 2]) -> ([f32; 2], f32) {
+        let (v, pdf_v, i_v) = self.marginal.sample(u[1]);
+        let (u, pdf_u, _) = self.conditional[i_v].sample(u[0]);
+        ([u, v], pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, uv: &[f32; 2]) -> f32 {
+        let nv = self.conditional.len();
+        let i_v = ((uv[1] * nv as f32) as usize).min(nv - 1);
+        self.marginal.pdf(uv[1]) * self.conditional[i_v].pdf(uv[0])
+    }
+}
+
+#[test]
+fn test_piecewise_constant_2d() {
+    use rand::Rng;
+    use rand::SeedableRng;
+    let (nu, nv) = (5, 4);
+    let func: Vec<f32> = (0..nu * nv).map(|i| (i % 7) as f32).collect();
+    let distrib = PiecewiseConstant2D::new(&func, nu, nv);
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    for _ in 0..1000 {
+        let (uv, pdf) = distrib.sample(&[rng.random::<f32>(), rng.random::<f32>()]);
+        assert!((0. ..1.).contains(&uv[0]) && (0. ..1.).contains(&uv[1]));
+        assert!(pdf > 0.);
+        assert!((distrib.pdf(&uv) - pdf).abs() < 1.0e-4 * pdf);
+    }
+    // the pdf integrates to one
+    let sum: f32 = (0..nu * nv)
+        .map(|i| {
+            let uv = [
+                ((i % nu) as f32 + 0.5) / nu as f32,
+                ((i / nu) as f32 + 0.5) / nv as f32,
+            ];
+            distrib.pdf(&uv) / (nu * nv) as f32
+        })
+        .sum();
+    assert!((sum - 1.).abs() < 1.0e-4, "{}", sum);
+}
@@ -0,0 +1,230 @@
+//! Perturbation of the shading normal by the bump map or the tangent-space normal map.
+//! The tangent frame is spanned by the partial derivatives of the position with respect to
+//! the texture coordinate (see [`crate::shape::dpdu_dpdv_at`]). The conventions follow pbrt-v4
+
+/// step of the texture coordinate for the finite difference of the displacement.
+/// The same as pbrt-v4 without the ray differentials
+const DELTA_UV: f32 = 0.0005;
+
+/// image of the tangent-space normal where the RGB in `[0, 1]` encodes the vector in `[-1, 1]`
+pub struct NormalMap {
+    pub img_shape: (usize, usize),
+    /// `img_shape.0 * img_shape.1 * 3` values in `[0, 1]`. The first row is at the top (`v = 1`)
+    pub data: Vec<f32>,
+}
+
+impl NormalMap {
+    /// the pixel values are read without the sRGB conversion
+    pub fn read_from<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<Self> {
+        let img = image::open(path)?.to_rgb32f();
+        let img_shape = (img.width() as usize, img.height() as usize);
+        Ok(NormalMap {
+            img_shape,
+            data: img.into_raw(),
+        })
+    }
+
+    /// unit normal in the tangent space at the texture coordinate, bilinearly interpolated.
+    /// The image repeats outside `[0, 1]`
+    pub fn lookup(&self, uv: &[f32; 2]) -> [f32; 3] {
+        let (w, h) = self.img_shape;
+        let x = uv[0].rem_euclid(1f32) * w as f32 - 0.5;
+        let y = (1f32 - uv[1]).rem_euclid(1f32) * h as f32 - 0.5;
+        let (ix, iy) = (x.floor(), y.floor());
+        let (rx, ry) = (x - ix, y - iy);
+        let texel = |ix: i64, iy: i64| {
+            let i_pix = iy.rem_euclid(h as i64) as usize * w + ix.rem_euclid(w as i64) as usize;
+            arrayref::array_ref![self.data, i_pix * 3, 3]
+        };
+        let (ix, iy) = (ix as i64, iy as i64);
+        let c: [f32; 3] = std::array::from_fn(|i| {
+            (1f32 - rx) * (1f32 - ry) * texel(ix, iy)[i]
+                + rx * (1f32 - ry) * texel(ix + 1, iy)[i]
+                + (1f32 - rx) * ry * texel(ix, iy + 1)[i]
+                + rx * ry * texel(ix + 1, iy + 1)[i]
+        });
+        del_geo_core::vec3::normalize(&c.map(|v| 2f32 * v - 1f32))
+    }
+}
+
+/// perturbation of the shading normal of the material
+pub struct NormalPerturbation {
+    /// float texture of the displacement along the normal
+    pub displacement_texture: usize, // valid if != usize:MAX
+    pub normal_map: Option<std::sync::Arc<NormalMap>>,
+}
+
+/// normal of the surface displaced by the texture. The variation of the normal itself is neglected
+/// * `nrm` - unit normal of the surface
+/// * `dpdu`, `dpdv` - partial derivatives of the position with respect to the texture coordinate
+pub fn bump(
+    textures: &[crate::textures::Texture],
+    displacement_texture: usize,
+    uv: &[f32; 2],
+    nrm: &[f32; 3],
+    dpdu: &[f32; 3],
+    dpdv: &[f32; 3],
+) -> [f32; 3] {
+    use del_geo_core::vec3::Vec3;
+    let displacement =
+        |uv: &[f32; 2]| crate::textures::eval_float(textures, displacement_texture, 0f32, uv);
+    let d = displacement(uv);
+    let d_u = displacement(&[uv[0] + DELTA_UV, uv[1]]);
+    let d_v = displacement(&[uv[0], uv[1] + DELTA_UV]);
+    let dpdu = dpdu.add(&nrm.scale((d_u - d) / DELTA_UV));
+    let dpdv = dpdv.add(&nrm.scale((d_v - d) / DELTA_UV));
+    let n = dpdu.cross(&dpdv);
+    if n.dot(&n) == 0f32 {
+        return *nrm;
+    }
+    // the side of the original normal
+    let n = n.normalize();
+    if n.dot(nrm) < 0f32 {
+        n.scale(-1f32)
+    } else {
+        n
+    }
+}
+
+/// normal given by the normal map in the frame whose x-axis is `dpdu` and z-axis is `nrm`
+pub fn normal_map(map: &NormalMap, uv: &[f32; 2], nrm: &[f32; 3], dpdu: &[f32; 3]) -> [f32; 3] {
+    use del_geo_core::vec3::Vec3;
+    let t = dpdu.sub(&nrm.scale(nrm.dot(dpdu)));
+    if t.dot(&t) == 0f32 {
+        return *nrm;
+    }
+    let t = t.normalize();
+    let b = nrm.cross(&t);
+    let n = map.lookup(uv);
+    t.scale(n[0])
+        .add(&b.scale(n[1]))
+        .add(&nrm.scale(n[2]))
+        .normalize()
+}
+
+/// shading normal perturbed by the bump map or the normal map. The normal map is used if both are given
+/// * `nrm` - unit outward normal of the surface
+/// * `dpdu_dpdv` - see [`crate::shape::dpdu_dpdv_at`]. `nrm` is returned as it is if `None`
+pub fn shading_normal(
+    perturbation: &NormalPerturbation,
+    textures: &[crate::textures::Texture],
+    uv: &[f32; 2],
+    nrm: &[f32; 3],
+    dpdu_dpdv: Option<([f32; 3], [f32; 3])>,
+) -> [f32; 3] {
+    let Some((dpdu, dpdv)) = dpdu_dpdv else {
+        return *nrm;
+    };
+    if let Some(map) = &perturbation.normal_map {
+        return normal_map(map, uv, nrm, &dpdu);
+    }
+    if perturbation.displacement_texture != usize::MAX {
+        let i_texture = perturbation.displacement_texture;
+        return bump(textures, i_texture, uv, nrm, &dpdu, &dpdv);
+    }
+    *nrm
+}
+
+#[test]
+fn test_shading_normal() {
+    use del_geo_core::vec3::Vec3;
+    // the triangle in the xy-plane whose texture coordinates are rotated by 90 degrees
+    let se = crate::shape::ShapeEntity::new_untransformed(crate::shape::ShapeType::TriangleMesh {
+        tri2vtx: vec![0, 1, 2],
+        vtx2xyz: vec![0., 0., 0., 2., 0., 0., 0., 2., 0.],
+        vtx2nrm: vec![],
+        vtx2uv: vec![0., 0., 0., 1., -1., 0.],
+        tri2cumsumarea: None,
+    });
+    let pos = [0.5f32, 0.5, 0.];
+    let nrm = [0f32, 0., 1.];
+    let (dpdu, dpdv) = crate::shape::dpdu_dpdv_at(&se, &pos, 0).unwrap();
+    assert!(dpdu.sub(&[0., -2., 0.]).norm() < 1.0e-5, "{:?}", dpdu);
+    assert!(dpdv.sub(&[2., 0., 0.]).norm() < 1.0e-5, "{:?}", dpdv);
+    // the derivatives are consistent with the texture coordinate
+    let uv = crate::shape::uv_at(&se, &pos, 0);
+    let uv1 = crate::shape::uv_at(&se, &del_geo_core::vec3::axpy(0.01, &dpdu, &pos), 0);
+    assert!((uv1[0] - uv[0] - 0.01).abs() < 1.0e-5 && (uv1[1] - uv[1]).abs() < 1.0e-5);
+    // the sphere
+    let se_sphere = crate::shape::ShapeEntity {
+        shape: crate::shape::ShapeType::Sphere { radius: 2. },
+        ..se
+    };
+    let pos_sphere = del_geo_core::vec3::normalize(&[0.3f32, -0.5, 0.4]).scale(2.);
+    let (dpdu, dpdv) = crate::shape::dpdu_dpdv_at(&se_sphere, &pos_sphere, 0).unwrap();
+    let uv = crate::shape::uv_at(&se_sphere, &pos_sphere, 0);
+    for (dp, duv) in [(dpdu, [1e-3, 0.]), (dpdv, [0., 1e-3])] {
+        let p1 = del_geo_core::vec3::axpy(1.0e-3, &dp, &pos_sphere);
+        let uv1 = crate::shape::uv_at(&se_sphere, &p1, 0);
+        assert!(
+            (uv1[0] - uv[0] - duv[0]).abs() < 1.0e-4,
+            "{:?} {:?}",
+            uv,
+            uv1
+        );
+        assert!(
+            (uv1[1] - uv[1] - duv[1]).abs() < 1.0e-4,
+            "{:?} {:?}",
+            uv,
+            uv1
+        );
+    }
+    // outward as the normal of the sphere
+    assert!(dpdu.cross(&dpdv).dot(&pos_sphere) > 0.);
+    // ----------------
+    // the flat normal map does not change the normal
+    let (dpdu, dpdv) = ([2f32, 0., 0.], [0f32, 2., 0.]);
+    let flat = NormalMap {
+        img_shape: (2, 2),
+        data: [0.5f32, 0.5, 1.].repeat(4),
+    };
+    let n = normal_map(&flat, &[0.3, 0.6], &nrm, &dpdu);
+    assert!(n.sub(&nrm).norm() < 1.0e-5, "{:?}", n);
+    // the normal tilted to `+u` and `+v`
+    let tilted = NormalMap {
+        img_shape: (1, 1),
+        data: vec![0.75, 0.75, 0.75],
+    };
+    let n = normal_map(&tilted, &[0.3, 0.6], &nrm, &dpdu);
+    assert!(
+        n.sub(&[1f32, 1., 1.].normalize()).norm() < 1.0e-5,
+        "{:?}",
+        n
+    );
+    // ----------------
+    // the step of the height rising toward `+u` tilts the normal toward `-u`
+    let textures = [crate::textures::Texture::Checkerboard(
+        crate::textures::CheckerBoardTexture {
+            uscale: 2.,
+            vscale: 1.,
+            tex1: [0.; 3],
+            tex2: [1.0e-4; 3],
+        },
+    )];
+    let perturbation = NormalPerturbation {
+        displacement_texture: 0,
+        normal_map: None,
+    };
+    let n = shading_normal(
+        &perturbation,
+        &textures,
+        &[0.25, 0.5],
+        &nrm,
+        Some((dpdu, dpdv)),
+    );
+    assert!(n.sub(&nrm).norm() < 1.0e-5, "{:?}", n);
+    let n = shading_normal(
+        &perturbation,
+        &textures,
+        &[0.5 - 0.5 * DELTA_UV, 0.5],
+        &nrm,
+        Some((dpdu, dpdv)),
+    );
+    assert!((n.norm() - 1.).abs() < 1.0e-5);
+    assert!(n[0] < -0.01 && n[1].abs() < 1.0e-5 && n[2] > 0., "{:?}", n);
+    // no perturbation without the tangent frame
+    assert_eq!(
+        shading_normal(&perturbation, &textures, &[0.5, 0.5], &nrm, None),
+        nrm
+    );
+}
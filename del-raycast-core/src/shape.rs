@@ -6,6 +6,23 @@ pub struct ShapeEntity {
     pub area_light_index: Option<usize>,
 }
 
+#[cfg(test)]
+impl ShapeEntity {
+    /// shape entity without the transformation, the material, and the area light for the tests
+    pub(crate) fn new_untransformed(shape: ShapeType) -> Self {
+        let identity = [
+            1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.,
+        ];
+        ShapeEntity {
+            transform_objlcl2world: identity,
+            transform_world2objlcl: identity,
+            shape,
+            material_index: None,
+            area_light_index: None,
+        }
+    }
+}
+
 impl ShapeEntity {
     /// # Returns
     /// (pos: [f32;3], nrm: [f32;3], pdf: f32)
@@ -31,7 +48,7 @@ impl ShapeEntity {
     ) -> Option<([f32; 3], [f32; 3], f32)> {
         use del_geo_core::vec3;
         match self.shape {
-            ShapeType::TriangleMesh { .. } => {
+            ShapeType::TriangleMesh { .. } | ShapeType::Curves { .. } => {
                 let (pos_light, nrm_light, pdf_obj) = self.sample_uniform(rnd);
                 let uvec_hit2light = vec3::normalize(&vec3::sub(&pos_light, pos_observe));
                 let cos_theta_light = -vec3::dot(&nrm_light, &uvec_hit2light);
@@ -80,6 +97,7 @@ impl ShapeEntity {
                 let area = tri2cumsumarea.as_ref().unwrap().last().unwrap();
                 1.0 / area
             }
+            ShapeType::Curves { .. } => 1.0 / self.shape.cog_and_area().1,
             ShapeType::Sphere { radius } => {
                 use del_geo_core::vec3;
                 let pos_center = del_geo_core::mat4_col_major::transform_homogeneous(
@@ -108,11 +126,22 @@ pub enum ShapeType {
         tri2vtx: Vec<usize>,
         vtx2xyz: Vec<f32>,
         vtx2nrm: Vec<f32>,
+        /// texture coordinates of the vertices. Can be empty
+        vtx2uv: Vec<f32>,
         tri2cumsumarea: Option<Vec<f32>>,
     },
     Sphere {
         radius: f32,
     },
+    /// polylines with the circular cross section such as the hair and the fur.
+    /// Each segment is a cylinder without the caps
+    Curves {
+        edge2vtx: Vec<usize>,
+        vtx2xyz: Vec<f32>,
+        radius: f32,
+        /// cumulative sum of the segment lengths, required only when the curves are an area light
+        edge2cumsumlength: Option<Vec<f32>>,
+    },
 }
 
 impl ShapeType {
@@ -139,6 +168,38 @@ impl ShapeType {
                 let pos = del_geo_core::vec3::scale(&nrm, *radius);
                 (pos, nrm, 1. / area)
             }
+            ShapeType::Curves {
+                edge2vtx,
+                vtx2xyz,
+                radius,
+                edge2cumsumlength,
+            } => {
+                use del_geo_core::vec3::Vec3;
+                let edge2cumsumlength = edge2cumsumlength.as_ref().unwrap();
+                let length = *edge2cumsumlength.last().unwrap();
+                let r0 = rnd[0] * length;
+                let i_edge = edge2cumsumlength
+                    .partition_point(|&v| v <= r0)
+                    .min(edge2cumsumlength.len() - 1);
+                let (iv0, iv1) = (edge2vtx[i_edge * 2], edge2vtx[i_edge * 2 + 1]);
+                let p0 = arrayref::array_ref![vtx2xyz, iv0 * 3, 3];
+                let p1 = arrayref::array_ref![vtx2xyz, iv1 * 3, 3];
+                let l0 = if i_edge == 0 {
+                    0f32
+                } else {
+                    edge2cumsumlength[i_edge - 1]
+                };
+                let s = ((r0 - l0) / (edge2cumsumlength[i_edge] - l0)).clamp(0f32, 1f32);
+                let frame = del_geo_core::mat3_col_major::transform_lcl2world_given_local_z(
+                    &p1.sub(p0).normalize(),
+                );
+                let phi = rnd[1] * 2f32 * std::f32::consts::PI;
+                let nrm =
+                    del_geo_core::mat3_col_major::mult_vec(&frame, &[phi.cos(), phi.sin(), 0f32]);
+                let pos = p0.add(&p1.sub(p0).scale(s)).add(&nrm.scale(*radius));
+                let area = 2f32 * std::f32::consts::PI * radius * length;
+                (pos, nrm, 1. / area)
+            }
         }
     }
 
@@ -148,6 +209,25 @@ impl ShapeType {
                 tri2vtx, vtx2xyz, ..
             } => del_msh_cpu::trimesh3::cog_and_area(tri2vtx, vtx2xyz).unwrap(),
             ShapeType::Sphere { radius } => ([0f32; 3], del_geo_core::sphere::area(*radius)),
+            ShapeType::Curves {
+                edge2vtx,
+                vtx2xyz,
+                radius,
+                ..
+            } => {
+                use del_geo_core::vec3::Vec3;
+                let mut cog = [0f32; 3];
+                let mut length = 0f32;
+                for e in edge2vtx.chunks(2) {
+                    let p0 = arrayref::array_ref![vtx2xyz, e[0] * 3, 3];
+                    let p1 = arrayref::array_ref![vtx2xyz, e[1] * 3, 3];
+                    let l = del_geo_core::edge3::length(p0, p1);
+                    cog = cog.add(&p0.add(p1).scale(0.5 * l));
+                    length += l;
+                }
+                let area = 2f32 * std::f32::consts::PI * radius * length;
+                (cog.scale(1f32 / length), area)
+            }
         }
     }
 }
@@ -202,6 +282,26 @@ pub fn intersection_ray_against_shape_entities(
                     }
                 }
             }
+            ShapeType::Curves {
+                edge2vtx,
+                vtx2xyz,
+                radius,
+                ..
+            } => {
+                for (i_edge, e) in edge2vtx.chunks(2).enumerate() {
+                    let p0 = arrayref::array_ref![vtx2xyz, e[0] * 3, 3];
+                    let p1 = arrayref::array_ref![vtx2xyz, e[1] * 3, 3];
+                    if let Some(t) =
+                        intersection_ray_cylinder(&ray_org_objlcl, &ray_dir_objlcl, p0, p1, *radius)
+                    {
+                        if t < t_min {
+                            t_min = t;
+                            i_shape_entity_min = i_shape_entity;
+                            i_elem_min = i_edge;
+                        }
+                    }
+                }
+            }
         };
     }
     if t_min == f32::INFINITY {
@@ -221,12 +321,250 @@ pub fn normal_at(se: &ShapeEntity, hit_pos_world: &[f32; 3], i_elem: usize) -> [
             tri2vtx, vtx2xyz, ..
         } => del_msh_cpu::trimesh3::to_tri3(tri2vtx, vtx2xyz, i_elem).normal(),
         ShapeType::Sphere { radius: _ } => hit_pos_objlcl.to_owned(),
+        ShapeType::Curves {
+            edge2vtx, vtx2xyz, ..
+        } => {
+            use del_geo_core::vec3::Vec3;
+            let p0 = arrayref::array_ref![vtx2xyz, edge2vtx[i_elem * 2] * 3, 3];
+            let p1 = arrayref::array_ref![vtx2xyz, edge2vtx[i_elem * 2 + 1] * 3, 3];
+            let d = p1.sub(p0);
+            let s = (hit_pos_objlcl.sub(p0).dot(&d) / d.dot(&d)).clamp(0f32, 1f32);
+            hit_pos_objlcl.sub(&p0.add(&d.scale(s)))
+        }
     };
     let hit_nrm_world =
         mat4_col_major::transform_direction(&se.transform_objlcl2world, &hit_nrm_objlcl);
     del_geo_core::vec3::normalize(&hit_nrm_world)
 }
 
+/// texture coordinate of the hit point.
+/// The parameterizations of pbrt-v4 are used for the triangle mesh without `vtx2uv` and the sphere.
+/// For the curves, `u` is the parameter along the segment
+pub fn uv_at(se: &ShapeEntity, hit_pos_world: &[f32; 3], i_elem: usize) -> [f32; 2] {
+    use del_geo_core::mat4_col_major;
+    let hit_pos_objlcl =
+        mat4_col_major::transform_homogeneous(&se.transform_world2objlcl, hit_pos_world).unwrap();
+    match &se.shape {
+        ShapeType::TriangleMesh {
+            tri2vtx,
+            vtx2xyz,
+            vtx2uv,
+            ..
+        } => {
+            let node2vtx = arrayref::array_ref![tri2vtx, i_elem * 3, 3];
+            let p0 = arrayref::array_ref![vtx2xyz, node2vtx[0] * 3, 3];
+            let p1 = arrayref::array_ref![vtx2xyz, node2vtx[1] * 3, 3];
+            let p2 = arrayref::array_ref![vtx2xyz, node2vtx[2] * 3, 3];
+            let bc = del_geo_core::tri3::to_barycentric_coords(p0, p1, p2, &hit_pos_objlcl);
+            if vtx2uv.is_empty() {
+                // (0,0), (1,0) and (1,1) at the corners
+                return [bc[1] + bc[2], bc[2]];
+            }
+            let mut uv = [0f32; 2];
+            for (i_node, &i_vtx) in node2vtx.iter().enumerate() {
+                uv[0] += bc[i_node] * vtx2uv[i_vtx * 2];
+                uv[1] += bc[i_node] * vtx2uv[i_vtx * 2 + 1];
+            }
+            uv
+        }
+        ShapeType::Sphere { radius } => {
+            let p = hit_pos_objlcl;
+            let phi = p[1].atan2(p[0]);
+            let phi = if phi < 0f32 {
+                phi + 2f32 * std::f32::consts::PI
+            } else {
+                phi
+            };
+            let theta = (p[2] / radius).clamp(-1f32, 1f32).acos();
+            [
+                phi * 0.5f32 * std::f32::consts::FRAC_1_PI,
+                1f32 - theta * std::f32::consts::FRAC_1_PI,
+            ]
+        }
+        ShapeType::Curves {
+            edge2vtx, vtx2xyz, ..
+        } => {
+            use del_geo_core::vec3::Vec3;
+            let p0 = arrayref::array_ref![vtx2xyz, edge2vtx[i_elem * 2] * 3, 3];
+            let p1 = arrayref::array_ref![vtx2xyz, edge2vtx[i_elem * 2 + 1] * 3, 3];
+            let d = p1.sub(p0);
+            let s = (hit_pos_objlcl.sub(p0).dot(&d) / d.dot(&d)).clamp(0f32, 1f32);
+            [s, 0f32]
+        }
+    }
+}
+
+/// partial derivatives of the position with respect to the texture coordinate given by [`uv_at`]
+/// in the world coordinate, which span the tangent frame of the bump and the normal map.
+/// # Return
+/// `Some((dpdu, dpdv))`. `None` for the curves and the degenerate texture coordinates
+pub fn dpdu_dpdv_at(
+    se: &ShapeEntity,
+    hit_pos_world: &[f32; 3],
+    i_elem: usize,
+) -> Option<([f32; 3], [f32; 3])> {
+    use del_geo_core::mat4_col_major;
+    use del_geo_core::vec3::Vec3;
+    let (dpdu, dpdv) = match &se.shape {
+        ShapeType::TriangleMesh {
+            tri2vtx,
+            vtx2xyz,
+            vtx2uv,
+            ..
+        } => {
+            let node2vtx = arrayref::array_ref![tri2vtx, i_elem * 3, 3];
+            let p0 = arrayref::array_ref![vtx2xyz, node2vtx[0] * 3, 3];
+            let p1 = arrayref::array_ref![vtx2xyz, node2vtx[1] * 3, 3];
+            let p2 = arrayref::array_ref![vtx2xyz, node2vtx[2] * 3, 3];
+            let uvs: [[f32; 2]; 3] = if vtx2uv.is_empty() {
+                [[0f32, 0f32], [1f32, 0f32], [1f32, 1f32]]
+            } else {
+                node2vtx.map(|i_vtx| [vtx2uv[i_vtx * 2], vtx2uv[i_vtx * 2 + 1]])
+            };
+            let duv02 = [uvs[0][0] - uvs[2][0], uvs[0][1] - uvs[2][1]];
+            let duv12 = [uvs[1][0] - uvs[2][0], uvs[1][1] - uvs[2][1]];
+            let (dp02, dp12) = (p0.sub(p2), p1.sub(p2));
+            let det = duv02[0] * duv12[1] - duv02[1] * duv12[0];
+            if det.abs() < 1.0e-9 {
+                return None;
+            }
+            let dpdu = dp02
+                .scale(duv12[1])
+                .sub(&dp12.scale(duv02[1]))
+                .scale(1f32 / det);
+            let dpdv = dp12
+                .scale(duv02[0])
+                .sub(&dp02.scale(duv12[0]))
+                .scale(1f32 / det);
+            (dpdu, dpdv)
+        }
+        ShapeType::Sphere { radius } => {
+            let p =
+                mat4_col_major::transform_homogeneous(&se.transform_world2objlcl, hit_pos_world)
+                    .unwrap();
+            let r_xy = (p[0] * p[0] + p[1] * p[1]).sqrt();
+            if r_xy == 0f32 {
+                return None; // pole
+            }
+            let (cos_phi, sin_phi) = (p[0] / r_xy, p[1] / r_xy);
+            let pi = std::f32::consts::PI;
+            let dpdu = [-2f32 * pi * p[1], 2f32 * pi * p[0], 0f32];
+            // `v` increases as `theta` decreases
+            let sin_theta = r_xy / radius;
+            let dpdv = [p[2] * cos_phi, p[2] * sin_phi, -radius * sin_theta].scale(-pi);
+            (dpdu, dpdv)
+        }
+        ShapeType::Curves { .. } => return None,
+    };
+    Some((
+        mat4_col_major::transform_direction(&se.transform_objlcl2world, &dpdu),
+        mat4_col_major::transform_direction(&se.transform_objlcl2world, &dpdv),
+    ))
+}
+
+/// tangent of the curve in the world coordinate. `None` for the surfaces
+pub fn tangent_at(se: &ShapeEntity, i_elem: usize) -> Option<[f32; 3]> {
+    let ShapeType::Curves {
+        edge2vtx, vtx2xyz, ..
+    } = &se.shape
+    else {
+        return None;
+    };
+    let p0 = arrayref::array_ref![vtx2xyz, edge2vtx[i_elem * 2] * 3, 3];
+    let p1 = arrayref::array_ref![vtx2xyz, edge2vtx[i_elem * 2 + 1] * 3, 3];
+    let t = del_geo_core::vec3::sub(p1, p0);
+    let t = del_geo_core::mat4_col_major::transform_direction(&se.transform_objlcl2world, &t);
+    Some(del_geo_core::vec3::normalize(&t))
+}
+
+/// cumulative sum of the segment lengths of [`ShapeType::Curves`] used to sample a point uniformly
+pub fn edge2cumsumlength(edge2vtx: &[usize], vtx2xyz: &[f32]) -> Vec<f32> {
+    edge2vtx
+        .chunks(2)
+        .scan(0f32, |sum, e| {
+            let (p0, p1) = (
+                arrayref::array_ref![vtx2xyz, e[0] * 3, 3],
+                arrayref::array_ref![vtx2xyz, e[1] * 3, 3],
+            );
+            *sum += del_geo_core::edge3::length(p0, p1);
+            Some(*sum)
+        })
+        .collect()
+}
+
+/// vertices of the polyline approximating the piecewise cubic Bezier curve
+/// * `cps` - the control points `(p0,p1,p2,p3), (p3,p4,p5,p6), ...` whose segments share the end points
+/// * `num_div` - number of the polyline segments per Bezier segment
+pub fn polyline_from_cubic_bezier(cps: &[f32], num_div: usize) -> Vec<f32> {
+    let num_cp = cps.len() / 3;
+    assert!(num_cp >= 4 && (num_cp - 1) % 3 == 0);
+    let mut vtx2xyz = cps[0..3].to_vec();
+    for i_seg in 0..(num_cp - 1) / 3 {
+        let p = |i: usize| arrayref::array_ref![cps, (i_seg * 3 + i) * 3, 3];
+        for i_div in 1..=num_div {
+            let t = i_div as f32 / num_div as f32;
+            let s = 1f32 - t;
+            let w = [s * s * s, 3. * s * s * t, 3. * s * t * t, t * t * t];
+            vtx2xyz.extend((0..3).map(|k| (0..4).map(|i| w[i] * p(i)[k]).sum::<f32>()));
+        }
+    }
+    vtx2xyz
+}
+
+/// segments of the polylines for [`ShapeType::Curves`]
+/// * `polyline2idx` - the `i`-th polyline has the vertices from `polyline2idx[i]` to `polyline2idx[i+1]-1`
+pub fn edge2vtx_from_polylines(polyline2idx: &[usize]) -> Vec<usize> {
+    let mut edge2vtx = vec![];
+    for i_polyline in 0..polyline2idx.len() - 1 {
+        for i_vtx in polyline2idx[i_polyline]..polyline2idx[i_polyline + 1] - 1 {
+            edge2vtx.push(i_vtx);
+            edge2vtx.push(i_vtx + 1);
+        }
+    }
+    edge2vtx
+}
+
+/// first intersection of the ray against the side of the cylinder between `p0` and `p1`
+fn intersection_ray_cylinder(
+    ray_org: &[f32; 3],
+    ray_dir: &[f32; 3],
+    p0: &[f32; 3],
+    p1: &[f32; 3],
+    radius: f32,
+) -> Option<f32> {
+    use del_geo_core::vec3::Vec3;
+    let d = p1.sub(p0);
+    let len2 = d.dot(&d);
+    if len2 == 0f32 {
+        return None;
+    }
+    // components perpendicular to the axis
+    let a_vec = ray_dir.sub(&d.scale(ray_dir.dot(&d) / len2));
+    let o = ray_org.sub(p0);
+    let b_vec = o.sub(&d.scale(o.dot(&d) / len2));
+    let a = a_vec.dot(&a_vec);
+    if a < f32::EPSILON {
+        return None;
+    }
+    let b = 2f32 * a_vec.dot(&b_vec);
+    let c = b_vec.dot(&b_vec) - radius * radius;
+    let discriminant = b * b - 4f32 * a * c;
+    if discriminant < 0f32 {
+        return None;
+    }
+    let sq = discriminant.sqrt();
+    for t in [(-b - sq) / (2f32 * a), (-b + sq) / (2f32 * a)] {
+        if t <= 0f32 {
+            continue;
+        }
+        let s = o.add(&ray_dir.scale(t)).dot(&d) / len2;
+        if (0f32..=1f32).contains(&s) {
+            return Some(t);
+        }
+    }
+    None
+}
+
 pub fn write_wavefront_obj_file_from_camera_view(
     file_name: &str,
     shape_entities: &[ShapeEntity],
@@ -247,6 +585,7 @@ pub fn write_wavefront_obj_file_from_camera_view(
                         del_msh_cpu::trimesh3_primitive::sphere_yup::<usize, f32>(*radius, 32, 32);
                     (tri2vtx, vtx2xyz)
                 }
+                ShapeType::Curves { .. } => (vec![], vec![]),
             };
             let vtx2xyz_world = del_msh_cpu::vtx2xyz::transform_homogeneous(
                 &vtx2xyz_objlcl,
@@ -0,0 +1,201 @@
+//! Named spectra of pbrt-v4 (e.g., `"spectrum eta" "metal-Cu-eta"`) reduced to RGB.
+//! The glasses are given by the Sellmeier equation and can also be evaluated at any wavelength.
+//! The metals are the measured eta and k reduced to RGB at the representative wavelengths.
+//! The blackbody emission is reduced to RGB with the CIE color matching functions.
+//! See <https://pbrt.org/fileformat-v4#spectrum> and <https://refractiveindex.info>
+
+/// representative wavelengths (nm) of the RGB channels
+pub const RGB_WAVELENGTHS: [f32; 3] = [630f32, 532f32, 465f32];
+
+/// Sellmeier coefficients `(B, C)` of the glasses with the wavelength in micrometer (Schott catalog)
+const GLASSES: [(&str, [f32; 3], [f32; 3]); 7] = [
+    (
+        "glass-BK7",
+        [1.03961, 0.231792, 1.01047],
+        [0.00600070, 0.0200179, 103.561],
+    ),
+    (
+        "glass-BAF10",
+        [1.58515, 0.143559, 1.08521],
+        [0.00926681, 0.0424490, 105.614],
+    ),
+    (
+        "glass-FK51A",
+        [0.971248, 0.216901, 0.904652],
+        [0.00472302, 0.0153576, 168.681],
+    ),
+    (
+        "glass-LASF9",
+        [2.00030, 0.298927, 1.80692],
+        [0.0121426, 0.0538736, 156.531],
+    ),
+    (
+        "glass-F5",
+        [1.52482, 0.187086, 1.42729],
+        [0.0112548, 0.0588995, 129.142],
+    ),
+    (
+        "glass-F10",
+        [1.62154, 0.256288, 1.64448],
+        [0.0122241, 0.0595737, 147.469],
+    ),
+    (
+        "glass-F11",
+        [1.73760, 0.313747, 1.89878],
+        [0.0131887, 0.0623068, 155.236],
+    ),
+];
+
+/// `(name, eta, k)` of the metals in RGB
+const METALS: [(&str, [f32; 3], [f32; 3]); 7] = [
+    (
+        "Ag",
+        [0.155_265, 0.116_723, 0.138_342],
+        [4.828_35, 3.122_25, 2.146_96],
+    ),
+    (
+        "Al",
+        [1.657_46, 0.880_369, 0.521_229],
+        [9.223_87, 6.269_52, 4.837],
+    ),
+    (
+        "Au",
+        [0.143_119, 0.374_957, 1.442_48],
+        [3.983_16, 2.385_72, 1.603_22],
+    ),
+    (
+        "Cu",
+        [0.200_438, 0.924_033, 1.102_21],
+        [3.912_95, 2.452_85, 2.142_19],
+    ),
+    ("CuZn", [0.444, 0.527, 1.094], [3.695, 2.765, 1.829]),
+    ("MgO", [1.732, 1.738, 1.750], [0., 0., 0.]),
+    ("TiO2", [2.58, 2.63, 2.76], [0., 0., 0.]),
+];
+
+/// index of refraction from the Sellmeier equation
+/// * `lambda` - wavelength in nanometer
+pub fn sellmeier_ior(b: &[f32; 3], c: &[f32; 3], lambda: f32) -> f32 {
+    let l2 = (lambda * 1.0e-3) * (lambda * 1.0e-3);
+    let n2 = 1f32 + b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum::<f32>();
+    n2.max(1f32).sqrt()
+}
+
+/// value of the named spectrum at the wavelength (nm). Only the glasses are supported
+pub fn named_spectrum_at_wavelength(name: &str, lambda: f32) -> Option<f32> {
+    GLASSES
+        .iter()
+        .find(|(n, _, _)| *n == name)
+        .map(|(_, b, c)| sellmeier_ior(b, c, lambda))
+}
+
+/// named spectrum such as `"glass-BK7"`, `"metal-Au-eta"` and `"metal-Au-k"` in RGB
+/// # Return
+/// `None` if the name is unknown
+pub fn named_spectrum_rgb(name: &str) -> Option<[f32; 3]> {
+    if name.starts_with("glass-") {
+        let (_, b, c) = GLASSES.iter().find(|(n, _, _)| *n == name)?;
+        return Some(RGB_WAVELENGTHS.map(|lambda| sellmeier_ior(b, c, lambda)));
+    }
+    let metal = name.strip_prefix("metal-")?;
+    let (metal, is_eta) = if let Some(metal) = metal.strip_suffix("-eta") {
+        (metal, true)
+    } else {
+        (metal.strip_suffix("-k")?, false)
+    };
+    METALS
+        .iter()
+        .find(|(n, _, _)| *n == metal)
+        .map(|(_, eta, k)| if is_eta { *eta } else { *k })
+}
+
+/// spectral radiance of the blackbody by Planck's law (W / (sr m^2 m))
+/// * `lambda` - wavelength in nanometer
+/// * `temperature` - temperature in Kelvin
+pub fn blackbody(lambda: f32, temperature: f32) -> f32 {
+    if temperature <= 0f32 {
+        return 0f32;
+    }
+    let c = 299_792_458f64;
+    let h = 6.626_070_15e-34f64;
+    let kb = 1.380_649e-23f64;
+    let l = lambda as f64 * 1.0e-9;
+    let le =
+        2f64 * h * c * c / (l.powi(5) * ((h * c / (l * kb * temperature as f64)).exp() - 1f64));
+    le as f32
+}
+
+/// CIE 1931 color matching functions by the multi-lobe Gaussian fit of
+/// "Simple Analytic Approximations to the CIE XYZ Color Matching Functions" by Wyman et al. 2013
+/// * `lambda` - wavelength in nanometer
+pub fn cie_xyz(lambda: f32) -> [f32; 3] {
+    let g = |mu: f32, sigma1: f32, sigma2: f32| {
+        let t = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+        (-0.5f32 * t * t).exp()
+    };
+    [
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    ]
+}
+
+/// CIE XYZ to the linear sRGB
+pub fn xyz_to_linear_srgb(xyz: &[f32; 3]) -> [f32; 3] {
+    [
+        3.240_454_2 * xyz[0] - 1.537_138_5 * xyz[1] - 0.498_531_4 * xyz[2],
+        -0.969_266 * xyz[0] + 1.876_010_8 * xyz[1] + 0.041_556 * xyz[2],
+        0.055_643_4 * xyz[0] - 0.204_025_9 * xyz[1] + 1.057_225_2 * xyz[2],
+    ]
+}
+
+/// color of the blackbody in the linear sRGB normalized so that the luminance is one as in pbrt-v4.
+/// The colors out of the gamut are clamped
+/// * `temperature` - temperature in Kelvin
+pub fn blackbody_rgb(temperature: f32) -> [f32; 3] {
+    let mut xyz = [0f32; 3];
+    for lambda in 360..=830 {
+        let lambda = lambda as f32;
+        let le = blackbody(lambda, temperature);
+        let cmf = cie_xyz(lambda);
+        xyz = std::array::from_fn(|i| xyz[i] + le * cmf[i]);
+    }
+    if xyz[1] <= 0f32 {
+        return [0f32; 3];
+    }
+    xyz_to_linear_srgb(&xyz.map(|v| v / xyz[1])).map(|v| v.max(0f32))
+}
+
+#[test]
+fn test_named_spectrum() {
+    // refractive index of BK7 at the sodium d-line (587.6 nm) is 1.5168
+    let n_d = named_spectrum_at_wavelength("glass-BK7", 587.6).unwrap();
+    assert!((n_d - 1.5168).abs() < 1.0e-3, "{}", n_d);
+    // normal dispersion
+    let eta = named_spectrum_rgb("glass-F11").unwrap();
+    assert!(eta[0] < eta[1] && eta[1] < eta[2]);
+    assert_eq!(named_spectrum_rgb("metal-Cu-k").unwrap()[0], 3.912_95);
+    assert!(named_spectrum_rgb("metal-Xx-eta").is_none());
+    assert!(named_spectrum_rgb("glass-Xx").is_none());
+}
+
+#[test]
+fn test_blackbody_rgb() {
+    // Wien's displacement law: the peak is at 2.898e6 / T (nm)
+    let temperature = 5000f32;
+    let lambda_peak = 2.897_772e6 / temperature;
+    assert!(blackbody(lambda_peak, temperature) > blackbody(lambda_peak - 10., temperature));
+    assert!(blackbody(lambda_peak, temperature) > blackbody(lambda_peak + 10., temperature));
+    // the blackbody around the temperature of the daylight is almost white
+    let rgb = blackbody_rgb(6504.);
+    for v in rgb {
+        assert!((v - 1.).abs() < 0.15, "{:?}", rgb);
+    }
+    let luminance = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+    assert!((luminance - 1.).abs() < 1.0e-2, "{}", luminance);
+    // the candle is reddish and the blue sky is bluish
+    let rgb = blackbody_rgb(2000.);
+    assert!(rgb[0] > rgb[1] && rgb[1] > rgb[2], "{:?}", rgb);
+    let rgb = blackbody_rgb(12000.);
+    assert!(rgb[2] > rgb[1] && rgb[1] > rgb[0], "{:?}", rgb);
+}
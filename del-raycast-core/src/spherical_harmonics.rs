@@ -0,0 +1,297 @@
+//! Real spherical harmonics (SH) and the precomputed radiance transfer (PRT).
+//! The environment map is projected to the SH coefficients and the transfer vector at each vertex
+//! is precomputed with the visibility rays. The relighting is then the dot product of the two.
+//!
+//! The `(l, m)` basis is stored at the index `l * (l + 1) + m` with `0 <= l < num_band` and `-l <= m <= l`.
+//! See "Spherical Harmonic Lighting: The Gritty Details" by Robin Green
+
+/// number of the SH coefficients for `num_band` bands
+pub fn num_coeff(num_band: usize) -> usize {
+    num_band * num_band
+}
+
+/// associated Legendre polynomial `P_l^m(x)`
+fn legendre_polynomial(l: usize, m: usize, x: f64) -> f64 {
+    let mut pmm = 1f64;
+    if m > 0 {
+        let somx2 = ((1f64 - x) * (1f64 + x)).max(0f64).sqrt();
+        let mut fact = 1f64;
+        for _i in 0..m {
+            pmm *= -fact * somx2;
+            fact += 2f64;
+        }
+    }
+    if l == m {
+        return pmm;
+    }
+    let mut pmmp1 = x * (2 * m + 1) as f64 * pmm;
+    if l == m + 1 {
+        return pmmp1;
+    }
+    let mut pll = 0f64;
+    for ll in m + 2..=l {
+        pll = ((2 * ll - 1) as f64 * x * pmmp1 - (ll + m - 1) as f64 * pmm) / (ll - m) as f64;
+        pmm = pmmp1;
+        pmmp1 = pll;
+    }
+    pll
+}
+
+/// normalization constant of the SH basis
+fn normalization(l: usize, m: usize) -> f64 {
+    // (l-m)!/(l+m)!
+    let ratio = (l - m + 1..=l + m).fold(1f64, |r, k| r / k as f64);
+    ((2 * l + 1) as f64 * ratio / (4f64 * std::f64::consts::PI)).sqrt()
+}
+
+/// evaluate all the SH bases in the direction
+/// * `dir` - unit vector
+/// * `basis` - (output) values of the bases. The size `num_coeff(num_band)` gives the number of the bands
+pub fn eval_basis(dir: &[f32; 3], basis: &mut [f32]) {
+    let num_band = (basis.len() as f32).sqrt() as usize;
+    assert_eq!(num_coeff(num_band), basis.len());
+    let cos_theta = (dir[2] as f64).clamp(-1f64, 1f64);
+    let phi = (dir[1] as f64).atan2(dir[0] as f64);
+    for l in 0..num_band {
+        let idx0 = l * (l + 1);
+        basis[idx0] = (normalization(l, 0) * legendre_polynomial(l, 0, cos_theta)) as f32;
+        for m in 1..=l {
+            let p = std::f64::consts::SQRT_2
+                * normalization(l, m)
+                * legendre_polynomial(l, m, cos_theta);
+            basis[idx0 + m] = (p * (m as f64 * phi).cos()) as f32;
+            basis[idx0 - m] = (p * (m as f64 * phi).sin()) as f32;
+        }
+    }
+}
+
+/// reconstruct the RGB value in the direction from the SH coefficients
+pub fn eval(coeffs: &[[f32; 3]], dir: &[f32; 3]) -> [f32; 3] {
+    let num_band = (coeffs.len() as f32).sqrt() as usize;
+    assert_eq!(num_coeff(num_band), coeffs.len());
+    let mut basis = vec![0f32; num_coeff(num_band)];
+    eval_basis(dir, &mut basis);
+    coeffs
+        .iter()
+        .zip(basis.iter())
+        .fold([0f32; 3], |acc, (c, &y)| {
+            del_geo_core::vec3::axpy(y, c, &acc)
+        })
+}
+
+/// project the environment map in the equal-area octahedral parameterization
+/// (the same as [`crate::env_map::envmap2unitsphere`]) to the SH coefficients.
+/// * `tex_data` - RGB radiance of the environment map such as the one loaded by [`crate::io_pfm::PFM`]
+/// * `transform_envlcl2world` - transformation of the direction from the map to the world
+/// # Return
+/// RGB coefficients with the size of `num_coeff(num_band)`
+pub fn project_envmap(
+    tex_data: &[f32],
+    tex_shape: (usize, usize),
+    transform_envlcl2world: &[f32; 16],
+    num_band: usize,
+) -> Vec<[f32; 3]> {
+    let (w, h) = tex_shape;
+    assert_eq!(tex_data.len(), w * h * 3);
+    // every texel has the same solid angle in the equal-area parameterization
+    let solid_angle = 4f32 * std::f32::consts::PI / (w * h) as f32;
+    let mut coeffs = vec![[0f32; 3]; num_coeff(num_band)];
+    let mut basis = vec![0f32; num_coeff(num_band)];
+    for ih in 0..h {
+        for iw in 0..w {
+            let uv = [(iw as f32 + 0.5) / w as f32, (ih as f32 + 0.5) / h as f32];
+            let dir = crate::env_map::envmap2unitsphere(&uv);
+            let dir =
+                del_geo_core::mat4_col_major::transform_direction(transform_envlcl2world, &dir);
+            let dir = del_geo_core::vec3::normalize(&dir);
+            let radiance = arrayref::array_ref![tex_data, (ih * w + iw) * 3, 3];
+            eval_basis(&dir, &mut basis);
+            for (c, y) in coeffs.iter_mut().zip(basis.iter()) {
+                *c = del_geo_core::vec3::axpy(y * solid_angle, radiance, c);
+            }
+        }
+    }
+    coeffs
+}
+
+/// transfer vectors of the diffuse surface with the self-shadowing
+/// `T_i = \int V(w) max(cos, 0) Y_i(w) dw` at each vertex, where `V` is the visibility.
+/// The arguments are the same as [`crate::vertex_bake::vtx2ao_bentnormal`].
+/// # Return
+/// `vtx2transfer` with the size of `num_vtx * num_coeff(num_band)`
+#[allow(clippy::too_many_arguments)]
+pub fn vtx2transfer(
+    tri2vtx: &[usize],
+    vtx2xyz: &[f32],
+    vtx2nrm: &[f32],
+    occluder_tri2vtx: &[usize],
+    occluder_vtx2xyz: &[f32],
+    bvhnodes: &[usize],
+    bvhnode2aabb: &[f32],
+    num_sample: usize,
+    num_band: usize,
+) -> Vec<f32> {
+    let vtx2nrm = if vtx2nrm.is_empty() {
+        crate::vertex_bake::vtx2nrm_from_triangles(tri2vtx, vtx2xyz)
+    } else {
+        vtx2nrm.to_vec()
+    };
+    // the pdf of the cosine-weighted sampling is cos/pi
+    let weight = std::f32::consts::PI / num_sample as f32;
+    // (transfer vector, buffer of the bases reused for all the rays of the vertex)
+    let vtx2transfer: Vec<(Vec<f32>, Vec<f32>)> = crate::vertex_bake::cast_hemisphere_rays(
+        vtx2xyz,
+        &vtx2nrm,
+        occluder_tri2vtx,
+        occluder_vtx2xyz,
+        bvhnodes,
+        bvhnode2aabb,
+        num_sample,
+        f32::INFINITY,
+        |(transfer, basis): &mut (Vec<f32>, Vec<f32>), ray_dir, is_occluded| {
+            if transfer.is_empty() {
+                transfer.resize(num_coeff(num_band), 0f32);
+                basis.resize(num_coeff(num_band), 0f32);
+            }
+            if is_occluded {
+                return;
+            }
+            eval_basis(ray_dir, basis);
+            for (t, y) in transfer.iter_mut().zip(basis.iter()) {
+                *t += y * weight;
+            }
+        },
+    );
+    vtx2transfer
+        .into_iter()
+        .flat_map(|(mut transfer, _basis)| {
+            transfer.resize(num_coeff(num_band), 0f32);
+            transfer
+        })
+        .collect()
+}
+
+/// relight the vertices with the dot product of the transfer vectors and the SH coefficients of the lighting
+/// # Return
+/// `vtx2irradiance` with the size of `num_vtx * 3`. Multiplying the reflectance divided by pi gives the outgoing radiance
+pub fn relight(vtx2transfer: &[f32], coeffs: &[[f32; 3]]) -> Vec<f32> {
+    let num_coeff = coeffs.len();
+    vtx2transfer
+        .chunks(num_coeff)
+        .flat_map(|transfer| {
+            transfer
+                .iter()
+                .zip(coeffs.iter())
+                .fold([0f32; 3], |acc, (&t, c)| {
+                    del_geo_core::vec3::axpy(t, c, &acc)
+                })
+        })
+        .collect()
+}
+
+#[test]
+fn test_project_constant_envmap() {
+    let tex_shape = (64, 64);
+    let tex_data = vec![1f32; tex_shape.0 * tex_shape.1 * 3];
+    let transform = [
+        1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.,
+    ];
+    let coeffs = project_envmap(&tex_data, tex_shape, &transform, 3);
+    // integral of the constant basis Y_00 = 1/(2 sqrt(pi)) over the sphere
+    let c0 = 2f32 * std::f32::consts::PI.sqrt();
+    assert!((coeffs[0][0] - c0).abs() < 1.0e-3);
+    for c in coeffs[1..].iter() {
+        assert!(c[0].abs() < 1.0e-2, "{}", c[0]);
+    }
+    let rad = eval(&coeffs, &del_geo_core::vec3::normalize(&[1., 2., 3.]));
+    assert!((rad[0] - 1.).abs() < 1.0e-2);
+    // irradiance of the un-occluded surface is pi. Only the first coefficient is non-zero for the constant lighting
+    let mut y0 = [0f32; 1];
+    eval_basis(&[0., 0., 1.], &mut y0);
+    let t0 = std::f32::consts::PI * y0[0];
+    let irradiance = relight(&[t0], &coeffs[0..1]);
+    assert!((irradiance[0] - std::f32::consts::PI).abs() < 1.0e-3);
+}
+
+#[cfg(test)]
+fn transfer_at_center_of_plane(
+    occluder_tri2vtx: &[usize],
+    occluder_vtx2xyz: &[f32],
+    num_band: usize,
+) -> Vec<f32> {
+    // square on the xy-plane with the vertex `4` at the center
+    let vtx2xyz = vec![
+        -1., -1., 0., 1., -1., 0., 1., 1., 0., -1., 1., 0., 0., 0., 0.,
+    ];
+    let tri2vtx = vec![0, 1, 4, 1, 2, 4, 2, 3, 4, 3, 0, 4];
+    let bvhnodes =
+        del_msh_cpu::bvhnodes_morton::from_triangle_mesh(occluder_tri2vtx, occluder_vtx2xyz, 3);
+    let bvhnode2aabb = del_msh_cpu::bvhnode2aabb3::from_uniform_mesh_with_bvh(
+        0,
+        &bvhnodes,
+        Some((occluder_tri2vtx, 3)),
+        occluder_vtx2xyz,
+        None,
+    );
+    let vtx2transfer = vtx2transfer(
+        &tri2vtx,
+        &vtx2xyz,
+        &[],
+        occluder_tri2vtx,
+        occluder_vtx2xyz,
+        &bvhnodes,
+        &bvhnode2aabb,
+        4000,
+        num_band,
+    );
+    vtx2transfer[4 * num_coeff(num_band)..5 * num_coeff(num_band)].to_vec()
+}
+
+#[test]
+fn test_transfer_open_plane() {
+    use std::f32::consts::PI;
+    // the plane itself is the only occluder, which never blocks the rays in the upper hemisphere
+    let tri2vtx = vec![0, 1, 2, 0, 2, 3];
+    let vtx2xyz = vec![-1., -1., 0., 1., -1., 0., 1., 1., 0., -1., 1., 0.];
+    let transfer = transfer_at_center_of_plane(&tri2vtx, &vtx2xyz, 3);
+    // the transfer of the open surface is the projection of the cosine lobe around the normal `z`
+    // * `T_00 = pi Y_00`
+    // * `T_10 = sqrt(3/(4 pi)) 2pi/3`
+    // * `T_20 = sqrt(5/(16 pi)) pi/2`
+    // * `T_lm = 0` for `m != 0` because the lobe is symmetric around the normal
+    let t00 = PI * 0.5 / PI.sqrt();
+    assert!((transfer[0] - t00).abs() < 1.0e-4, "{}", transfer[0]);
+    let t10 = (3. / (4. * PI)).sqrt() * 2. * PI / 3.;
+    assert!((transfer[2] - t10).abs() < 0.02, "{}", transfer[2]);
+    let t20 = (5. / (16. * PI)).sqrt() * PI / 2.;
+    assert!((transfer[6] - t20).abs() < 0.03, "{}", transfer[6]);
+    for idx in [1, 3, 4, 5, 7, 8] {
+        assert!(transfer[idx].abs() < 0.03, "{} {}", idx, transfer[idx]);
+    }
+}
+
+#[test]
+fn test_transfer_occluded_plane() {
+    let tri2vtx = vec![0, 1, 2, 0, 2, 3];
+    let vtx2xyz_open = vec![-1., -1., 0., 1., -1., 0., 1., 1., 0., -1., 1., 0.];
+    let transfer_open = transfer_at_center_of_plane(&tri2vtx, &vtx2xyz_open, 2);
+    // roof above the center of the plane
+    let vtx2xyz_roof = vec![
+        -0.5, -0.5, 0.5, 0.5, -0.5, 0.5, 0.5, 0.5, 0.5, -0.5, 0.5, 0.5,
+    ];
+    let transfer_roof = transfer_at_center_of_plane(&tri2vtx, &vtx2xyz_roof, 2);
+    // the roof blocks the directions near the normal where the cosine lobe is the largest
+    assert!(
+        transfer_roof[0] < 0.7 * transfer_open[0],
+        "{} {}",
+        transfer_roof[0],
+        transfer_open[0]
+    );
+    assert!(transfer_roof[0] > 0., "{}", transfer_roof[0]);
+    assert!(
+        transfer_roof[2] < transfer_open[2],
+        "{} {}",
+        transfer_roof[2],
+        transfer_open[2]
+    );
+}
@@ -0,0 +1,250 @@
+//! Subsurface scattering by the volumetric random walk inside the closed shape.
+//! The walk samples the free-flight distance of a randomly chosen RGB channel and
+//! weights the path with the average pdf over the channels (spectral MIS), so the chromatic media converge.
+//! See "Path traced subsurface scattering using anisotropic phase functions and non-exponential free flights"
+//! by Wrenninge et al. 2017
+
+use crate::material::{SubsurfaceBoundary, SubsurfaceMaterial};
+
+/// the walk longer than this is regarded as absorbed
+const MAX_NUM_SCATTER: usize = 1024;
+
+/// single-scattering albedo that gives the multiple-scattering reflectance `reflectance` of the semi-infinite medium.
+/// The inversion of van de Hulst's approximation
+pub fn single_scattering_albedo_from_reflectance(reflectance: f32) -> f32 {
+    let a = reflectance.clamp(0f32, 1f32);
+    let s = 4.09712f32 + 4.20863f32 * a - (9.59217f32 + 41.6808f32 * a + 17.7126f32 * a * a).sqrt();
+    (1f32 - s * s).clamp(0f32, 1f32)
+}
+
+/// outward normal of the boundary at the hit point of the ray starting inside the shape
+fn boundary_normal(
+    se: &crate::shape::ShapeEntity,
+    pos: &[f32; 3],
+    dir: &[f32; 3],
+    i_elem: usize,
+) -> [f32; 3] {
+    use del_geo_core::vec3::Vec3;
+    let nrm = crate::shape::normal_at(se, pos, i_elem);
+    if nrm.dot(dir) < 0f32 {
+        nrm.scale(-1f32)
+    } else {
+        nrm
+    }
+}
+
+/// run the random walk from the surface of the shape entity until the path leaves the surface
+/// * `hit_nrm` - outward unit normal at the hit point
+/// * `ray_in_outward` - unit vector toward the viewer
+/// # Return
+/// `(pos, nrm, dir, weight)` of the ray leaving the surface at `pos` whose outward normal is `nrm`.
+/// `weight` is zero if the path is absorbed
+pub fn sample_random_walk<RNG>(
+    se: &crate::shape::ShapeEntity,
+    mat: &SubsurfaceMaterial,
+    hit_pos: &[f32; 3],
+    hit_nrm: &[f32; 3],
+    ray_in_outward: &[f32; 3],
+    rng: &mut RNG,
+) -> ([f32; 3], [f32; 3], [f32; 3], [f32; 3])
+where
+    RNG: rand::Rng,
+{
+    use del_geo_core::vec3::Vec3;
+    let absorbed = (*hit_pos, *hit_nrm, *ray_in_outward, [0f32; 3]);
+    // enter the medium
+    let mut dir = match mat.boundary {
+        SubsurfaceBoundary::Diffuse => crate::sampling::hemisphere_cos_weighted(
+            &hit_nrm.scale(-1f32),
+            &[rng.random::<f32>(), rng.random::<f32>()],
+        ),
+        SubsurfaceBoundary::Dielectric => {
+            let cos = ray_in_outward.dot(hit_nrm);
+            let r = crate::material::fresnel_dielectric(mat.eta, cos);
+            let refracted = crate::material::refract(ray_in_outward, hit_nrm, mat.eta);
+            match refracted {
+                Some(dir) if rng.random::<f32>() >= r => dir,
+                _ => {
+                    // the specular reflection on the surface
+                    let dir = hit_nrm.scale(2f32 * cos).sub(ray_in_outward);
+                    return (*hit_pos, *hit_nrm, dir, [1f32; 3]);
+                }
+            }
+        }
+    };
+    let sigma_t: [f32; 3] = std::array::from_fn(|i| mat.sigma_a[i] + mat.sigma_s[i]);
+    let mut pos = del_geo_core::vec3::axpy(-1.0e-3, hit_nrm, hit_pos);
+    let mut weight = [1f32; 3];
+    for _i_scatter in 0..MAX_NUM_SCATTER {
+        let i_channel = ((rng.random::<f32>() * 3f32) as usize).min(2);
+        if sigma_t[i_channel] <= 0f32 {
+            return absorbed;
+        }
+        let dist = -(1f32 - rng.random::<f32>()).ln() / sigma_t[i_channel];
+        let hit = crate::shape::intersection_ray_against_shape_entities(
+            &pos,
+            &dir,
+            std::slice::from_ref(se),
+        );
+        match hit {
+            Some((t, _, i_elem)) if t < dist => {
+                // reach the boundary
+                let tr = sigma_t.map(|s| (-s * t).exp());
+                let pdf = (tr[0] + tr[1] + tr[2]) / 3f32;
+                weight = std::array::from_fn(|i| weight[i] * tr[i] / pdf);
+                let pos_b = del_geo_core::vec3::axpy(t, &dir, &pos);
+                let nrm = boundary_normal(se, &pos_b, &dir, i_elem);
+                match mat.boundary {
+                    SubsurfaceBoundary::Diffuse => {
+                        let dir_out = crate::sampling::hemisphere_cos_weighted(
+                            &nrm,
+                            &[rng.random::<f32>(), rng.random::<f32>()],
+                        );
+                        return (pos_b, nrm, dir_out, weight);
+                    }
+                    SubsurfaceBoundary::Dielectric => {
+                        let wi = dir.scale(-1f32);
+                        let r = crate::material::fresnel_dielectric(mat.eta, wi.dot(&nrm));
+                        let refracted =
+                            crate::material::refract(&wi, &nrm.scale(-1f32), 1f32 / mat.eta);
+                        match refracted {
+                            Some(dir_out) if rng.random::<f32>() >= r => {
+                                return (pos_b, nrm, dir_out, weight);
+                            }
+                            _ => {
+                                // the internal reflection
+                                dir = dir.sub(&nrm.scale(2f32 * dir.dot(&nrm)));
+                                pos = del_geo_core::vec3::axpy(-1.0e-3, &nrm, &pos_b);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {
+                // scatter in the medium
+                let tr = sigma_t.map(|s| (-s * dist).exp());
+                let pdf = (0..3).map(|i| sigma_t[i] * tr[i]).sum::<f32>() / 3f32;
+                weight = std::array::from_fn(|i| weight[i] * mat.sigma_s[i] * tr[i] / pdf);
+                if weight == [0f32; 3] {
+                    return absorbed;
+                }
+                pos = del_geo_core::vec3::axpy(dist, &dir, &pos);
+                let (dir_new, _) = crate::layered_material::sample_henyey_greenstein(
+                    &dir.scale(-1f32),
+                    mat.g,
+                    &[rng.random::<f32>(), rng.random::<f32>()],
+                );
+                dir = dir_new;
+            }
+        }
+    }
+    absorbed
+}
+
+#[test]
+fn test_random_walk_in_sphere() {
+    use rand::SeedableRng;
+    let (tri2vtx, vtx2xyz) = del_msh_cpu::trimesh3_primitive::sphere_yup::<usize, f32>(1., 32, 32);
+    let se = crate::shape::ShapeEntity::new_untransformed(crate::shape::ShapeType::TriangleMesh {
+        tri2vtx,
+        vtx2xyz,
+        vtx2nrm: vec![],
+        vtx2uv: vec![],
+        tri2cumsumarea: None,
+    });
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    let hit_pos = [0f32, 1., 0.];
+    let hit_nrm = [0f32, 1., 0.];
+    let ray_in = del_geo_core::vec3::normalize(&[0.3, 1., 0.]);
+    for boundary in [SubsurfaceBoundary::Diffuse, SubsurfaceBoundary::Dielectric] {
+        // no energy is lost without the absorption even if the medium is chromatic
+        let mat = SubsurfaceMaterial {
+            sigma_a: [0.; 3],
+            sigma_s: [2., 3., 4.],
+            g: 0.3,
+            eta: 1.33,
+            boundary,
+        };
+        let num_sample = 1000;
+        let mut sum = [0f32; 3];
+        for _ in 0..num_sample {
+            let (pos, nrm, dir, weight) =
+                sample_random_walk(&se, &mat, &hit_pos, &hit_nrm, &ray_in, &mut rng);
+            assert!((del_geo_core::vec3::norm(&pos) - 1.).abs() < 1.0e-2);
+            assert!(del_geo_core::vec3::dot(&nrm, &dir) >= 0.);
+            sum = std::array::from_fn(|i| sum[i] + weight[i] / num_sample as f32);
+        }
+        for v in sum {
+            assert!((v - 1.).abs() < 0.1, "{:?}", sum);
+        }
+        // the absorption reduces the energy
+        let mat = SubsurfaceMaterial {
+            sigma_a: [0.5; 3],
+            ..mat
+        };
+        let mut sum = 0f32;
+        for _ in 0..num_sample {
+            let (_, _, _, weight) =
+                sample_random_walk(&se, &mat, &hit_pos, &hit_nrm, &ray_in, &mut rng);
+            sum += weight[0] / num_sample as f32;
+        }
+        assert!(sum < 0.9, "{}", sum);
+    }
+    // the generic BxDF path absorbs the light instead of panicking
+    let mat = crate::material::Material::Subsurface(SubsurfaceMaterial {
+        sigma_a: [0.; 3],
+        sigma_s: [1.; 3],
+        g: 0.,
+        eta: 1.33,
+        boundary: SubsurfaceBoundary::Dielectric,
+    });
+    assert!(crate::material::bxdf(&mat, &[], &[0.; 2], None, 0.).is_none());
+    assert!((single_scattering_albedo_from_reflectance(1.) - 1.).abs() < 1.0e-2);
+    assert!(single_scattering_albedo_from_reflectance(0.) < 1.0e-2);
+}
+
+#[test]
+fn test_random_walk_dielectric_exit() {
+    use del_geo_core::vec3::Vec3;
+    use rand::SeedableRng;
+    let se = crate::shape::ShapeEntity::new_untransformed(crate::shape::ShapeType::Sphere {
+        radius: 1.,
+    });
+    // almost transparent medium without the absorption
+    let mat = SubsurfaceMaterial {
+        sigma_a: [0.; 3],
+        sigma_s: [1.0e-4; 3],
+        g: 0.,
+        eta: 1.5,
+        boundary: SubsurfaceBoundary::Dielectric,
+    };
+    let hit_pos = [0f32, 1., 0.];
+    let hit_nrm = [0f32, 1., 0.];
+    let ray_in = del_geo_core::vec3::normalize(&[3f32.sqrt(), 1., 0.]);
+    // the refracted ray hits the far side of the sphere with the refracted angle.
+    // The reflectance is the same at the entrance and the exit
+    let dir = crate::material::refract(&ray_in, &hit_nrm, mat.eta).unwrap();
+    let pos_far = del_geo_core::vec3::axpy(-2f32 * hit_pos.dot(&dir), &dir, &hit_pos);
+    let r = crate::material::fresnel_dielectric(mat.eta, ray_in.dot(&hit_nrm));
+    let mut rng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    let num_sample = 20000;
+    let mut num_far = 0;
+    let mut sum = [0f32; 3];
+    for _ in 0..num_sample {
+        let (pos, nrm, dir, weight) =
+            sample_random_walk(&se, &mat, &hit_pos, &hit_nrm, &ray_in, &mut rng);
+        assert!((pos.norm() - 1.).abs() < 1.0e-2);
+        assert!(nrm.dot(&dir) >= 0.);
+        if pos.sub(&pos_far).norm() < 1.0e-2 {
+            num_far += 1;
+        }
+        sum = std::array::from_fn(|i| sum[i] + weight[i] / num_sample as f32);
+    }
+    // no energy is lost at the dielectric boundary
+    for v in sum {
+        assert!((v - 1.).abs() < 1.0e-2, "{:?}", sum);
+    }
+    let ratio = num_far as f32 / num_sample as f32;
+    let expected = (1f32 - r) * (1f32 - r);
+    assert!((ratio - expected).abs() < 1.0e-2, "{} {}", ratio, expected);
+}
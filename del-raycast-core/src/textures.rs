@@ -29,3 +29,106 @@ pub fn sample_checkerboard(
         [tex2[0], tex2[1], tex2[2]]
     }
 }
+
+/// value of the texture at the texture coordinate `uv`
+pub fn sample_texture(tex: &Texture, uv: &[f32; 2]) -> [f32; 3] {
+    match tex {
+        Texture::Checkerboard(tex) => {
+            sample_checkerboard(uv, tex.uscale, tex.vscale, &tex.tex1, &tex.tex2)
+        }
+    }
+}
+
+/// the spectrum parameter of the material given by the constant `value` or the texture `i_texture`
+/// * `i_texture` - index of the texture. The constant is used if it is `usize::MAX`
+pub fn eval_spectrum(
+    textures: &[Texture],
+    i_texture: usize,
+    value: &[f32; 3],
+    uv: &[f32; 2],
+) -> [f32; 3] {
+    if i_texture == usize::MAX {
+        return *value;
+    }
+    sample_texture(&textures[i_texture], uv)
+}
+
+/// the float parameter of the material given by the constant `value` or the texture `i_texture`.
+/// The first channel of the texture is used
+pub fn eval_float(textures: &[Texture], i_texture: usize, value: f32, uv: &[f32; 2]) -> f32 {
+    if i_texture == usize::MAX {
+        return value;
+    }
+    sample_texture(&textures[i_texture], uv)[0]
+}
+
+#[test]
+fn test_textured_diffuse() {
+    let textures = [Texture::Checkerboard(CheckerBoardTexture {
+        uscale: 2.,
+        vscale: 2.,
+        tex1: [0.8, 0.2, 0.1],
+        tex2: [0.1, 0.3, 0.5],
+    })];
+    let mat = crate::material::Material::Diff(crate::material::DiffuseMaterial {
+        reflectance: [1.; 3],
+        reflectance_texture: 0,
+    });
+    let nrm = [0f32, 0., 1.];
+    let wo = del_geo_core::vec3::normalize(&[0.3, 0.2, 1.0]);
+    let wi = del_geo_core::vec3::normalize(&[-0.1, 0.4, 1.0]);
+    for (uv, tex) in [
+        ([0.2f32, 0.2], [0.8, 0.2, 0.1]),
+        ([0.7, 0.2], [0.1, 0.3, 0.5]),
+    ] {
+        let f = crate::material::eval_brdf(&mat, &textures, &uv, &nrm, None, &wo, &wi, 0.);
+        for (v, t) in f.iter().zip(tex) {
+            assert!((v * std::f32::consts::PI - t).abs() < 1.0e-5);
+        }
+    }
+    // the constant is used without the texture
+    let mat = crate::material::Material::Diff(crate::material::DiffuseMaterial {
+        reflectance: [1.; 3],
+        reflectance_texture: usize::MAX,
+    });
+    let f = crate::material::eval_brdf(&mat, &[], &[0.7, 0.2], &nrm, None, &wo, &wi, 0.);
+    assert!((f[0] * std::f32::consts::PI - 1.).abs() < 1.0e-5);
+}
+
+#[test]
+fn test_textured_conductor() {
+    let (tex1, tex2) = ([0.9f32, 0.6, 0.2], [0.3f32, 0.5, 0.7]);
+    let textures = [Texture::Checkerboard(CheckerBoardTexture {
+        uscale: 2.,
+        vscale: 2.,
+        tex1,
+        tex2,
+    })];
+    // the copper eta and k are replaced by the ones derived from the textured reflectance
+    let mat = crate::material::Material::Cond(crate::material::ConductorMaterial {
+        uroughness: 0.,
+        vroughness: 0.,
+        remaproughness: false,
+        reflectance: [1.; 3],
+        k: crate::spectrum::named_spectrum_rgb("metal-Cu-k").unwrap(),
+        eta: crate::spectrum::named_spectrum_rgb("metal-Cu-eta").unwrap(),
+        reflectance_texture: 0,
+        uroughness_texture: usize::MAX,
+        vroughness_texture: usize::MAX,
+        k_texture: usize::MAX,
+        eta_texture: usize::MAX,
+        multiple_scattering: false,
+    });
+    for (uv, tex) in [([0.2f32, 0.2], tex1), ([0.7, 0.2], tex2)] {
+        let Some(crate::material::MaterialBxDF::Conductor(bxdf)) =
+            crate::material::bxdf(&mat, &textures, &uv, None, 0.)
+        else {
+            panic!("the conductor BxDF is not built")
+        };
+        assert_eq!(bxdf.reflectance, [1.; 3]);
+        for i in 0..3 {
+            let r = crate::material::fresnel_conductor_reflectance(bxdf.eta[i], bxdf.k[i], 1.);
+            assert!((r - tex[i]).abs() < 1.0e-4, "{} {}", r, tex[i]);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Baking the shading at the vertices of a triangle mesh for the vertex-color previews.
+//! The occlusion is computed by casting rays in the hemisphere against a triangle mesh with BVH
+
+/// area-weighted vertex normals computed from the triangles
+pub fn vtx2nrm_from_triangles(tri2vtx: &[usize], vtx2xyz: &[f32]) -> Vec<f32> {
+    use del_geo_core::vec3::Vec3;
+    let mut vtx2nrm = vec![0f32; vtx2xyz.len()];
+    for i_tri in 0..tri2vtx.len() / 3 {
+        let (p0, p1, p2) = del_msh_cpu::trimesh3::to_corner_points(tri2vtx, vtx2xyz, i_tri);
+        let n = p1.sub(&p0).cross(&p2.sub(&p0)); // the length is twice the area
+        for &i_vtx in &tri2vtx[i_tri * 3..i_tri * 3 + 3] {
+            let nrm = arrayref::array_mut_ref![vtx2nrm, i_vtx * 3, 3];
+            *nrm = nrm.add(&n);
+        }
+    }
+    for nrm in vtx2nrm.chunks_mut(3) {
+        let nrm = arrayref::array_mut_ref![nrm, 0, 3];
+        if nrm.norm() > 0f32 {
+            *nrm = nrm.normalize();
+        }
+    }
+    vtx2nrm
+}
+
+/// cast cosine-weighted rays in the hemisphere around each vertex and
+/// accumulate `visit(&mut acc, ray_dir, is_occluded)` for each ray
+/// # Return
+/// the accumulated value for each vertex
+#[allow(clippy::too_many_arguments)]
+pub fn cast_hemisphere_rays<T, VISIT>(
+    vtx2xyz: &[f32],
+    vtx2nrm: &[f32],
+    occluder_tri2vtx: &[usize],
+    occluder_vtx2xyz: &[f32],
+    bvhnodes: &[usize],
+    bvhnode2aabb: &[f32],
+    num_sample: usize,
+    max_distance: f32,
+    visit: VISIT,
+) -> Vec<T>
+where
+    T: Default + Send,
+    VISIT: Fn(&mut T, &[f32; 3], bool) + Sync,
+{
+    let trimesh = del_msh_cpu::search_bvh3::TriMeshWithBvh {
+        tri2vtx: occluder_tri2vtx,
+        vtx2xyz: occluder_vtx2xyz,
+        bvhnodes,
+        bvhnode2aabb,
+    };
+    let per_vertex = |i_vtx: usize| -> T {
+        use rand::Rng;
+        use rand::SeedableRng;
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(i_vtx as u64);
+        let pos = arrayref::array_ref![vtx2xyz, i_vtx * 3, 3];
+        let nrm = arrayref::array_ref![vtx2nrm, i_vtx * 3, 3];
+        let ray_org = del_geo_core::vec3::axpy(1.0e-3, nrm, pos);
+        let mut acc = T::default();
+        for _i_sample in 0..num_sample {
+            let ray_dir = crate::sampling::hemisphere_cos_weighted(
+                nrm,
+                &[rng.random::<f32>(), rng.random::<f32>()],
+            );
+            let is_occluded = del_msh_cpu::search_bvh3::first_intersection_ray(
+                &ray_org,
+                &ray_dir,
+                &trimesh,
+                0,
+                max_distance,
+            )
+            .is_some();
+            visit(&mut acc, &ray_dir, is_occluded);
+        }
+        acc
+    };
+    use rayon::prelude::*;
+    (0..vtx2xyz.len() / 3)
+        .into_par_iter()
+        .map(per_vertex)
+        .collect()
+}
+
+/// ambient occlusion and bent normal at the vertices
+/// * `vtx2nrm` - unit normal at the vertices. Computed from the triangles if empty
+/// * `occluder_tri2vtx`, `occluder_vtx2xyz`, `bvhnodes`, `bvhnode2aabb` - the scene for occlusion with BVH
+/// * `max_distance` - the occluders farther than this distance are ignored
+/// # Return
+/// `(vtx2ao, vtx2bentnrm)`
+/// * `vtx2ao` - the ratio of the un-occluded directions weighted by the cosine. `1` for the fully open vertex
+/// * `vtx2bentnrm` - unit average direction of the un-occluded rays. The vertex normal if all the rays are occluded
+#[allow(clippy::too_many_arguments)]
+pub fn vtx2ao_bentnormal(
+    tri2vtx: &[usize],
+    vtx2xyz: &[f32],
+    vtx2nrm: &[f32],
+    occluder_tri2vtx: &[usize],
+    occluder_vtx2xyz: &[f32],
+    bvhnodes: &[usize],
+    bvhnode2aabb: &[f32],
+    num_sample: usize,
+    max_distance: f32,
+) -> (Vec<f32>, Vec<f32>) {
+    let vtx2nrm = if vtx2nrm.is_empty() {
+        vtx2nrm_from_triangles(tri2vtx, vtx2xyz)
+    } else {
+        vtx2nrm.to_vec()
+    };
+    // (number of un-occluded rays, sum of un-occluded directions)
+    let vtx2sum: Vec<(usize, [f32; 3])> = cast_hemisphere_rays(
+        vtx2xyz,
+        &vtx2nrm,
+        occluder_tri2vtx,
+        occluder_vtx2xyz,
+        bvhnodes,
+        bvhnode2aabb,
+        num_sample,
+        max_distance,
+        |sum: &mut (usize, [f32; 3]), ray_dir, is_occluded| {
+            if is_occluded {
+                return;
+            }
+            sum.0 += 1;
+            sum.1 = del_geo_core::vec3::add(&sum.1, ray_dir);
+        },
+    );
+    let mut vtx2ao = vec![0f32; vtx2sum.len()];
+    let mut vtx2bentnrm = vtx2nrm.clone();
+    for (i_vtx, &(cnt, dir)) in vtx2sum.iter().enumerate() {
+        vtx2ao[i_vtx] = cnt as f32 / num_sample as f32;
+        if cnt == 0 {
+            continue;
+        }
+        let dir = del_geo_core::vec3::normalize(&dir);
+        vtx2bentnrm[i_vtx * 3..i_vtx * 3 + 3].copy_from_slice(&dir);
+    }
+    (vtx2ao, vtx2bentnrm)
+}
+
+/// irradiance at the vertices lit by the environment
+/// * `env` - radiance of the environment coming from the direction (unit vector pointing the outside)
+/// # Return
+/// `vtx2irradiance` with the size of `num_vtx * 3`. Multiplying the reflectance divided by pi gives the outgoing radiance
+#[allow(clippy::too_many_arguments)]
+pub fn vtx2irradiance<ENV>(
+    tri2vtx: &[usize],
+    vtx2xyz: &[f32],
+    vtx2nrm: &[f32],
+    occluder_tri2vtx: &[usize],
+    occluder_vtx2xyz: &[f32],
+    bvhnodes: &[usize],
+    bvhnode2aabb: &[f32],
+    num_sample: usize,
+    env: ENV,
+) -> Vec<f32>
+where
+    ENV: Fn(&[f32; 3]) -> [f32; 3] + Sync,
+{
+    let vtx2nrm = if vtx2nrm.is_empty() {
+        vtx2nrm_from_triangles(tri2vtx, vtx2xyz)
+    } else {
+        vtx2nrm.to_vec()
+    };
+    // the pdf of the cosine-weighted sampling is cos/pi
+    let weight = std::f32::consts::PI / num_sample as f32;
+    let vtx2irradiance: Vec<[f32; 3]> = cast_hemisphere_rays(
+        vtx2xyz,
+        &vtx2nrm,
+        occluder_tri2vtx,
+        occluder_vtx2xyz,
+        bvhnodes,
+        bvhnode2aabb,
+        num_sample,
+        f32::INFINITY,
+        |irradiance: &mut [f32; 3], ray_dir, is_occluded| {
+            if is_occluded {
+                return;
+            }
+            *irradiance = del_geo_core::vec3::axpy(weight, &env(ray_dir), irradiance);
+        },
+    );
+    vtx2irradiance.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+fn ao_with_self_occlusion(
+    tri2vtx: &[usize],
+    vtx2xyz: &[f32],
+    num_sample: usize,
+) -> (Vec<f32>, Vec<f32>) {
+    let bvhnodes = del_msh_cpu::bvhnodes_morton::from_triangle_mesh(tri2vtx, vtx2xyz, 3);
+    let bvhnode2aabb = del_msh_cpu::bvhnode2aabb3::from_uniform_mesh_with_bvh(
+        0,
+        &bvhnodes,
+        Some((tri2vtx, 3)),
+        vtx2xyz,
+        None,
+    );
+    vtx2ao_bentnormal(
+        tri2vtx,
+        vtx2xyz,
+        &[],
+        tri2vtx,
+        vtx2xyz,
+        &bvhnodes,
+        &bvhnode2aabb,
+        num_sample,
+        f32::INFINITY,
+    )
+}
+
+#[test]
+fn test_ao_open_plane() {
+    // square on the xy-plane with the vertex at the center
+    let vtx2xyz = vec![
+        -1., -1., 0., 1., -1., 0., 1., 1., 0., -1., 1., 0., 0., 0., 0.,
+    ];
+    let tri2vtx = vec![0, 1, 4, 1, 2, 4, 2, 3, 4, 3, 0, 4];
+    let (vtx2ao, vtx2bentnrm) = ao_with_self_occlusion(&tri2vtx, &vtx2xyz, 1000);
+    for (ao, bentnrm) in vtx2ao.iter().zip(vtx2bentnrm.chunks(3)) {
+        assert!((ao - 1.).abs() < 1.0e-5, "{}", ao);
+        assert!(bentnrm[2] > 0.99, "{:?}", bentnrm);
+    }
+}
+
+#[test]
+fn test_ao_right_angle_corner() {
+    // the floor `z=0, x>0` and the wall `x=0, z>0` sharing the vertex `0` at the origin
+    let l = 100f32;
+    #[rustfmt::skip]
+    let vtx2xyz = vec![
+        0., 0., 0.,
+        0., -l, 0.,
+        0., l, 0.,
+        l, -l, 0.,
+        l, l, 0.,
+        0., -l, l,
+        0., l, l,
+    ];
+    let tri2vtx = vec![
+        1, 3, 0, 0, 3, 4, 0, 4, 2, // floor
+        1, 0, 5, 0, 6, 5, 0, 2, 6, // wall
+    ];
+    let (vtx2ao, vtx2bentnrm) = ao_with_self_occlusion(&tri2vtx, &vtx2xyz, 4000);
+    // the vertex normal bisects the corner, and the occluded directions are the two lunes
+    // whose projected areas are `(1 - 1/sqrt(2))/2` of the unit disk
+    let expected = std::f32::consts::FRAC_1_SQRT_2;
+    assert!((vtx2ao[0] - expected).abs() < 0.02, "{}", vtx2ao[0]);
+    // the un-occluded directions are symmetric about the vertex normal
+    let bentnrm = arrayref::array_ref![vtx2bentnrm, 0, 3];
+    assert!((bentnrm[0] - bentnrm[2]).abs() < 0.05, "{:?}", bentnrm);
+    assert!(bentnrm[1].abs() < 0.05, "{:?}", bentnrm);
+}